@@ -1,13 +1,16 @@
+use std::collections::HashMap;
 use egui::{Align, Color32, Layout, RichText, Rounding, Vec2};
 use crate::{
     db::Database,
     models::*,
     screens::{
         login::{AuthAction, LoginScreen},
-        chat::{ActiveChat, ChatAction, ChatScreen},
-        inventory::{InventoryAction, InventoryScreen},
+        chat::{ActiveChat, ChatAction, ChatScreen, ChatTab, MESSAGE_PAGE},
+        inventory::{crossed_to_empty, InventoryAction, InventoryScreen, InventoryView, SUGGEST_LIMIT},
+        palette::{rank_results, CommandPalette, PaletteAction, PaletteItem, PALETTE_LIMIT},
         settings::{SettingsAction, SettingsScreen},
         splash::{SplashScreen, SplashState},
+        whats_new::{self, WhatsNew},
     },
     theme::{self, NimColors},
 };
@@ -29,11 +32,83 @@ pub enum Screen {
 // APP STATE
 // ──────────────────────────────────────────────
 
+/// Counters shown on the bottom nav bar.
+#[derive(Debug, Clone, Default)]
+pub struct NavBadges {
+    pub unread: u64,
+    /// Products at or below their alert level (see `Product::needs_reorder`).
+    pub low_stock: u64,
+}
+
+/// Max results shown for a message search.
+const SEARCH_LIMIT: usize = 50;
+
+/// Seconds between badge refreshes while logged in.
+const BADGE_POLL_SECS: f64 = 5.0;
+
+/// How long the "Nuevo mensaje" toast stays up.
+const MESSAGE_TOAST_SECS: f64 = 4.0;
+
+/// A contact whose unread count grew since the last poll, skipping the chat
+/// that's open (its messages are on screen already).
+fn newly_unread<'a>(
+    before: &HashMap<String, u32>,
+    now: &'a HashMap<String, u32>,
+    open_chat: Option<&str>,
+) -> Option<&'a str> {
+    now.iter()
+        .filter(|(uid, _)| Some(uid.as_str()) != open_chat)
+        .find(|(uid, count)| **count > before.get(*uid).copied().unwrap_or(0))
+        .map(|(uid, _)| uid.as_str())
+}
+
+/// Whether the badge counters are due for a refresh (`None` = never polled).
+fn poll_due(last: Option<f64>, now: f64, interval: f64) -> bool {
+    last.is_none_or(|t| now - t >= interval)
+}
+
+/// Whether the bottom nav bar is shown: logged-in screens only, and not
+/// inside an open conversation, where the input bar needs the room.
+fn nav_bar_visible(screen: &Screen, logged_in: bool, in_chat: bool) -> bool {
+    logged_in
+        && !matches!(screen, Screen::Auth | Screen::Splash)
+        && !(*screen == Screen::Chat && in_chat)
+}
+
 pub struct NimbuzynApp {
     pub db: Database,
     pub current_screen: Screen,
     pub current_user: Option<User>,
     pub theme: AppTheme,
+    /// Custom primary color (packed RGBA) layered over `theme`.
+    pub accent_color: Option<u32>,
+    /// `accent_color` is a color picker preview not yet written to the database.
+    accent_unsaved: bool,
+    pub app_focused: bool,
+    /// In-app text size and the platform scale it's multiplied by.
+    pub font_size: f32,
+    pub system_font_scale: f32,
+    /// Auth request queued by the login screen with the frame it was queued
+    /// in. It runs only once a later frame (showing the busy state) has been
+    /// painted, since the Argon2 check blocks the UI thread.
+    pub pending_auth: Option<(AuthAction, u64)>,
+    /// The database on disk is encrypted and not open yet: `db` is a blank
+    /// stand-in until the next login supplies the key.
+    #[cfg(feature = "encryption")]
+    db_locked: bool,
+
+    // Nav bar badges, refreshed every `badge_poll_secs`
+    pub badges: NavBadges,
+    pub badge_poll_secs: f64,
+    last_badge_poll: Option<f64>,
+
+    /// "Notificaciones" setting: toast when a message lands in a chat that
+    /// isn't open. Unread counts per contact at the last poll (`None` until
+    /// the first, so old unread messages don't toast at login).
+    pub notifications_enabled: bool,
+    last_unread: Option<HashMap<String, u32>>,
+    /// "Nuevo mensaje" toast: text and when it was first shown.
+    pub message_toast: Option<(String, Option<f64>)>,
 
     // Screen state
     pub splash_screen: SplashScreen,
@@ -41,31 +116,121 @@ pub struct NimbuzynApp {
     pub chat_screen: ChatScreen,
     pub inventory_screen: InventoryScreen,
     pub settings_screen: Option<SettingsScreen>,
+    pub palette: CommandPalette,
+    /// Release notes waiting to be shown after login.
+    pub whats_new: Option<WhatsNew>,
 }
 
 impl NimbuzynApp {
     pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
         // Determine database path (platform-specific)
         let db_path = Self::db_path();
-        let db = Database::open(&db_path).expect("No se pudo abrir la base de datos");
+        Self::migrate_default_db(&db_path);
+        let db = Self::open_db(&db_path).expect("No se pudo abrir la base de datos");
+        // Follow the OS until an account's saved preference is loaded
+        let app_theme = match cc.integration_info.system_theme {
+            Some(eframe::Theme::Light) => AppTheme::Light,
+            _ => AppTheme::Dark,
+        };
 
-        let mut app = NimbuzynApp {
+        let mut app = Self::with_db(db, app_theme);
+        #[cfg(feature = "encryption")]
+        {
+            app.db_locked = crate::db::is_encrypted(&db_path);
+        }
+        app.whats_new = app.pending_whats_new();
+
+        theme::apply_theme(&cc.egui_ctx, &app.theme, app.accent_color);
+        app.apply_font_size(&cc.egui_ctx);
+
+        // A remembered login skips Auth once the splash is done
+        match app.db.validate_session() {
+            Ok(Some(user)) => app.load_user(user, &cc.egui_ctx),
+            Ok(None) => {}
+            Err(e) => log::warn!("No se pudo leer la sesión guardada: {}", e),
+        }
+        app
+    }
+
+    /// App state on the splash screen with nobody logged in.
+    fn with_db(db: Database, app_theme: AppTheme) -> Self {
+        NimbuzynApp {
             db,
             current_screen: Screen::Splash,
             current_user: None,
-            theme: AppTheme::Dark,
+            theme: app_theme,
+            accent_color: None,
+            accent_unsaved: false,
+            app_focused: true,
+            font_size: theme::DEFAULT_FONT_SIZE,
+            system_font_scale: theme::system_font_scale(),
+            pending_auth: None,
+            #[cfg(feature = "encryption")]
+            db_locked: false,
+            badges: NavBadges::default(),
+            badge_poll_secs: BADGE_POLL_SECS,
+            last_badge_poll: None,
+            notifications_enabled: true,
+            last_unread: None,
+            message_toast: None,
             splash_screen: SplashScreen::new(),
             login_screen: LoginScreen::default(),
             chat_screen: ChatScreen::default(),
             inventory_screen: InventoryScreen::default(),
             settings_screen: None,
-        };
+            palette: CommandPalette::default(),
+            whats_new: None,
+        }
+    }
 
-        theme::apply_theme(&cc.egui_ctx, &app.theme);
-        app
+    /// Release notes to show if this build is newer than the last one the
+    /// user saw. A fresh install has nothing new to tell, so it just records
+    /// the version.
+    fn pending_whats_new(&self) -> Option<WhatsNew> {
+        let last_seen = self.db.get_last_seen_version().unwrap_or(None);
+        if !whats_new::should_show(whats_new::APP_VERSION, last_seen.as_deref()) {
+            return None;
+        }
+        let notes = whats_new::notes_for(whats_new::APP_VERSION);
+        if notes.is_none() || (last_seen.is_none() && self.db.user_count().unwrap_or(0) == 0) {
+            let _ = self.db.set_last_seen_version(whats_new::APP_VERSION);
+            return None;
+        }
+        notes.map(|notes| WhatsNew { version: whats_new::APP_VERSION, notes })
+    }
+
+    fn open_db(db_path: &str) -> anyhow::Result<Database> {
+        // An encrypted database opens at login; a blank one stands in until then
+        #[cfg(feature = "encryption")]
+        if crate::db::is_encrypted(db_path) {
+            return Database::open(":memory:");
+        }
+        let pepper = Self::pepper();
+        Database::open_with_pepper(db_path, pepper.as_deref().map(str::as_bytes))
+    }
+
+    /// Optional password pepper, only ever supplied at runtime.
+    fn pepper() -> Option<String> {
+        std::env::var("NIMBUZYN_PEPPER").ok().filter(|p| !p.is_empty())
     }
 
     fn db_path() -> String {
+        #[cfg(target_os = "android")]
+        {
+            Self::default_db_path()
+        }
+        #[cfg(not(target_os = "android"))]
+        {
+            // `--db <path>` on the command line wins over NIMBUZYN_DB_PATH
+            let mut args = std::env::args().skip_while(|a| a != "--db").skip(1);
+            args.next()
+                .or_else(|| std::env::var("NIMBUZYN_DB_PATH").ok())
+                .filter(|p| !p.trim().is_empty())
+                .unwrap_or_else(Self::default_db_path)
+        }
+    }
+
+    fn default_db_path() -> String {
         #[cfg(target_os = "android")]
         {
             // On Android, use the app's files directory
@@ -79,11 +244,96 @@ impl NimbuzynApp {
         }
     }
 
+    /// If a custom DB path is configured but still empty while the default
+    /// location holds data, carry the old database over.
+    fn migrate_default_db(db_path: &str) {
+        let default_path = Self::default_db_path();
+        if db_path == default_path
+            || std::path::Path::new(db_path).exists()
+            || !std::path::Path::new(&default_path).exists()
+        {
+            return;
+        }
+        match Database::migrate_from(&default_path, db_path) {
+            Ok(()) => log::info!("Base de datos migrada de {} a {}", default_path, db_path),
+            Err(e) => log::warn!("No se pudo migrar la base de datos: {}", e),
+        }
+    }
+
+    // ──────────────────────────────────────────
+    // LIFECYCLE
+    // ──────────────────────────────────────────
+
+    /// App went to the background (Android may kill it from here on):
+    /// make sure everything written so far is in the main DB file.
+    pub fn on_pause(&mut self) {
+        self.save_accent_color();
+        if let (Some(user), Some(active)) = (&self.current_user, &self.chat_screen.active_chat) {
+            let _ = self.db.save_draft(&user.uid, active.chat_id, &active.input_text);
+        }
+        if let Err(e) = self.db.checkpoint() {
+            log::warn!("Checkpoint al pausar falló: {}", e);
+        }
+        self.chat_screen.add_preview_user = None;
+    }
+
+    /// App is back in the foreground: settings may have changed meanwhile.
+    pub fn on_resume(&mut self, ctx: &egui::Context) {
+        let Some(ref user) = self.current_user else { return };
+        if let Ok(settings) = self.db.get_settings(&user.uid) {
+            self.theme = settings.theme;
+            self.accent_color = settings.accent_color;
+            theme::apply_theme(ctx, &self.theme, self.accent_color);
+            // Older builds allowed sizes outside today's slider range
+            self.font_size = settings.font_size.clamp(theme::MIN_BASE_FONT_SIZE, theme::MAX_BASE_FONT_SIZE);
+            self.apply_font_size(ctx);
+            self.notifications_enabled = settings.notifications_enabled;
+        }
+    }
+
+    /// Logged-in screens need a user; fall back to Auth instead of panicking.
+    fn guard_session(&mut self) {
+        if self.current_user.is_none()
+            && matches!(self.current_screen, Screen::Chat | Screen::Inventory | Screen::Settings)
+        {
+            log::warn!("{:?} sin sesión activa, volviendo a Auth", self.current_screen);
+            self.settings_screen = None;
+            self.current_screen = Screen::Auth;
+        }
+    }
+
+    /// Show `color` as the accent everywhere, without saving it.
+    fn set_accent_color(&mut self, color: Option<u32>, ctx: &egui::Context) {
+        self.accent_color = color;
+        theme::apply_theme(ctx, &self.theme, self.accent_color);
+        if let Some(ref mut s) = self.settings_screen {
+            s.accent_color = color;
+        }
+    }
+
+    /// Write a previewed accent color to the database, if there is one.
+    fn save_accent_color(&mut self) {
+        if !self.accent_unsaved {
+            return;
+        }
+        self.accent_unsaved = false;
+        let Some(ref user) = self.current_user else { return };
+        if let Err(e) = self.db.update_accent_color(&user.uid, self.accent_color) {
+            log::warn!("No se pudo guardar el color de acento: {}", e);
+        }
+    }
+
+    fn apply_font_size(&self, ctx: &egui::Context) {
+        theme::apply_font_size(ctx, theme::effective_font_size(self.font_size, self.system_font_scale));
+    }
+
     // ──────────────────────────────────────────
     // NAVIGATION
     // ──────────────────────────────────────────
 
     fn navigate_to(&mut self, screen: Screen, ctx: &egui::Context) {
+        self.save_accent_color();
+        self.refresh_badges();
         // Load data when navigating
         match &screen {
             Screen::Chat => {
@@ -94,7 +344,24 @@ impl NimbuzynApp {
             }
             Screen::Settings => {
                 if let Some(ref user) = self.current_user {
-                    self.settings_screen = Some(SettingsScreen::new(user));
+                    let mut settings = SettingsScreen::new(user);
+                    settings.stats = self.db.profile_stats(&user.uid).unwrap_or_default();
+                    settings.density = self.inventory_screen.density;
+                    settings.accent_color = self.accent_color;
+                    settings.currency = self.inventory_screen.currency.clone();
+                    settings.font_size = self.font_size;
+                    let prefs = self.db.get_settings(&user.uid).unwrap_or_default();
+                    settings.read_receipts = prefs.read_receipts;
+                    settings.group_window_mins = prefs.group_window_mins;
+                    settings.text_autoformat = prefs.text_autoformat;
+                    settings.copy_links = prefs.copy_links;
+                    settings.notifications_enabled = prefs.notifications_enabled;
+                    settings.confirm_acquaintance_send = prefs.confirm_acquaintance_send;
+                    settings.schema_version = self.db.schema_version().unwrap_or(0);
+                    settings.last_backup_at = self.db.last_backup_at().unwrap_or(None);
+                    settings.is_last_account = self.db.user_count().unwrap_or(0) == 1;
+                    settings.orphaned_chats = self.db.orphaned_chats(&user.uid).map(|c| c.len()).unwrap_or(0);
+                    self.settings_screen = Some(settings);
                 }
             }
             _ => {}
@@ -117,14 +384,93 @@ impl NimbuzynApp {
                 .db
                 .get_contacts(&uid, "acquaintance")
                 .unwrap_or_default();
+            self.chat_screen.mutual_uids = self.db.mutual_contact_uids(&uid).unwrap_or_default();
+            self.chat_screen.unread = self.db.unread_by_contact(&uid).unwrap_or_default();
+            self.chat_screen.blocked = self.db.blocked_uids(&uid).unwrap_or_default();
+            self.chat_screen.drafts = self.db.drafts_by_contact(&uid).unwrap_or_default();
         }
     }
 
+    /// Re-query only the cheap aggregates behind the nav bar badges.
+    fn refresh_badges(&mut self) {
+        if let Some(ref user) = self.current_user {
+            self.badges.unread = self.db.total_unread(&user.uid).unwrap_or(0);
+            self.badges.low_stock = self.db.low_stock_count(&user.uid, None).unwrap_or(0);
+
+            let unread = self.db.unread_by_contact(&user.uid).unwrap_or_default();
+            if let Some(before) = self.last_unread.take() {
+                let open = self.chat_screen.active_chat.as_ref().map(|a| a.contact.contact_uid.as_str());
+                if let (true, Some(from)) = (self.notifications_enabled, newly_unread(&before, &unread, open)) {
+                    let name = self
+                        .chat_screen
+                        .contacts_friends
+                        .iter()
+                        .chain(&self.chat_screen.contacts_acquaintances)
+                        .find(|ct| ct.contact_uid == from)
+                        .map(|ct| ct.display_name.clone());
+                    let text = match name {
+                        Some(name) => format!("💬 Nuevo mensaje de {}", name),
+                        None => "💬 Nuevo mensaje".to_string(),
+                    };
+                    self.message_toast = Some((text, None));
+                }
+            }
+            self.last_unread = Some(unread);
+        }
+    }
+
+    /// Pick up read marks on our own messages in the open chat: the contact
+    /// may have opened the conversation since its history was loaded.
+    fn refresh_read_marks(&mut self) {
+        let (Some(user), Some(active)) = (&self.current_user, &mut self.chat_screen.active_chat) else { return };
+        let first_unread = active
+            .messages
+            .iter()
+            .filter(|m| m.sender_uid == user.uid && !m.is_read)
+            .map(|m| m.id)
+            .min();
+        let Some(from_id) = first_unread else { return };
+        match self.db.read_message_ids(active.chat_id, &user.uid, from_id) {
+            Ok(read) => {
+                for msg in active.messages.iter_mut().filter(|m| read.contains(&m.id)) {
+                    msg.is_read = true;
+                }
+            }
+            Err(e) => log::warn!("No se pudo actualizar el estado de lectura: {}", e),
+        }
+    }
+
+    /// Floating "Nuevo mensaje" toast; clears itself after `MESSAGE_TOAST_SECS`.
+    fn show_message_toast(&mut self, ctx: &egui::Context) {
+        let c = NimColors::for_theme(&self.theme, self.accent_color);
+        let frame = egui::Frame::none()
+            .fill(c.bg_elevated)
+            .stroke(egui::Stroke::new(1.0, c.primary))
+            .rounding(Rounding::same(12.0))
+            .inner_margin(egui::Margin::symmetric(16.0, 10.0));
+        theme::show_toast(ctx, "message_toast", &mut self.message_toast, MESSAGE_TOAST_SECS, 16.0, frame, |text| {
+            RichText::new(text).size(14.0).color(c.text_primary)
+        });
+    }
+
     fn refresh_products(&mut self) {
         if let Some(ref user) = self.current_user {
             let uid = user.uid.clone();
-            self.inventory_screen.products = self.db.get_products(&uid).unwrap_or_default();
-            self.inventory_screen.summary = self.db.inventory_summary(&uid).unwrap_or_default();
+            let inv = &mut self.inventory_screen;
+            inv.categories = self.db.list_categories(&uid).unwrap_or_default();
+            // The filtered category may have just been emptied or renamed away
+            if inv.category_filter.as_ref().is_some_and(|cat| !inv.categories.contains(cat)) {
+                inv.category_filter = None;
+            }
+            let filter = inv.category_filter.clone();
+            inv.products = self.db.get_products_sorted(&uid, filter.as_deref(), inv.sort).unwrap_or_default();
+            let summary_filter = filter.as_deref().filter(|_| inv.summary_follows_filter);
+            inv.summary = self.db.inventory_summary(&uid, summary_filter).unwrap_or_default();
+            self.badges.low_stock = if summary_filter.is_none() {
+                inv.summary.low_stock_count
+            } else {
+                self.db.low_stock_count(&uid, None).unwrap_or(0)
+            };
         }
     }
 
@@ -135,25 +481,53 @@ impl NimbuzynApp {
     fn handle_auth_action(&mut self, action: AuthAction, ctx: &egui::Context) {
         match action {
             AuthAction::Login { username, password } => {
+                #[cfg(feature = "encryption")]
+                if let Err(e) = self.unlock_database(&password) {
+                    self.login_screen.login_error = Some(e.to_string());
+                    return;
+                }
                 match self.db.login(&username, &password) {
                     Ok(user) => {
-                        // Load theme preference
-                        if let Ok(settings) = self.db.get_settings(&user.uid) {
-                            self.theme = settings.theme;
-                            theme::apply_theme(ctx, &self.theme);
+                        #[cfg(feature = "encryption")]
+                        self.encrypt_database(&password);
+                        self.start_session(user, ctx)
+                    }
+                    Err(e) => {
+                        self.login_screen.login_error = Some(e.to_string());
+                        if let Ok(Some(wait)) = self.db.login_cooldown(&username) {
+                            let now = ctx.input(|i| i.time);
+                            self.login_screen.login_locked_until = Some(now + wait as f64);
                         }
-                        self.current_user = Some(user);
-                        self.login_screen.login_error = None;
-                        self.navigate_to(Screen::Chat, ctx);
                     }
+                }
+            }
+            #[cfg(debug_assertions)]
+            AuthAction::DevLogin => {
+                match self.db.seed_dev_user() {
+                    Ok(user) => self.start_session(user, ctx),
                     Err(e) => {
                         self.login_screen.login_error = Some(e.to_string());
                     }
                 }
             }
             AuthAction::Register { username, display_name, password } => {
+                #[cfg(feature = "encryption")]
+                if crate::db::is_encrypted(&Self::db_path()) {
+                    self.login_screen.reg_error =
+                        Some("Los datos de este dispositivo están cifrados con la contraseña de otra cuenta".into());
+                    return;
+                }
                 match self.db.register_user(&username, &display_name, &password) {
-                    Ok(_) => {
+                    Ok(user) => {
+                        // Keep the theme the account was created under (the OS
+                        // one on a fresh install) instead of the column default
+                        if let Err(e) = self.db.update_theme(&user.uid, self.theme.as_str()) {
+                            log::warn!("No se pudo guardar el tema inicial: {}", e);
+                        }
+                        match self.db.create_recovery_phrase(&user.uid) {
+                            Ok(phrase) => self.login_screen.recovery_phrase = Some(phrase),
+                            Err(e) => log::warn!("No se pudo crear la frase de recuperación: {}", e),
+                        }
                         self.login_screen.reg_success =
                             Some("Cuenta creada. Ahora inicia sesión.".into());
                         self.login_screen.reg_error = None;
@@ -165,10 +539,60 @@ impl NimbuzynApp {
                     }
                 }
             }
+            AuthAction::ResetPassword { username, phrase, new_password } => {
+                #[cfg(feature = "encryption")]
+                if crate::db::is_encrypted(&Self::db_path()) {
+                    // The key comes from the password, so the phrase can't open the data
+                    self.login_screen.rec_error =
+                        Some("Los datos cifrados solo se abren con la contraseña".into());
+                    return;
+                }
+                match self.db.reset_password_with_phrase(&username, &phrase, &new_password) {
+                    Ok(()) => {
+                        let screen = &mut self.login_screen;
+                        screen.recovering = false;
+                        screen.rec_phrase.clear();
+                        screen.rec_pass.clear();
+                        screen.rec_pass2.clear();
+                        screen.login_user = username;
+                        screen.login_pass.clear();
+                        screen.login_error = None;
+                        screen.login_notice = Some("Contraseña restablecida. Ya puedes iniciar sesión.".into());
+                    }
+                    Err(e) => self.login_screen.rec_error = Some(e.to_string()),
+                }
+            }
             AuthAction::None => {}
         }
     }
 
+    fn start_session(&mut self, user: User, ctx: &egui::Context) {
+        if let Err(e) = self.db.create_session(&user.uid) {
+            log::warn!("No se pudo recordar la sesión: {}", e);
+        }
+        self.load_user(user, ctx);
+        self.navigate_to(Screen::Chat, ctx);
+    }
+
+    /// Make `user` the current user and apply their saved preferences.
+    fn load_user(&mut self, user: User, ctx: &egui::Context) {
+        // Load theme preference and the last chat tab
+        if let Ok(settings) = self.db.get_settings(&user.uid) {
+            self.theme = settings.theme;
+            self.accent_color = settings.accent_color;
+            theme::apply_theme(ctx, &self.theme, self.accent_color);
+            self.chat_screen.tab = ChatTab::from_contact_type(&settings.last_chat_tab);
+            self.inventory_screen.density = settings.list_density;
+            self.inventory_screen.currency = settings.currency;
+            // Older builds allowed sizes outside today's slider range
+            self.font_size = settings.font_size.clamp(theme::MIN_BASE_FONT_SIZE, theme::MAX_BASE_FONT_SIZE);
+            self.apply_font_size(ctx);
+            self.notifications_enabled = settings.notifications_enabled;
+        }
+        self.current_user = Some(user);
+        self.login_screen.login_error = None;
+    }
+
     // ──────────────────────────────────────────
     // CHAT HANDLERS
     // ──────────────────────────────────────────
@@ -180,6 +604,110 @@ impl NimbuzynApp {
         match action {
             ChatAction::LoadContacts => self.refresh_contacts(),
 
+            ChatAction::MarkAllRead => {
+                let prefs = self.db.get_settings(&uid).unwrap_or_default();
+                if let Err(e) = self.db.mark_all_read(&uid, prefs.read_receipts) {
+                    log::warn!("No se pudieron marcar los chats como leídos: {}", e);
+                }
+                self.refresh_badges();
+                self.refresh_contacts();
+            }
+
+            ChatAction::SwitchTab { tab } => {
+                let tab_str = match tab.contact_type() {
+                    ContactType::Friend => "friend",
+                    ContactType::Acquaintance => "acquaintance",
+                };
+                let _ = self.db.update_last_chat_tab(&uid, tab_str);
+                self.refresh_contacts();
+            }
+
+            ChatAction::ShowEditHistory { message_id } => {
+                let history = self.db.message_edit_history(message_id).unwrap_or_default();
+                if let Some(ref mut active) = self.chat_screen.active_chat {
+                    active.edit_history = Some((message_id, history));
+                }
+            }
+
+            ChatAction::EditMessage { message_id, content } => {
+                let Some(ref mut active) = self.chat_screen.active_chat else { return };
+                let Some(msg) = active.messages.iter_mut().find(|m| m.id == message_id) else { return };
+                match self.db.edit_message(message_id, &uid, &content) {
+                    Ok(edited_at) => {
+                        if msg.content != content.trim() {
+                            msg.content = content.trim().to_string();
+                            msg.edited_at = Some(edited_at);
+                        }
+                        active.editing = None;
+                    }
+                    Err(e) => {
+                        if let Some(ref mut edit) = active.editing {
+                            edit.error = Some(e.to_string());
+                        }
+                    }
+                }
+            }
+
+            ChatAction::DeleteMessage { message_id } => {
+                if let Err(e) = self.db.delete_message(message_id, &uid) {
+                    log::warn!("No se pudo eliminar el mensaje: {}", e);
+                    return;
+                }
+                if let Some(ref mut active) = self.chat_screen.active_chat {
+                    // Swap in the tombstone rather than reloading, so older
+                    // pages the user scrolled back to stay loaded.
+                    if let Ok(Some(tombstone)) = self.db.get_message(message_id) {
+                        if let Some(m) = active.messages.iter_mut().find(|m| m.id == message_id) {
+                            *m = tombstone;
+                        }
+                    }
+                    active.reactions = self.db.chat_reactions(active.chat_id, &uid).unwrap_or_default();
+                    if active.editing.as_ref().is_some_and(|e| e.message_id == message_id) {
+                        active.editing = None;
+                    }
+                }
+                self.refresh_contacts();
+            }
+
+            ChatAction::ToggleReaction { message_id, emoji } => {
+                if let Err(e) = self.db.toggle_reaction(message_id, &uid, &emoji) {
+                    log::warn!("No se pudo reaccionar: {}", e);
+                }
+                if let Some(ref mut active) = self.chat_screen.active_chat {
+                    active.reactions = self.db.chat_reactions(active.chat_id, &uid).unwrap_or_default();
+                }
+            }
+
+            ChatAction::ShowMedia { chat_id } => {
+                let media = self.db.get_media_messages(chat_id).unwrap_or_default();
+                if let Some(ref mut active) = self.chat_screen.active_chat {
+                    active.media = Some(media);
+                }
+            }
+
+            ChatAction::SearchMessages { chat_id, query, all_chats } => {
+                let Some(ref mut active) = self.chat_screen.active_chat else { return };
+                active.search_results = if all_chats {
+                    self.db.search_all_messages(&uid, &query, SEARCH_LIMIT).unwrap_or_default()
+                } else {
+                    self.db
+                        .search_messages(chat_id, &query, SEARCH_LIMIT)
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(|message| SearchHit {
+                            message,
+                            contact_uid: active.contact.contact_uid.clone(),
+                            contact_name: active.contact.display_name.clone(),
+                        })
+                        .collect()
+                };
+                // Start on the newest hit of this chat
+                active.search_cursor = active.search_results.iter().position(|hit| hit.message.chat_id == chat_id);
+                active.scroll_to_message = active
+                    .search_cursor
+                    .map(|i| active.search_results[i].message.id);
+            }
+
             ChatAction::PreviewUser { uid: target_uid } => {
                 match self.db.find_user_by_uid(&target_uid) {
                     Ok(found) => {
@@ -223,43 +751,249 @@ impl NimbuzynApp {
 
             ChatAction::OpenChat { contact } => {
                 if let Ok(chat) = self.db.get_or_create_chat(&uid, &contact.contact_uid) {
-                    let messages = self.db.get_messages(chat.id, 100, 0).unwrap_or_default();
-                    self.chat_screen.active_chat = Some(ActiveChat {
-                        chat_id: chat.id,
-                        contact,
-                        messages,
-                        input_text: String::new(),
-                        scroll_to_bottom: true,
-                        char_count: 0,
-                        file_error: None,
-                    });
+                    let prefs = self.db.get_settings(&uid).unwrap_or_default();
+                    if let Err(e) = self.db.mark_chat_read(chat.id, &uid, prefs.read_receipts) {
+                        log::warn!("No se pudo marcar el chat como leído: {}", e);
+                    }
+                    self.refresh_badges();
+                    self.chat_screen.unread.remove(&contact.contact_uid);
+                    let messages = self.db.get_messages(chat.id, MESSAGE_PAGE, 0).unwrap_or_default();
+                    let mutual = self.db.is_mutual_contact(&uid, &contact.contact_uid).unwrap_or(false);
+                    let draft = self.db.get_draft(&uid, chat.id).unwrap_or(None);
+                    let mut active = ActiveChat::new(chat.id, contact, messages, mutual);
+                    active.input_text = draft.unwrap_or_default();
+                    active.read_receipts = prefs.read_receipts;
+                    active.confirm_acquaintance_send = prefs.confirm_acquaintance_send;
+                    active.group_window_secs = i64::from(prefs.group_window_mins) * 60;
+                    active.text_autoformat = prefs.text_autoformat;
+                    active.copy_links = prefs.copy_links;
+                    active.blocked = self.chat_screen.blocked.contains(&active.contact.contact_uid);
+                    active.reactions = self.db.chat_reactions(chat.id, &uid).unwrap_or_default();
+                    self.chat_screen.active_chat = Some(active);
+                    self.load_quoted();
                 }
             }
 
-            ChatAction::SendMessage { chat_id, content } => {
-                let msg = self.db.send_message(chat_id, &uid, &content, "text", None, None);
-                if let Ok(m) = msg {
-                    if let Some(ref mut active) = self.chat_screen.active_chat {
-                        active.messages.push(m);
-                        active.scroll_to_bottom = true;
+            ChatAction::SendMessage { chat_id, local_id, content, reply_to } => {
+                let result = self.db.send_message(chat_id, &uid, &content, "text", None, None, reply_to);
+                let Some(ref mut active) = self.chat_screen.active_chat else { return };
+                match result {
+                    Ok(m) => {
+                        let _ = self.db.clear_draft(&uid, chat_id);
+                        active.mark_sent(local_id, m);
+                    }
+                    Err(e) => {
+                        log::warn!("No se pudo enviar el mensaje: {}", e);
+                        active.mark_failed(local_id, e.to_string());
+                    }
+                }
+            }
+
+            ChatAction::SendFile { chat_id, path } => {
+                let result = self.send_file(chat_id, &uid, std::path::Path::new(&path));
+                let Some(ref mut active) = self.chat_screen.active_chat else { return };
+                match result {
+                    Ok(m) => active.push_message(m),
+                    Err(e) => active.file_error = Some(e.to_string()),
+                }
+            }
+
+            ChatAction::LoadMoreMessages { chat_id, before_id } => {
+                let Some(ref mut active) = self.chat_screen.active_chat else { return };
+                // Stale if the chat changed or a page already landed
+                if active.chat_id != chat_id || active.messages.first().map(|m| m.id) != Some(before_id) {
+                    return;
+                }
+                match self.db.get_messages(chat_id, MESSAGE_PAGE, active.history_loaded) {
+                    Ok(older) => active.prepend_older(older),
+                    Err(e) => log::warn!("No se pudieron cargar mensajes anteriores: {}", e),
+                }
+                self.load_quoted();
+            }
+
+            ChatAction::ShowQuoted { chat_id, message_id } => {
+                let Some(ref mut active) = self.chat_screen.active_chat else { return };
+                if active.chat_id != chat_id {
+                    return;
+                }
+                // Load back far enough to include the original, then jump to it
+                let result = self
+                    .db
+                    .messages_through(chat_id, message_id)
+                    .and_then(|n| self.db.get_messages(chat_id, n.max(active.history_loaded), 0));
+                match result {
+                    Ok(messages) => {
+                        active.replace_history(messages);
+                        active.scroll_to_message = Some(message_id);
                     }
+                    Err(e) => log::warn!("No se pudo cargar el mensaje citado: {}", e),
                 }
             }
 
+            ChatAction::CloseChat { chat_id, draft } => {
+                if let Err(e) = self.db.save_draft(&uid, chat_id, &draft) {
+                    log::warn!("No se pudo guardar el borrador: {}", e);
+                }
+                self.chat_screen.active_chat = None;
+                self.refresh_contacts();
+            }
+
             ChatAction::ToggleStar { contact_uid, .. } => {
                 let _ = self.db.toggle_star(&uid, &contact_uid);
                 self.refresh_contacts();
             }
 
+            ChatAction::TogglePin { contact_uid } => {
+                let _ = self.db.toggle_pin_contact(&uid, &contact_uid);
+                self.refresh_contacts();
+            }
+
             ChatAction::RemoveContact { contact_uid } => {
                 let _ = self.db.remove_contact(&uid, &contact_uid);
                 self.refresh_contacts();
             }
 
+            ChatAction::RenameContact { contact_uid, name } => {
+                let result = self.db.update_contact_name(&uid, &contact_uid, &name);
+                if result.is_ok() {
+                    if let Some(ref mut active) = self.chat_screen.active_chat {
+                        if active.contact.contact_uid == contact_uid {
+                            active.contact.display_name = name.clone();
+                        }
+                    }
+                }
+                if let Some(ref mut detail) = self.chat_screen.contact_detail {
+                    match result {
+                        Ok(()) => {
+                            detail.contact.display_name = name;
+                            detail.error = None;
+                            detail.saved = Some("Nombre actualizado".into());
+                        }
+                        Err(e) => {
+                            detail.error = Some(e.to_string());
+                            detail.saved = None;
+                        }
+                    }
+                }
+                self.refresh_contacts();
+            }
+
+            ChatAction::BlockContact { contact_uid } => {
+                self.set_blocked(&uid, &contact_uid, true);
+            }
+
+            ChatAction::UnblockContact { contact_uid } => {
+                self.set_blocked(&uid, &contact_uid, false);
+            }
+
+            ChatAction::SetContactNote { contact_uid, note } => {
+                let result = self.db.set_contact_note(&uid, &contact_uid, &note);
+                if let Some(ref mut detail) = self.chat_screen.contact_detail {
+                    match result {
+                        Ok(()) => {
+                            let note = note.trim();
+                            detail.contact.note = (!note.is_empty()).then(|| note.to_string());
+                            detail.error = None;
+                            detail.saved = Some("Nota guardada".into());
+                        }
+                        Err(e) => {
+                            detail.error = Some(e.to_string());
+                            detail.saved = None;
+                        }
+                    }
+                }
+                self.refresh_contacts();
+            }
+
             _ => {}
         }
     }
 
+    /// Fetch the originals quoted by loaded replies that aren't loaded themselves.
+    fn load_quoted(&mut self) {
+        let Some(ref mut active) = self.chat_screen.active_chat else { return };
+        for id in active.missing_quotes() {
+            match self.db.get_message(id) {
+                Ok(Some(m)) => {
+                    active.quoted.insert(id, m);
+                }
+                Ok(None) => {}
+                Err(e) => log::warn!("No se pudo cargar el mensaje citado: {}", e),
+            }
+        }
+    }
+
+    /// Block or unblock a contact and reflect it in the open chat and dialog.
+    fn set_blocked(&mut self, uid: &str, contact_uid: &str, blocked: bool) {
+        let result = if blocked {
+            self.db.block_contact(uid, contact_uid)
+        } else {
+            self.db.unblock_contact(uid, contact_uid)
+        };
+        if let Err(e) = result {
+            log::warn!("No se pudo cambiar el bloqueo: {}", e);
+            return;
+        }
+        if let Some(ref mut detail) = self.chat_screen.contact_detail {
+            if detail.contact.contact_uid == contact_uid {
+                detail.blocked = blocked;
+            }
+        }
+        if let Some(ref mut active) = self.chat_screen.active_chat {
+            if active.contact.contact_uid == contact_uid {
+                active.blocked = blocked;
+            }
+        }
+        self.refresh_contacts();
+    }
+
+    /// Check a picked file, copy it into the app's files folder and send it
+    /// as a message typed by its extension.
+    fn send_file(&self, chat_id: i64, uid: &str, path: &std::path::Path) -> anyhow::Result<Message> {
+        let ext = path.extension().and_then(|e| e.to_str()).unwrap_or_default();
+        if !Message::is_valid_file_type(ext) {
+            anyhow::bail!("Tipo de archivo no permitido");
+        }
+        let size = std::fs::metadata(path)?.len();
+        if !Message::is_valid_file_size(size) {
+            anyhow::bail!("El archivo supera los {} MB", Message::MAX_FILE_SIZE / (1024 * 1024));
+        }
+        let msg_type = MessageType::from_extension(ext).unwrap_or(MessageType::Document);
+        let file_name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        // A short unique prefix keeps same-named files from overwriting each other
+        let dir = Self::files_dir();
+        std::fs::create_dir_all(&dir)?;
+        let dest = dir.join(format!("{}-{}", &uuid::Uuid::new_v4().simple().to_string()[..8], file_name));
+        std::fs::copy(path, &dest)?;
+
+        let sent = self.db.send_message(
+            chat_id,
+            uid,
+            &dest.to_string_lossy(),
+            &msg_type.to_string(),
+            Some(&file_name),
+            Some(size),
+            None,
+        );
+        if sent.is_err() {
+            let _ = std::fs::remove_file(&dest);
+        }
+        sent
+    }
+
+    /// Attachments sent from this device, next to the database.
+    fn files_dir() -> std::path::PathBuf {
+        std::path::Path::new(&Self::db_path())
+            .parent()
+            .map(std::path::Path::to_path_buf)
+            .unwrap_or_default()
+            .join("files")
+    }
+
     // ──────────────────────────────────────────
     // INVENTORY HANDLERS
     // ──────────────────────────────────────────
@@ -267,18 +1001,233 @@ impl NimbuzynApp {
     fn handle_inventory_action(&mut self, action: InventoryAction) {
         match action {
             InventoryAction::LoadProducts => self.refresh_products(),
+            InventoryAction::LoadMovements { id } => {
+                self.inventory_screen.movements = self.db.product_movements(id, 50).unwrap_or_default();
+            }
+            InventoryAction::SetSort(sort) => {
+                self.inventory_screen.sort = sort;
+                self.refresh_products();
+            }
             InventoryAction::SaveProduct { product } => {
-                let _ = self.db.upsert_product(&product);
+                let excluding = (product.id != 0).then_some(product.id);
+                let taken = if self.db.product_code_exists(&product.owner_uid, &product.code, excluding).unwrap_or(false) {
+                    Some("Ya existe un producto con ese código")
+                } else if product.barcode.as_deref().is_some_and(|b| {
+                    self.db.product_barcode_exists(&product.owner_uid, b, excluding).unwrap_or(false)
+                }) {
+                    Some("Ese código de barras ya pertenece a otro producto")
+                } else {
+                    None
+                };
+                if let Some(msg) = taken {
+                    self.inventory_screen.save_failed(msg.to_string());
+                    return;
+                }
+                let old_qty = self
+                    .inventory_screen
+                    .products
+                    .iter()
+                    .find(|p| p.id == product.id && product.id != 0)
+                    .map(|p| p.quantity);
+                match self.db.upsert_product(&product) {
+                    Ok(_) => {
+                        if self.inventory_screen.view == InventoryView::List {
+                            self.inventory_screen.quick_add.clear();
+                        }
+                        let emptied = old_qty
+                            .is_some_and(|old| crossed_to_empty(old, product.quantity, product.low_stock_threshold));
+                        if emptied {
+                            self.inventory_screen.stock_alert = Some((product.name.clone(), None));
+                        }
+                    }
+                    Err(e) => self.inventory_screen.save_failed(e.to_string()),
+                }
+                self.refresh_products();
+            }
+            InventoryAction::RevertLastMovement { id } => {
+                let result = self.db.revert_last_movement(id);
+                self.refresh_products();
+                if let Some(p) = self.inventory_screen.products.iter().find(|p| p.id == id).cloned() {
+                    self.inventory_screen.open_product(&p);
+                }
+                match result {
+                    Ok(()) => self.inventory_screen.form_success = Some("Último cambio revertido".into()),
+                    Err(e) => self.inventory_screen.form_error = Some(e.to_string()),
+                }
+            }
+            InventoryAction::AdjustQuantity { id, delta } => {
+                let product = self.inventory_screen.products.iter().find(|p| p.id == id).cloned();
+                match self.db.adjust_quantity(id, delta) {
+                    Ok(new_qty) => {
+                        if let Some(p) = product {
+                            if crossed_to_empty(p.quantity, new_qty, p.low_stock_threshold) {
+                                self.inventory_screen.stock_alert = Some((p.name, None));
+                            }
+                        }
+                    }
+                    Err(e) => log::warn!("Ajuste de stock falló: {}", e),
+                }
                 self.refresh_products();
             }
             InventoryAction::DeleteProduct { id } => {
                 let _ = self.db.delete_product(id);
                 self.refresh_products();
             }
+            InventoryAction::AdjustPrices { category, percent } => {
+                if let Some(ref user) = self.current_user {
+                    if let Err(e) = self.db.adjust_prices(&user.uid, category.as_deref(), percent) {
+                        log::warn!("Ajuste de precios falló: {}", e);
+                    }
+                }
+                self.refresh_products();
+            }
+            InventoryAction::ImportCsv { rows, mapping, merge_duplicates } => {
+                let Some(ref user) = self.current_user else { return };
+                let result = self.db.import_products_mapped(&user.uid, &rows, &mapping, merge_duplicates);
+                if let Some(ref mut dialog) = self.inventory_screen.import {
+                    match result {
+                        Ok(report) => dialog.report = Some(report),
+                        Err(e) => dialog.error = Some(e.to_string()),
+                    }
+                }
+                self.refresh_products();
+            }
+            InventoryAction::SuggestProducts { prefix } => {
+                let Some(ref user) = self.current_user else { return };
+                self.inventory_screen.suggestions = self
+                    .db
+                    .suggest_products(&user.uid, &prefix, SUGGEST_LIMIT)
+                    .unwrap_or_default();
+            }
+            InventoryAction::SaveValuation => {
+                let Some(ref user) = self.current_user else { return };
+                self.inventory_screen.export_msg = match self.save_valuation(&user.uid) {
+                    Ok(Some(path)) => Some((format!("Valuación guardada en {}", path.display()), false)),
+                    Ok(None) => None,
+                    Err(e) => Some((format!("No se pudo guardar la valuación: {}", e), true)),
+                };
+            }
+            InventoryAction::ExportCsv => {
+                let Some(ref user) = self.current_user else { return };
+                self.inventory_screen.export_msg = match self.export_products(&user.uid) {
+                    Ok(Some(path)) => Some((format!("Inventario exportado a {}", path.display()), false)),
+                    Ok(None) => None,
+                    Err(e) => Some((format!("No se pudo exportar: {}", e), true)),
+                };
+            }
+            InventoryAction::PickImportFile => {
+                #[cfg(not(target_os = "android"))]
+                if let Some(path) = rfd::FileDialog::new().add_filter("CSV", &["csv", "txt"]).pick_file() {
+                    if let Some(ref mut dialog) = self.inventory_screen.import {
+                        match std::fs::read_to_string(&path) {
+                            Ok(text) => {
+                                dialog.csv_text = text;
+                                dialog.error = None;
+                            }
+                            Err(e) => dialog.error = Some(format!("No se pudo leer el archivo: {}", e)),
+                        }
+                    }
+                }
+            }
             InventoryAction::None => {}
         }
     }
 
+    /// Write a valuation snapshot as CSV where the user picks (desktop) or
+    /// next to the database (Android), and keep it in the history. `None`
+    /// when the save dialog was cancelled.
+    fn save_valuation(&self, owner_uid: &str) -> anyhow::Result<Option<std::path::PathBuf>> {
+        #[cfg(not(target_os = "android"))]
+        let path = rfd::FileDialog::new()
+            .set_file_name(chrono::Local::now().format("valuacion-%Y%m%d-%H%M%S.csv").to_string())
+            .add_filter("CSV", &["csv"])
+            .save_file();
+        #[cfg(target_os = "android")]
+        let path = Some(Self::export_path("valuacion", "csv"));
+        let Some(path) = path else { return Ok(None) };
+        let snapshot = self.db.valuation_snapshot(owner_uid)?;
+        std::fs::write(&path, snapshot.to_csv())?;
+        self.db.record_valuation(&snapshot)?;
+        Ok(Some(path))
+    }
+
+    /// Write the inventory CSV where the user picks (desktop) or next to the
+    /// database (Android). `None` when the save dialog was cancelled.
+    fn export_products(&self, owner_uid: &str) -> anyhow::Result<Option<std::path::PathBuf>> {
+        let csv = self.db.export_products_csv(owner_uid)?;
+        #[cfg(not(target_os = "android"))]
+        let path = rfd::FileDialog::new()
+            .set_file_name("inventario.csv")
+            .add_filter("CSV", &["csv"])
+            .save_file();
+        #[cfg(target_os = "android")]
+        let path = Some(Self::export_path("inventario", "csv"));
+        let Some(path) = path else { return Ok(None) };
+        std::fs::write(&path, csv)?;
+        Ok(Some(path))
+    }
+
+    /// Write the account's data where the user picks (desktop) or next to
+    /// the database (Android). A `.json` name gets one document; anything
+    /// else is streamed as JSON lines. `None` when the dialog was cancelled.
+    fn export_account(&self, uid: &str) -> anyhow::Result<Option<std::path::PathBuf>> {
+        #[cfg(not(target_os = "android"))]
+        let path = rfd::FileDialog::new()
+            .set_title("Exportar mis datos")
+            .set_file_name(chrono::Local::now().format("nimbuzyn-datos-%Y%m%d.jsonl").to_string())
+            .add_filter("JSON Lines", &["jsonl"])
+            .add_filter("JSON", &["json"])
+            .save_file();
+        #[cfg(target_os = "android")]
+        let path = Some(Self::export_path("nimbuzyn-datos", "jsonl"));
+        let Some(path) = path else { return Ok(None) };
+        if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json")) {
+            std::fs::write(&path, self.db.export_account_json(uid)?)?;
+        } else {
+            let mut writer = std::io::BufWriter::new(std::fs::File::create(&path)?);
+            self.db.export_account_jsonl(uid, &mut writer)?;
+        }
+        Ok(Some(path))
+    }
+
+    /// Timestamped `<stem>-AAAAMMDD-HHMMSS.<ext>` in the database's folder.
+    #[cfg(target_os = "android")]
+    fn export_path(stem: &str, ext: &str) -> std::path::PathBuf {
+        let dir = std::path::Path::new(&Self::db_path())
+            .parent()
+            .map(std::path::Path::to_path_buf)
+            .unwrap_or_default();
+        dir.join(format!("{}-{}.{}", stem, chrono::Local::now().format("%Y%m%d-%H%M%S"), ext))
+    }
+
+    // ──────────────────────────────────────────
+    // COMMAND PALETTE
+    // ──────────────────────────────────────────
+
+    fn handle_palette_action(&mut self, action: PaletteAction, ctx: &egui::Context) {
+        let Some(ref user) = self.current_user.clone() else { return };
+        match action {
+            PaletteAction::Search(query) => {
+                let contacts: Vec<Contact> = ["friend", "acquaintance"]
+                    .into_iter()
+                    .flat_map(|t| self.db.get_contacts(&user.uid, t).unwrap_or_default())
+                    .collect();
+                let products = self.db.search_products(&user.uid, &query, PALETTE_LIMIT).unwrap_or_default();
+                self.palette.set_results(rank_results(&query, &contacts, &products, PALETTE_LIMIT));
+            }
+            PaletteAction::Select(PaletteItem::Screen { screen, .. }) => self.navigate_to(screen, ctx),
+            PaletteAction::Select(PaletteItem::Contact(contact)) => {
+                self.navigate_to(Screen::Chat, ctx);
+                self.handle_chat_action(ChatAction::OpenChat { contact }, ctx);
+            }
+            PaletteAction::Select(PaletteItem::Product(product)) => {
+                self.navigate_to(Screen::Inventory, ctx);
+                self.inventory_screen.open_product(&product);
+            }
+            PaletteAction::None => {}
+        }
+    }
+
     // ──────────────────────────────────────────
     // SETTINGS HANDLERS
     // ──────────────────────────────────────────
@@ -287,22 +1236,47 @@ impl NimbuzynApp {
         let Some(ref user) = self.current_user.clone() else { return };
 
         match action {
-            SettingsAction::UpdateDisplayName(name) => {
-                if let Ok(()) = self.db.update_display_name(&user.uid, &name) {
+            SettingsAction::UpdateDisplayName(name) => match self.db.update_display_name(&user.uid, &name) {
+                Ok(()) => {
                     if let Some(ref mut u) = self.current_user {
                         u.display_name = name.clone();
                     }
                     if let Some(ref mut s) = self.settings_screen {
+                        s.display_name = name;
                         s.name_success = Some("Nombre actualizado".into());
                         s.name_error = None;
                     }
                 }
+                // Autosaved on blur: put the saved name back so it doesn't look kept
+                Err(e) => {
+                    if let Some(ref mut s) = self.settings_screen {
+                        s.display_name = user.display_name.clone();
+                        s.name_success = None;
+                        s.name_error = Some(e.to_string());
+                    }
+                }
+            },
+            SettingsAction::SetAvatarColor(color) => {
+                match self.db.update_avatar_color(&user.uid, color) {
+                    Ok(()) => {
+                        if let Some(ref mut u) = self.current_user {
+                            u.avatar_color = color;
+                        }
+                    }
+                    Err(e) => log::warn!("No se pudo guardar el color: {}", e),
+                }
             }
             SettingsAction::ChangePassword { old_pass, new_pass } => {
                 // Verify old password
                 match self.db.login(&user.username, &old_pass) {
                     Ok(_) => {
                         if let Ok(()) = self.db.update_password(&user.uid, &new_pass) {
+                            #[cfg(feature = "encryption")]
+                            self.rekey_database(&new_pass);
+                            // The change ended every remembered session; keep this one
+                            if let Err(e) = self.db.create_session(&user.uid) {
+                                log::warn!("No se pudo recordar la sesión: {}", e);
+                            }
                             if let Some(ref mut s) = self.settings_screen {
                                 s.pass_success = Some("Contraseña actualizada".into());
                                 s.pass_error = None;
@@ -319,40 +1293,347 @@ impl NimbuzynApp {
                     }
                 }
             }
-            SettingsAction::ToggleTheme => {
-                self.theme = match self.theme {
-                    AppTheme::Dark => AppTheme::Light,
-                    AppTheme::Light => AppTheme::Dark,
-                };
-                theme::apply_theme(ctx, &self.theme);
-                let theme_str = match self.theme {
-                    AppTheme::Dark => "dark",
-                    AppTheme::Light => "light",
-                };
-                let _ = self.db.update_theme(&user.uid, theme_str);
+            SettingsAction::SetTheme(new_theme) => {
+                self.theme = new_theme;
+                theme::apply_theme(ctx, &self.theme, self.accent_color);
+                let _ = self.db.update_theme(&user.uid, self.theme.as_str());
+            }
+            SettingsAction::SetCurrency(code) => {
+                match self.db.update_currency(&user.uid, &code) {
+                    Ok(()) => {
+                        if let Some(ref mut s) = self.settings_screen {
+                            s.currency = code.clone();
+                        }
+                        self.inventory_screen.currency = code;
+                    }
+                    Err(e) => log::warn!("No se pudo guardar la moneda: {}", e),
+                }
+            }
+            SettingsAction::PreviewAccentColor(color) => {
+                self.set_accent_color(Some(color), ctx);
+                self.accent_unsaved = true;
+            }
+            SettingsAction::SetAccentColor(color) => {
+                self.set_accent_color(color, ctx);
+                self.accent_unsaved = true;
+                self.save_accent_color();
+            }
+            SettingsAction::SetDensity(density) => {
+                if self.db.update_list_density(&user.uid, density).is_ok() {
+                    self.inventory_screen.density = density;
+                    if let Some(ref mut s) = self.settings_screen {
+                        s.density = density;
+                    }
+                }
+            }
+            SettingsAction::SetFontSize(size) => {
+                self.font_size = size;
+                self.apply_font_size(ctx);
+                if let Err(e) = self.db.update_font_size(&user.uid, size) {
+                    log::warn!("No se pudo guardar el tamaño de texto: {}", e);
+                }
+            }
+            SettingsAction::DeleteAccount { password, secure_wipe } => {
+                if let Err(e) = self.db.delete_account(&user.uid, &password) {
+                    if let Some(ref mut s) = self.settings_screen {
+                        s.delete_error = Some(e.to_string());
+                    }
+                    return;
+                }
+                // An encrypted database with no accounts left can't be opened again
+                #[cfg(feature = "encryption")]
+                let secure_wipe = secure_wipe || crate::db::is_encrypted(&Self::db_path());
+                if secure_wipe && self.db.user_count().unwrap_or(1) == 0 {
+                    self.wipe_database();
+                }
+                self.end_session(ctx);
+            }
+            SettingsAction::SetGroupWindow(minutes) => {
+                if self.db.update_group_window(&user.uid, minutes).is_ok() {
+                    if let Some(ref mut active) = self.chat_screen.active_chat {
+                        active.group_window_secs = i64::from(minutes) * 60;
+                    }
+                }
             }
-            SettingsAction::Logout => {
-                self.current_user = None;
-                self.current_screen = Screen::Auth;
-                self.login_screen = LoginScreen::default();
-                self.chat_screen = ChatScreen::default();
-                self.inventory_screen = InventoryScreen::default();
-                self.settings_screen = None;
+            SettingsAction::SetTextAutoformat(enabled) => {
+                if self.db.update_text_autoformat(&user.uid, enabled).is_ok() {
+                    if let Some(ref mut s) = self.settings_screen {
+                        s.text_autoformat = enabled;
+                    }
+                    if let Some(ref mut active) = self.chat_screen.active_chat {
+                        active.text_autoformat = enabled;
+                    }
+                }
+            }
+            SettingsAction::SetCopyLinks(enabled) => {
+                if self.db.update_copy_links(&user.uid, enabled).is_ok() {
+                    if let Some(ref mut s) = self.settings_screen {
+                        s.copy_links = enabled;
+                    }
+                    if let Some(ref mut active) = self.chat_screen.active_chat {
+                        active.copy_links = enabled;
+                    }
+                }
+            }
+            SettingsAction::ToggleNotifications(enabled) => {
+                if self.db.update_notifications(&user.uid, enabled).is_ok() {
+                    self.notifications_enabled = enabled;
+                    if !enabled {
+                        self.message_toast = None;
+                    }
+                    if let Some(ref mut s) = self.settings_screen {
+                        s.notifications_enabled = enabled;
+                    }
+                }
+            }
+            SettingsAction::SetReadReceipts(enabled) => {
+                if self.db.update_read_receipts(&user.uid, enabled).is_ok() {
+                    if let Some(ref mut s) = self.settings_screen {
+                        s.read_receipts = enabled;
+                    }
+                }
+            }
+            SettingsAction::SetConfirmAcquaintanceSend(enabled) => {
+                if self.db.update_confirm_acquaintance_send(&user.uid, enabled).is_ok() {
+                    if let Some(ref mut s) = self.settings_screen {
+                        s.confirm_acquaintance_send = enabled;
+                    }
+                }
+            }
+            SettingsAction::PurgeOrphanedChats => {
+                let result = self.db.purge_orphaned_chats(&user.uid);
+                if let Some(ref mut s) = self.settings_screen {
+                    match result {
+                        Ok(n) => {
+                            s.orphaned_chats = 0;
+                            s.maintenance_msg = Some(format!("{} chats eliminados", n));
+                        }
+                        Err(e) => log::warn!("Limpieza de chats falló: {}", e),
+                    }
+                    s.stats = self.db.profile_stats(&user.uid).unwrap_or_default();
+                }
             }
+            SettingsAction::BackupTo(path) => {
+                let result = self.db.backup_to(&path);
+                if let Some(ref mut s) = self.settings_screen {
+                    match result {
+                        Ok(()) => {
+                            s.backup_msg = Some(format!("Respaldo guardado en {}", path));
+                            s.last_backup_at = self.db.last_backup_at().unwrap_or(None);
+                        }
+                        Err(e) => s.backup_error = Some(e.to_string()),
+                    }
+                }
+            }
+            SettingsAction::ExportAccount => {
+                let result = self.export_account(&user.uid);
+                if let Some(ref mut s) = self.settings_screen {
+                    match result {
+                        Ok(Some(path)) => s.backup_msg = Some(format!("Datos exportados a {}", path.display())),
+                        Ok(None) => {}
+                        Err(e) => s.backup_error = Some(format!("No se pudo exportar: {}", e)),
+                    }
+                }
+            }
+            SettingsAction::RestoreFrom(path) => {
+                #[cfg(feature = "encryption")]
+                let result = self.restore_database(&path);
+                #[cfg(not(feature = "encryption"))]
+                let result = self.db.restore_from(&path);
+                match result {
+                    // The restored accounts may not include this one
+                    Ok(()) => self.end_session(ctx),
+                    Err(e) => {
+                        if let Some(ref mut s) = self.settings_screen {
+                            s.backup_error = Some(e.to_string());
+                        }
+                    }
+                }
+            }
+            SettingsAction::Logout => self.end_session(ctx),
             SettingsAction::None => {}
         }
     }
+
+    fn end_session(&mut self, ctx: &egui::Context) {
+        self.save_accent_color();
+        if let Err(e) = self.db.clear_session() {
+            log::warn!("No se pudo cerrar la sesión guardada: {}", e);
+        }
+        self.current_user = None;
+        self.current_screen = Screen::Auth;
+        self.login_screen = LoginScreen::default();
+        self.chat_screen = ChatScreen::default();
+        self.inventory_screen = InventoryScreen::default();
+        self.settings_screen = None;
+        self.palette = CommandPalette::default();
+        self.badges = NavBadges::default();
+        self.last_badge_poll = None;
+        self.last_unread = None;
+        self.message_toast = None;
+        self.font_size = theme::DEFAULT_FONT_SIZE;
+        self.apply_font_size(ctx);
+        self.accent_color = None;
+        theme::apply_theme(ctx, &self.theme, None);
+        #[cfg(feature = "encryption")]
+        self.lock_database();
+    }
+
+    /// Swap the open database for a blank in-memory one (e.g. before its
+    /// file is replaced) and hand it back.
+    fn take_db(&mut self) -> anyhow::Result<Database> {
+        Ok(std::mem::replace(&mut self.db, Database::open(":memory:")?))
+    }
+
+    /// Securely wipe the database file and start over with an empty one.
+    fn wipe_database(&mut self) {
+        let path = Self::db_path();
+        let db = match self.take_db() {
+            Ok(db) => db,
+            Err(e) => {
+                log::warn!("Borrado seguro cancelado: {}", e);
+                return;
+            }
+        };
+        if let Err(e) = db.secure_wipe(&path) {
+            log::warn!("Borrado seguro incompleto: {}", e);
+        }
+        match Self::open_db(&path) {
+            Ok(db) => self.db = db,
+            Err(e) => log::warn!("No se pudo recrear la base de datos: {}", e),
+        }
+    }
+}
+
+// ──────────────────────────────────────────────
+// ENCRYPTION AT REST
+// ──────────────────────────────────────────────
+// The database key is derived from the password of the account that first
+// logs in, so the file is only readable between that login and logout.
+
+#[cfg(feature = "encryption")]
+impl NimbuzynApp {
+    fn database_key(path: &str, password: &str) -> anyhow::Result<String> {
+        crate::db::derive_db_key(password, &crate::db::database_salt(path)?)
+    }
+
+    /// Open the encrypted database with the key `password` derives, if it
+    /// isn't open already. A wrong password fails like a wrong login.
+    fn unlock_database(&mut self, password: &str) -> anyhow::Result<()> {
+        if !self.db_locked {
+            return Ok(());
+        }
+        let path = Self::db_path();
+        let key = Self::database_key(&path, password)?;
+        let pepper = Self::pepper();
+        self.db = Database::open_encrypted_with_pepper(&path, &key, pepper.as_deref().map(str::as_bytes))
+            .map_err(|_| anyhow::anyhow!("Contraseña incorrecta"))?;
+        self.db_locked = false;
+        Ok(())
+    }
+
+    /// Encrypt a plaintext database with `password` once its only account
+    /// has logged in. Shared databases stay plaintext: one password can't
+    /// open them for everyone.
+    fn encrypt_database(&mut self, password: &str) {
+        let path = Self::db_path();
+        if crate::db::is_encrypted(&path) || self.db.user_count().unwrap_or(0) != 1 {
+            return;
+        }
+        let result = Self::database_key(&path, password).and_then(|key| {
+            drop(self.take_db()?);
+            Database::encrypt_plaintext(&path, &key)?;
+            let pepper = Self::pepper();
+            Database::open_encrypted_with_pepper(&path, &key, pepper.as_deref().map(str::as_bytes))
+        });
+        match result {
+            Ok(db) => self.db = db,
+            Err(e) => {
+                log::warn!("No se pudo cifrar la base de datos: {}", e);
+                match Self::open_db(&path) {
+                    Ok(db) => self.db = db,
+                    Err(e) => log::warn!("No se pudo reabrir la base de datos: {}", e),
+                }
+            }
+        }
+    }
+
+    /// Re-encrypt under the new password after a password change.
+    fn rekey_database(&mut self, new_password: &str) {
+        let path = Self::db_path();
+        if !crate::db::is_encrypted(&path) {
+            return;
+        }
+        if let Err(e) = Self::database_key(&path, new_password).and_then(|key| self.db.rekey(&key)) {
+            log::warn!("No se pudo cambiar la clave de cifrado: {}", e);
+        }
+    }
+
+    /// Close an encrypted database again (logout) until the next login.
+    fn lock_database(&mut self) {
+        if self.db_locked || !crate::db::is_encrypted(&Self::db_path()) {
+            return;
+        }
+        match self.take_db() {
+            Ok(_) => self.db_locked = true,
+            Err(e) => log::warn!("No se pudo cerrar la base de datos cifrada: {}", e),
+        }
+    }
+
+    /// Restore a backup when either side is encrypted by swapping the file;
+    /// an encrypted backup then opens at the next login.
+    fn restore_database(&mut self, src: &str) -> anyhow::Result<()> {
+        let path = Self::db_path();
+        if !crate::db::is_encrypted(src) && !crate::db::is_encrypted(&path) {
+            return self.db.restore_from(src);
+        }
+        if !std::path::Path::new(src).is_file() {
+            return Err(anyhow::anyhow!("El archivo no es un respaldo de Nimbuzyn"));
+        }
+        drop(self.take_db()?);
+        let swapped = crate::db::replace_with_backup(&path, src);
+        self.db = Self::open_db(&path)?;
+        self.db_locked = crate::db::is_encrypted(&path);
+        swapped
+    }
 }
 
 impl eframe::App for NimbuzynApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        // ── Lifecycle: losing focus is our pause/resume signal ────────────
+        let focused = ctx.input(|i| i.focused);
+        if focused != self.app_focused {
+            self.app_focused = focused;
+            if focused {
+                self.on_resume(ctx);
+            } else {
+                self.on_pause();
+            }
+        }
+
+        // ── Session guard ─────────────────────────────────────────────────
+        self.guard_session();
+
+        // ── Badge polling (whenever logged in, nav bar shown or not) ──────
+        if self.current_user.is_some() && !matches!(self.current_screen, Screen::Auth | Screen::Splash) {
+            let now = ctx.input(|i| i.time);
+            if poll_due(self.last_badge_poll, now, self.badge_poll_secs) {
+                self.refresh_badges();
+                self.refresh_read_marks();
+                self.last_badge_poll = Some(now);
+            }
+            ctx.request_repaint_after(std::time::Duration::from_secs_f64(self.badge_poll_secs));
+        }
+
         // ── Bottom navigation bar (only when logged in) ───────────────────
-        if self.current_user.is_some()
-            && self.current_screen != Screen::Auth
-            && self.current_screen != Screen::Splash
-        {
-            let c = NimColors::for_theme(&self.theme);
+        if nav_bar_visible(
+            &self.current_screen,
+            self.current_user.is_some(),
+            self.chat_screen.active_chat.is_some(),
+        ) {
+            let c = NimColors::for_theme(&self.theme, self.accent_color);
             let current_screen = self.current_screen.clone();
+            let badges = self.badges.clone();
 
             egui::TopBottomPanel::bottom("nav_bar")
                 .frame(
@@ -364,10 +1645,10 @@ impl eframe::App for NimbuzynApp {
                 .show(ctx, |ui| {
                     ui.horizontal(|ui| {
                         let btn_w = ui.available_width() / 3.0;
-                        for (icon, label, screen) in [
-                            ("💬", "Chat",        Screen::Chat),
-                            ("📦", "Inventario",  Screen::Inventory),
-                            ("⚙",  "Cuenta",      Screen::Settings),
+                        for (icon, label, screen, badge) in [
+                            ("💬", "Chat",        Screen::Chat,      badges.unread),
+                            ("📦", "Inventario",  Screen::Inventory, badges.low_stock),
+                            ("⚙",  "Cuenta",      Screen::Settings,  0),
                         ] {
                             let selected = current_screen == screen;
                             let fg = if selected { c.primary } else { c.text_muted };
@@ -382,7 +1663,20 @@ impl eframe::App for NimbuzynApp {
                             .fill(bg)
                             .rounding(Rounding::ZERO);
 
-                            if ui.add(btn).clicked() && !selected {
+                            let resp = ui.add(btn);
+                            if badge > 0 {
+                                let center = resp.rect.center_top() + Vec2::new(16.0, 12.0);
+                                let text = if badge > 99 { "99+".to_string() } else { badge.to_string() };
+                                ui.painter().circle_filled(center, 9.0, c.danger);
+                                ui.painter().text(
+                                    center,
+                                    egui::Align2::CENTER_CENTER,
+                                    text,
+                                    egui::FontId::proportional(10.0),
+                                    Color32::WHITE,
+                                );
+                            }
+                            if resp.clicked() && !selected {
                                 self.navigate_to(screen, ctx);
                             }
                         }
@@ -390,19 +1684,68 @@ impl eframe::App for NimbuzynApp {
                 });
         }
 
+        // ── Command palette (Ctrl+K, desktop) ─────────────────────────────
+        #[cfg(not(target_os = "android"))]
+        if self.current_user.is_some()
+            && ctx.input_mut(|i| i.consume_key(egui::Modifiers::COMMAND, egui::Key::K))
+        {
+            self.palette.toggle();
+        }
+        if self.palette.open && self.current_user.is_some() {
+            let action = self.palette.show(ctx, &self.theme, self.accent_color);
+            self.handle_palette_action(action, ctx);
+        }
+
+        if self.current_user.is_some() {
+            self.show_message_toast(ctx);
+        }
+
+        // ── "Novedades" after an update, once logged in ───────────────────
+        if self.current_user.is_some() {
+            if let Some(ref notes) = self.whats_new {
+                if notes.show(ctx, &self.theme, self.accent_color) {
+                    if let Err(e) = self.db.set_last_seen_version(notes.version) {
+                        log::warn!("No se pudo guardar la versión vista: {}", e);
+                    }
+                    self.whats_new = None;
+                }
+            }
+        }
+
         // ── Screen routing ────────────────────────────────────────────────
         match self.current_screen.clone() {
             Screen::Splash => {
                 self.splash_screen.show(ctx);
                 if self.splash_screen.state == SplashState::Finished {
-                    self.current_screen = Screen::Auth;
+                    if self.current_user.is_some() {
+                        self.navigate_to(Screen::Chat, ctx);
+                    } else {
+                        self.current_screen = Screen::Auth;
+                    }
                 }
                 return; // No nav bar during splash
             }
 
             Screen::Auth => {
-                let action = self.login_screen.show(ctx, &self.theme);
-                self.handle_auth_action(action, ctx);
+                let action = self.login_screen.show(ctx, &self.theme, self.accent_color);
+                let frame = ctx.frame_nr();
+                match self.pending_auth.take() {
+                    // This frame's output is the first to show the spinner;
+                    // let it reach the screen before blocking.
+                    Some((pending, queued)) if frame <= queued + 1 => {
+                        self.pending_auth = Some((pending, queued));
+                        ctx.request_repaint();
+                    }
+                    Some((pending, _)) => {
+                        self.handle_auth_action(pending, ctx);
+                        self.login_screen.finish_auth();
+                    }
+                    None => {}
+                }
+                if !matches!(action, AuthAction::None) {
+                    self.pending_auth = Some((action, frame));
+                    ctx.request_repaint();
+                }
             }
 
             Screen::Chat => {
@@ -412,7 +1755,7 @@ impl eframe::App for NimbuzynApp {
                         .as_ref()
                         .map(|u| u.uid.clone())
                         .unwrap_or_default();
-                    self.chat_screen.show(ctx, &self.theme, &uid)
+                    self.chat_screen.show(ctx, &self.theme, self.accent_color, &uid)
                 };
                 self.handle_chat_action(action, ctx);
             }
@@ -423,17 +1766,166 @@ impl eframe::App for NimbuzynApp {
                     .as_ref()
                     .map(|u| u.uid.clone())
                     .unwrap_or_default();
-                let action = self.inventory_screen.show(ctx, &self.theme, &uid);
+                let action = self.inventory_screen.show(ctx, &self.theme, self.accent_color, &uid);
                 self.handle_inventory_action(action);
             }
 
             Screen::Settings => {
+                let Some(user) = self.current_user.clone() else { return };
+                if self.settings_screen.is_none() {
+                    self.navigate_to(Screen::Settings, ctx);
+                }
                 if let Some(ref mut settings) = self.settings_screen {
-                    let user = self.current_user.as_ref().unwrap();
-                    let action = settings.show(ctx, &self.theme, user);
+                    let action = settings.show(ctx, &self.theme, self.accent_color, &user);
                     self.handle_settings_action(action, ctx);
                 }
             }
         }
     }
+
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        self.on_pause();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A headless app logged in as a fresh account on `db`.
+    fn logged_in(db: Database) -> NimbuzynApp {
+        let user = db.register_user("ana", "Ana", "secreto123").unwrap();
+        let mut app = NimbuzynApp::with_db(db, AppTheme::Dark);
+        app.current_user = Some(user);
+        app
+    }
+
+    /// Add `username` as a friend and open the chat with them.
+    /// Returns their uid and the chat id.
+    fn open_chat_with(app: &mut NimbuzynApp, username: &str) -> (String, i64) {
+        let uid = app.current_user.as_ref().unwrap().uid.clone();
+        let other = app.db.register_user(username, username, "secreto123").unwrap();
+        app.db.add_contact(&uid, &other.uid, username, other.avatar_color, "friend").unwrap();
+        let contact = app.db.get_contacts(&uid, "friend").unwrap().into_iter().find(|c| c.contact_uid == other.uid).unwrap();
+        let chat = app.db.get_or_create_chat(&uid, &other.uid).unwrap();
+        app.chat_screen.active_chat = Some(ActiveChat::new(chat.id, contact, vec![], false));
+        (other.uid, chat.id)
+    }
+
+    #[test]
+    fn pausing_saves_the_draft_and_checkpoints_the_wal() {
+        let dir = std::env::temp_dir().join(format!("nimbuzyn-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("pausa.db").to_string_lossy().into_owned();
+        let mut app = logged_in(Database::open(&path).unwrap());
+        let uid = app.current_user.as_ref().unwrap().uid.clone();
+        let (_, chat_id) = open_chat_with(&mut app, "beto");
+        app.chat_screen.active_chat.as_mut().unwrap().input_text = "a medio escribir".into();
+
+        app.on_pause();
+        assert_eq!(app.db.get_draft(&uid, chat_id).unwrap().as_deref(), Some("a medio escribir"));
+        let wal = std::fs::metadata(format!("{}-wal", path)).map(|m| m.len()).unwrap_or(0);
+        assert_eq!(wal, 0);
+    }
+
+    #[test]
+    fn badges_poll_first_thing_and_then_every_interval() {
+        assert!(poll_due(None, 0.0, BADGE_POLL_SECS));
+        assert!(!poll_due(Some(10.0), 10.0 + BADGE_POLL_SECS - 0.1, BADGE_POLL_SECS));
+        assert!(poll_due(Some(10.0), 10.0 + BADGE_POLL_SECS, BADGE_POLL_SECS));
+        assert!(poll_due(Some(10.0), 99.0, BADGE_POLL_SECS));
+    }
+
+    #[test]
+    fn settings_without_a_user_falls_back_to_auth() {
+        let mut app = NimbuzynApp::with_db(Database::open(":memory:").unwrap(), AppTheme::Dark);
+        app.navigate_to(Screen::Settings, &egui::Context::default());
+        assert_eq!(app.current_screen, Screen::Settings);
+        assert!(app.settings_screen.is_none());
+
+        app.guard_session();
+        assert_eq!(app.current_screen, Screen::Auth);
+    }
+
+    #[test]
+    fn inventory_search_survives_the_product_form() {
+        let mut app = logged_in(Database::open(":memory:").unwrap());
+        let uid = app.current_user.as_ref().unwrap().uid.clone();
+        for line in ["A1 | Arroz | 5 | 1 | 2", "A2 | Arvejas | 5 | 1 | 2"] {
+            let product = crate::screens::inventory::parse_quick_add(line, &uid).unwrap();
+            app.db.upsert_product(&product).unwrap();
+        }
+        let ctx = egui::Context::default();
+        app.navigate_to(Screen::Inventory, &ctx);
+        app.inventory_screen.search = "arr".into();
+
+        let arroz = app.inventory_screen.products[0].clone();
+        app.inventory_screen.open_product(&arroz);
+        let mut edited = arroz.clone();
+        edited.quantity = 4.0;
+        app.handle_inventory_action(InventoryAction::SaveProduct { product: edited });
+        assert_eq!(app.inventory_screen.search, "arr");
+        assert_eq!(app.inventory_screen.scroll_to_product, Some(arroz.id));
+
+        let arvejas = app.inventory_screen.products[1].clone();
+        app.inventory_screen.open_product(&arvejas);
+        app.inventory_screen.view = InventoryView::List;
+        app.handle_inventory_action(InventoryAction::DeleteProduct { id: arvejas.id });
+        app.navigate_to(Screen::Chat, &ctx);
+        app.navigate_to(Screen::Inventory, &ctx);
+        assert_eq!(app.inventory_screen.search, "arr");
+    }
+
+    #[test]
+    fn draft_shows_on_the_contact_row_until_the_message_is_sent() {
+        let mut app = logged_in(Database::open(":memory:").unwrap());
+        let uid = app.current_user.as_ref().unwrap().uid.clone();
+        let (beto, chat_id) = open_chat_with(&mut app, "beto");
+        app.db.save_draft(&uid, chat_id, "nos vemos mañana").unwrap();
+        app.refresh_contacts();
+        assert_eq!(app.chat_screen.drafts.get(&beto).map(String::as_str), Some("nos vemos mañana"));
+
+        let active = app.chat_screen.active_chat.as_mut().unwrap();
+        let local_id = active.queue_outgoing("nos vemos mañana".into(), None);
+        let send = ChatAction::SendMessage { chat_id, local_id, content: "nos vemos mañana".into(), reply_to: None };
+        app.handle_chat_action(send, &egui::Context::default());
+        app.refresh_contacts();
+        assert!(app.chat_screen.drafts.is_empty());
+        assert_eq!(app.db.get_draft(&uid, chat_id).unwrap(), None);
+    }
+
+    #[test]
+    fn inventory_badge_matches_the_summary_low_stock_count() {
+        let mut app = logged_in(Database::open(":memory:").unwrap());
+        let uid = app.current_user.as_ref().unwrap().uid.clone();
+        let quick = |line: &str| crate::screens::inventory::parse_quick_add(line, &uid).unwrap();
+        let mut reorder = quick("C1 | Café | 11.9 | 1 | 2");
+        reorder.reorder_point = Some(10.0);
+        reorder.alert_pct = 0.2;
+        let mut bulk = quick("H1 | Harina suelta | 0.2 | 1 | 2");
+        bulk.low_stock_threshold = 0.25;
+        for p in [quick("A1 | Arroz | 0 | 1 | 2"), quick("B1 | Yerba | 5 | 1 | 2"), reorder, bulk] {
+            app.db.upsert_product(&p).unwrap();
+        }
+
+        app.navigate_to(Screen::Inventory, &egui::Context::default());
+        assert_eq!(app.inventory_screen.summary.low_stock_count, 3);
+        assert_eq!(app.badges.low_stock, app.inventory_screen.summary.low_stock_count);
+        app.inventory_screen.category_filter = Some("Nada".into());
+        app.refresh_badges();
+        assert_eq!(app.badges.low_stock, 3);
+    }
+
+    #[test]
+    fn nav_bar_hides_in_an_open_chat_and_before_login() {
+        for screen in [Screen::Chat, Screen::Inventory, Screen::Settings] {
+            assert!(nav_bar_visible(&screen, true, false), "{:?}", screen);
+            assert!(!nav_bar_visible(&screen, false, false), "{:?}", screen);
+        }
+        assert!(!nav_bar_visible(&Screen::Chat, true, true));
+        // A chat left open doesn't hide the bar on the other screens
+        assert!(nav_bar_visible(&Screen::Inventory, true, true));
+        assert!(!nav_bar_visible(&Screen::Auth, true, false));
+        assert!(!nav_bar_visible(&Screen::Splash, true, false));
+    }
 }
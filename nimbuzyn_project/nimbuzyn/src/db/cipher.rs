@@ -0,0 +1,145 @@
+use std::io::Read;
+use std::path::Path;
+use anyhow::{anyhow, Result};
+use argon2::{password_hash::rand_core::{OsRng, RngCore}, Argon2};
+use rusqlite::{params, Connection};
+use super::Database;
+
+// ──────────────────────────────────────────────
+// ENCRYPTION AT REST (SQLCipher, "encryption" feature)
+// ──────────────────────────────────────────────
+
+/// Bytes of random salt behind each encrypted database's key.
+const SALT_LEN: usize = 16;
+
+/// The salt can't live inside the database (it's needed to read it), so it
+/// sits next to it.
+fn salt_path(db_path: &str) -> String {
+    format!("{}-salt", db_path)
+}
+
+/// Whether the file at `db_path` is encrypted: a plaintext SQLite file
+/// starts with its format header, an encrypted one with random bytes. A
+/// missing or empty file isn't encrypted (yet).
+pub fn is_encrypted(db_path: &str) -> bool {
+    let mut header = [0u8; 16];
+    match std::fs::File::open(db_path).and_then(|mut f| f.read_exact(&mut header)) {
+        Ok(()) => &header != b"SQLite format 3\0",
+        Err(_) => false,
+    }
+}
+
+/// Key-derivation salt for the database at `db_path`, created on first use.
+pub fn database_salt(db_path: &str) -> Result<Vec<u8>> {
+    let path = salt_path(db_path);
+    if Path::new(&path).exists() {
+        let salt = std::fs::read(&path)?;
+        if salt.len() != SALT_LEN {
+            return Err(anyhow!("Sal de cifrado dañada en {}", path));
+        }
+        return Ok(salt);
+    }
+    let mut salt = vec![0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    std::fs::write(&path, &salt)?;
+    Ok(salt)
+}
+
+/// SQLCipher key from the user's password: Argon2id over the stored salt,
+/// handed over as a raw key (`x'…'`) so SQLCipher skips its own KDF.
+pub fn derive_db_key(password: &str, salt: &[u8]) -> Result<String> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(password.as_bytes(), salt, &mut key)
+        .map_err(|e| anyhow!("No se pudo derivar la clave: {}", e))?;
+    let hex: String = key.iter().map(|b| format!("{:02x}", b)).collect();
+    Ok(format!("x'{}'", hex))
+}
+
+/// Put the backup at `src` (and its salt, when it's encrypted) in place of
+/// the database at `db_path`, which must be closed. An encrypted backup
+/// opens with the password it was made under.
+pub fn replace_with_backup(db_path: &str, src: &str) -> Result<()> {
+    let encrypted = is_encrypted(src);
+    if encrypted && !Path::new(&salt_path(src)).exists() {
+        return Err(anyhow!("Falta la sal de cifrado del respaldo ({})", salt_path(src)));
+    }
+    for file in [format!("{}-wal", db_path), format!("{}-shm", db_path)] {
+        if Path::new(&file).exists() {
+            std::fs::remove_file(&file)?;
+        }
+    }
+    std::fs::copy(src, db_path)?;
+    if encrypted {
+        std::fs::copy(salt_path(src), salt_path(db_path))?;
+    }
+    Ok(())
+}
+
+impl Database {
+    /// Open (or create) an encrypted database. `key` is set before anything
+    /// is read, so a wrong key fails here rather than in the migrations.
+    pub fn open_encrypted(path: &str, key: &str) -> Result<Self> {
+        Self::open_encrypted_with_pepper(path, key, None)
+    }
+
+    /// [`open_encrypted`](Self::open_encrypted) with a password pepper, as
+    /// in [`open_with_pepper`](Self::open_with_pepper).
+    pub fn open_encrypted_with_pepper(path: &str, key: &str, pepper: Option<&[u8]>) -> Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.pragma_update(None, "key", key)?;
+        // SQLCipher only checks the key on the first read
+        conn.query_row("SELECT COUNT(*) FROM sqlite_master", [], |r| r.get::<_, i64>(0))
+            .map_err(|_| anyhow!("Clave de cifrado incorrecta"))?;
+        Self::from_connection(conn, pepper)
+    }
+
+    /// Re-encrypt the open database under `key`, e.g. after the password
+    /// it was derived from changed.
+    pub fn rekey(&self, key: &str) -> Result<()> {
+        self.conn.pragma_update(None, "rekey", key)?;
+        Ok(())
+    }
+
+    /// Path of the open database file when it is encrypted.
+    pub(super) fn encrypted_path(&self) -> Option<String> {
+        self.conn.path().filter(|p| is_encrypted(p)).map(str::to_string)
+    }
+
+    /// Backup of an encrypted database: the file itself, still encrypted,
+    /// and the salt its key needs next to it as `<dest>-salt`.
+    pub(super) fn backup_encrypted(&self, path: &str, dest: &str) -> Result<()> {
+        self.checkpoint()?;
+        std::fs::copy(path, dest)?;
+        std::fs::copy(salt_path(path), salt_path(dest))?;
+        Ok(())
+    }
+
+    /// Encrypt the plaintext database at `path` with `key`, in place. The
+    /// data is exported into a new encrypted file that then replaces the
+    /// original, so a failure part-way leaves the plaintext copy untouched.
+    pub fn encrypt_plaintext(path: &str, key: &str) -> Result<()> {
+        let tmp = format!("{}.encrypting", path);
+        let _ = std::fs::remove_file(&tmp);
+
+        let conn = Connection::open(path)?;
+        conn.execute_batch("PRAGMA wal_checkpoint(TRUNCATE);")?;
+        let version: i64 = conn.query_row("PRAGMA user_version", [], |r| r.get(0))?;
+        conn.execute("ATTACH DATABASE ?1 AS encrypted KEY ?2", params![tmp, key])?;
+        conn.query_row("SELECT sqlcipher_export('encrypted')", [], |_| Ok(()))?;
+        // sqlcipher_export copies the data but not the schema version
+        conn.execute_batch(&format!("PRAGMA encrypted.user_version = {}; DETACH DATABASE encrypted;", version))?;
+        conn.close().map_err(|(_, e)| anyhow!("No se pudo cerrar la base de datos: {}", e))?;
+
+        for file in [format!("{}-wal", path), format!("{}-shm", path)] {
+            if Path::new(&file).exists() {
+                std::fs::remove_file(&file)?;
+            }
+        }
+        std::fs::rename(&tmp, path)?;
+
+        Database::open_encrypted(path, key)
+            .map(|_| ())
+            .map_err(|e| anyhow!("La copia cifrada en {} no se pudo abrir: {}", path, e))
+    }
+}
@@ -0,0 +1,65 @@
+use anyhow::Result;
+use crate::models::*;
+use super::Database;
+
+// ──────────────────────────────────────────────
+// DEV SEED (debug builds only)
+// ──────────────────────────────────────────────
+
+pub const DEV_USERNAME: &str = "dev";
+pub const DEV_PASSWORD: &str = "nimbuzyn-dev";
+
+impl Database {
+    /// Make sure the dev account exists and has some sample data to play with.
+    /// Safe to call repeatedly: existing users, contacts and codes are kept.
+    pub fn seed_dev_user(&self) -> Result<User> {
+        // Looked up rather than logged into: a failed login would count
+        // towards the lockout, and a changed password isn't "missing"
+        let user = match self.find_user_by_username(DEV_USERNAME)? {
+            Some(user) => user,
+            None => self.register_user(DEV_USERNAME, "Usuario de prueba", DEV_PASSWORD)?,
+        };
+
+        for (username, display_name, contact_type) in [
+            ("dev_ana", "Ana (prueba)", "friend"),
+            ("dev_luis", "Luis (prueba)", "acquaintance"),
+        ] {
+            let contact = match self.find_user_by_username(username)? {
+                Some(contact) => contact,
+                None => self.register_user(username, display_name, DEV_PASSWORD)?,
+            };
+            self.add_contact(&user.uid, &contact.uid, &contact.display_name, contact.avatar_color, contact_type)?;
+        }
+
+        for (code, name, quantity, net_value, sale_value) in [
+            ("DEV-001", "Café molido 500g", 24.0, 3.20, 5.50),
+            ("DEV-002", "Azúcar 1kg", 10.0, 1.10, 1.80),
+            ("DEV-003", "Aceite 900ml", 0.0, 2.40, 3.90),
+        ] {
+            if self.find_product_by_code(&user.uid, code)?.is_some() {
+                continue;
+            }
+            let mut p = Product {
+                id: 0,
+                owner_uid: user.uid.clone(),
+                code: code.to_string(),
+                barcode: None,
+                name: name.to_string(),
+                quantity,
+                net_value,
+                sale_value,
+                profit_value: 0.0,
+                category: Some("Prueba".to_string()),
+                reorder_point: None,
+                alert_pct: 0.0,
+                low_stock_threshold: DEFAULT_LOW_STOCK_THRESHOLD,
+                created_at: String::new(),
+                updated_at: String::new(),
+            };
+            p.calculate_profit();
+            self.upsert_product(&p)?;
+        }
+
+        Ok(user)
+    }
+}
@@ -0,0 +1,154 @@
+use std::io::Write;
+use anyhow::Result;
+use rusqlite::params;
+use serde::Serialize;
+use crate::models::*;
+use crate::util::csv_field;
+use super::{message_from_row, product_from_row, Database, MESSAGE_COLUMNS, PRODUCT_COLUMNS};
+
+// ──────────────────────────────────────────────
+// ACCOUNT EXPORT
+// ──────────────────────────────────────────────
+
+/// One exported record, tagged with its `kind`.
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ExportRecord {
+    User(User),
+    Contact(Contact),
+    Chat(Chat),
+    Message(Message),
+    Product(Product),
+}
+
+impl ExportRecord {
+    /// Key of this record's array in the single-document export.
+    fn group(&self) -> &'static str {
+        match self {
+            ExportRecord::User(_) => "user",
+            ExportRecord::Contact(_) => "contacts",
+            ExportRecord::Chat(_) => "chats",
+            ExportRecord::Message(_) => "messages",
+            ExportRecord::Product(_) => "products",
+        }
+    }
+}
+
+impl Database {
+    /// Stream everything an account owns to `writer` as JSON lines, one
+    /// record per line. Messages are written as they're read, so memory use
+    /// doesn't grow with the history.
+    pub fn export_account_jsonl<W: Write>(&self, uid: &str, writer: &mut W) -> Result<()> {
+        self.for_each_export_record(uid, |record| {
+            serde_json::to_writer(&mut *writer, &record)?;
+            writer.write_all(b"\n")?;
+            Ok(())
+        })?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// The same data as one JSON document (`{"user": …, "contacts": […], …}`).
+    /// Builds it all in memory: prefer [`Database::export_account_jsonl`] for
+    /// large accounts.
+    pub fn export_account_json(&self, uid: &str) -> Result<String> {
+        let mut doc = serde_json::Map::new();
+        for key in ["contacts", "chats", "messages", "products"] {
+            doc.insert(key.to_string(), serde_json::Value::Array(vec![]));
+        }
+        self.for_each_export_record(uid, |record| {
+            let key = record.group();
+            let mut value = serde_json::to_value(&record)?;
+            if let Some(obj) = value.as_object_mut() {
+                obj.remove("kind");
+            }
+            match doc.get_mut(key).and_then(|v| v.as_array_mut()) {
+                Some(list) => list.push(value),
+                None => {
+                    doc.insert(key.to_string(), value);
+                }
+            }
+            Ok(())
+        })?;
+        Ok(serde_json::to_string_pretty(&doc)?)
+    }
+
+    /// Feed every record of `uid` to `emit`: user, contacts, chats, messages, products.
+    fn for_each_export_record(&self, uid: &str, mut emit: impl FnMut(ExportRecord) -> Result<()>) -> Result<()> {
+        emit(ExportRecord::User(self.find_user_by_uid(uid)?))?;
+
+        for contact_type in ["friend", "acquaintance"] {
+            for contact in self.get_contacts(uid, contact_type)? {
+                emit(ExportRecord::Contact(contact))?;
+            }
+        }
+
+        let mut stmt = self.conn.prepare(
+            "SELECT id, participant_a, participant_b, created_at, last_message, last_msg_at
+             FROM chats WHERE participant_a = ?1 OR participant_b = ?1 ORDER BY id",
+        )?;
+        let mut rows = stmt.query(params![uid])?;
+        while let Some(row) = rows.next()? {
+            emit(ExportRecord::Chat(Chat {
+                id: row.get(0)?,
+                participant_a: row.get(1)?,
+                participant_b: row.get(2)?,
+                created_at: row.get(3)?,
+                last_message: row.get(4)?,
+                last_message_at: row.get(5)?,
+            }))?;
+        }
+
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM messages
+             WHERE chat_id IN (SELECT id FROM chats WHERE participant_a = ?1 OR participant_b = ?1)
+             ORDER BY chat_id, id",
+            MESSAGE_COLUMNS
+        ))?;
+        let mut rows = stmt.query(params![uid])?;
+        while let Some(row) = rows.next()? {
+            emit(ExportRecord::Message(message_from_row(row)?))?;
+        }
+
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM products WHERE owner_uid = ?1 ORDER BY id",
+            PRODUCT_COLUMNS
+        ))?;
+        let mut rows = stmt.query(params![uid])?;
+        while let Some(row) = rows.next()? {
+            emit(ExportRecord::Product(product_from_row(row)?))?;
+        }
+        Ok(())
+    }
+}
+
+// ──────────────────────────────────────────────
+// PRODUCT CSV
+// ──────────────────────────────────────────────
+
+impl Database {
+    /// The owner's inventory as CSV, one product per line after the header.
+    /// Headers match what the CSV import recognizes, so the file round-trips.
+    pub fn export_products_csv(&self, owner_uid: &str) -> Result<String> {
+        let mut out = String::from("Código,Nombre,Cantidad,Valor neto,Valor venta,Ganancia,Actualizado\n");
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM products WHERE owner_uid = ?1 ORDER BY code COLLATE NOCASE",
+            PRODUCT_COLUMNS
+        ))?;
+        let mut rows = stmt.query(params![owner_uid])?;
+        while let Some(row) = rows.next()? {
+            let p = product_from_row(row)?;
+            out.push_str(&format!(
+                "{},{},{},{:.2},{:.2},{:.2},{}\n",
+                csv_field(&p.code),
+                csv_field(&p.name),
+                p.quantity,
+                p.net_value,
+                p.sale_value,
+                p.profit_value,
+                csv_field(&p.updated_at),
+            ));
+        }
+        Ok(out)
+    }
+}
@@ -0,0 +1,406 @@
+use anyhow::Result;
+use crate::models::*;
+use crate::util::parse_csv;
+use super::Database;
+
+// ──────────────────────────────────────────────
+// CSV IMPORT (column mapping)
+// ──────────────────────────────────────────────
+
+/// Product fields a CSV column can be mapped to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProductField {
+    Code,
+    Barcode,
+    Name,
+    Quantity,
+    NetValue,
+    SaleValue,
+    Category,
+}
+
+impl ProductField {
+    pub const ALL: [ProductField; 7] = [
+        ProductField::Code,
+        ProductField::Barcode,
+        ProductField::Name,
+        ProductField::Quantity,
+        ProductField::NetValue,
+        ProductField::SaleValue,
+        ProductField::Category,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ProductField::Code      => "Código",
+            ProductField::Barcode   => "Código de barras",
+            ProductField::Name      => "Nombre",
+            ProductField::Quantity  => "Cantidad",
+            ProductField::NetValue  => "Valor neto",
+            ProductField::SaleValue => "Valor venta",
+            ProductField::Category  => "Categoría",
+        }
+    }
+
+    pub fn is_required(&self) -> bool {
+        matches!(self, ProductField::Code | ProductField::Name)
+    }
+
+    /// Guess the field from a header name as other POS systems write it.
+    fn detect(header: &str) -> Option<Self> {
+        let h = header.trim().to_lowercase();
+        let h = h.as_str();
+        match h {
+            "code" | "codigo" | "código" | "sku" | "ref" | "referencia" => Some(ProductField::Code),
+            "barcode" | "ean" | "upc" | "gtin" | "codigo de barras" | "código de barras" => Some(ProductField::Barcode),
+            "name" | "nombre" | "producto" | "descripcion" | "descripción" | "item" => Some(ProductField::Name),
+            "quantity" | "qty" | "cantidad" | "stock" | "existencias" => Some(ProductField::Quantity),
+            "net_value" | "cost" | "costo" | "coste" | "valor neto" | "neto" => Some(ProductField::NetValue),
+            "sale_value" | "price" | "precio" | "venta" | "valor venta" | "pvp" => Some(ProductField::SaleValue),
+            "category" | "categoria" | "categoría" | "familia" => Some(ProductField::Category),
+            _ => None,
+        }
+    }
+}
+
+/// Which CSV column feeds which product field (index = CSV column).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ColumnMapping {
+    pub columns: Vec<Option<ProductField>>,
+}
+
+impl ColumnMapping {
+    /// Pre-fill the mapping from recognizable header names.
+    pub fn detect(headers: &[String]) -> Self {
+        let mut columns: Vec<Option<ProductField>> = Vec::with_capacity(headers.len());
+        for h in headers {
+            let field = ProductField::detect(h).filter(|f| !columns.contains(&Some(*f)));
+            columns.push(field);
+        }
+        ColumnMapping { columns }
+    }
+
+    pub fn column_of(&self, field: ProductField) -> Option<usize> {
+        self.columns.iter().position(|c| *c == Some(field))
+    }
+
+    /// Required fields that no column feeds yet; the import is blocked until empty.
+    pub fn missing_required(&self) -> Vec<ProductField> {
+        ProductField::ALL
+            .into_iter()
+            .filter(|f| f.is_required() && self.column_of(*f).is_none())
+            .collect()
+    }
+}
+
+/// One validated CSV row. Unmapped fields are `None` so updates keep the
+/// stored value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProductRow {
+    pub line: usize,
+    pub code: String,
+    pub barcode: Option<String>,
+    pub name: String,
+    pub quantity: Option<f64>,
+    pub net_value: Option<f64>,
+    pub sale_value: Option<f64>,
+    pub category: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ImportReport {
+    pub inserted: usize,
+    pub updated: usize,
+    pub skipped: usize,
+    /// Rows folded into an earlier row with the same code.
+    pub merged: usize,
+    pub errors: Vec<String>,
+}
+
+impl ProductRow {
+    /// Build a row from raw CSV fields; `line` is the 1-based line in the file.
+    pub fn from_record(record: &[String], mapping: &ColumnMapping, line: usize) -> Result<Self, String> {
+        let get = |field: ProductField| -> Option<&str> {
+            mapping
+                .column_of(field)
+                .and_then(|i| record.get(i))
+                .map(|v| v.trim())
+        };
+        let number = |field: ProductField| -> Result<Option<f64>, String> {
+            match get(field) {
+                None | Some("") => Ok(None),
+                Some(raw) => parse_number(raw)
+                    .map(Some)
+                    .ok_or_else(|| format!("Línea {}: {} inválido '{}'", line, field.label().to_lowercase(), raw)),
+            }
+        };
+
+        let code = normalize_code(get(ProductField::Code).unwrap_or(""));
+        let name = get(ProductField::Name).unwrap_or("").to_string();
+        if code.is_empty() {
+            return Err(format!("Línea {}: falta el código", line));
+        }
+        if name.is_empty() {
+            return Err(format!("Línea {}: falta el nombre", line));
+        }
+
+        let row = ProductRow {
+            line,
+            code,
+            barcode: get(ProductField::Barcode).and_then(normalize_barcode),
+            name,
+            quantity: number(ProductField::Quantity)?,
+            net_value: number(ProductField::NetValue)?,
+            sale_value: number(ProductField::SaleValue)?,
+            category: get(ProductField::Category).filter(|c| !c.is_empty()).map(str::to_string),
+        };
+        if row.net_value.is_some_and(|v| v < 0.0) || row.sale_value.is_some_and(|v| v < 0.0) {
+            return Err(format!("Línea {}: los valores no pueden ser negativos", line));
+        }
+        Ok(row)
+    }
+}
+
+/// Lenient number parsing for foreign exports: "$1,50", "1.234,50",
+/// "1,234.50", " 12 ". When both `.` and `,` appear, the last one is the
+/// decimal separator; a lone separator repeated ("1.234.567") groups digits.
+/// A single separator followed by exactly three digits ("12.500", "1,234")
+/// groups thousands too, as COP/CLP/ARS and US prices are written.
+fn parse_number(raw: &str) -> Option<f64> {
+    let cleaned: String = raw
+        .chars()
+        .filter(|c| c.is_ascii_digit() || matches!(c, '.' | ',' | '-'))
+        .collect();
+    let decimal = match (cleaned.rfind('.'), cleaned.rfind(',')) {
+        (Some(dot), Some(comma)) => Some(if dot > comma { '.' } else { ',' }),
+        (Some(_), None) => Some('.'),
+        (None, Some(_)) => Some(','),
+        (None, None) => None,
+    }
+    .filter(|&sep| cleaned.matches(sep).count() == 1)
+    .filter(|&sep| {
+        let (int_part, frac) = cleaned.split_once(sep).unwrap_or_default();
+        let groups_thousands = frac.len() == 3
+            && !matches!(int_part.trim_start_matches('-'), "" | "0")
+            && !cleaned.contains(if sep == '.' { ',' } else { '.' });
+        !groups_thousands
+    });
+    let normalized: String = cleaned
+        .chars()
+        .filter_map(|c| match c {
+            '.' | ',' if Some(c) == decimal => Some('.'),
+            '.' | ',' => None,
+            _ => Some(c),
+        })
+        .collect();
+    normalized.parse::<f64>().ok().filter(|v| v.is_finite())
+}
+
+/// Fold rows sharing a `code` (e.g. split lots) into the first one: quantities
+/// are summed and the prices of the last row win. Order of first appearance is kept.
+pub fn merge_duplicate_rows(rows: Vec<ProductRow>) -> Vec<ProductRow> {
+    let mut merged: Vec<ProductRow> = Vec::with_capacity(rows.len());
+    let mut index: std::collections::HashMap<String, usize> = std::collections::HashMap::new();
+    for row in rows {
+        match index.get(&row.code) {
+            Some(&i) => {
+                let first = &mut merged[i];
+                first.quantity = match (first.quantity, row.quantity) {
+                    (Some(a), Some(b)) => Some(a + b),
+                    (a, b) => a.or(b),
+                };
+                if row.net_value.is_some() { first.net_value = row.net_value; }
+                if row.sale_value.is_some() { first.sale_value = row.sale_value; }
+                if row.category.is_some() { first.category = row.category; }
+                if row.barcode.is_some() { first.barcode = row.barcode; }
+            }
+            None => {
+                index.insert(row.code.clone(), merged.len());
+                merged.push(row);
+            }
+        }
+    }
+    merged
+}
+
+impl Database {
+    /// Import a CSV file whose header names the columns (as
+    /// [`Database::export_products_csv`] writes them). Unknown columns are ignored.
+    pub fn import_products_csv(&self, owner_uid: &str, csv: &str) -> Result<ImportReport> {
+        let mut records = parse_csv(csv);
+        if records.is_empty() {
+            return Err(anyhow::anyhow!("El archivo está vacío"));
+        }
+        let headers = records.remove(0);
+        let mapping = ColumnMapping::detect(&headers);
+        self.import_products_mapped(owner_uid, &records, &mapping, false)
+    }
+
+    /// Import raw CSV records (header row excluded) through a column mapping.
+    /// Products are upserted by code; bad rows are reported, not fatal.
+    pub fn import_products_mapped(
+        &self,
+        owner_uid: &str,
+        rows: &[Vec<String>],
+        mapping: &ColumnMapping,
+        merge_duplicates: bool,
+    ) -> Result<ImportReport> {
+        let mut report = ImportReport::default();
+        let mut parsed = Vec::with_capacity(rows.len());
+        for (i, record) in rows.iter().enumerate() {
+            // +2: 1-based, and the header took the first line
+            match ProductRow::from_record(record, mapping, i + 2) {
+                Ok(row) => parsed.push(row),
+                Err(e) => {
+                    report.skipped += 1;
+                    report.errors.push(e);
+                }
+            }
+        }
+        if merge_duplicates {
+            let before = parsed.len();
+            parsed = merge_duplicate_rows(parsed);
+            report.merged = before - parsed.len();
+        }
+        self.import_product_rows(owner_uid, parsed, mapping, &mut report)?;
+        Ok(report)
+    }
+
+    fn import_product_rows(
+        &self,
+        owner_uid: &str,
+        rows: Vec<ProductRow>,
+        mapping: &ColumnMapping,
+        report: &mut ImportReport,
+    ) -> Result<()> {
+        let missing = mapping.missing_required();
+        if !missing.is_empty() {
+            let names: Vec<&str> = missing.iter().map(|f| f.label()).collect();
+            return Err(anyhow::anyhow!("Faltan columnas obligatorias: {}", names.join(", ")));
+        }
+
+        // One transaction for the whole file; each row's upsert is a savepoint
+        // in it, so a bad row is skipped without undoing the others
+        self.transaction(|_| {
+            for row in rows {
+                let existing = self.find_product_by_code(owner_uid, &row.code)?;
+                let is_update = existing.is_some();
+                let mut p = existing.unwrap_or_else(|| Product {
+                    id: 0,
+                    owner_uid: owner_uid.to_string(),
+                    code: row.code.clone(),
+                    barcode: None,
+                    name: String::new(),
+                    quantity: 0.0,
+                    net_value: 0.0,
+                    sale_value: 0.0,
+                    profit_value: 0.0,
+                    category: None,
+                    reorder_point: None,
+                    alert_pct: 0.0,
+                    low_stock_threshold: DEFAULT_LOW_STOCK_THRESHOLD,
+                    created_at: String::new(),
+                    updated_at: String::new(),
+                });
+                p.name = row.name;
+                if let Some(q) = row.quantity { p.quantity = q; }
+                if let Some(v) = row.net_value { p.net_value = v; }
+                if let Some(v) = row.sale_value { p.sale_value = v; }
+                if row.category.is_some() { p.category = row.category; }
+                if let Some(barcode) = row.barcode {
+                    let excluding = is_update.then_some(p.id);
+                    if self.product_barcode_exists(owner_uid, &barcode, excluding)? {
+                        report.skipped += 1;
+                        report.errors.push(format!(
+                            "Línea {}: el código de barras {} ya pertenece a otro producto",
+                            row.line, barcode
+                        ));
+                        continue;
+                    }
+                    p.barcode = Some(barcode);
+                }
+                p.calculate_profit();
+
+                match self.upsert_product(&p) {
+                    Ok(_) if is_update => report.updated += 1,
+                    Ok(_) => report.inserted += 1,
+                    Err(e) => {
+                        report.skipped += 1;
+                        report.errors.push(format!("Línea {}: {}", row.line, e));
+                    }
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{merge_duplicate_rows, parse_number, ProductRow};
+
+    fn row(line: usize, code: &str, quantity: Option<f64>, sale_value: Option<f64>) -> ProductRow {
+        ProductRow {
+            line,
+            code: code.into(),
+            barcode: None,
+            name: format!("Producto {}", code),
+            quantity,
+            net_value: Some(1.0),
+            sale_value,
+            category: None,
+        }
+    }
+
+    #[test]
+    fn parses_us_grouping() {
+        assert_eq!(parse_number("1,234.50"), Some(1234.5));
+        assert_eq!(parse_number("1,234,567"), Some(1234567.0));
+        assert_eq!(parse_number("0.75"), Some(0.75));
+    }
+
+    #[test]
+    fn parses_eu_grouping() {
+        assert_eq!(parse_number("1.234,50"), Some(1234.5));
+        assert_eq!(parse_number("1.234.567"), Some(1234567.0));
+        assert_eq!(parse_number("1,50"), Some(1.5));
+    }
+
+    #[test]
+    fn lone_separator_before_three_digits_groups_thousands() {
+        assert_eq!(parse_number("12.500"), Some(12500.0));
+        assert_eq!(parse_number("1,234"), Some(1234.0));
+        assert_eq!(parse_number("$1.234"), Some(1234.0));
+        assert_eq!(parse_number("0.750"), Some(0.75));
+        assert_eq!(parse_number("1,2345"), Some(1.2345));
+    }
+
+    #[test]
+    fn parses_currency_prefixed() {
+        assert_eq!(parse_number("$1,234.50"), Some(1234.5));
+        assert_eq!(parse_number("€ 1.234,50"), Some(1234.5));
+        assert_eq!(parse_number("S/ 12"), Some(12.0));
+        assert_eq!(parse_number(" 12 "), Some(12.0));
+        assert_eq!(parse_number("-3,5"), Some(-3.5));
+    }
+
+    #[test]
+    fn rejects_non_numbers() {
+        assert_eq!(parse_number(""), None);
+        assert_eq!(parse_number("abc"), None);
+    }
+
+    #[test]
+    fn duplicate_codes_sum_quantities_and_keep_the_last_price() {
+        let merged = merge_duplicate_rows(vec![
+            row(2, "A1", Some(3.0), Some(10.0)),
+            row(3, "B1", Some(1.0), Some(5.0)),
+            row(4, "A1", Some(2.5), Some(12.0)),
+            row(5, "A1", None, None),
+        ]);
+        assert_eq!(merged.len(), 2);
+        assert_eq!((merged[0].code.as_str(), merged[0].line), ("A1", 2));
+        assert_eq!((merged[0].quantity, merged[0].sale_value), (Some(5.5), Some(12.0)));
+        assert_eq!((merged[1].code.as_str(), merged[1].quantity), ("B1", Some(1.0)));
+    }
+}
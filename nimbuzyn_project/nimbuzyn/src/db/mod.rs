@@ -1,10 +1,30 @@
 use anyhow::{anyhow, Result};
-use rusqlite::{Connection, params};
+use rusqlite::{Connection, DatabaseName, OpenFlags, params};
 use argon2::{
-    Argon2,
+    Algorithm, Argon2, Params, Version,
     password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString, rand_core::OsRng},
 };
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
 use crate::models::*;
+use crate::validation::normalize_username;
+
+mod export;
+mod import;
+mod recovery;
+mod valuation;
+#[cfg(feature = "encryption")]
+mod cipher;
+#[cfg(feature = "encryption")]
+pub use cipher::{database_salt, derive_db_key, is_encrypted, replace_with_backup};
+pub use export::ExportRecord;
+pub use import::{merge_duplicate_rows, ColumnMapping, ImportReport, ProductField, ProductRow};
+pub use recovery::RECOVERY_PHRASE_WORDS;
+pub use valuation::{ValuationLine, ValuationSnapshot};
+#[cfg(debug_assertions)]
+mod dev;
+#[cfg(test)]
+mod tests;
 
 // ──────────────────────────────────────────────
 // DATABASE MANAGER
@@ -12,27 +32,231 @@ use crate::models::*;
 
 pub struct Database {
     conn: Connection,
+    /// Application secret mixed into Argon2 (never written to the DB).
+    pepper: Option<Vec<u8>>,
 }
 
+/// Schema steps in order: step `i` takes a database from version `i` to
+/// `i + 1` (as recorded in `PRAGMA user_version`). Only ever append here;
+/// a released step must keep doing the same thing.
+const MIGRATIONS: &[fn(&Database) -> Result<()>] = &[
+    Database::migrate_base_schema,
+    Database::migrate_cascading_messages,
+    Database::migrate_rgba_avatars,
+    Database::migrate_late_columns,
+    Database::migrate_recovery_phrase,
+    Database::migrate_message_replies,
+    Database::migrate_accent_color,
+    Database::migrate_chat_drafts,
+    Database::migrate_currency,
+    Database::migrate_movement_reverts,
+    Database::migrate_product_codes,
+    Database::migrate_copy_links,
+    Database::migrate_drop_chat_unread,
+];
+
+/// Schema version of a database after all migrations have run.
+pub const SCHEMA_VERSION: i64 = MIGRATIONS.len() as i64;
+
+/// How long a remembered login stays valid.
+pub const SESSION_TTL_DAYS: i64 = 30;
+
+/// Longest display name accepted, in characters (after trimming).
+pub const MAX_DISPLAY_NAME_CHARS: usize = 40;
+
+/// Failed logins allowed before the account is locked for a while.
+pub const MAX_LOGIN_FAILURES: i64 = 5;
+/// Failures further apart than this start the count over.
+const LOGIN_FAILURE_WINDOW_SECS: i64 = 15 * 60;
+/// First lockout; each further failure doubles it, up to the cap.
+const LOGIN_COOLDOWN_SECS: i64 = 30;
+const MAX_LOGIN_COOLDOWN_SECS: i64 = 60 * 60;
+
+/// The default avatar blue as pre-v3 databases stored it (ARGB order).
+const LEGACY_ARGB_AVATAR_COLOR: u32 = 0xFF_4A_90_E2;
+
+/// How many previous versions of an edited message are kept.
+const MAX_EDITS_PER_MESSAGE: usize = 20;
+
 impl Database {
     /// Open (or create) the SQLite database at the given path.
     pub fn open(path: &str) -> Result<Self> {
-        let conn = Connection::open(path)?;
+        Self::open_with_pepper(path, None)
+    }
+
+    /// Open the database with an optional password pepper. Hashes created
+    /// with a pepper only verify when the same pepper is supplied again.
+    pub fn open_with_pepper(path: &str, pepper: Option<&[u8]>) -> Result<Self> {
+        Self::from_connection(Connection::open(path)?, pepper)
+    }
 
+    /// Finish opening `conn` (already keyed, when encrypted): pragmas, then
+    /// migrations.
+    fn from_connection(conn: Connection, pepper: Option<&[u8]>) -> Result<Self> {
         // Enable WAL mode for better concurrent performance
         conn.execute_batch("PRAGMA journal_mode=WAL;")?;
         conn.execute_batch("PRAGMA foreign_keys=ON;")?;
 
-        let db = Database { conn };
+        let db = Database { conn, pepper: pepper.map(<[u8]>::to_vec) };
         db.run_migrations()?;
         Ok(db)
     }
 
+    /// Copy the database at `old_path` to `new_path` (only if nothing exists
+    /// there yet) and verify the copy opens.
+    pub fn migrate_from(old_path: &str, new_path: &str) -> Result<()> {
+        if Path::new(new_path).exists() {
+            return Err(anyhow!("Ya existe una base de datos en {}", new_path));
+        }
+
+        // Fold the WAL into the main file so the copy is complete
+        let old = Connection::open(old_path)?;
+        old.execute_batch("PRAGMA wal_checkpoint(TRUNCATE);")?;
+        drop(old);
+
+        if let Some(parent) = Path::new(new_path).parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::copy(old_path, new_path)?;
+
+        Database::open(new_path)
+            .map(|_| ())
+            .map_err(|e| anyhow!("La copia en {} no se pudo abrir: {}", new_path, e))
+    }
+
+    /// Flush the WAL into the main database file.
+    pub fn checkpoint(&self) -> Result<()> {
+        self.conn.execute_batch("PRAGMA wal_checkpoint(TRUNCATE);")?;
+        Ok(())
+    }
+
+    /// Write a consistent copy of the whole database to `dest` (every
+    /// account, ready to restore on another device) and note the time.
+    pub fn backup_to(&self, dest: &str) -> Result<()> {
+        #[cfg(feature = "encryption")]
+        if let Some(path) = self.encrypted_path() {
+            self.backup_encrypted(&path, dest)
+                .map_err(|e| anyhow!("No se pudo guardar el respaldo: {}", e))?;
+            return self.record_backup();
+        }
+        self.checkpoint()?;
+        self.conn
+            .backup(DatabaseName::Main, dest, None)
+            .map_err(|e| anyhow!("No se pudo guardar el respaldo: {}", e))?;
+        self.record_backup()
+    }
+
+    /// Replace everything in this database with the backup at `src`, then
+    /// bring it up to the current schema. The file is checked first, so a
+    /// wrong pick leaves the current data alone. Encrypted databases and
+    /// backups are swapped file by file with [`replace_with_backup`] instead.
+    pub fn restore_from(&mut self, src: &str) -> Result<()> {
+        #[cfg(feature = "encryption")]
+        if is_encrypted(src) || self.encrypted_path().is_some() {
+            return Err(anyhow!("Los respaldos cifrados se restauran con la base de datos cerrada"));
+        }
+        let source = Connection::open_with_flags(src, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        let has_users: bool = source
+            .query_row(
+                "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = 'users'",
+                [],
+                |r| r.get::<_, i64>(0),
+            )
+            .map(|n| n > 0)
+            .unwrap_or(false);
+        if !has_users {
+            return Err(anyhow!("El archivo no es un respaldo de Nimbuzyn"));
+        }
+        let version: i64 = source.query_row("PRAGMA user_version", [], |r| r.get(0))?;
+        if version > SCHEMA_VERSION {
+            return Err(anyhow!("El respaldo es de una versión más nueva de la app"));
+        }
+        drop(source);
+
+        self.conn
+            .restore(DatabaseName::Main, src, None::<fn(rusqlite::backup::Progress)>)
+            .map_err(|e| anyhow!("No se pudo restaurar el respaldo: {}", e))?;
+        self.run_migrations()
+    }
+
+    /// Run `f` inside a transaction: committed when it returns `Ok`, rolled
+    /// back when it fails (or panics), so multi-row writes land all or nothing.
+    /// Called while another transaction is open (e.g. `upsert_product` from
+    /// an import) it runs in a savepoint of that one instead: a failure undoes
+    /// only `f`'s own writes and the outer transaction decides the rest.
+    pub fn transaction<T>(&self, f: impl FnOnce(&Connection) -> Result<T>) -> Result<T> {
+        if !self.conn.is_autocommit() {
+            self.conn.execute_batch("SAVEPOINT nested_write")?;
+            return match f(&self.conn) {
+                Ok(out) => {
+                    self.conn.execute_batch("RELEASE nested_write")?;
+                    Ok(out)
+                }
+                Err(e) => {
+                    self.conn.execute_batch("ROLLBACK TO nested_write; RELEASE nested_write")?;
+                    Err(e)
+                }
+            };
+        }
+        let tx = self.conn.unchecked_transaction()?;
+        let out = f(&tx)?;
+        tx.commit()?;
+        Ok(out)
+    }
+
+    /// Close the database and destroy its file at `path` along with the
+    /// `-wal`/`-shm`/`-salt` siblings: each is overwritten with zeros before being
+    /// removed. Best effort on flash storage (wear levelling may keep old
+    /// blocks), but nothing readable is left behind in the files themselves.
+    pub fn secure_wipe(self, path: &str) -> Result<()> {
+        let _ = self.checkpoint();
+        self.conn.close().map_err(|(_, e)| anyhow!("No se pudo cerrar la base de datos: {}", e))?;
+
+        for file in ["", "-wal", "-shm", "-salt"].map(|suffix| format!("{}{}", path, suffix)) {
+            let Ok(meta) = std::fs::metadata(&file) else { continue };
+            if let Err(e) = overwrite_with_zeros(&file, meta.len()) {
+                log::warn!("No se pudo sobrescribir {}: {}", file, e);
+            }
+            std::fs::remove_file(&file)?;
+        }
+        Ok(())
+    }
+
     // ──────────────────────────────────────────
     // MIGRATIONS / SCHEMA
     // ──────────────────────────────────────────
 
+    /// Apply every migration the database hasn't seen yet.
     fn run_migrations(&self) -> Result<()> {
+        self.apply_migrations(MIGRATIONS)
+    }
+
+    /// Run the `steps` past the database's `user_version`, each in one
+    /// transaction with its version bump: a step that fails is rolled back
+    /// whole and leaves the version where it was, to be retried on next open.
+    /// Foreign keys are off meanwhile so steps can rebuild tables (SQLite
+    /// ignores that pragma inside a transaction).
+    fn apply_migrations(&self, steps: &[fn(&Database) -> Result<()>]) -> Result<()> {
+        let current = self.schema_version()?.max(0) as usize;
+        if current >= steps.len() {
+            return Ok(());
+        }
+        self.conn.execute_batch("PRAGMA foreign_keys=OFF;")?;
+        let result = steps.iter().enumerate().skip(current).try_for_each(|(i, step)| {
+            self.transaction(|tx| {
+                step(self)?;
+                tx.execute_batch(&format!("PRAGMA user_version = {};", i + 1))?;
+                Ok(())
+            })
+            .map_err(|e| anyhow!("Migración {} fallida: {}", i + 1, e))
+        });
+        self.conn.execute_batch("PRAGMA foreign_keys=ON;")?;
+        result
+    }
+
+    /// v1: the original tables, plus the columns added before versioning.
+    /// Written with `IF NOT EXISTS` so unversioned databases adopt it too.
+    fn migrate_base_schema(&self) -> Result<()> {
         self.conn.execute_batch("
             CREATE TABLE IF NOT EXISTS users (
                 id          INTEGER PRIMARY KEY AUTOINCREMENT,
@@ -72,7 +296,7 @@ impl Database {
 
             CREATE TABLE IF NOT EXISTS messages (
                 id          INTEGER PRIMARY KEY AUTOINCREMENT,
-                chat_id     INTEGER NOT NULL REFERENCES chats(id),
+                chat_id     INTEGER NOT NULL REFERENCES chats(id) ON DELETE CASCADE,
                 sender_uid  TEXT    NOT NULL,
                 content     TEXT    NOT NULL,
                 msg_type    TEXT    NOT NULL DEFAULT 'text',
@@ -96,20 +320,376 @@ impl Database {
                 UNIQUE(owner_uid, code)
             );
 
+            CREATE TABLE IF NOT EXISTS message_edits (
+                id          INTEGER PRIMARY KEY AUTOINCREMENT,
+                message_id  INTEGER NOT NULL REFERENCES messages(id) ON DELETE CASCADE,
+                old_content TEXT    NOT NULL,
+                edited_at   TEXT    NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS reactions (
+                id          INTEGER PRIMARY KEY AUTOINCREMENT,
+                message_id  INTEGER NOT NULL REFERENCES messages(id) ON DELETE CASCADE,
+                user_uid    TEXT    NOT NULL,
+                emoji       TEXT    NOT NULL,
+                created_at  TEXT    NOT NULL,
+                UNIQUE(message_id, user_uid, emoji)
+            );
+
+            CREATE TABLE IF NOT EXISTS stock_movements (
+                id              INTEGER PRIMARY KEY AUTOINCREMENT,
+                product_id      INTEGER NOT NULL,
+                delta           REAL    NOT NULL DEFAULT 0,
+                old_sale_value  REAL,
+                new_sale_value  REAL,
+                note            TEXT,
+                created_at      TEXT    NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS drafts (
+                owner_uid   TEXT NOT NULL,
+                contact_uid TEXT NOT NULL,
+                content     TEXT NOT NULL,
+                updated_at  TEXT NOT NULL,
+                PRIMARY KEY (owner_uid, contact_uid)
+            );
+
+            CREATE TABLE IF NOT EXISTS app_meta (
+                key         TEXT PRIMARY KEY,
+                value       TEXT NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS blocks (
+                owner_uid   TEXT NOT NULL,
+                blocked_uid TEXT NOT NULL,
+                blocked_at  TEXT NOT NULL,
+                PRIMARY KEY (owner_uid, blocked_uid)
+            );
+
+            CREATE TABLE IF NOT EXISTS valuations (
+                id          INTEGER PRIMARY KEY AUTOINCREMENT,
+                owner_uid   TEXT NOT NULL,
+                taken_at    TEXT NOT NULL,
+                total_value REAL NOT NULL,
+                data        TEXT NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS login_attempts (
+                username     TEXT PRIMARY KEY,
+                failures     INTEGER NOT NULL DEFAULT 0,
+                last_attempt INTEGER NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS sessions (
+                token       TEXT PRIMARY KEY,
+                uid         TEXT NOT NULL,
+                expires_at  TEXT NOT NULL
+            );
+
             CREATE INDEX IF NOT EXISTS idx_messages_chat_id ON messages(chat_id);
             CREATE INDEX IF NOT EXISTS idx_messages_sent_at ON messages(sent_at);
             CREATE INDEX IF NOT EXISTS idx_products_owner  ON products(owner_uid);
             CREATE INDEX IF NOT EXISTS idx_contacts_owner  ON contacts(owner_uid);
+            CREATE INDEX IF NOT EXISTS idx_message_edits   ON message_edits(message_id);
+            CREATE INDEX IF NOT EXISTS idx_reactions_msg   ON reactions(message_id);
+            CREATE INDEX IF NOT EXISTS idx_movements_prod  ON stock_movements(product_id);
+        ")?;
+
+        // Columns added after the first release
+        self.ensure_column("products", "category", "TEXT")?;
+        self.ensure_column("users", "last_chat_tab", "TEXT NOT NULL DEFAULT 'friend'")?;
+        self.ensure_column("users", "list_density", "TEXT NOT NULL DEFAULT 'cozy'")?;
+        self.ensure_column("messages", "edited_at", "TEXT")?;
+        self.ensure_column("contacts", "pinned", "INTEGER NOT NULL DEFAULT 0")?;
+        self.ensure_column("contacts", "note", "TEXT")?;
+        Ok(())
+    }
+
+    /// v2: deleting a chat takes its messages (and their edits/reactions) along.
+    fn migrate_cascading_messages(&self) -> Result<()> {
+        if !self.cascades("messages", "chats")? {
+            self.rebuild_message_tables()?;
+        }
+        Ok(())
+    }
+
+    /// v3: avatar colors are RGBA; older builds stored the default blue as ARGB.
+    fn migrate_rgba_avatars(&self) -> Result<()> {
+        for table in ["users", "contacts"] {
+            self.conn.execute(
+                &format!("UPDATE {} SET avatar_color = ?1 WHERE avatar_color = ?2", table),
+                params![DEFAULT_AVATAR_COLOR, LEGACY_ARGB_AVATAR_COLOR],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// v4: columns that were added while every database was still stamped v3,
+    /// so some of them may already exist. Later columns get their own step
+    /// and a plain `ALTER TABLE`.
+    fn migrate_late_columns(&self) -> Result<()> {
+        self.ensure_column("users", "read_receipts", "INTEGER NOT NULL DEFAULT 1")?;
+        // Read with receipts on: a networked build would tell the sender
+        self.ensure_column("messages", "notify_read", "INTEGER NOT NULL DEFAULT 0")?;
+        self.ensure_column("products", "reorder_point", "REAL")?;
+        self.ensure_column("users", "confirm_acquaintance_send", "INTEGER NOT NULL DEFAULT 0")?;
+        self.ensure_column("users", "group_window_mins", "INTEGER NOT NULL DEFAULT 2")?;
+        self.ensure_column("users", "text_autoformat", "INTEGER NOT NULL DEFAULT 0")?;
+        self.ensure_column("messages", "deleted", "INTEGER NOT NULL DEFAULT 0")?;
+        self.ensure_column("products", "alert_pct", "REAL NOT NULL DEFAULT 0")?;
+        self.ensure_column("products", "low_stock_threshold", "REAL NOT NULL DEFAULT 1")?;
+        // Optional barcode, unique per owner; NULL (no barcode) never collides
+        self.ensure_column("products", "barcode", "TEXT")?;
+        self.conn.execute_batch(
+            "CREATE UNIQUE INDEX IF NOT EXISTS idx_products_owner_barcode
+             ON products(owner_uid, barcode) WHERE barcode IS NOT NULL AND barcode != '';",
+        )?;
+        Ok(())
+    }
+
+    /// v5: Argon2 hash of the account's recovery phrase (none for older accounts).
+    fn migrate_recovery_phrase(&self) -> Result<()> {
+        self.conn.execute_batch("ALTER TABLE users ADD COLUMN recovery_hash TEXT;")?;
+        Ok(())
+    }
+
+    /// v6: the message a reply quotes (same chat; `NULL` for plain messages).
+    fn migrate_message_replies(&self) -> Result<()> {
+        self.conn.execute_batch("ALTER TABLE messages ADD COLUMN reply_to INTEGER;")?;
+        Ok(())
+    }
+
+    /// v7: custom accent color (packed RGBA; `NULL` keeps the theme's blue).
+    fn migrate_accent_color(&self) -> Result<()> {
+        self.conn.execute_batch("ALTER TABLE users ADD COLUMN accent_color INTEGER;")?;
+        Ok(())
+    }
+
+    /// v8: drafts belong to a chat (and the account typing them) rather than
+    /// to a contact, and go away with the chat.
+    fn migrate_chat_drafts(&self) -> Result<()> {
+        self.transaction(|tx| {
+            tx.execute_batch("
+                CREATE TABLE drafts_new (
+                    chat_id     INTEGER NOT NULL REFERENCES chats(id) ON DELETE CASCADE,
+                    owner_uid   TEXT    NOT NULL,
+                    content     TEXT    NOT NULL,
+                    updated_at  TEXT    NOT NULL,
+                    PRIMARY KEY (chat_id, owner_uid)
+                );
+                INSERT INTO drafts_new (chat_id, owner_uid, content, updated_at)
+                    SELECT c.id, d.owner_uid, d.content, d.updated_at
+                    FROM drafts d
+                    JOIN chats c ON (c.participant_a = d.owner_uid AND c.participant_b = d.contact_uid)
+                                 OR (c.participant_b = d.owner_uid AND c.participant_a = d.contact_uid);
+                DROP TABLE drafts;
+                ALTER TABLE drafts_new RENAME TO drafts;
+            ")?;
+            Ok(())
+        })
+    }
+
+    /// v9: currency inventory amounts are shown in (ISO code).
+    fn migrate_currency(&self) -> Result<()> {
+        self.conn.execute_batch("ALTER TABLE users ADD COLUMN currency TEXT NOT NULL DEFAULT 'USD';")?;
+        Ok(())
+    }
+
+    /// v10: mark stock movements that were undone, so reverting walks back
+    /// through history. Movements directly followed by a reversión already
+    /// were.
+    fn migrate_movement_reverts(&self) -> Result<()> {
+        self.conn.execute_batch("
+            ALTER TABLE stock_movements ADD COLUMN reverted INTEGER NOT NULL DEFAULT 0;
+            UPDATE stock_movements SET reverted = 1
+            WHERE note IS NOT 'reversión'
+              AND (SELECT n.note FROM stock_movements n
+                   WHERE n.product_id = stock_movements.product_id AND n.id > stock_movements.id
+                   ORDER BY n.id LIMIT 1) = 'reversión';
+        ")?;
+        Ok(())
+    }
+
+    /// v11: store product codes in their [`normalize_code`] form and enforce
+    /// uniqueness per owner regardless of case. Codes that only differed in
+    /// case or spacing get a `-2`, `-3`… suffix instead of colliding.
+    fn migrate_product_codes(&self) -> Result<()> {
+        self.transaction(|tx| {
+            let rows: Vec<(i64, String, String)> = tx
+                .prepare("SELECT id, owner_uid, code FROM products ORDER BY id")?
+                .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))?
+                .collect::<std::result::Result<_, _>>()?;
+            let mut taken: HashSet<(String, String)> = rows
+                .iter()
+                .map(|(_, owner, code)| (owner.clone(), code.clone()))
+                .collect();
+            let mut update = tx.prepare("UPDATE products SET code = ?1 WHERE id = ?2")?;
+            for (id, owner, code) in rows {
+                let normalized = normalize_code(&code);
+                if normalized == code {
+                    continue;
+                }
+                let mut candidate = normalized.clone();
+                let mut n = 2;
+                while taken.contains(&(owner.clone(), candidate.clone())) {
+                    candidate = format!("{}-{}", normalized, n);
+                    n += 1;
+                }
+                taken.remove(&(owner.clone(), code));
+                update.execute(params![candidate, id])?;
+                taken.insert((owner, candidate));
+            }
+            tx.execute_batch(
+                "CREATE UNIQUE INDEX IF NOT EXISTS idx_products_owner_code
+                 ON products(owner_uid, code COLLATE NOCASE);",
+            )?;
+            Ok(())
+        })
+    }
+
+    /// v12: tapping a link in a message copies it instead of opening it.
+    fn migrate_copy_links(&self) -> Result<()> {
+        self.conn.execute_batch("ALTER TABLE users ADD COLUMN copy_links INTEGER NOT NULL DEFAULT 0;")?;
+        Ok(())
+    }
+
+    /// v13: drop `chats.unread_count`. One number can't hold both
+    /// participants' unread counts; those come from `messages.is_read`.
+    fn migrate_drop_chat_unread(&self) -> Result<()> {
+        self.conn.execute_batch("ALTER TABLE chats DROP COLUMN unread_count;")?;
+        Ok(())
+    }
+
+    /// Whether `table`'s foreign key to `parent` is `ON DELETE CASCADE`.
+    fn cascades(&self, table: &str, parent: &str) -> Result<bool> {
+        let n: i64 = self.conn.query_row(
+            &format!(
+                "SELECT COUNT(*) FROM pragma_foreign_key_list('{}') WHERE \"table\" = ?1 AND on_delete = 'CASCADE'",
+                table
+            ),
+            params![parent],
+            |r| r.get(0),
+        )?;
+        Ok(n > 0)
+    }
+
+    /// Recreate `messages`, `message_edits` and `reactions` with cascading
+    /// foreign keys (SQLite can't alter a constraint in place). Runs as a
+    /// migration step, with foreign keys off.
+    fn rebuild_message_tables(&self) -> Result<()> {
+        self.conn.execute_batch("
+            CREATE TABLE messages_new (
+                id          INTEGER PRIMARY KEY AUTOINCREMENT,
+                chat_id     INTEGER NOT NULL REFERENCES chats(id) ON DELETE CASCADE,
+                sender_uid  TEXT    NOT NULL,
+                content     TEXT    NOT NULL,
+                msg_type    TEXT    NOT NULL DEFAULT 'text',
+                file_name   TEXT,
+                file_size   INTEGER,
+                sent_at     TEXT    NOT NULL,
+                is_read     INTEGER NOT NULL DEFAULT 0,
+                edited_at   TEXT
+            );
+            INSERT INTO messages_new
+                SELECT id, chat_id, sender_uid, content, msg_type, file_name, file_size, sent_at, is_read, edited_at
+                FROM messages;
+            DROP TABLE messages;
+            ALTER TABLE messages_new RENAME TO messages;
+
+            CREATE TABLE message_edits_new (
+                id          INTEGER PRIMARY KEY AUTOINCREMENT,
+                message_id  INTEGER NOT NULL REFERENCES messages(id) ON DELETE CASCADE,
+                old_content TEXT    NOT NULL,
+                edited_at   TEXT    NOT NULL
+            );
+            INSERT INTO message_edits_new SELECT id, message_id, old_content, edited_at FROM message_edits;
+            DROP TABLE message_edits;
+            ALTER TABLE message_edits_new RENAME TO message_edits;
+
+            CREATE TABLE reactions_new (
+                id          INTEGER PRIMARY KEY AUTOINCREMENT,
+                message_id  INTEGER NOT NULL REFERENCES messages(id) ON DELETE CASCADE,
+                user_uid    TEXT    NOT NULL,
+                emoji       TEXT    NOT NULL,
+                created_at  TEXT    NOT NULL,
+                UNIQUE(message_id, user_uid, emoji)
+            );
+            INSERT INTO reactions_new SELECT id, message_id, user_uid, emoji, created_at FROM reactions;
+            DROP TABLE reactions;
+            ALTER TABLE reactions_new RENAME TO reactions;
+
+            CREATE INDEX IF NOT EXISTS idx_messages_chat_id ON messages(chat_id);
+            CREATE INDEX IF NOT EXISTS idx_messages_sent_at ON messages(sent_at);
+            CREATE INDEX IF NOT EXISTS idx_message_edits   ON message_edits(message_id);
+            CREATE INDEX IF NOT EXISTS idx_reactions_msg   ON reactions(message_id);
         ")?;
         Ok(())
     }
 
+    /// Schema version of the open database (`PRAGMA user_version`).
+    pub fn schema_version(&self) -> Result<i64> {
+        Ok(self.conn.query_row("PRAGMA user_version", [], |r| r.get(0))?)
+    }
+
+    fn get_meta(&self, key: &str) -> Result<Option<String>> {
+        match self.conn.query_row("SELECT value FROM app_meta WHERE key = ?1", params![key], |r| r.get(0)) {
+            Ok(v) => Ok(Some(v)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn set_meta(&self, key: &str, value: &str) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO app_meta (key, value) VALUES (?1, ?2)
+             ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+            params![key, value],
+        )?;
+        Ok(())
+    }
+
+    /// When the last successful backup was taken (RFC 3339), if ever.
+    pub fn last_backup_at(&self) -> Result<Option<String>> {
+        self.get_meta("last_backup_at")
+    }
+
+    /// Remember that a backup just completed.
+    pub fn record_backup(&self) -> Result<()> {
+        self.set_meta("last_backup_at", &chrono::Utc::now().to_rfc3339())
+    }
+
+    /// Last app version whose "Novedades" the user dismissed.
+    pub fn get_last_seen_version(&self) -> Result<Option<String>> {
+        self.get_meta("last_seen_version")
+    }
+
+    pub fn set_last_seen_version(&self, version: &str) -> Result<()> {
+        self.set_meta("last_seen_version", version)
+    }
+
+    /// Add a column to an existing table unless it's already there
+    /// (`CREATE TABLE IF NOT EXISTS` never touches older databases).
+    fn ensure_column(&self, table: &str, column: &str, decl: &str) -> Result<()> {
+        let exists: bool = self.conn.query_row(
+            &format!("SELECT COUNT(*) FROM pragma_table_info('{}') WHERE name = ?1", table),
+            params![column],
+            |r| r.get::<_, i64>(0),
+        )? > 0;
+        if !exists {
+            self.conn.execute_batch(&format!("ALTER TABLE {} ADD COLUMN {} {};", table, column, decl))?;
+        }
+        Ok(())
+    }
+
     // ──────────────────────────────────────────
     // AUTH
     // ──────────────────────────────────────────
 
     /// Register a new user; password is hashed with Argon2id.
     pub fn register_user(&self, username: &str, display_name: &str, password: &str) -> Result<User> {
+        // The UI normalizes too; this keeps other callers from storing odd names
+        let username = normalize_username(username).map_err(|e| anyhow!(e))?;
+        check_display_name(display_name)?;
         // Check uniqueness
         let exists: bool = self.conn.query_row(
             "SELECT COUNT(*) FROM users WHERE username = ?1",
@@ -121,13 +701,37 @@ impl Database {
         }
 
         // Hash password with Argon2id
-        let salt = SaltString::generate(&mut OsRng);
-        let argon2 = Argon2::default();
-        let hash = argon2
-            .hash_password(password.as_bytes(), &salt)
-            .map_err(|e| anyhow!("Error al cifrar contraseña: {}", e))?
-            .to_string();
+        let hash = self.hash_password(password)?;
+        self.insert_user(&username, display_name, &hash)
+    }
+
+    /// Register a user migrated from another system with their existing
+    /// Argon2 PHC hash (`$argon2id$v=19$...`), so they keep their password.
+    /// Unpeppered hashes are accepted even when this database has a pepper;
+    /// they are rehashed with it on the user's first login.
+    pub fn register_user_with_hash(&self, username: &str, display_name: &str, password_hash: &str) -> Result<User> {
+        let username = normalize_username(username).map_err(|e| anyhow!(e))?;
+        check_display_name(display_name)?;
+        let parsed = PasswordHash::new(password_hash.trim())
+            .map_err(|e| anyhow!("Hash inválido: {}", e))?;
+        if !parsed.algorithm.as_str().starts_with("argon2") {
+            return Err(anyhow!("Hash inválido: se esperaba Argon2, no {}", parsed.algorithm));
+        }
+        if parsed.hash.is_none() || parsed.salt.is_none() {
+            return Err(anyhow!("Hash inválido: falta la sal o el hash"));
+        }
+        let exists: bool = self.conn.query_row(
+            "SELECT COUNT(*) FROM users WHERE username = ?1",
+            params![username],
+            |row| row.get::<_, i64>(0),
+        )? > 0;
+        if exists {
+            return Err(anyhow!("El nombre de usuario ya existe"));
+        }
+        self.insert_user(&username, display_name, password_hash.trim())
+    }
 
+    fn insert_user(&self, username: &str, display_name: &str, hash: &str) -> Result<User> {
         let uid = format!(
             "NIM-{}",
             &uuid::Uuid::new_v4().to_string().to_uppercase()[..6]
@@ -137,7 +741,7 @@ impl Database {
         self.conn.execute(
             "INSERT INTO users (uid, username, display_name, password_hash, avatar_color, created_at)
              VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-            params![uid, username, display_name, hash, 0xFF_4A_90_E2u32, now],
+            params![uid, username, display_name, hash, DEFAULT_AVATAR_COLOR, now],
         )?;
 
         let id = self.conn.last_insert_rowid();
@@ -146,16 +750,101 @@ impl Database {
             uid,
             username: username.to_string(),
             display_name: display_name.to_string(),
-            avatar_color: 0xFF_4A_90_E2,
+            avatar_color: DEFAULT_AVATAR_COLOR,
             created_at: now,
         })
     }
 
-    /// Verify credentials and return the User if valid.
+    /// Verify credentials and return the User if valid. After
+    /// [`MAX_LOGIN_FAILURES`] failures in a row the username is locked out
+    /// for a cooldown that doubles with each further failure.
     pub fn login(&self, username: &str, password: &str) -> Result<User> {
+        if let Some(wait) = self.login_cooldown(username)? {
+            return Err(cooldown_error(wait));
+        }
+        match self.check_credentials(username, password) {
+            Ok(user) => {
+                self.clear_login_failures(username)?;
+                Ok(user)
+            }
+            Err(e) => {
+                self.record_login_failure(username)?;
+                match self.login_cooldown(username)? {
+                    Some(wait) => Err(cooldown_error(wait)),
+                    None => Err(e),
+                }
+            }
+        }
+    }
+
+    /// Seconds left before `username` may try to log in again, if locked out.
+    pub fn login_cooldown(&self, username: &str) -> Result<Option<u64>> {
+        let key = self.login_attempt_key(username)?;
+        let row = self.conn.query_row(
+            "SELECT failures, last_attempt FROM login_attempts WHERE username = ?1",
+            params![key],
+            |r| Ok((r.get::<_, i64>(0)?, r.get::<_, i64>(1)?)),
+        );
+        let (failures, last_attempt) = match row {
+            Ok(v) => v,
+            Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        if failures < MAX_LOGIN_FAILURES {
+            return Ok(None);
+        }
+        let doublings = (failures - MAX_LOGIN_FAILURES).min(16) as u32;
+        let cooldown = (LOGIN_COOLDOWN_SECS << doublings).min(MAX_LOGIN_COOLDOWN_SECS);
+        let left = last_attempt + cooldown - chrono::Utc::now().timestamp();
+        Ok((left > 0).then_some(left as u64))
+    }
+
+    /// What failed logins of `username` are counted under: the account it
+    /// resolves to, so "Ana" and "ana" share one lockout, or else the
+    /// lowercased name as typed.
+    fn login_attempt_key(&self, username: &str) -> Result<String> {
+        match self.conn.query_row(
+            "SELECT username FROM users WHERE username IN (?1, lower(?1))
+             ORDER BY username = ?1 DESC LIMIT 1",
+            params![username],
+            |r| r.get(0),
+        ) {
+            Ok(stored) => Ok(stored),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(username.trim().to_lowercase()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn record_login_failure(&self, username: &str) -> Result<()> {
+        let key = self.login_attempt_key(username)?;
+        let now = chrono::Utc::now().timestamp();
+        // Rows that no longer count nor lock anything out, e.g. made-up names
+        self.conn.execute(
+            "DELETE FROM login_attempts WHERE ?1 - last_attempt > ?2",
+            params![now, LOGIN_FAILURE_WINDOW_SECS.max(MAX_LOGIN_COOLDOWN_SECS)],
+        )?;
+        self.conn.execute(
+            "INSERT INTO login_attempts (username, failures, last_attempt) VALUES (?1, 1, ?2)
+             ON CONFLICT(username) DO UPDATE SET
+                 failures = CASE WHEN ?2 - last_attempt > ?3 THEN 1 ELSE failures + 1 END,
+                 last_attempt = ?2",
+            params![key, now, LOGIN_FAILURE_WINDOW_SECS],
+        )?;
+        Ok(())
+    }
+
+    fn clear_login_failures(&self, username: &str) -> Result<()> {
+        let key = self.login_attempt_key(username)?;
+        self.conn.execute("DELETE FROM login_attempts WHERE username = ?1", params![key])?;
+        Ok(())
+    }
+
+    fn check_credentials(&self, username: &str, password: &str) -> Result<User> {
+        // New accounts are stored lowercase; older ones may be mixed-case
         let result = self.conn.query_row(
             "SELECT id, uid, username, display_name, password_hash, avatar_color, created_at
-             FROM users WHERE username = ?1",
+             FROM users WHERE username IN (?1, lower(?1))
+             ORDER BY username = ?1 DESC LIMIT 1",
             params![username],
             |row| {
                 Ok((
@@ -174,17 +863,135 @@ impl Database {
             Ok((id, uid, uname, display_name, hash_str, avatar_color, created_at)) => {
                 let parsed_hash = PasswordHash::new(&hash_str)
                     .map_err(|e| anyhow!("Hash inválido: {}", e))?;
-                Argon2::default()
-                    .verify_password(password.as_bytes(), &parsed_hash)
-                    .map_err(|_| anyhow!("Contraseña incorrecta"))?;
+                if self.argon2()?.verify_password(password.as_bytes(), &parsed_hash).is_err() {
+                    // Hashes stored before a pepper was configured verify
+                    // without it; upgrade them now that we know the password.
+                    if self.pepper.is_none()
+                        || Argon2::default().verify_password(password.as_bytes(), &parsed_hash).is_err()
+                    {
+                        return Err(anyhow!("Contraseña incorrecta"));
+                    }
+                    let rehashed = self.hash_password(password)?;
+                    self.conn.execute(
+                        "UPDATE users SET password_hash = ?1 WHERE id = ?2",
+                        params![rehashed, id],
+                    )?;
+                }
                 Ok(User { id, uid, username: uname, display_name, avatar_color, created_at })
             }
             Err(_) => Err(anyhow!("Usuario no encontrado")),
         }
     }
 
+    /// Remember `uid` as logged in on this device for [`SESSION_TTL_DAYS`].
+    /// The device holds one session at a time, so any previous one is dropped.
+    /// The session lives only in this database; its token is just the row key.
+    pub fn create_session(&self, uid: &str) -> Result<()> {
+        let token = uuid::Uuid::new_v4().to_string();
+        let expires_at = (chrono::Utc::now() + chrono::Duration::days(SESSION_TTL_DAYS))
+            .to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+        self.transaction(|tx| {
+            tx.execute("DELETE FROM sessions", [])?;
+            tx.execute(
+                "INSERT INTO sessions (token, uid, expires_at) VALUES (?1, ?2, ?3)",
+                params![token, uid, expires_at],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// The user of the remembered session, if it hasn't expired and the
+    /// account still exists. Stale sessions are cleared on the way.
+    pub fn validate_session(&self) -> Result<Option<User>> {
+        let now = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+        self.conn.execute("DELETE FROM sessions WHERE expires_at <= ?1", params![now])?;
+        let uid: String = match self.conn.query_row(
+            "SELECT uid FROM sessions ORDER BY expires_at DESC LIMIT 1",
+            [],
+            |r| r.get(0),
+        ) {
+            Ok(uid) => uid,
+            Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        match self.find_user_by_uid(&uid) {
+            Ok(user) => Ok(Some(user)),
+            Err(_) => {
+                self.clear_session()?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Forget the remembered session (logout).
+    pub fn clear_session(&self) -> Result<()> {
+        self.conn.execute("DELETE FROM sessions", [])?;
+        Ok(())
+    }
+
+    /// Argon2id hasher, keyed with the pepper when one was configured.
+    fn argon2(&self) -> Result<Argon2<'_>> {
+        match self.pepper {
+            Some(ref pepper) => Argon2::new_with_secret(pepper, Algorithm::Argon2id, Version::V0x13, Params::default())
+                .map_err(|e| anyhow!("Pepper inválido: {}", e)),
+            None => Ok(Argon2::default()),
+        }
+    }
+
+    fn hash_password(&self, password: &str) -> Result<String> {
+        let salt = SaltString::generate(&mut OsRng);
+        Ok(self
+            .argon2()?
+            .hash_password(password.as_bytes(), &salt)
+            .map_err(|e| anyhow!("Error al cifrar contraseña: {}", e))?
+            .to_string())
+    }
+
+    /// Number of registered accounts in this database.
+    pub fn user_count(&self) -> Result<u64> {
+        let n: i64 = self.conn.query_row("SELECT COUNT(*) FROM users", [], |r| r.get(0))?;
+        Ok(n as u64)
+    }
+
+    /// Delete an account and everything it owns: its contacts (and other
+    /// users' contact entries pointing at it), its chats with their messages,
+    /// its products and their stock movements. `password` must match; wrong
+    /// guesses count towards the login lockout.
+    pub fn delete_account(&self, uid: &str, password: &str) -> Result<()> {
+        let username: String = self.conn.query_row(
+            "SELECT username FROM users WHERE uid = ?1",
+            params![uid],
+            |r| r.get(0),
+        )?;
+        self.login(&username, password)?;
+
+        self.transaction(|tx| {
+            tx.execute("DELETE FROM reactions WHERE user_uid = ?1", params![uid])?;
+            // Messages, edits and reactions in these chats cascade
+            tx.execute("DELETE FROM chats WHERE participant_a = ?1 OR participant_b = ?1", params![uid])?;
+            tx.execute("DELETE FROM contacts WHERE owner_uid = ?1 OR contact_uid = ?1", params![uid])?;
+            // Drafts in its chats went with them
+            tx.execute("DELETE FROM drafts WHERE owner_uid = ?1", params![uid])?;
+            tx.execute(
+                "DELETE FROM stock_movements WHERE product_id IN (SELECT id FROM products WHERE owner_uid = ?1)",
+                params![uid],
+            )?;
+            tx.execute("DELETE FROM products WHERE owner_uid = ?1", params![uid])?;
+            tx.execute("DELETE FROM valuations WHERE owner_uid = ?1", params![uid])?;
+            tx.execute("DELETE FROM blocks WHERE owner_uid = ?1 OR blocked_uid = ?1", params![uid])?;
+            tx.execute("DELETE FROM sessions WHERE uid = ?1", params![uid])?;
+            tx.execute(
+                "DELETE FROM login_attempts WHERE username = (SELECT username FROM users WHERE uid = ?1)",
+                params![uid],
+            )?;
+            tx.execute("DELETE FROM users WHERE uid = ?1", params![uid])?;
+            Ok(())
+        })
+    }
+
     /// Update display name for a user.
     pub fn update_display_name(&self, uid: &str, display_name: &str) -> Result<()> {
+        check_display_name(display_name)?;
         self.conn.execute(
             "UPDATE users SET display_name = ?1 WHERE uid = ?2",
             params![display_name, uid],
@@ -192,18 +999,28 @@ impl Database {
         Ok(())
     }
 
-    /// Update user password.
+    /// Change a user's avatar color (packed RGBA). Contact rows keep a copy
+    /// of it, so everyone who added the user sees it on their next refresh.
+    pub fn update_avatar_color(&self, uid: &str, color: u32) -> Result<()> {
+        self.transaction(|tx| {
+            tx.execute("UPDATE users SET avatar_color = ?1 WHERE uid = ?2", params![color, uid])?;
+            tx.execute("UPDATE contacts SET avatar_color = ?1 WHERE contact_uid = ?2", params![color, uid])?;
+            Ok(())
+        })
+    }
+
+    /// Update user password. Remembered sessions of the account are dropped,
+    /// so the new password is needed on the next start.
     pub fn update_password(&self, uid: &str, new_password: &str) -> Result<()> {
-        let salt = SaltString::generate(&mut OsRng);
-        let hash = Argon2::default()
-            .hash_password(new_password.as_bytes(), &salt)
-            .map_err(|e| anyhow!("{}", e))?
-            .to_string();
-        self.conn.execute(
-            "UPDATE users SET password_hash = ?1 WHERE uid = ?2",
-            params![hash, uid],
-        )?;
-        Ok(())
+        let hash = self.hash_password(new_password)?;
+        self.transaction(|tx| {
+            tx.execute(
+                "UPDATE users SET password_hash = ?1 WHERE uid = ?2",
+                params![hash, uid],
+            )?;
+            tx.execute("DELETE FROM sessions WHERE uid = ?1", params![uid])?;
+            Ok(())
+        })
     }
 
     /// Save theme preference.
@@ -215,21 +1032,134 @@ impl Database {
         Ok(())
     }
 
-    /// Get user settings.
-    pub fn get_settings(&self, uid: &str) -> Result<AppSettings> {
-        let (theme_str, notifications, font_size): (String, i64, f64) = self.conn.query_row(
-            "SELECT theme, notifications, font_size FROM users WHERE uid = ?1",
-            params![uid],
-            |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)),
+    /// Remember which chat tab ("friend" / "acquaintance") was open last.
+    pub fn update_last_chat_tab(&self, uid: &str, tab: &str) -> Result<()> {
+        self.conn.execute(
+            "UPDATE users SET last_chat_tab = ?1 WHERE uid = ?2",
+            params![tab, uid],
         )?;
-        Ok(AppSettings {
-            theme: if theme_str == "dark" { AppTheme::Dark } else { AppTheme::Light },
-            notifications_enabled: notifications != 0,
-            font_size: font_size as f32,
-        })
+        Ok(())
     }
 
-    // ──────────────────────────────────────────
+    /// Update list row density preference.
+    pub fn update_list_density(&self, uid: &str, density: ListDensity) -> Result<()> {
+        self.conn.execute(
+            "UPDATE users SET list_density = ?1 WHERE uid = ?2",
+            params![density.as_str(), uid],
+        )?;
+        Ok(())
+    }
+
+    /// Save the in-app text size (before the system font scale is applied).
+    pub fn update_font_size(&self, uid: &str, font_size: f32) -> Result<()> {
+        self.conn.execute(
+            "UPDATE users SET font_size = ?1 WHERE uid = ?2",
+            params![font_size as f64, uid],
+        )?;
+        Ok(())
+    }
+
+    /// Turn read receipts on or off.
+    pub fn update_read_receipts(&self, uid: &str, enabled: bool) -> Result<()> {
+        self.conn.execute(
+            "UPDATE users SET read_receipts = ?1 WHERE uid = ?2",
+            params![enabled as i64, uid],
+        )?;
+        Ok(())
+    }
+
+    /// Ask (once per chat) before messaging an acquaintance.
+    pub fn update_confirm_acquaintance_send(&self, uid: &str, enabled: bool) -> Result<()> {
+        self.conn.execute(
+            "UPDATE users SET confirm_acquaintance_send = ?1 WHERE uid = ?2",
+            params![enabled as i64, uid],
+        )?;
+        Ok(())
+    }
+
+    /// Time window, in minutes, for stacking consecutive messages.
+    pub fn update_group_window(&self, uid: &str, minutes: u32) -> Result<()> {
+        self.conn.execute(
+            "UPDATE users SET group_window_mins = ?1 WHERE uid = ?2",
+            params![minutes, uid],
+        )?;
+        Ok(())
+    }
+
+    /// In-app alerts for messages arriving in chats that aren't open.
+    pub fn update_notifications(&self, uid: &str, enabled: bool) -> Result<()> {
+        self.conn.execute(
+            "UPDATE users SET notifications = ?1 WHERE uid = ?2",
+            params![enabled as i64, uid],
+        )?;
+        Ok(())
+    }
+
+    /// Convert emoticons to emoji when sending.
+    pub fn update_text_autoformat(&self, uid: &str, enabled: bool) -> Result<()> {
+        self.conn.execute(
+            "UPDATE users SET text_autoformat = ?1 WHERE uid = ?2",
+            params![enabled as i64, uid],
+        )?;
+        Ok(())
+    }
+
+    /// Copy tapped links to the clipboard instead of opening them.
+    pub fn update_copy_links(&self, uid: &str, enabled: bool) -> Result<()> {
+        self.conn.execute(
+            "UPDATE users SET copy_links = ?1 WHERE uid = ?2",
+            params![enabled as i64, uid],
+        )?;
+        Ok(())
+    }
+
+    /// Override the theme's primary color; `None` restores the default.
+    pub fn update_accent_color(&self, uid: &str, color: Option<u32>) -> Result<()> {
+        self.conn.execute(
+            "UPDATE users SET accent_color = ?1 WHERE uid = ?2",
+            params![color, uid],
+        )?;
+        Ok(())
+    }
+
+    /// Currency (ISO code) inventory amounts are formatted in.
+    pub fn update_currency(&self, uid: &str, currency: &str) -> Result<()> {
+        self.conn.execute(
+            "UPDATE users SET currency = ?1 WHERE uid = ?2",
+            params![currency, uid],
+        )?;
+        Ok(())
+    }
+
+    /// Get user settings.
+    pub fn get_settings(&self, uid: &str) -> Result<AppSettings> {
+        let settings = self.conn.query_row(
+            "SELECT theme, notifications, font_size, last_chat_tab, list_density, read_receipts, confirm_acquaintance_send,
+                    group_window_mins, text_autoformat, accent_color, currency, copy_links
+             FROM users WHERE uid = ?1",
+            params![uid],
+            |r| {
+                let last_tab: String = r.get(3)?;
+                Ok(AppSettings {
+                    theme: AppTheme::from_stored(&r.get::<_, String>(0)?),
+                    notifications_enabled: r.get::<_, i64>(1)? != 0,
+                    font_size: r.get::<_, f64>(2)? as f32,
+                    last_chat_tab: if last_tab == "acquaintance" { ContactType::Acquaintance } else { ContactType::Friend },
+                    list_density: ListDensity::from_db(&r.get::<_, String>(4)?),
+                    read_receipts: r.get::<_, i64>(5)? != 0,
+                    confirm_acquaintance_send: r.get::<_, i64>(6)? != 0,
+                    group_window_mins: r.get(7)?,
+                    text_autoformat: r.get::<_, i64>(8)? != 0,
+                    accent_color: r.get(9)?,
+                    currency: r.get(10)?,
+                    copy_links: r.get::<_, i64>(11)? != 0,
+                })
+            },
+        )?;
+        Ok(settings)
+    }
+
+    // ──────────────────────────────────────────
     // CONTACTS
     // ──────────────────────────────────────────
 
@@ -249,6 +1179,27 @@ impl Database {
         ).map_err(|_| anyhow!("ID '{}' no encontrado", uid))
     }
 
+    /// Find a user by username, `None` if there is no such account.
+    pub fn find_user_by_username(&self, username: &str) -> Result<Option<User>> {
+        let found = self.conn.query_row(
+            "SELECT id, uid, username, display_name, avatar_color, created_at FROM users WHERE username = ?1",
+            params![username],
+            |row| Ok(User {
+                id: row.get(0)?,
+                uid: row.get(1)?,
+                username: row.get(2)?,
+                display_name: row.get(3)?,
+                avatar_color: row.get(4)?,
+                created_at: row.get(5)?,
+            }),
+        );
+        match found {
+            Ok(user) => Ok(Some(user)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
     /// Add a contact (friend or acquaintance).
     pub fn add_contact(
         &self,
@@ -283,13 +1234,112 @@ impl Database {
         Ok(new_val == 1)
     }
 
-    /// Get all contacts of a user, sorted: starred first then A-Z.
+    /// Toggle the pinned state of a contact (independent of starring).
+    pub fn toggle_pin_contact(&self, owner_uid: &str, contact_uid: &str) -> Result<bool> {
+        let current: i64 = self.conn.query_row(
+            "SELECT pinned FROM contacts WHERE owner_uid = ?1 AND contact_uid = ?2",
+            params![owner_uid, contact_uid],
+            |r| r.get(0),
+        )?;
+        let new_val = if current == 0 { 1 } else { 0 };
+        self.conn.execute(
+            "UPDATE contacts SET pinned = ?1 WHERE owner_uid = ?2 AND contact_uid = ?3",
+            params![new_val, owner_uid, contact_uid],
+        )?;
+        Ok(new_val == 1)
+    }
+
+    /// Rename a contact in the owner's list only; the user's own display
+    /// name is untouched.
+    pub fn update_contact_name(&self, owner_uid: &str, contact_uid: &str, new_name: &str) -> Result<()> {
+        check_display_name(new_name)?;
+        let n = self.conn.execute(
+            "UPDATE contacts SET display_name = ?1 WHERE owner_uid = ?2 AND contact_uid = ?3",
+            params![new_name.trim(), owner_uid, contact_uid],
+        )?;
+        if n == 0 {
+            return Err(anyhow!("Contacto no encontrado"));
+        }
+        Ok(())
+    }
+
+    /// Save the owner's private note on a contact; blank clears it.
+    pub fn set_contact_note(&self, owner_uid: &str, contact_uid: &str, note: &str) -> Result<()> {
+        let note = Some(note.trim()).filter(|n| !n.is_empty());
+        self.conn.execute(
+            "UPDATE contacts SET note = ?1 WHERE owner_uid = ?2 AND contact_uid = ?3",
+            params![note, owner_uid, contact_uid],
+        )?;
+        Ok(())
+    }
+
+    /// Block `blocked_uid`: their messages to `owner_uid` are refused.
+    pub fn block_contact(&self, owner_uid: &str, blocked_uid: &str) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR IGNORE INTO blocks (owner_uid, blocked_uid, blocked_at) VALUES (?1, ?2, ?3)",
+            params![owner_uid, blocked_uid, chrono::Utc::now().to_rfc3339()],
+        )?;
+        Ok(())
+    }
+
+    pub fn unblock_contact(&self, owner_uid: &str, blocked_uid: &str) -> Result<()> {
+        self.conn.execute(
+            "DELETE FROM blocks WHERE owner_uid = ?1 AND blocked_uid = ?2",
+            params![owner_uid, blocked_uid],
+        )?;
+        Ok(())
+    }
+
+    /// True when `owner_uid` has blocked `other_uid`.
+    pub fn is_blocked(&self, owner_uid: &str, other_uid: &str) -> Result<bool> {
+        Ok(self.conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM blocks WHERE owner_uid = ?1 AND blocked_uid = ?2)",
+            params![owner_uid, other_uid],
+            |r| r.get(0),
+        )?)
+    }
+
+    /// Everyone `owner_uid` has blocked.
+    pub fn blocked_uids(&self, owner_uid: &str) -> Result<HashSet<String>> {
+        let mut stmt = self.conn.prepare("SELECT blocked_uid FROM blocks WHERE owner_uid = ?1")?;
+        let uids = stmt
+            .query_map(params![owner_uid], |r| r.get(0))?
+            .collect::<rusqlite::Result<HashSet<String>>>()?;
+        Ok(uids)
+    }
+
+    /// True when `a_uid` and `b_uid` have added each other as contacts.
+    pub fn is_mutual_contact(&self, a_uid: &str, b_uid: &str) -> Result<bool> {
+        let count: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM contacts
+             WHERE (owner_uid = ?1 AND contact_uid = ?2)
+                OR (owner_uid = ?2 AND contact_uid = ?1)",
+            params![a_uid, b_uid],
+            |row| row.get(0),
+        )?;
+        Ok(count >= 2)
+    }
+
+    /// Contacts of `owner_uid` who have added them back, in one query.
+    pub fn mutual_contact_uids(&self, owner_uid: &str) -> Result<HashSet<String>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT c.contact_uid FROM contacts c
+             JOIN contacts back ON back.owner_uid = c.contact_uid AND back.contact_uid = c.owner_uid
+             WHERE c.owner_uid = ?1",
+        )?;
+        let uids = stmt
+            .query_map(params![owner_uid], |r| r.get(0))?
+            .collect::<rusqlite::Result<HashSet<String>>>()?;
+        Ok(uids)
+    }
+
+    /// Get all contacts of a user, sorted: pinned, then starred, then A-Z.
     pub fn get_contacts(&self, owner_uid: &str, contact_type: &str) -> Result<Vec<Contact>> {
         let mut stmt = self.conn.prepare(
-            "SELECT id, owner_uid, contact_uid, display_name, avatar_color, contact_type, starred, added_at
+            "SELECT id, owner_uid, contact_uid, display_name, avatar_color, contact_type, starred, added_at, pinned, note
              FROM contacts
              WHERE owner_uid = ?1 AND contact_type = ?2
-             ORDER BY starred DESC, display_name ASC",
+             ORDER BY pinned DESC, starred DESC, display_name ASC",
         )?;
         let rows = stmt.query_map(params![owner_uid, contact_type], |row| {
             Ok(Contact {
@@ -304,12 +1354,41 @@ impl Database {
                 },
                 starred: row.get::<_, i64>(6)? != 0,
                 added_at: row.get(7)?,
+                pinned: row.get::<_, i64>(8)? != 0,
+                note: row.get(9)?,
             })
         })?;
         rows.collect::<std::result::Result<Vec<_>, _>>()
             .map_err(|e| anyhow!("{}", e))
     }
 
+    /// Counts shown in the settings profile card.
+    pub fn profile_stats(&self, uid: &str) -> Result<ProfileStats> {
+        let (friends, acquaintances): (i64, i64) = self.conn.query_row(
+            "SELECT COALESCE(SUM(contact_type = 'friend'), 0),
+                    COALESCE(SUM(contact_type != 'friend'), 0)
+             FROM contacts WHERE owner_uid = ?1",
+            params![uid],
+            |r| Ok((r.get(0)?, r.get(1)?)),
+        )?;
+        let chats: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM chats WHERE participant_a = ?1 OR participant_b = ?1",
+            params![uid],
+            |r| r.get(0),
+        )?;
+        let messages_sent: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM messages WHERE sender_uid = ?1",
+            params![uid],
+            |r| r.get(0),
+        )?;
+        Ok(ProfileStats {
+            friends: friends as u64,
+            acquaintances: acquaintances as u64,
+            chats: chats as u64,
+            messages_sent: messages_sent as u64,
+        })
+    }
+
     /// Remove a contact.
     pub fn remove_contact(&self, owner_uid: &str, contact_uid: &str) -> Result<()> {
         self.conn.execute(
@@ -327,44 +1406,126 @@ impl Database {
     pub fn get_or_create_chat(&self, uid_a: &str, uid_b: &str) -> Result<Chat> {
         let (a, b) = if uid_a < uid_b { (uid_a, uid_b) } else { (uid_b, uid_a) };
 
-        // Try to find existing
-        let existing = self.conn.query_row(
-            "SELECT id, participant_a, participant_b, created_at, last_message, last_msg_at, unread_count
-             FROM chats WHERE participant_a = ?1 AND participant_b = ?2",
-            params![a, b],
-            |row| Ok(Chat {
-                id: row.get(0)?,
-                participant_a: row.get(1)?,
-                participant_b: row.get(2)?,
-                created_at: row.get(3)?,
-                last_message: row.get(4)?,
-                last_message_at: row.get(5)?,
-                unread_count: row.get::<_, u32>(6)?,
-            }),
-        );
+        // Insert-if-missing, then always read back the canonical row: a double
+        // tap racing two calls can't trip the UNIQUE constraint this way.
+        let now = chrono::Utc::now().to_rfc3339();
+        self.transaction(|tx| {
+            tx.execute(
+                "INSERT OR IGNORE INTO chats (participant_a, participant_b, created_at) VALUES (?1, ?2, ?3)",
+                params![a, b, now],
+            )?;
+
+            tx.query_row(
+                "SELECT id, participant_a, participant_b, created_at, last_message, last_msg_at
+                 FROM chats WHERE participant_a = ?1 AND participant_b = ?2",
+                params![a, b],
+                |row| Ok(Chat {
+                    id: row.get(0)?,
+                    participant_a: row.get(1)?,
+                    participant_b: row.get(2)?,
+                    created_at: row.get(3)?,
+                    last_message: row.get(4)?,
+                    last_message_at: row.get(5)?,
+                }),
+            ).map_err(|e| anyhow!("{}", e))
+        })
+    }
 
-        if let Ok(chat) = existing {
-            return Ok(chat);
+    /// Remember the unsent text `owner_uid` typed in a chat; blank text
+    /// clears it.
+    pub fn save_draft(&self, owner_uid: &str, chat_id: i64, content: &str) -> Result<()> {
+        if content.trim().is_empty() {
+            return self.clear_draft(owner_uid, chat_id);
         }
+        self.conn.execute(
+            "INSERT INTO drafts (chat_id, owner_uid, content, updated_at) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(chat_id, owner_uid) DO UPDATE SET content = excluded.content, updated_at = excluded.updated_at",
+            params![chat_id, owner_uid, content, chrono::Utc::now().to_rfc3339()],
+        )?;
+        Ok(())
+    }
 
-        let now = chrono::Utc::now().to_rfc3339();
+    /// The saved draft of a chat, if there is a non-empty one.
+    pub fn get_draft(&self, owner_uid: &str, chat_id: i64) -> Result<Option<String>> {
+        match self.conn.query_row(
+            "SELECT content FROM drafts WHERE chat_id = ?1 AND owner_uid = ?2",
+            params![chat_id, owner_uid],
+            |r| r.get::<_, String>(0),
+        ) {
+            Ok(d) => Ok(Some(d).filter(|d| !d.trim().is_empty())),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Every saved draft of `owner_uid`, keyed by the other participant of
+    /// its chat (for the contact list).
+    pub fn drafts_by_contact(&self, owner_uid: &str) -> Result<HashMap<String, String>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT CASE WHEN c.participant_a = ?1 THEN c.participant_b ELSE c.participant_a END, d.content
+             FROM drafts d JOIN chats c ON c.id = d.chat_id
+             WHERE d.owner_uid = ?1 AND TRIM(d.content) != ''",
+        )?;
+        let rows = stmt.query_map(params![owner_uid], |r| Ok((r.get(0)?, r.get(1)?)))?;
+        rows.collect::<std::result::Result<HashMap<_, _>, _>>()
+            .map_err(|e| anyhow!("{}", e))
+    }
+
+    /// Forget the draft of a chat (e.g. once it was sent).
+    pub fn clear_draft(&self, owner_uid: &str, chat_id: i64) -> Result<()> {
         self.conn.execute(
-            "INSERT INTO chats (participant_a, participant_b, created_at) VALUES (?1, ?2, ?3)",
-            params![a, b, now],
+            "DELETE FROM drafts WHERE chat_id = ?1 AND owner_uid = ?2",
+            params![chat_id, owner_uid],
         )?;
-        let id = self.conn.last_insert_rowid();
-        Ok(Chat {
-            id,
-            participant_a: a.to_string(),
-            participant_b: b.to_string(),
-            created_at: now.clone(),
-            last_message: None,
-            last_message_at: None,
-            unread_count: 0,
+        Ok(())
+    }
+
+    /// Chats of `owner_uid` whose other participant is no longer one of
+    /// their contacts (left behind when a contact is removed).
+    pub fn orphaned_chats(&self, owner_uid: &str) -> Result<Vec<Chat>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, participant_a, participant_b, created_at, last_message, last_msg_at
+             FROM chats c
+             WHERE (c.participant_a = ?1 OR c.participant_b = ?1)
+               AND NOT EXISTS (
+                   SELECT 1 FROM contacts ct
+                   WHERE ct.owner_uid = ?1
+                     AND ct.contact_uid = CASE WHEN c.participant_a = ?1 THEN c.participant_b ELSE c.participant_a END
+               )
+             ORDER BY c.created_at ASC",
+        )?;
+        let rows = stmt.query_map(params![owner_uid], |row| Ok(Chat {
+            id: row.get(0)?,
+            participant_a: row.get(1)?,
+            participant_b: row.get(2)?,
+            created_at: row.get(3)?,
+            last_message: row.get(4)?,
+            last_message_at: row.get(5)?,
+        }))?;
+        rows.collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| anyhow!("{}", e))
+    }
+
+    /// Delete every orphaned chat of `owner_uid` with its messages.
+    /// Returns how many chats were removed.
+    pub fn purge_orphaned_chats(&self, owner_uid: &str) -> Result<usize> {
+        let orphans = self.orphaned_chats(owner_uid)?;
+        self.transaction(|tx| {
+            for chat in &orphans {
+                tx.execute("DELETE FROM chats WHERE id = ?1", params![chat.id])?;
+            }
+            Ok(orphans.len())
         })
     }
 
+    /// Delete a chat; its messages (with their edits and reactions) cascade.
+    pub fn delete_chat(&self, chat_id: i64) -> Result<()> {
+        self.conn.execute("DELETE FROM chats WHERE id = ?1", params![chat_id])?;
+        Ok(())
+    }
+
     /// Send a text message.
+    #[allow(clippy::too_many_arguments)]
     pub fn send_message(
         &self,
         chat_id: i64,
@@ -373,25 +1534,35 @@ impl Database {
         msg_type: &str,
         file_name: Option<&str>,
         file_size: Option<u64>,
+        reply_to: Option<i64>,
     ) -> Result<Message> {
         let now = chrono::Utc::now().to_rfc3339();
-        self.conn.execute(
-            "INSERT INTO messages (chat_id, sender_uid, content, msg_type, file_name, file_size, sent_at, is_read)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, 0)",
-            params![chat_id, sender_uid, content, msg_type, file_name, file_size.map(|s| s as i64), now],
-        )?;
-        let id = self.conn.last_insert_rowid();
+        let id = self.transaction(|tx| {
+            check_deliverable(tx, chat_id, sender_uid)?;
+            if let Some(parent) = reply_to {
+                let same_chat: bool = tx.query_row(
+                    "SELECT COUNT(*) FROM messages WHERE id = ?1 AND chat_id = ?2",
+                    params![parent, chat_id],
+                    |r| r.get::<_, i64>(0),
+                )? > 0;
+                if !same_chat {
+                    return Err(anyhow!("El mensaje citado no es de este chat"));
+                }
+            }
+            tx.execute(
+                "INSERT INTO messages (chat_id, sender_uid, content, msg_type, file_name, file_size, sent_at, is_read, reply_to)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, 0, ?8)",
+                params![chat_id, sender_uid, content, msg_type, file_name, file_size.map(|s| s as i64), now, reply_to],
+            )?;
+            let id = tx.last_insert_rowid();
 
-        // Update last message on chat
-        let preview = if msg_type == "text" {
-            content.chars().take(50).collect::<String>()
-        } else {
-            format!("[{}]", msg_type)
-        };
-        self.conn.execute(
-            "UPDATE chats SET last_message = ?1, last_msg_at = ?2 WHERE id = ?3",
-            params![preview, now, chat_id],
-        )?;
+            // Update last message on chat; it's unread until the recipient opens it
+            tx.execute(
+                "UPDATE chats SET last_message = ?1, last_msg_at = ?2 WHERE id = ?3",
+                params![message_preview(content, msg_type), now, chat_id],
+            )?;
+            Ok(id)
+        })?;
 
         Ok(Message {
             id,
@@ -403,121 +1574,824 @@ impl Database {
             file_size,
             sent_at: now,
             is_read: false,
+            edited_at: None,
+            deleted: false,
+            reply_to,
+        })
+    }
+
+    /// Insert many messages in one transaction (much faster than repeated
+    /// [`send_message`](Self::send_message) calls) and point each affected
+    /// chat's last-message fields at its last message in `msgs`.
+    /// Returns the new ids in input order.
+    pub fn send_messages_batch(&self, msgs: &[NewMessage]) -> Result<Vec<i64>> {
+        self.transaction(|tx| {
+            let mut ids = Vec::with_capacity(msgs.len());
+            let mut last_by_chat: HashMap<i64, (String, String)> = HashMap::new();
+            let mut checked: HashSet<(i64, &str)> = HashSet::new();
+            {
+                let mut insert = tx.prepare(
+                    "INSERT INTO messages (chat_id, sender_uid, content, msg_type, file_name, file_size, sent_at, is_read)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, 0)",
+                )?;
+                for m in msgs {
+                    if checked.insert((m.chat_id, m.sender_uid.as_str())) {
+                        check_deliverable(tx, m.chat_id, &m.sender_uid)?;
+                    }
+                    let now = chrono::Utc::now().to_rfc3339();
+                    insert.execute(params![
+                        m.chat_id, m.sender_uid, m.content, m.msg_type,
+                        m.file_name, m.file_size.map(|s| s as i64), now
+                    ])?;
+                    ids.push(tx.last_insert_rowid());
+                    last_by_chat.insert(m.chat_id, (message_preview(&m.content, &m.msg_type), now));
+                }
+                let mut update = tx.prepare(
+                    "UPDATE chats SET last_message = ?1, last_msg_at = ?2 WHERE id = ?3",
+                )?;
+                for (chat_id, (preview, at)) in &last_by_chat {
+                    update.execute(params![preview, at, chat_id])?;
+                }
+            }
+            Ok(ids)
         })
     }
 
-    /// Load messages for a chat (paginated, newest last).
+    /// Replace the content of one of `requester_uid`'s own text messages,
+    /// keeping the previous version in `message_edits`. Returns the edit
+    /// timestamp.
+    pub fn edit_message(&self, message_id: i64, requester_uid: &str, new_content: &str) -> Result<String> {
+        let new_content = new_content.trim();
+        if new_content.is_empty() {
+            return Err(anyhow!("El mensaje no puede estar vacío"));
+        }
+        if new_content.len() > Message::MAX_TEXT_LEN {
+            return Err(anyhow!("Máximo {} caracteres", Message::MAX_TEXT_LEN));
+        }
+
+        self.transaction(|tx| {
+            let (sender_uid, old_content, msg_type, deleted): (String, String, String, bool) = tx
+                .query_row(
+                    "SELECT sender_uid, content, msg_type, deleted FROM messages WHERE id = ?1",
+                    params![message_id],
+                    |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get::<_, i64>(3)? != 0)),
+                )
+                .map_err(|_| anyhow!("Mensaje no encontrado"))?;
+            if sender_uid != requester_uid {
+                return Err(anyhow!("Solo puedes editar tus propios mensajes"));
+            }
+            if deleted {
+                return Err(anyhow!("El mensaje fue eliminado"));
+            }
+            if MessageType::from_str(&msg_type) != MessageType::Text {
+                return Err(anyhow!("Solo se pueden editar mensajes de texto"));
+            }
+            let now = chrono::Utc::now().to_rfc3339();
+            if old_content == new_content {
+                return Ok(now);
+            }
+
+            tx.execute(
+                "INSERT INTO message_edits (message_id, old_content, edited_at) VALUES (?1, ?2, ?3)",
+                params![message_id, old_content, now],
+            )?;
+            tx.execute(
+                "UPDATE messages SET content = ?1, edited_at = ?2 WHERE id = ?3",
+                params![new_content, now, message_id],
+            )?;
+            // Only the most recent versions are worth keeping around
+            tx.execute(
+                "DELETE FROM message_edits WHERE message_id = ?1 AND id NOT IN
+                 (SELECT id FROM message_edits WHERE message_id = ?1 ORDER BY id DESC LIMIT ?2)",
+                params![message_id, MAX_EDITS_PER_MESSAGE as i64],
+            )?;
+            Ok(now)
+        })
+    }
+
+    /// Delete one of `requester_uid`'s own messages. The row stays as a
+    /// tombstone (content, file info, edit history and reactions are wiped)
+    /// so the conversation keeps its shape; the chat preview follows if it
+    /// was the latest message.
+    pub fn delete_message(&self, message_id: i64, requester_uid: &str) -> Result<()> {
+        let (chat_id, sender_uid): (i64, String) = self
+            .conn
+            .query_row(
+                "SELECT chat_id, sender_uid FROM messages WHERE id = ?1",
+                params![message_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .map_err(|_| anyhow!("Mensaje no encontrado"))?;
+        if sender_uid != requester_uid {
+            return Err(anyhow!("Solo puedes eliminar tus propios mensajes"));
+        }
+
+        self.transaction(|tx| {
+            tx.execute(
+                "UPDATE messages SET content = '', file_name = NULL, file_size = NULL, deleted = 1
+                 WHERE id = ?1",
+                params![message_id],
+            )?;
+            tx.execute("DELETE FROM message_edits WHERE message_id = ?1", params![message_id])?;
+            tx.execute("DELETE FROM reactions WHERE message_id = ?1", params![message_id])?;
+            tx.execute(
+                "UPDATE chats SET last_message = ?1
+                 WHERE id = ?2 AND ?3 = (SELECT MAX(id) FROM messages WHERE chat_id = ?2)",
+                params![Message::DELETED_TEXT, chat_id, message_id],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Previous versions of a message as `(old_content, edited_at)`, oldest first.
+    pub fn message_edit_history(&self, message_id: i64) -> Result<Vec<(String, String)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT old_content, edited_at FROM message_edits
+             WHERE message_id = ?1 ORDER BY id ASC",
+        )?;
+        let rows = stmt.query_map(params![message_id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| anyhow!("{}", e))
+    }
+
+    /// Load a page of a chat's messages counting back from the newest:
+    /// `offset` skips that many of the latest. Returned oldest first.
     pub fn get_messages(&self, chat_id: i64, limit: usize, offset: usize) -> Result<Vec<Message>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT * FROM (
+                 SELECT {} FROM messages WHERE chat_id = ?1
+                 ORDER BY sent_at DESC, id DESC
+                 LIMIT ?2 OFFSET ?3
+             ) ORDER BY sent_at ASC, id ASC",
+            MESSAGE_COLUMNS
+        ))?;
+        let rows = stmt.query_map(params![chat_id, limit as i64, offset as i64], message_from_row)?;
+        rows.collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| anyhow!("{}", e))
+    }
+
+    /// A single message by id, e.g. the original a reply quotes when it
+    /// isn't among the loaded page.
+    pub fn get_message(&self, message_id: i64) -> Result<Option<Message>> {
+        match self.conn.query_row(
+            &format!("SELECT {} FROM messages WHERE id = ?1", MESSAGE_COLUMNS),
+            params![message_id],
+            message_from_row,
+        ) {
+            Ok(m) => Ok(Some(m)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// How many of `chat_id`'s newest messages must be loaded (in
+    /// [`get_messages`](Self::get_messages) order) to include `message_id`.
+    pub fn messages_through(&self, chat_id: i64, message_id: i64) -> Result<usize> {
+        let n: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM messages
+             WHERE chat_id = ?1
+               AND (sent_at, id) >= (SELECT sent_at, id FROM messages WHERE id = ?2)",
+            params![chat_id, message_id],
+            |r| r.get(0),
+        )?;
+        Ok(n as usize)
+    }
+
+    /// Mark everything `reader_uid` received in `chat_id` as read, which
+    /// clears their unread badge. Only with `send_receipts` are the messages
+    /// also flagged `notify_read` for the sender. Returns how many changed.
+    pub fn mark_chat_read(&self, chat_id: i64, reader_uid: &str, send_receipts: bool) -> Result<usize> {
+        Ok(self.conn.execute(
+            "UPDATE messages SET is_read = 1, notify_read = ?3
+             WHERE chat_id = ?1 AND sender_uid != ?2 AND is_read = 0",
+            params![chat_id, reader_uid, send_receipts as i64],
+        )?)
+    }
+
+    /// [`mark_chat_read`](Self::mark_chat_read) for every chat of
+    /// `reader_uid` at once. Returns how many messages changed.
+    pub fn mark_all_read(&self, reader_uid: &str, send_receipts: bool) -> Result<usize> {
+        Ok(self.conn.execute(
+            "UPDATE messages SET is_read = 1, notify_read = ?2
+             WHERE sender_uid != ?1 AND is_read = 0
+               AND chat_id IN (SELECT id FROM chats WHERE participant_a = ?1 OR participant_b = ?1)",
+            params![reader_uid, send_receipts as i64],
+        )?)
+    }
+
+    /// Unread messages sent to `uid`, per other participant's UID.
+    /// Chats with nothing unread are left out.
+    pub fn unread_by_contact(&self, uid: &str) -> Result<HashMap<String, u32>> {
         let mut stmt = self.conn.prepare(
-            "SELECT id, chat_id, sender_uid, content, msg_type, file_name, file_size, sent_at, is_read
-             FROM messages WHERE chat_id = ?1
-             ORDER BY sent_at ASC
-             LIMIT ?2 OFFSET ?3",
+            "SELECT CASE WHEN c.participant_a = ?1 THEN c.participant_b ELSE c.participant_a END,
+                    COUNT(*)
+             FROM messages m JOIN chats c ON c.id = m.chat_id
+             WHERE (c.participant_a = ?1 OR c.participant_b = ?1)
+               AND m.sender_uid != ?1 AND m.is_read = 0
+             GROUP BY c.id",
         )?;
-        let rows = stmt.query_map(params![chat_id, limit as i64, offset as i64], |row| {
-            Ok(Message {
-                id: row.get(0)?,
-                chat_id: row.get(1)?,
-                sender_uid: row.get(2)?,
-                content: row.get(3)?,
-                msg_type: {
-                    let t: String = row.get(4)?;
-                    MessageType::from_str(&t)
-                },
-                file_name: row.get(5)?,
-                file_size: row.get::<_, Option<i64>>(6)?.map(|s| s as u64),
-                sent_at: row.get(7)?,
-                is_read: row.get::<_, i64>(8)? != 0,
+        let rows = stmt.query_map(params![uid], |r| Ok((r.get(0)?, r.get(1)?)))?;
+        rows.collect::<std::result::Result<HashMap<_, _>, _>>()
+            .map_err(|e| anyhow!("{}", e))
+    }
+
+    /// Ids of `sender_uid`'s messages in `chat_id` (from `from_id` on) that
+    /// the other side has read, so an open chat can update its ✓✓ marks.
+    pub fn read_message_ids(&self, chat_id: i64, sender_uid: &str, from_id: i64) -> Result<HashSet<i64>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id FROM messages WHERE chat_id = ?1 AND sender_uid = ?2 AND id >= ?3 AND is_read = 1",
+        )?;
+        let rows = stmt.query_map(params![chat_id, sender_uid, from_id], |r| r.get(0))?;
+        rows.collect::<std::result::Result<HashSet<_>, _>>()
+            .map_err(|e| anyhow!("{}", e))
+    }
+
+    /// Unread messages sent to `uid` across all of their chats.
+    pub fn total_unread(&self, uid: &str) -> Result<u64> {
+        let count: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM messages m JOIN chats c ON c.id = m.chat_id
+             WHERE (c.participant_a = ?1 OR c.participant_b = ?1)
+               AND m.sender_uid != ?1 AND m.is_read = 0",
+            params![uid],
+            |r| r.get(0),
+        )?;
+        Ok(count as u64)
+    }
+
+    /// Text messages of one chat containing `query` (case-insensitive),
+    /// newest first. An empty query returns nothing.
+    pub fn search_messages(&self, chat_id: i64, query: &str, limit: usize) -> Result<Vec<Message>> {
+        let Some(pattern) = like_pattern(query) else { return Ok(vec![]) };
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM messages
+             WHERE chat_id = ?1 AND msg_type = 'text' AND content LIKE ?2 ESCAPE '\\'
+             ORDER BY sent_at DESC LIMIT ?3",
+            MESSAGE_COLUMNS
+        ))?;
+        let rows = stmt.query_map(params![chat_id, pattern, limit as i64], message_from_row)?;
+        rows.collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| anyhow!("{}", e))
+    }
+
+    /// Like [`search_messages`](Self::search_messages) but across every chat
+    /// of `owner_uid`, tagging each hit with the other participant.
+    pub fn search_all_messages(&self, owner_uid: &str, query: &str, limit: usize) -> Result<Vec<SearchHit>> {
+        let Some(pattern) = like_pattern(query) else { return Ok(vec![]) };
+        let columns: Vec<String> = MESSAGE_COLUMNS.split(", ").map(|c| format!("m.{}", c)).collect();
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {0}, {1}, COALESCE(ct.display_name, {1})
+             FROM messages m
+             JOIN chats c ON c.id = m.chat_id
+             LEFT JOIN contacts ct ON ct.owner_uid = ?1 AND ct.contact_uid = {1}
+             WHERE (c.participant_a = ?1 OR c.participant_b = ?1)
+               AND m.msg_type = 'text' AND m.content LIKE ?2 ESCAPE '\\'
+             ORDER BY m.sent_at DESC LIMIT ?3",
+            columns.join(", "),
+            "CASE WHEN c.participant_a = ?1 THEN c.participant_b ELSE c.participant_a END",
+        ))?;
+        let rows = stmt.query_map(params![owner_uid, pattern, limit as i64], |row| {
+            Ok(SearchHit {
+                message: message_from_row(row)?,
+                contact_uid: row.get(columns.len())?,
+                contact_name: row.get(columns.len() + 1)?,
             })
         })?;
         rows.collect::<std::result::Result<Vec<_>, _>>()
             .map_err(|e| anyhow!("{}", e))
     }
 
+    /// All file messages (images, videos, documents, archives) of a chat, newest first.
+    pub fn get_media_messages(&self, chat_id: i64) -> Result<Vec<Message>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM messages WHERE chat_id = ?1 AND msg_type != 'text' AND deleted = 0
+             ORDER BY sent_at DESC",
+            MESSAGE_COLUMNS
+        ))?;
+        let rows = stmt.query_map(params![chat_id], message_from_row)?;
+        rows.collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| anyhow!("{}", e))
+    }
+
+    /// Add `emoji` from `user_uid` to a message, or remove it if already there.
+    /// Returns whether the reaction is now present.
+    pub fn toggle_reaction(&self, message_id: i64, user_uid: &str, emoji: &str) -> Result<bool> {
+        let removed = self.conn.execute(
+            "DELETE FROM reactions WHERE message_id = ?1 AND user_uid = ?2 AND emoji = ?3",
+            params![message_id, user_uid, emoji],
+        )?;
+        if removed > 0 {
+            return Ok(false);
+        }
+        let now = chrono::Utc::now().to_rfc3339();
+        self.conn.execute(
+            "INSERT INTO reactions (message_id, user_uid, emoji, created_at) VALUES (?1, ?2, ?3, ?4)",
+            params![message_id, user_uid, emoji, now],
+        )?;
+        Ok(true)
+    }
+
+    /// Reactions of every message in a chat, grouped per message and emoji.
+    pub fn chat_reactions(&self, chat_id: i64, viewer_uid: &str) -> Result<HashMap<i64, Vec<ReactionCount>>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT r.message_id, r.emoji, COUNT(*), MAX(r.user_uid = ?2)
+             FROM reactions r JOIN messages m ON m.id = r.message_id
+             WHERE m.chat_id = ?1
+             GROUP BY r.message_id, r.emoji
+             ORDER BY r.message_id, MIN(r.id)",
+        )?;
+        let rows = stmt.query_map(params![chat_id, viewer_uid], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                ReactionCount {
+                    emoji: row.get(1)?,
+                    count: row.get::<_, i64>(2)? as u32,
+                    mine: row.get::<_, i64>(3)? != 0,
+                },
+            ))
+        })?;
+        let mut map: HashMap<i64, Vec<ReactionCount>> = HashMap::new();
+        for row in rows {
+            let (message_id, reaction) = row?;
+            map.entry(message_id).or_default().push(reaction);
+        }
+        Ok(map)
+    }
+
     // ──────────────────────────────────────────
     // PRODUCTS / INVENTORY
     // ──────────────────────────────────────────
 
-    /// Insert or replace a product.
+    /// Insert or replace a product. Quantity and sale price changes are
+    /// recorded in `stock_movements`.
     pub fn upsert_product(&self, p: &Product) -> Result<i64> {
         let now = chrono::Utc::now().to_rfc3339();
-        if p.id == 0 {
-            self.conn.execute(
-                "INSERT INTO products
-                 (owner_uid, code, name, quantity, net_value, sale_value, profit_value, created_at, updated_at)
-                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?8)",
-                params![
-                    p.owner_uid, p.code, p.name, p.quantity,
-                    p.net_value, p.sale_value, p.profit_value, now
-                ],
+        let barcode = p.barcode.as_deref().and_then(normalize_barcode);
+        self.transaction(|tx| {
+            if p.id == 0 {
+                tx.execute(
+                    "INSERT INTO products
+                     (owner_uid, code, name, quantity, net_value, sale_value, profit_value, category,
+                      reorder_point, alert_pct, low_stock_threshold, created_at, updated_at, barcode)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?12, ?13)",
+                    params![
+                        p.owner_uid, normalize_code(&p.code), p.name, p.quantity,
+                        p.net_value, p.sale_value, p.profit_value, p.category,
+                        p.reorder_point, p.alert_pct, p.low_stock_threshold, now, barcode
+                    ],
+                )?;
+                let id = tx.last_insert_rowid();
+                if p.quantity != 0.0 {
+                    log_movement(tx, id, p.quantity, None, Some("alta"))?;
+                }
+                Ok(id)
+            } else {
+                let (old_qty, old_sale): (f64, f64) = tx.query_row(
+                    "SELECT quantity, sale_value FROM products WHERE id = ?1",
+                    params![p.id],
+                    |r| Ok((r.get(0)?, r.get(1)?)),
+                )?;
+                let price = (old_sale != p.sale_value).then_some((old_sale, p.sale_value));
+                if old_qty != p.quantity || price.is_some() {
+                    log_movement(tx, p.id, p.quantity - old_qty, price, None)?;
+                }
+                tx.execute(
+                    "UPDATE products SET code=?1, name=?2, quantity=?3, net_value=?4,
+                     sale_value=?5, profit_value=?6, category=?7, reorder_point=?8,
+                     alert_pct=?9, low_stock_threshold=?10, updated_at=?11, barcode=?12
+                     WHERE id=?13",
+                    params![
+                        normalize_code(&p.code), p.name, p.quantity, p.net_value,
+                        p.sale_value, p.profit_value, p.category,
+                        p.reorder_point, p.alert_pct, p.low_stock_threshold, now, barcode, p.id
+                    ],
+                )?;
+                Ok(p.id)
+            }
+        })
+    }
+
+    /// Get all products for a user, by name.
+    pub fn get_products(&self, owner_uid: &str) -> Result<Vec<Product>> {
+        self.get_products_sorted(owner_uid, None, ProductSort::Name)
+    }
+
+    /// Products of a user (optionally one category) in the list order
+    /// `sort`; ties fall back to the name.
+    pub fn get_products_sorted(&self, owner_uid: &str, category: Option<&str>, sort: ProductSort) -> Result<Vec<Product>> {
+        let order = match sort {
+            ProductSort::Name            => "name COLLATE NOCASE ASC",
+            ProductSort::Quantity        => "quantity ASC, name COLLATE NOCASE ASC",
+            ProductSort::Profit          => "quantity * profit_value DESC, name COLLATE NOCASE ASC",
+            ProductSort::RecentlyUpdated => "updated_at DESC, name COLLATE NOCASE ASC",
+        };
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM products WHERE owner_uid = ?1 AND (?2 IS NULL OR category = ?2) ORDER BY {}",
+            PRODUCT_COLUMNS, order
+        ))?;
+        let rows = stmt.query_map(params![owner_uid, category], product_from_row)?;
+        rows.collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| anyhow!("{}", e))
+    }
+
+    /// Distinct non-blank categories in use, alphabetically.
+    pub fn list_categories(&self, owner_uid: &str) -> Result<Vec<String>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT DISTINCT category FROM products
+             WHERE owner_uid = ?1 AND TRIM(COALESCE(category, '')) != ''
+             ORDER BY category COLLATE NOCASE ASC",
+        )?;
+        let rows = stmt.query_map(params![owner_uid], |r| r.get(0))?;
+        rows.collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| anyhow!("{}", e))
+    }
+
+    /// Products of `owner_uid` whose name or code contains `query`.
+    pub fn search_products(&self, owner_uid: &str, query: &str, limit: usize) -> Result<Vec<Product>> {
+        let Some(pattern) = like_pattern(query) else { return Ok(vec![]) };
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM products
+             WHERE owner_uid = ?1 AND (name LIKE ?2 ESCAPE '\\' OR code LIKE ?2 ESCAPE '\\')
+             ORDER BY name ASC LIMIT ?3",
+            PRODUCT_COLUMNS
+        ))?;
+        let rows = stmt.query_map(params![owner_uid, pattern, limit as i64], product_from_row)?;
+        rows.collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| anyhow!("{}", e))
+    }
+
+    /// Products whose name starts with `prefix` (case-insensitive), by name.
+    /// Feeds the name autocomplete in the product form.
+    pub fn suggest_products(&self, owner_uid: &str, prefix: &str, limit: usize) -> Result<Vec<Product>> {
+        let Some(pattern) = like_pattern(prefix) else { return Ok(vec![]) };
+        // like_pattern gives `%q%`; drop the leading wildcard for a prefix match
+        let pattern = &pattern[1..];
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM products
+             WHERE owner_uid = ?1 AND name LIKE ?2 ESCAPE '\\'
+             ORDER BY name COLLATE NOCASE ASC LIMIT ?3",
+            PRODUCT_COLUMNS
+        ))?;
+        let rows = stmt.query_map(params![owner_uid, pattern, limit as i64], product_from_row)?;
+        rows.collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| anyhow!("{}", e))
+    }
+
+    /// Look up a product by its code (exact match).
+    pub fn find_product_by_code(&self, owner_uid: &str, code: &str) -> Result<Option<Product>> {
+        let result = self.conn.query_row(
+            &format!("SELECT {} FROM products WHERE owner_uid = ?1 AND code = ?2 COLLATE NOCASE", PRODUCT_COLUMNS),
+            params![owner_uid, normalize_code(code)],
+            product_from_row,
+        );
+        match result {
+            Ok(p) => Ok(Some(p)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Whether another product of `owner_uid` already uses `code` (compared
+    /// normalized). `excluding_id` skips the product being edited.
+    pub fn product_code_exists(&self, owner_uid: &str, code: &str, excluding_id: Option<i64>) -> Result<bool> {
+        let count: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM products
+             WHERE owner_uid = ?1 AND code = ?2 COLLATE NOCASE AND id != ?3",
+            params![owner_uid, normalize_code(code), excluding_id.unwrap_or(0)],
+            |r| r.get(0),
+        )?;
+        Ok(count > 0)
+    }
+
+    /// Look up a product by its barcode, as a scanner would.
+    pub fn find_product_by_barcode(&self, owner_uid: &str, barcode: &str) -> Result<Option<Product>> {
+        let Some(barcode) = normalize_barcode(barcode) else { return Ok(None) };
+        let result = self.conn.query_row(
+            &format!("SELECT {} FROM products WHERE owner_uid = ?1 AND barcode = ?2", PRODUCT_COLUMNS),
+            params![owner_uid, barcode],
+            product_from_row,
+        );
+        match result {
+            Ok(p) => Ok(Some(p)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Whether another product of `owner_uid` already uses `barcode`. A blank
+    /// barcode never collides. `excluding_id` skips the product being edited.
+    pub fn product_barcode_exists(&self, owner_uid: &str, barcode: &str, excluding_id: Option<i64>) -> Result<bool> {
+        let Some(barcode) = normalize_barcode(barcode) else { return Ok(false) };
+        let count: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM products WHERE owner_uid = ?1 AND barcode = ?2 AND id != ?3",
+            params![owner_uid, barcode, excluding_id.unwrap_or(0)],
+            |r| r.get(0),
+        )?;
+        Ok(count > 0)
+    }
+
+    /// Raise (or lower) every sale price by `percent`, optionally only within
+    /// one category. Profit is recomputed; net value is left untouched.
+    /// Returns how many products were updated.
+    pub fn adjust_prices(&self, owner_uid: &str, category: Option<&str>, percent: f64) -> Result<usize> {
+        if !percent.is_finite() || percent <= -100.0 {
+            return Err(anyhow!("Porcentaje inválido"));
+        }
+        let factor = 1.0 + percent / 100.0;
+        let now = chrono::Utc::now().to_rfc3339();
+
+        self.transaction(|tx| {
+            tx.execute(
+                "INSERT INTO stock_movements (product_id, delta, old_sale_value, new_sale_value, note, created_at)
+                 SELECT id, 0, sale_value, ROUND(sale_value * ?1, 2), 'ajuste de precios', ?2
+                 FROM products WHERE owner_uid = ?3 AND (?4 IS NULL OR category = ?4)",
+                params![factor, now, owner_uid, category],
             )?;
-            Ok(self.conn.last_insert_rowid())
-        } else {
-            self.conn.execute(
-                "UPDATE products SET code=?1, name=?2, quantity=?3, net_value=?4,
-                 sale_value=?5, profit_value=?6, updated_at=?7
-                 WHERE id=?8",
-                params![
-                    p.code, p.name, p.quantity, p.net_value,
-                    p.sale_value, p.profit_value, now, p.id
-                ],
+            let updated = tx.execute(
+                "UPDATE products
+                 SET sale_value = ROUND(sale_value * ?1, 2),
+                     profit_value = ROUND(sale_value * ?1, 2) - net_value,
+                     updated_at = ?2
+                 WHERE owner_uid = ?3 AND (?4 IS NULL OR category = ?4)",
+                params![factor, now, owner_uid, category],
             )?;
-            Ok(p.id)
-        }
+            Ok(updated)
+        })
     }
 
-    /// Get all products for a user.
-    pub fn get_products(&self, owner_uid: &str) -> Result<Vec<Product>> {
+    /// Nudge a product's stock by `delta` (the list's −/+ buttons), never
+    /// below zero. The applied change is logged; returns the new quantity.
+    pub fn adjust_quantity(&self, id: i64, delta: f64) -> Result<f64> {
+        self.transaction(|tx| {
+            let old: f64 = tx.query_row(
+                "SELECT quantity FROM products WHERE id = ?1",
+                params![id],
+                |r| r.get(0),
+            )?;
+            let new = (old + delta).max(0.0);
+            if new != old {
+                tx.execute(
+                    "UPDATE products SET quantity = ?1, updated_at = ?2 WHERE id = ?3",
+                    params![new, chrono::Utc::now().to_rfc3339(), id],
+                )?;
+                log_movement(tx, id, new - old, None, Some("ajuste"))?;
+            }
+            Ok(new)
+        })
+    }
+
+    /// A product's latest stock/price movements, newest first.
+    pub fn product_movements(&self, product_id: i64, limit: usize) -> Result<Vec<StockMovement>> {
         let mut stmt = self.conn.prepare(
-            "SELECT id, owner_uid, code, name, quantity, net_value, sale_value, profit_value, created_at, updated_at
-             FROM products WHERE owner_uid = ?1
-             ORDER BY name ASC",
+            "SELECT id, delta, old_sale_value, new_sale_value, note, created_at FROM stock_movements
+             WHERE product_id = ?1 ORDER BY id DESC LIMIT ?2",
         )?;
-        let rows = stmt.query_map(params![owner_uid], |row| {
-            Ok(Product {
-                id: row.get(0)?,
-                owner_uid: row.get(1)?,
-                code: row.get(2)?,
-                name: row.get(3)?,
-                quantity: row.get(4)?,
-                net_value: row.get(5)?,
-                sale_value: row.get(6)?,
-                profit_value: row.get(7)?,
-                created_at: row.get(8)?,
-                updated_at: row.get(9)?,
+        let rows = stmt.query_map(params![product_id, limit as i64], |r| {
+            Ok(StockMovement {
+                id: r.get(0)?,
+                delta: r.get(1)?,
+                old_sale_value: r.get(2)?,
+                new_sale_value: r.get(3)?,
+                note: r.get(4)?,
+                created_at: r.get(5)?,
             })
         })?;
         rows.collect::<std::result::Result<Vec<_>, _>>()
             .map_err(|e| anyhow!("{}", e))
     }
 
+    /// Undo the most recent stock/price movement of a product that isn't
+    /// undone yet by applying the opposite change, logged as a compensating
+    /// movement. Calling it again undoes the movement before that one.
+    pub fn revert_last_movement(&self, product_id: i64) -> Result<()> {
+        self.transaction(|tx| {
+            let last = tx.query_row(
+                "SELECT id, delta, old_sale_value, new_sale_value FROM stock_movements
+                 WHERE product_id = ?1 AND reverted = 0 AND note IS NOT 'reversión'
+                 ORDER BY id DESC LIMIT 1",
+                params![product_id],
+                |r| Ok((r.get::<_, i64>(0)?, r.get::<_, f64>(1)?, r.get::<_, Option<f64>>(2)?, r.get::<_, Option<f64>>(3)?)),
+            );
+            let (movement_id, delta, old_sale, new_sale) = match last {
+                Ok(row) => row,
+                Err(rusqlite::Error::QueryReturnedNoRows) => {
+                    return Err(anyhow!("No hay cambios para revertir"));
+                }
+                Err(e) => return Err(e.into()),
+            };
+
+            let now = chrono::Utc::now().to_rfc3339();
+            tx.execute(
+                "UPDATE products
+                 SET quantity = quantity - ?1,
+                     sale_value = COALESCE(?2, sale_value),
+                     profit_value = COALESCE(?2, sale_value) - net_value,
+                     updated_at = ?3
+                 WHERE id = ?4",
+                params![delta, old_sale, now, product_id],
+            )?;
+            let price = old_sale.zip(new_sale).map(|(old, new)| (new, old));
+            log_movement(tx, product_id, -delta, price, Some("reversión"))?;
+            tx.execute("UPDATE stock_movements SET reverted = 1 WHERE id = ?1", params![movement_id])?;
+            Ok(())
+        })
+    }
+
     /// Delete a product by ID.
     pub fn delete_product(&self, id: i64) -> Result<()> {
+        self.conn.execute("DELETE FROM stock_movements WHERE product_id = ?1", params![id])?;
         self.conn.execute("DELETE FROM products WHERE id = ?1", params![id])?;
         Ok(())
     }
 
-    /// Compute summary stats for the inventory dashboard.
-    pub fn inventory_summary(&self, owner_uid: &str) -> Result<InventorySummary> {
+    /// Compute summary stats for the inventory dashboard, over every product
+    /// or just one `category`.
+    pub fn inventory_summary(&self, owner_uid: &str, category: Option<&str>) -> Result<InventorySummary> {
         let (total_products, total_net, total_profit): (i64, f64, f64) = self.conn.query_row(
             "SELECT COUNT(*), SUM(quantity * net_value), SUM(quantity * profit_value)
-             FROM products WHERE owner_uid = ?1",
-            params![owner_uid],
+             FROM products WHERE owner_uid = ?1 AND (?2 IS NULL OR category = ?2)",
+            params![owner_uid, category],
             |r| Ok((r.get(0)?, r.get::<_, Option<f64>>(1)?.unwrap_or(0.0),
                      r.get::<_, Option<f64>>(2)?.unwrap_or(0.0))),
         )?;
-        let out_of_stock: i64 = self.conn.query_row(
-            "SELECT COUNT(*) FROM products WHERE owner_uid = ?1 AND quantity < 1",
-            params![owner_uid],
-            |r| r.get(0),
-        )?;
         Ok(InventorySummary {
             total_products: total_products as u64,
             total_net_value: total_net,
             total_profit_value: total_profit,
-            out_of_stock_count: out_of_stock as u64,
+            out_of_stock_count: self.out_of_stock_count(owner_uid, category)?,
+            low_stock_count: self.low_stock_count(owner_uid, category)?,
         })
     }
+
+    /// Number of products raising the low-stock alert: at or under their
+    /// reorder point plus margin, or below their out-of-stock threshold when
+    /// they have none. Same rule as `Product::needs_reorder`. `category`
+    /// narrows the count to one category.
+    pub fn low_stock_count(&self, owner_uid: &str, category: Option<&str>) -> Result<u64> {
+        let count: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM products
+             WHERE owner_uid = ?1 AND (?2 IS NULL OR category = ?2) AND CASE
+                 WHEN reorder_point IS NULL THEN quantity < low_stock_threshold
+                 ELSE quantity <= reorder_point * (1 + alert_pct) + ?3
+             END",
+            params![owner_uid, category, QUANTITY_EPSILON],
+            |r| r.get(0),
+        )?;
+        Ok(count as u64)
+    }
+
+    /// Number of products below their out-of-stock threshold.
+    pub fn out_of_stock_count(&self, owner_uid: &str, category: Option<&str>) -> Result<u64> {
+        let count: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM products
+             WHERE owner_uid = ?1 AND (?2 IS NULL OR category = ?2) AND quantity < low_stock_threshold",
+            params![owner_uid, category],
+            |r| r.get(0),
+        )?;
+        Ok(count as u64)
+    }
+}
+
+// ──────────────────────────────────────────────
+// ROW MAPPERS
+// ──────────────────────────────────────────────
+
+/// A message from `sender_uid` may go into `chat_id`: the chat exists
+/// (checked up front, a bare FK violation doesn't say what was missing) and
+/// the other participant hasn't blocked the sender.
+fn check_deliverable(conn: &Connection, chat_id: i64, sender_uid: &str) -> Result<()> {
+    let blocked: bool = match conn.query_row(
+        "SELECT EXISTS(
+             SELECT 1 FROM blocks
+             WHERE blocked_uid = ?2
+               AND owner_uid = CASE WHEN c.participant_a = ?2 THEN c.participant_b ELSE c.participant_a END
+         )
+         FROM chats c WHERE c.id = ?1",
+        params![chat_id, sender_uid],
+        |r| r.get(0),
+    ) {
+        Ok(b) => b,
+        Err(rusqlite::Error::QueryReturnedNoRows) => return Err(anyhow!("El chat {} no existe", chat_id)),
+        Err(e) => return Err(e.into()),
+    };
+    if blocked {
+        return Err(anyhow!("No puedes enviar mensajes a este contacto"));
+    }
+    Ok(())
+}
+
+/// Record a stock/price change of a product in `stock_movements`. Takes the
+/// connection so callers can log inside their own transaction.
+fn log_movement(
+    conn: &Connection,
+    product_id: i64,
+    delta: f64,
+    price: Option<(f64, f64)>,
+    note: Option<&str>,
+) -> Result<()> {
+    let now = chrono::Utc::now().to_rfc3339();
+    conn.execute(
+        "INSERT INTO stock_movements (product_id, delta, old_sale_value, new_sale_value, note, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![product_id, delta, price.map(|p| p.0), price.map(|p| p.1), note, now],
+    )?;
+    Ok(())
+}
+
+/// The lockout error shown on the login screen.
+fn cooldown_error(wait_secs: u64) -> anyhow::Error {
+    anyhow!("Demasiados intentos, espera {} segundos", wait_secs)
+}
+
+/// Display names must have something visible in them, and fit in a row.
+fn check_display_name(display_name: &str) -> Result<()> {
+    let name = display_name.trim();
+    if name.is_empty() {
+        return Err(anyhow!("El nombre no puede estar vacío"));
+    }
+    if name.chars().count() > MAX_DISPLAY_NAME_CHARS {
+        return Err(anyhow!("El nombre no puede tener más de {} caracteres", MAX_DISPLAY_NAME_CHARS));
+    }
+    Ok(())
+}
+
+/// Chat list preview of a message: the start of the text, or `[tipo]`.
+fn message_preview(content: &str, msg_type: &str) -> String {
+    if msg_type == "text" {
+        content.chars().take(50).collect()
+    } else {
+        format!("[{}]", msg_type)
+    }
+}
+
+/// Replace the first `len` bytes of `path` with zeros and sync to disk.
+fn overwrite_with_zeros(path: &str, len: u64) -> std::io::Result<()> {
+    use std::io::Write;
+    let mut file = std::fs::OpenOptions::new().write(true).open(path)?;
+    let zeros = [0u8; 8192];
+    let mut left = len;
+    while left > 0 {
+        let n = left.min(zeros.len() as u64) as usize;
+        file.write_all(&zeros[..n])?;
+        left -= n as u64;
+    }
+    file.sync_all()
+}
+
+/// `%query%` for a LIKE match with `\` as escape; `None` for a blank query.
+fn like_pattern(query: &str) -> Option<String> {
+    let query = query.trim();
+    if query.is_empty() {
+        return None;
+    }
+    let escaped = query.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+    Some(format!("%{}%", escaped))
+}
+
+const MESSAGE_COLUMNS: &str =
+    "id, chat_id, sender_uid, content, msg_type, file_name, file_size, sent_at, is_read, edited_at, deleted, reply_to";
+
+fn message_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Message> {
+    Ok(Message {
+        id: row.get(0)?,
+        chat_id: row.get(1)?,
+        sender_uid: row.get(2)?,
+        content: row.get(3)?,
+        msg_type: {
+            let t: String = row.get(4)?;
+            MessageType::from_str(&t)
+        },
+        file_name: row.get(5)?,
+        file_size: row.get::<_, Option<i64>>(6)?.map(|s| s as u64),
+        sent_at: row.get(7)?,
+        is_read: row.get::<_, i64>(8)? != 0,
+        edited_at: row.get(9)?,
+        deleted: row.get::<_, i64>(10)? != 0,
+        reply_to: row.get(11)?,
+    })
+}
+
+const PRODUCT_COLUMNS: &str =
+    "id, owner_uid, code, name, quantity, net_value, sale_value, profit_value, category, created_at, updated_at,
+     reorder_point, alert_pct, low_stock_threshold, barcode";
+
+fn product_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Product> {
+    Ok(Product {
+        id: row.get(0)?,
+        owner_uid: row.get(1)?,
+        code: row.get(2)?,
+        name: row.get(3)?,
+        quantity: row.get(4)?,
+        net_value: row.get(5)?,
+        sale_value: row.get(6)?,
+        profit_value: row.get(7)?,
+        category: row.get(8)?,
+        created_at: row.get(9)?,
+        updated_at: row.get(10)?,
+        reorder_point: row.get(11)?,
+        alert_pct: row.get(12)?,
+        low_stock_threshold: row.get(13)?,
+        barcode: row.get(14)?,
+    })
 }
 
 #[derive(Debug, Clone, Default)]
@@ -526,4 +2400,24 @@ pub struct InventorySummary {
     pub total_net_value: f64,
     pub total_profit_value: f64,
     pub out_of_stock_count: u64,
+    pub low_stock_count: u64,
+}
+
+/// One row of a product's stock/price history.
+#[derive(Debug, Clone)]
+pub struct StockMovement {
+    pub id: i64,
+    pub delta: f64,
+    pub old_sale_value: Option<f64>,
+    pub new_sale_value: Option<f64>,
+    pub note: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ProfileStats {
+    pub friends: u64,
+    pub acquaintances: u64,
+    pub chats: u64,
+    pub messages_sent: u64,
 }
@@ -0,0 +1,109 @@
+use anyhow::{anyhow, Result};
+use argon2::password_hash::{rand_core::{OsRng, RngCore}, PasswordHash, PasswordVerifier};
+use rusqlite::params;
+use super::{cooldown_error, Database};
+
+// ──────────────────────────────────────────────
+// PASSWORD RECOVERY PHRASE
+// ──────────────────────────────────────────────
+
+/// Words in a recovery phrase. With 256 words that's 48 bits, and guesses
+/// go through Argon2 and the login lockout.
+pub const RECOVERY_PHRASE_WORDS: usize = 6;
+
+/// Short, accent-free Spanish words, easy to write down and type back.
+const WORDS: [&str; 256] = [
+    "abeja", "acero", "agua", "aguila", "aire", "ajo", "alba", "alga",
+    "alma", "almendra", "alto", "amigo", "ancla", "angel", "anillo", "antena",
+    "arbol", "arco", "arena", "arroz", "atlas", "avena", "azul", "bahia",
+    "balsa", "banco", "barco", "barro", "bosque", "bota", "brisa", "bruma",
+    "buho", "cabra", "cacao", "cafe", "caja", "calle", "cama", "campo",
+    "canoa", "canto", "capa", "cara", "carta", "casa", "cebra", "cedro",
+    "cena", "cerro", "cielo", "cine", "circo", "cisne", "clavo", "cobre",
+    "coco", "cofre", "cola", "color", "copa", "coral", "corona", "cruz",
+    "cuento", "cueva", "cuna", "dado", "dardo", "delta", "diente", "disco",
+    "dolar", "domingo", "duna", "eco", "eje", "elfo", "enero", "erizo",
+    "escoba", "espada", "estrella", "faro", "fiesta", "flecha", "flor", "foca",
+    "fresa", "fruta", "fuego", "fuente", "gallo", "ganso", "gato", "gema",
+    "globo", "golfo", "gorra", "gota", "grano", "grillo", "guante", "haba",
+    "hada", "hielo", "hierro", "higo", "hilo", "hoja", "hongo", "horno",
+    "hueso", "huevo", "humo", "iglu", "imagen", "isla", "jabon", "jardin",
+    "jarra", "jirafa", "joya", "juego", "jugo", "junco", "lago", "lana",
+    "lapiz", "laurel", "leche", "leon", "libro", "lima", "limon", "lince",
+    "lirio", "llama", "llave", "lluvia", "lobo", "loro", "luna", "lupa",
+    "madera", "maiz", "mango", "mapa", "mar", "marco", "media", "melon",
+    "mesa", "miel", "mina", "mono", "monte", "mora", "muro", "nabo",
+    "nave", "nido", "niebla", "nieve", "noche", "nube", "nuez", "ola",
+    "olivo", "olla", "oro", "oso", "otono", "pajaro", "pala", "palma",
+    "pan", "papel", "parque", "pasto", "pato", "pera", "perla", "piano",
+    "pico", "pie", "piedra", "pino", "pipa", "pluma", "polen", "pozo",
+    "prado", "puente", "puerta", "pulpo", "queso", "radio", "rama", "rana",
+    "raton", "rayo", "red", "regla", "reloj", "remo", "rio", "roble",
+    "roca", "rosa", "rueda", "sal", "salsa", "seda", "selva", "semilla",
+    "sierra", "silla", "sol", "sombra", "sopa", "taza", "techo", "tejado",
+    "tela", "tigre", "tinta", "tomate", "toro", "torre", "trebol", "trigo",
+    "trueno", "tubo", "tulipan", "uva", "vaca", "valle", "vapor", "vaso",
+    "vela", "venado", "verano", "vidrio", "viento", "vino", "violin", "volcan",
+    "yate", "yema", "yeso", "yunque", "zafiro", "zapato", "zorro", "zumo",
+];
+
+/// A fresh random phrase, words separated by single spaces.
+fn generate_phrase() -> String {
+    let mut bytes = [0u8; RECOVERY_PHRASE_WORDS];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|&b| WORDS[b as usize]).collect::<Vec<_>>().join(" ")
+}
+
+/// Case and spacing don't matter when the phrase is typed back.
+fn normalize_phrase(phrase: &str) -> String {
+    phrase.split_whitespace().map(str::to_lowercase).collect::<Vec<_>>().join(" ")
+}
+
+impl Database {
+    /// Give the account a new recovery phrase, replacing any earlier one.
+    /// Only its hash is stored, so the returned phrase must be shown now.
+    pub fn create_recovery_phrase(&self, uid: &str) -> Result<String> {
+        let phrase = generate_phrase();
+        let hash = self.hash_password(&phrase)?;
+        self.conn.execute(
+            "UPDATE users SET recovery_hash = ?1 WHERE uid = ?2",
+            params![hash, uid],
+        )?;
+        Ok(phrase)
+    }
+
+    /// Set a new password for `username` when `phrase` matches its recovery
+    /// phrase. Wrong phrases count towards the login lockout, and sessions
+    /// remembered with the old password are dropped.
+    pub fn reset_password_with_phrase(&self, username: &str, phrase: &str, new_pass: &str) -> Result<()> {
+        if let Some(wait) = self.login_cooldown(username)? {
+            return Err(cooldown_error(wait));
+        }
+        let row = self.conn.query_row(
+            "SELECT uid, recovery_hash FROM users WHERE username IN (?1, lower(?1))
+             ORDER BY username = ?1 DESC LIMIT 1",
+            params![username],
+            |r| Ok((r.get::<_, String>(0)?, r.get::<_, Option<String>>(1)?)),
+        );
+        let (uid, hash) = match row {
+            Ok(v) => v,
+            Err(rusqlite::Error::QueryReturnedNoRows) => return Err(anyhow!("Usuario no encontrado")),
+            Err(e) => return Err(e.into()),
+        };
+        let hash = hash.ok_or_else(|| anyhow!("Esta cuenta no tiene frase de recuperación"))?;
+
+        let parsed = PasswordHash::new(&hash).map_err(|e| anyhow!("Hash inválido: {}", e))?;
+        if self.argon2()?.verify_password(normalize_phrase(phrase).as_bytes(), &parsed).is_err() {
+            self.record_login_failure(username)?;
+            return Err(match self.login_cooldown(username)? {
+                Some(wait) => cooldown_error(wait),
+                None => anyhow!("La frase de recuperación no coincide"),
+            });
+        }
+
+        self.update_password(&uid, new_pass)?;
+        self.conn.execute("DELETE FROM sessions WHERE uid = ?1", params![uid])?;
+        self.clear_login_failures(username)?;
+        Ok(())
+    }
+}
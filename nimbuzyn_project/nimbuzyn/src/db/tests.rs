@@ -0,0 +1,1409 @@
+use super::*;
+
+fn mem_db() -> Database {
+    Database::open(":memory:").expect("in-memory database")
+}
+
+/// Path for a database file in a fresh temporary directory.
+fn temp_db_path(name: &str) -> String {
+    let dir = std::env::temp_dir().join(format!("nimbuzyn-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.join(name).to_string_lossy().into_owned()
+}
+
+fn new_user(db: &Database, username: &str) -> User {
+    db.register_user(username, username, "secreto123").expect("register")
+}
+
+fn product(owner_uid: &str, code: &str, name: &str, quantity: f64, net_value: f64, sale_value: f64) -> Product {
+    let now = chrono::Utc::now().to_rfc3339();
+    Product {
+        id: 0,
+        owner_uid: owner_uid.to_string(),
+        code: code.to_string(),
+        barcode: None,
+        name: name.to_string(),
+        quantity,
+        net_value,
+        sale_value,
+        profit_value: sale_value - net_value,
+        category: None,
+        reorder_point: None,
+        alert_pct: 0.0,
+        low_stock_threshold: DEFAULT_LOW_STOCK_THRESHOLD,
+        created_at: now.clone(),
+        updated_at: now,
+    }
+}
+
+// ── Transactions ────────────────────────────────
+
+#[test]
+fn failed_transaction_commits_nothing() {
+    let db = mem_db();
+    let owner = new_user(&db, "ana");
+    let result: Result<()> = db.transaction(|tx| {
+        tx.execute(
+            "UPDATE users SET display_name = 'Cambiado' WHERE uid = ?1",
+            params![owner.uid],
+        )?;
+        Err(anyhow!("falla a mitad"))
+    });
+    assert!(result.is_err());
+    assert_eq!(db.find_user_by_uid(&owner.uid).unwrap().display_name, "ana");
+}
+
+#[test]
+fn nested_transaction_joins_the_open_one() {
+    let db = mem_db();
+    let owner = new_user(&db, "ana");
+    let result: Result<()> = db.transaction(|_| {
+        db.upsert_product(&product(&owner.uid, "A1", "Arroz", 3.0, 1.0, 2.0))?;
+        Err(anyhow!("la externa falla"))
+    });
+    assert!(result.is_err());
+    assert!(db.get_products(&owner.uid).unwrap().is_empty());
+}
+
+#[test]
+fn csv_import_upserts_inside_its_transaction() {
+    let db = mem_db();
+    let owner = new_user(&db, "ana");
+    let report = db
+        .import_products_csv(&owner.uid, "codigo,nombre,cantidad,costo,precio\nA1,Arroz,3,1,2\nB2,Frijol,5,2,4\n")
+        .unwrap();
+    assert_eq!(report.errors, Vec::<String>::new());
+    assert_eq!((report.inserted, report.skipped), (2, 0));
+    assert_eq!(db.get_products(&owner.uid).unwrap().len(), 2);
+}
+
+// ── Migrations ──────────────────────────────────
+
+fn columns_by_table(db: &Database) -> Vec<(String, String)> {
+    db.conn
+        .prepare(
+            "SELECT m.name, p.name FROM sqlite_master m, pragma_table_info(m.name) p
+             WHERE m.type = 'table' AND m.name NOT LIKE 'sqlite_%' ORDER BY m.name, p.name",
+        )
+        .unwrap()
+        .query_map([], |r| Ok((r.get(0)?, r.get(1)?)))
+        .unwrap()
+        .collect::<std::result::Result<_, _>>()
+        .unwrap()
+}
+
+/// Schema of a database stamped v3, frozen as that build left it. Kept as
+/// SQL rather than replayed from `MIGRATIONS` so edits to old steps show up.
+const V3_SCHEMA: &str = "
+    CREATE TABLE users (
+        id            INTEGER PRIMARY KEY AUTOINCREMENT,
+        uid           TEXT    NOT NULL UNIQUE,
+        username      TEXT    NOT NULL UNIQUE,
+        display_name  TEXT    NOT NULL,
+        password_hash TEXT    NOT NULL,
+        avatar_color  INTEGER NOT NULL DEFAULT 0,
+        theme         TEXT    NOT NULL DEFAULT 'dark',
+        notifications INTEGER NOT NULL DEFAULT 1,
+        font_size     REAL    NOT NULL DEFAULT 14.0,
+        created_at    TEXT    NOT NULL,
+        last_chat_tab TEXT    NOT NULL DEFAULT 'friend',
+        list_density  TEXT    NOT NULL DEFAULT 'cozy'
+    );
+    CREATE TABLE contacts (
+        id           INTEGER PRIMARY KEY AUTOINCREMENT,
+        owner_uid    TEXT    NOT NULL,
+        contact_uid  TEXT    NOT NULL,
+        display_name TEXT    NOT NULL,
+        avatar_color INTEGER NOT NULL DEFAULT 0,
+        contact_type TEXT    NOT NULL DEFAULT 'acquaintance',
+        starred      INTEGER NOT NULL DEFAULT 0,
+        added_at     TEXT    NOT NULL,
+        pinned       INTEGER NOT NULL DEFAULT 0,
+        note         TEXT,
+        UNIQUE(owner_uid, contact_uid)
+    );
+    CREATE TABLE chats (
+        id            INTEGER PRIMARY KEY AUTOINCREMENT,
+        participant_a TEXT NOT NULL,
+        participant_b TEXT NOT NULL,
+        created_at    TEXT NOT NULL,
+        last_message  TEXT,
+        last_msg_at   TEXT,
+        unread_count  INTEGER NOT NULL DEFAULT 0,
+        UNIQUE(participant_a, participant_b)
+    );
+    CREATE TABLE messages (
+        id         INTEGER PRIMARY KEY AUTOINCREMENT,
+        chat_id    INTEGER NOT NULL REFERENCES chats(id) ON DELETE CASCADE,
+        sender_uid TEXT    NOT NULL,
+        content    TEXT    NOT NULL,
+        msg_type   TEXT    NOT NULL DEFAULT 'text',
+        file_name  TEXT,
+        file_size  INTEGER,
+        sent_at    TEXT    NOT NULL,
+        is_read    INTEGER NOT NULL DEFAULT 0,
+        edited_at  TEXT
+    );
+    CREATE TABLE products (
+        id           INTEGER PRIMARY KEY AUTOINCREMENT,
+        owner_uid    TEXT NOT NULL,
+        code         TEXT NOT NULL,
+        name         TEXT NOT NULL,
+        quantity     REAL NOT NULL DEFAULT 0.0,
+        net_value    REAL NOT NULL DEFAULT 0.0,
+        sale_value   REAL NOT NULL DEFAULT 0.0,
+        profit_value REAL NOT NULL DEFAULT 0.0,
+        created_at   TEXT NOT NULL,
+        updated_at   TEXT NOT NULL,
+        category     TEXT,
+        UNIQUE(owner_uid, code)
+    );
+    CREATE TABLE message_edits (
+        id          INTEGER PRIMARY KEY AUTOINCREMENT,
+        message_id  INTEGER NOT NULL REFERENCES messages(id) ON DELETE CASCADE,
+        old_content TEXT    NOT NULL,
+        edited_at   TEXT    NOT NULL
+    );
+    CREATE TABLE reactions (
+        id         INTEGER PRIMARY KEY AUTOINCREMENT,
+        message_id INTEGER NOT NULL REFERENCES messages(id) ON DELETE CASCADE,
+        user_uid   TEXT    NOT NULL,
+        emoji      TEXT    NOT NULL,
+        created_at TEXT    NOT NULL,
+        UNIQUE(message_id, user_uid, emoji)
+    );
+    CREATE TABLE stock_movements (
+        id             INTEGER PRIMARY KEY AUTOINCREMENT,
+        product_id     INTEGER NOT NULL,
+        delta          REAL    NOT NULL DEFAULT 0,
+        old_sale_value REAL,
+        new_sale_value REAL,
+        note           TEXT,
+        created_at     TEXT    NOT NULL
+    );
+    CREATE TABLE drafts (
+        owner_uid   TEXT NOT NULL,
+        contact_uid TEXT NOT NULL,
+        content     TEXT NOT NULL,
+        updated_at  TEXT NOT NULL,
+        PRIMARY KEY (owner_uid, contact_uid)
+    );
+    CREATE TABLE app_meta (
+        key   TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+    CREATE TABLE blocks (
+        owner_uid   TEXT NOT NULL,
+        blocked_uid TEXT NOT NULL,
+        blocked_at  TEXT NOT NULL,
+        PRIMARY KEY (owner_uid, blocked_uid)
+    );
+    CREATE TABLE valuations (
+        id          INTEGER PRIMARY KEY AUTOINCREMENT,
+        owner_uid   TEXT NOT NULL,
+        taken_at    TEXT NOT NULL,
+        total_value REAL NOT NULL,
+        data        TEXT NOT NULL
+    );
+    CREATE TABLE login_attempts (
+        username     TEXT PRIMARY KEY,
+        failures     INTEGER NOT NULL DEFAULT 0,
+        last_attempt INTEGER NOT NULL
+    );
+    CREATE TABLE sessions (
+        token      TEXT PRIMARY KEY,
+        uid        TEXT NOT NULL,
+        expires_at TEXT NOT NULL
+    );
+    CREATE INDEX idx_messages_chat_id ON messages(chat_id);
+    CREATE INDEX idx_messages_sent_at ON messages(sent_at);
+    CREATE INDEX idx_products_owner   ON products(owner_uid);
+    CREATE INDEX idx_contacts_owner   ON contacts(owner_uid);
+    CREATE INDEX idx_message_edits    ON message_edits(message_id);
+    CREATE INDEX idx_reactions_msg    ON reactions(message_id);
+    CREATE INDEX idx_movements_prod   ON stock_movements(product_id);
+    PRAGMA user_version = 3;
+";
+
+#[test]
+fn v3_database_upgrades_to_the_current_schema() {
+    let old = Database { conn: Connection::open_in_memory().unwrap(), pepper: None };
+    old.conn.execute_batch(V3_SCHEMA).unwrap();
+    let now = chrono::Utc::now().to_rfc3339();
+    old.conn
+        .execute_batch(&format!(
+            "INSERT INTO users (uid, username, display_name, password_hash, created_at)
+                 VALUES ('uid-ana', 'ana', 'Ana', 'x', '{now}'), ('uid-beto', 'beto', 'Beto', 'x', '{now}');
+             INSERT INTO chats (participant_a, participant_b, created_at) VALUES ('uid-ana', 'uid-beto', '{now}');
+             INSERT INTO messages (chat_id, sender_uid, content, sent_at) VALUES (1, 'uid-ana', 'hola', '{now}');"
+        ))
+        .unwrap();
+    assert_eq!(old.schema_version().unwrap(), 3);
+    assert!(columns_by_table(&old).len() < columns_by_table(&mem_db()).len());
+
+    old.run_migrations().unwrap();
+    assert_eq!(old.schema_version().unwrap(), SCHEMA_VERSION);
+    assert_eq!(columns_by_table(&old), columns_by_table(&mem_db()));
+    let messages = old.get_messages(1, 10, 0).unwrap();
+    assert_eq!(messages.len(), 1);
+    assert!(!messages[0].deleted);
+    assert_eq!(old.get_settings("uid-ana").unwrap().currency, "USD");
+}
+
+#[test]
+fn migrated_database_keeps_its_accounts() {
+    let old_path = temp_db_path("viejo.db");
+    let old = Database::open(&old_path).unwrap();
+    new_user(&old, "ana");
+    new_user(&old, "beto");
+    drop(old);
+
+    let new_path = temp_db_path("nuevo.db");
+    Database::migrate_from(&old_path, &new_path).unwrap();
+    assert_eq!(Database::open(&new_path).unwrap().user_count().unwrap(), 2);
+    // Never overwrites an existing database
+    assert!(Database::migrate_from(&old_path, &new_path).is_err());
+}
+
+#[test]
+fn fresh_database_reports_the_latest_schema_version() {
+    let db = mem_db();
+    assert_eq!(db.schema_version().unwrap(), MIGRATIONS.len() as i64);
+    assert_eq!(db.schema_version().unwrap(), SCHEMA_VERSION);
+}
+
+fn add_half_done_column(db: &Database) -> Result<()> {
+    db.conn.execute_batch("ALTER TABLE users ADD COLUMN half_done INTEGER;")?;
+    Ok(())
+}
+
+fn add_half_done_column_then_fail(db: &Database) -> Result<()> {
+    add_half_done_column(db)?;
+    Err(anyhow!("falla a medias"))
+}
+
+#[test]
+fn failed_migration_step_rolls_back_and_is_retried() {
+    let db = mem_db();
+    let mut steps = MIGRATIONS.to_vec();
+    steps.push(add_half_done_column_then_fail);
+    assert!(db.apply_migrations(&steps).is_err());
+    assert_eq!(db.schema_version().unwrap(), SCHEMA_VERSION);
+    let fks: i64 = db.conn.query_row("PRAGMA foreign_keys", [], |r| r.get(0)).unwrap();
+    assert_eq!(fks, 1);
+
+    // The column from the failed attempt is gone, so the step can run again
+    *steps.last_mut().unwrap() = add_half_done_column;
+    db.apply_migrations(&steps).unwrap();
+    assert_eq!(db.schema_version().unwrap(), SCHEMA_VERSION + 1);
+}
+
+#[test]
+fn backup_time_is_recorded() {
+    let db = Database::open(&temp_db_path("origen.db")).unwrap();
+    new_user(&db, "ana");
+    assert_eq!(db.last_backup_at().unwrap(), None);
+    db.backup_to(&temp_db_path("copia.db")).unwrap();
+    assert!(db.last_backup_at().unwrap().is_some());
+}
+
+// ── Password pepper ─────────────────────────────
+
+const PEPPER: &[u8] = b"pimienta-de-prueba";
+
+fn stored_hash(db: &Database, uid: &str) -> String {
+    db.conn
+        .query_row("SELECT password_hash FROM users WHERE uid = ?1", params![uid], |r| r.get(0))
+        .unwrap()
+}
+
+#[test]
+fn peppered_login_verifies_peppered_hash() {
+    let db = Database::open_with_pepper(":memory:", Some(PEPPER)).unwrap();
+    let user = new_user(&db, "ana");
+    let hash = stored_hash(&db, &user.uid);
+    assert_eq!(db.login("ana", "secreto123").unwrap().uid, user.uid);
+    assert!(db.login("ana", "otra-clave").is_err());
+    assert_eq!(stored_hash(&db, &user.uid), hash);
+}
+
+#[test]
+fn pre_pepper_hash_still_logs_in_and_is_rehashed() {
+    let plain = Database::open_with_pepper(":memory:", None).unwrap();
+    let old_hash = plain.hash_password("secreto123").unwrap();
+
+    let db = Database::open_with_pepper(":memory:", Some(PEPPER)).unwrap();
+    let user = db.register_user_with_hash("ana", "Ana", &old_hash).unwrap();
+    assert!(db.login("ana", "otra-clave").is_err());
+    assert_eq!(stored_hash(&db, &user.uid), old_hash);
+
+    assert_eq!(db.login("ana", "secreto123").unwrap().uid, user.uid);
+    let new_hash = stored_hash(&db, &user.uid);
+    assert_ne!(new_hash, old_hash);
+    let parsed = PasswordHash::new(&new_hash).unwrap();
+    assert!(Argon2::default().verify_password(b"secreto123", &parsed).is_err());
+    assert!(db.argon2().unwrap().verify_password(b"secreto123", &parsed).is_ok());
+    assert_eq!(db.login("ana", "secreto123").unwrap().uid, user.uid);
+}
+
+#[test]
+fn peppered_hash_needs_the_pepper_to_verify() {
+    let path = temp_db_path("pepper.db");
+    let db = Database::open_with_pepper(&path, Some(PEPPER)).unwrap();
+    new_user(&db, "ana");
+    drop(db);
+
+    let without = Database::open(&path).unwrap();
+    assert!(without.login("ana", "secreto123").is_err());
+    let wrong = Database::open_with_pepper(&path, Some(b"otra-pimienta")).unwrap();
+    assert!(wrong.login("ana", "secreto123").is_err());
+    let with = Database::open_with_pepper(&path, Some(PEPPER)).unwrap();
+    assert!(with.login("ana", "secreto123").is_ok());
+}
+
+// ── Messages ────────────────────────────────────
+
+fn text(db: &Database, chat_id: i64, sender_uid: &str, content: &str) -> Message {
+    db.send_message(chat_id, sender_uid, content, "text", None, None, None).expect("send")
+}
+
+#[test]
+fn search_all_messages_tags_hits_with_the_other_participant() {
+    let db = mem_db();
+    let ana = new_user(&db, "ana");
+    let beto = new_user(&db, "beto");
+    db.add_contact(&ana.uid, &beto.uid, "Beto del mercado", beto.avatar_color, "friend").unwrap();
+    let chat = db.get_or_create_chat(&ana.uid, &beto.uid).unwrap();
+    let sent = text(&db, chat.id, &beto.uid, "¿Llegó el pedido de arroz?");
+    text(&db, chat.id, &ana.uid, "Todavía no");
+
+    let hits = db.search_all_messages(&ana.uid, "arroz", 10).unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].message.id, sent.id);
+    assert_eq!(hits[0].message.reply_to, None);
+    assert_eq!(hits[0].contact_uid, beto.uid);
+    assert_eq!(hits[0].contact_name, "Beto del mercado");
+
+    // Without a contact entry the other participant's uid stands in for the name.
+    let hits = db.search_all_messages(&beto.uid, "todavía", 10).unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!((hits[0].contact_uid.as_str(), hits[0].contact_name.as_str()), (ana.uid.as_str(), ana.uid.as_str()));
+}
+
+#[test]
+fn deleted_message_leaves_a_tombstone() {
+    let db = mem_db();
+    let ana = new_user(&db, "ana");
+    let beto = new_user(&db, "beto");
+    let chat = db.get_or_create_chat(&ana.uid, &beto.uid).unwrap();
+    let msg = text(&db, chat.id, &ana.uid, "secreto");
+
+    assert!(db.delete_message(msg.id, &beto.uid).is_err());
+    db.delete_message(msg.id, &ana.uid).unwrap();
+    let tombstone = db.get_message(msg.id).unwrap().unwrap();
+    assert!(tombstone.deleted);
+    assert_eq!(tombstone.content, "");
+    assert!(db.search_all_messages(&ana.uid, "secreto", 10).unwrap().is_empty());
+}
+
+#[test]
+fn get_or_create_chat_returns_the_same_chat_every_time() {
+    let db = mem_db();
+    let ana = new_user(&db, "ana");
+    let beto = new_user(&db, "beto");
+    let first = db.get_or_create_chat(&ana.uid, &beto.uid).unwrap();
+    let again = db.get_or_create_chat(&ana.uid, &beto.uid).unwrap();
+    let reversed = db.get_or_create_chat(&beto.uid, &ana.uid).unwrap();
+    assert_eq!((again.id, reversed.id), (first.id, first.id));
+    let chats: i64 = db.conn.query_row("SELECT COUNT(*) FROM chats", [], |r| r.get(0)).unwrap();
+    assert_eq!(chats, 1);
+}
+
+#[test]
+fn every_edit_adds_a_history_entry_in_order() {
+    let db = mem_db();
+    let ana = new_user(&db, "ana");
+    let beto = new_user(&db, "beto");
+    let chat = db.get_or_create_chat(&ana.uid, &beto.uid).unwrap();
+    let msg = text(&db, chat.id, &ana.uid, "hola");
+    db.edit_message(msg.id, &ana.uid, "hola!").unwrap();
+    db.edit_message(msg.id, &ana.uid, "hola!!").unwrap();
+    let history: Vec<String> = db.message_edit_history(msg.id).unwrap().into_iter().map(|(old, _)| old).collect();
+    assert_eq!(history, ["hola", "hola!"]);
+
+    for i in 0..MAX_EDITS_PER_MESSAGE {
+        db.edit_message(msg.id, &ana.uid, &format!("versión {}", i)).unwrap();
+    }
+    let history = db.message_edit_history(msg.id).unwrap();
+    assert_eq!(history.len(), MAX_EDITS_PER_MESSAGE);
+    assert_eq!(history.last().unwrap().0, format!("versión {}", MAX_EDITS_PER_MESSAGE - 2));
+}
+
+#[test]
+fn media_list_leaves_out_text_and_deleted_messages() {
+    let db = mem_db();
+    let ana = new_user(&db, "ana");
+    let beto = new_user(&db, "beto");
+    let chat = db.get_or_create_chat(&ana.uid, &beto.uid).unwrap();
+    text(&db, chat.id, &ana.uid, "mirá esto");
+    let foto = db.send_message(chat.id, &ana.uid, "/tmp/foto.jpg", "image", Some("foto.jpg"), Some(2048), None).unwrap();
+    let pdf = db.send_message(chat.id, &beto.uid, "/tmp/lista.pdf", "document", Some("lista.pdf"), Some(512), None).unwrap();
+    let zip = db.send_message(chat.id, &beto.uid, "/tmp/fotos.zip", "archive", Some("fotos.zip"), Some(4096), None).unwrap();
+    text(&db, chat.id, &beto.uid, "gracias");
+    db.delete_message(zip.id, &beto.uid).unwrap();
+
+    let mut ids: Vec<i64> = db.get_media_messages(chat.id).unwrap().iter().map(|m| m.id).collect();
+    ids.sort();
+    assert_eq!(ids, [foto.id, pdf.id]);
+}
+
+#[test]
+fn all_chats_search_spans_chats_while_the_scoped_one_stays_put() {
+    let db = mem_db();
+    let ana = new_user(&db, "ana");
+    let beto = new_user(&db, "beto");
+    let caro = new_user(&db, "caro");
+    let with_beto = db.get_or_create_chat(&ana.uid, &beto.uid).unwrap();
+    let with_caro = db.get_or_create_chat(&ana.uid, &caro.uid).unwrap();
+    let other = db.get_or_create_chat(&beto.uid, &caro.uid).unwrap();
+    text(&db, with_beto.id, &beto.uid, "precio del arroz");
+    text(&db, with_caro.id, &ana.uid, "¿tenés arroz?");
+    text(&db, other.id, &caro.uid, "arroz para beto");
+
+    let scoped = db.search_messages(with_beto.id, "arroz", 10).unwrap();
+    assert_eq!(scoped.len(), 1);
+    assert_eq!(scoped[0].chat_id, with_beto.id);
+    let mut chats: Vec<i64> = db
+        .search_all_messages(&ana.uid, "arroz", 10)
+        .unwrap()
+        .iter()
+        .map(|hit| hit.message.chat_id)
+        .collect();
+    chats.sort();
+    assert_eq!(chats, [with_beto.id, with_caro.id]);
+}
+
+#[test]
+fn deleting_a_chat_cascades_to_its_messages() {
+    let db = mem_db();
+    let ana = new_user(&db, "ana");
+    let beto = new_user(&db, "beto");
+    let caro = new_user(&db, "caro");
+    let chat = db.get_or_create_chat(&ana.uid, &beto.uid).unwrap();
+    let kept = db.get_or_create_chat(&ana.uid, &caro.uid).unwrap();
+    let msg = text(&db, chat.id, &ana.uid, "hola");
+    db.edit_message(msg.id, &ana.uid, "hola!").unwrap();
+    db.toggle_reaction(msg.id, &beto.uid, "👍").unwrap();
+    text(&db, kept.id, &ana.uid, "sigo acá");
+
+    db.delete_chat(chat.id).unwrap();
+    let count = |table: &str| -> i64 {
+        db.conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |r| r.get(0)).unwrap()
+    };
+    assert_eq!((count("messages"), count("message_edits"), count("reactions")), (1, 0, 0));
+    assert_eq!(db.get_messages(kept.id, 10, 0).unwrap().len(), 1);
+}
+
+#[test]
+fn batch_insert_stores_every_message_and_the_last_preview() {
+    let db = mem_db();
+    let ana = new_user(&db, "ana");
+    let beto = new_user(&db, "beto");
+    let chat = db.get_or_create_chat(&ana.uid, &beto.uid).unwrap();
+    let msgs: Vec<NewMessage> = (0..1000)
+        .map(|i| NewMessage {
+            chat_id: chat.id,
+            sender_uid: if i % 2 == 0 { ana.uid.clone() } else { beto.uid.clone() },
+            content: format!("mensaje {}", i),
+            msg_type: "text".into(),
+            file_name: None,
+            file_size: None,
+        })
+        .collect();
+
+    let ids = db.send_messages_batch(&msgs).unwrap();
+    assert_eq!(ids.len(), 1000);
+    assert!(ids.windows(2).all(|w| w[0] < w[1]));
+    let stored: i64 = db.conn.query_row("SELECT COUNT(*) FROM messages WHERE chat_id = ?1", [chat.id], |r| r.get(0)).unwrap();
+    assert_eq!(stored, 1000);
+    let chat = db.get_or_create_chat(&ana.uid, &beto.uid).unwrap();
+    assert_eq!(chat.last_message.as_deref(), Some("mensaje 999"));
+    assert_eq!(db.get_message(ids[999]).unwrap().unwrap().sender_uid, beto.uid);
+}
+
+#[test]
+fn read_receipts_off_clears_unread_without_notifying() {
+    let db = mem_db();
+    let ana = new_user(&db, "ana");
+    let beto = new_user(&db, "beto");
+    let caro = new_user(&db, "caro");
+    let with_ana = db.get_or_create_chat(&ana.uid, &beto.uid).unwrap();
+    let with_caro = db.get_or_create_chat(&caro.uid, &beto.uid).unwrap();
+    let from_ana = text(&db, with_ana.id, &ana.uid, "hola");
+    let from_caro = text(&db, with_caro.id, &caro.uid, "hola");
+    let notified = |id: i64| -> bool {
+        db.conn.query_row("SELECT notify_read FROM messages WHERE id = ?1", [id], |r| r.get(0)).unwrap()
+    };
+
+    assert_eq!(db.mark_chat_read(with_ana.id, &beto.uid, false).unwrap(), 1);
+    assert!(db.get_message(from_ana.id).unwrap().unwrap().is_read);
+    assert!(!notified(from_ana.id));
+    assert_eq!(db.mark_all_read(&beto.uid, true).unwrap(), 1);
+    assert!(notified(from_caro.id));
+    assert_eq!(db.total_unread(&beto.uid).unwrap(), 0);
+}
+
+// ── Sessions ────────────────────────────────────
+
+#[test]
+fn session_is_remembered_until_cleared() {
+    let db = mem_db();
+    let ana = new_user(&db, "ana");
+    assert!(db.validate_session().unwrap().is_none());
+    db.create_session(&ana.uid).unwrap();
+    assert_eq!(db.validate_session().unwrap().unwrap().uid, ana.uid);
+    db.clear_session().unwrap();
+    assert!(db.validate_session().unwrap().is_none());
+}
+
+#[test]
+fn expired_session_is_dropped() {
+    let db = mem_db();
+    let ana = new_user(&db, "ana");
+    db.create_session(&ana.uid).unwrap();
+    db.conn.execute("UPDATE sessions SET expires_at = '2000-01-01T00:00:00Z'", []).unwrap();
+    assert!(db.validate_session().unwrap().is_none());
+    let left: i64 = db.conn.query_row("SELECT COUNT(*) FROM sessions", [], |r| r.get(0)).unwrap();
+    assert_eq!(left, 0);
+}
+
+#[test]
+fn password_change_ends_the_session() {
+    let db = mem_db();
+    let ana = new_user(&db, "ana");
+    db.create_session(&ana.uid).unwrap();
+    db.update_password(&ana.uid, "nueva-clave-1").unwrap();
+    assert!(db.validate_session().unwrap().is_none());
+    assert!(db.login("ana", "secreto123").is_err());
+    assert_eq!(db.login("ana", "nueva-clave-1").unwrap().uid, ana.uid);
+}
+
+#[test]
+fn imported_argon2_hash_logs_in_with_the_original_password() {
+    let db = mem_db();
+    // As exported by another system: plain Argon2, no pepper
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default().hash_password(b"clave-vieja", &salt).unwrap().to_string();
+    db.register_user_with_hash("ana", "Ana", &hash).unwrap();
+    assert!(db.login("ana", "otra-clave").is_err());
+    assert_eq!(db.login("ana", "clave-vieja").unwrap().username, "ana");
+
+    for garbage in ["", "no-es-un-hash", "$argon2id$v=19$m=19456,t=2,p=1", "$2b$12$abcdefghijklmnopqrstuv"] {
+        assert!(db.register_user_with_hash("beto", "Beto", garbage).is_err(), "{:?}", garbage);
+    }
+    assert_eq!(db.user_count().unwrap(), 1);
+}
+
+#[test]
+fn imported_hash_usernames_are_normalized() {
+    let db = mem_db();
+    let hash = db.hash_password("secreto123").unwrap();
+    let user = db.register_user_with_hash(" Ana ", "Ana", &hash).unwrap();
+    assert_eq!(user.username, "ana");
+    assert!(db.register_user_with_hash("admin", "Admin", &hash).is_err());
+    assert!(db.register_user_with_hash("ANA", "Otra", &hash).is_err());
+}
+
+// ── Stock movements ─────────────────────────────
+
+fn quantity_of(db: &Database, id: i64) -> f64 {
+    db.conn.query_row("SELECT quantity FROM products WHERE id = ?1", params![id], |r| r.get(0)).unwrap()
+}
+
+#[test]
+fn reverting_twice_walks_back_instead_of_redoing() {
+    let db = mem_db();
+    let owner = new_user(&db, "ana");
+    let id = db.upsert_product(&product(&owner.uid, "A1", "Arroz", 10.0, 1.0, 2.0)).unwrap();
+    db.conn.execute("DELETE FROM stock_movements", []).unwrap();
+    db.adjust_quantity(id, 2.0).unwrap();
+    db.adjust_quantity(id, 5.0).unwrap();
+    assert_eq!(quantity_of(&db, id), 17.0);
+
+    db.revert_last_movement(id).unwrap();
+    assert_eq!(quantity_of(&db, id), 12.0);
+    db.revert_last_movement(id).unwrap();
+    assert_eq!(quantity_of(&db, id), 10.0);
+    assert!(db.revert_last_movement(id).is_err());
+    assert_eq!(quantity_of(&db, id), 10.0);
+
+    let notes: Vec<Option<String>> = db.product_movements(id, 10).unwrap().into_iter().map(|m| m.note).collect();
+    assert_eq!(notes.iter().filter(|n| n.as_deref() == Some("reversión")).count(), 2);
+}
+
+#[test]
+fn only_the_sender_can_edit_a_message() {
+    let db = mem_db();
+    let ana = new_user(&db, "ana");
+    let beto = new_user(&db, "beto");
+    let chat = db.get_or_create_chat(&ana.uid, &beto.uid).unwrap();
+    let msg = text(&db, chat.id, &ana.uid, "hola");
+
+    assert!(db.edit_message(msg.id, &beto.uid, "adiós").is_err());
+    assert_eq!(db.get_message(msg.id).unwrap().unwrap().content, "hola");
+    assert!(db.message_edit_history(msg.id).unwrap().is_empty());
+
+    db.edit_message(msg.id, &ana.uid, "hola de nuevo").unwrap();
+    let edited = db.get_message(msg.id).unwrap().unwrap();
+    assert_eq!(edited.content, "hola de nuevo");
+    assert!(edited.edited_at.is_some());
+    assert_eq!(db.message_edit_history(msg.id).unwrap()[0].0, "hola");
+}
+
+#[test]
+fn price_adjustment_keeps_net_value_and_recomputes_profit() {
+    let db = mem_db();
+    let ana = new_user(&db, "ana");
+    let mut arroz = product(&ana.uid, "A1", "Arroz", 5.0, 80.0, 100.0);
+    arroz.category = Some("Almacén".into());
+    db.upsert_product(&arroz).unwrap();
+    db.upsert_product(&product(&ana.uid, "B1", "Jabón", 5.0, 30.0, 45.0)).unwrap();
+
+    assert_eq!(db.adjust_prices(&ana.uid, Some("Almacén"), 10.0).unwrap(), 1);
+    let products = db.get_products(&ana.uid).unwrap();
+    let arroz = products.iter().find(|p| p.code == "A1").unwrap();
+    assert_eq!((arroz.net_value, arroz.sale_value), (80.0, 110.0));
+    assert!((arroz.profit_value - 30.0).abs() < 1e-9);
+    let jabon = products.iter().find(|p| p.code == "B1").unwrap();
+    assert_eq!(jabon.sale_value, 45.0);
+    assert!(db.adjust_prices(&ana.uid, None, -100.0).is_err());
+}
+
+// ── Login lockout ───────────────────────────────
+
+#[test]
+fn lockout_counts_failures_per_account_whatever_the_case() {
+    let db = mem_db();
+    new_user(&db, "ana");
+    for (i, typed) in ["ana", "ANA", "Ana", " ana", "aNa"].iter().enumerate() {
+        assert!(db.login(typed, "mala").is_err(), "intento {}", i);
+    }
+    assert!(db.login_cooldown("ana").unwrap().is_some());
+    assert!(db.login("ANA", "secreto123").is_err());
+    let rows: i64 = db.conn.query_row("SELECT COUNT(*) FROM login_attempts", [], |r| r.get(0)).unwrap();
+    assert_eq!(rows, 1);
+}
+
+#[test]
+fn stale_login_attempts_are_pruned() {
+    let db = mem_db();
+    let old = chrono::Utc::now().timestamp() - 2 * MAX_LOGIN_COOLDOWN_SECS;
+    db.conn
+        .execute(
+            "INSERT INTO login_attempts (username, failures, last_attempt) VALUES ('inventado', 9, ?1)",
+            params![old],
+        )
+        .unwrap();
+    assert!(db.login("otro", "mala").is_err());
+    let names: Vec<String> = db
+        .conn
+        .prepare("SELECT username FROM login_attempts")
+        .unwrap()
+        .query_map([], |r| r.get(0))
+        .unwrap()
+        .collect::<std::result::Result<_, _>>()
+        .unwrap();
+    assert_eq!(names, vec!["otro".to_string()]);
+}
+
+#[test]
+fn successful_login_clears_the_failures() {
+    let db = mem_db();
+    new_user(&db, "ana");
+    assert!(db.login("Ana", "mala").is_err());
+    db.login("ana", "secreto123").unwrap();
+    let rows: i64 = db.conn.query_row("SELECT COUNT(*) FROM login_attempts", [], |r| r.get(0)).unwrap();
+    assert_eq!(rows, 0);
+}
+
+// ── Product codes ───────────────────────────────
+
+/// Schema of a database stamped v10, frozen like [`V3_SCHEMA`], for the
+/// steps after it.
+const V10_SCHEMA: &str = "
+    CREATE TABLE users (
+        id            INTEGER PRIMARY KEY AUTOINCREMENT,
+        uid           TEXT    NOT NULL UNIQUE,
+        username      TEXT    NOT NULL UNIQUE,
+        display_name  TEXT    NOT NULL,
+        password_hash TEXT    NOT NULL,
+        avatar_color  INTEGER NOT NULL DEFAULT 0,
+        theme         TEXT    NOT NULL DEFAULT 'dark',
+        notifications INTEGER NOT NULL DEFAULT 1,
+        font_size     REAL    NOT NULL DEFAULT 14.0,
+        created_at    TEXT    NOT NULL,
+        last_chat_tab TEXT    NOT NULL DEFAULT 'friend',
+        list_density  TEXT    NOT NULL DEFAULT 'cozy',
+        read_receipts INTEGER NOT NULL DEFAULT 1,
+        confirm_acquaintance_send INTEGER NOT NULL DEFAULT 0,
+        group_window_mins INTEGER NOT NULL DEFAULT 2,
+        text_autoformat INTEGER NOT NULL DEFAULT 0,
+        recovery_hash TEXT,
+        accent_color  INTEGER,
+        currency      TEXT    NOT NULL DEFAULT 'USD'
+    );
+    CREATE TABLE contacts (
+        id           INTEGER PRIMARY KEY AUTOINCREMENT,
+        owner_uid    TEXT    NOT NULL,
+        contact_uid  TEXT    NOT NULL,
+        display_name TEXT    NOT NULL,
+        avatar_color INTEGER NOT NULL DEFAULT 0,
+        contact_type TEXT    NOT NULL DEFAULT 'acquaintance',
+        starred      INTEGER NOT NULL DEFAULT 0,
+        added_at     TEXT    NOT NULL,
+        pinned       INTEGER NOT NULL DEFAULT 0,
+        note         TEXT,
+        UNIQUE(owner_uid, contact_uid)
+    );
+    CREATE TABLE chats (
+        id            INTEGER PRIMARY KEY AUTOINCREMENT,
+        participant_a TEXT NOT NULL,
+        participant_b TEXT NOT NULL,
+        created_at    TEXT NOT NULL,
+        last_message  TEXT,
+        last_msg_at   TEXT,
+        unread_count  INTEGER NOT NULL DEFAULT 0,
+        UNIQUE(participant_a, participant_b)
+    );
+    CREATE TABLE messages (
+        id          INTEGER PRIMARY KEY AUTOINCREMENT,
+        chat_id     INTEGER NOT NULL REFERENCES chats(id) ON DELETE CASCADE,
+        sender_uid  TEXT    NOT NULL,
+        content     TEXT    NOT NULL,
+        msg_type    TEXT    NOT NULL DEFAULT 'text',
+        file_name   TEXT,
+        file_size   INTEGER,
+        sent_at     TEXT    NOT NULL,
+        is_read     INTEGER NOT NULL DEFAULT 0,
+        edited_at   TEXT,
+        notify_read INTEGER NOT NULL DEFAULT 0,
+        deleted     INTEGER NOT NULL DEFAULT 0,
+        reply_to    INTEGER
+    );
+    CREATE TABLE products (
+        id           INTEGER PRIMARY KEY AUTOINCREMENT,
+        owner_uid    TEXT NOT NULL,
+        code         TEXT NOT NULL,
+        name         TEXT NOT NULL,
+        quantity     REAL NOT NULL DEFAULT 0.0,
+        net_value    REAL NOT NULL DEFAULT 0.0,
+        sale_value   REAL NOT NULL DEFAULT 0.0,
+        profit_value REAL NOT NULL DEFAULT 0.0,
+        created_at   TEXT NOT NULL,
+        updated_at   TEXT NOT NULL,
+        category     TEXT,
+        reorder_point REAL,
+        alert_pct    REAL NOT NULL DEFAULT 0,
+        low_stock_threshold REAL NOT NULL DEFAULT 1,
+        barcode      TEXT,
+        UNIQUE(owner_uid, code)
+    );
+    CREATE TABLE message_edits (
+        id          INTEGER PRIMARY KEY AUTOINCREMENT,
+        message_id  INTEGER NOT NULL REFERENCES messages(id) ON DELETE CASCADE,
+        old_content TEXT    NOT NULL,
+        edited_at   TEXT    NOT NULL
+    );
+    CREATE TABLE reactions (
+        id         INTEGER PRIMARY KEY AUTOINCREMENT,
+        message_id INTEGER NOT NULL REFERENCES messages(id) ON DELETE CASCADE,
+        user_uid   TEXT    NOT NULL,
+        emoji      TEXT    NOT NULL,
+        created_at TEXT    NOT NULL,
+        UNIQUE(message_id, user_uid, emoji)
+    );
+    CREATE TABLE stock_movements (
+        id             INTEGER PRIMARY KEY AUTOINCREMENT,
+        product_id     INTEGER NOT NULL,
+        delta          REAL    NOT NULL DEFAULT 0,
+        old_sale_value REAL,
+        new_sale_value REAL,
+        note           TEXT,
+        created_at     TEXT    NOT NULL,
+        reverted       INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE TABLE drafts (
+        chat_id    INTEGER NOT NULL REFERENCES chats(id) ON DELETE CASCADE,
+        owner_uid  TEXT    NOT NULL,
+        content    TEXT    NOT NULL,
+        updated_at TEXT    NOT NULL,
+        PRIMARY KEY (chat_id, owner_uid)
+    );
+    CREATE TABLE app_meta (
+        key   TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+    CREATE TABLE blocks (
+        owner_uid   TEXT NOT NULL,
+        blocked_uid TEXT NOT NULL,
+        blocked_at  TEXT NOT NULL,
+        PRIMARY KEY (owner_uid, blocked_uid)
+    );
+    CREATE TABLE valuations (
+        id          INTEGER PRIMARY KEY AUTOINCREMENT,
+        owner_uid   TEXT NOT NULL,
+        taken_at    TEXT NOT NULL,
+        total_value REAL NOT NULL,
+        data        TEXT NOT NULL
+    );
+    CREATE TABLE login_attempts (
+        username     TEXT PRIMARY KEY,
+        failures     INTEGER NOT NULL DEFAULT 0,
+        last_attempt INTEGER NOT NULL
+    );
+    CREATE TABLE sessions (
+        token      TEXT PRIMARY KEY,
+        uid        TEXT NOT NULL,
+        expires_at TEXT NOT NULL
+    );
+    CREATE INDEX idx_messages_chat_id ON messages(chat_id);
+    CREATE INDEX idx_messages_sent_at ON messages(sent_at);
+    CREATE INDEX idx_products_owner   ON products(owner_uid);
+    CREATE INDEX idx_contacts_owner   ON contacts(owner_uid);
+    CREATE INDEX idx_message_edits    ON message_edits(message_id);
+    CREATE INDEX idx_reactions_msg    ON reactions(message_id);
+    CREATE INDEX idx_movements_prod   ON stock_movements(product_id);
+    CREATE UNIQUE INDEX idx_products_owner_barcode
+        ON products(owner_uid, barcode) WHERE barcode IS NOT NULL AND barcode != '';
+    PRAGMA user_version = 10;
+";
+
+#[test]
+fn product_code_migration_normalizes_and_dedupes() {
+    let db = Database { conn: Connection::open_in_memory().unwrap(), pepper: None };
+    db.conn.execute_batch(V10_SCHEMA).unwrap();
+    let now = chrono::Utc::now().to_rfc3339();
+    for code in ["prod-1", " PROD-1 ", "caja  grande", "B2"] {
+        db.conn
+            .execute(
+                "INSERT INTO products (owner_uid, code, name, created_at, updated_at) VALUES ('uid-ana', ?1, 'x', ?2, ?2)",
+                params![code, now],
+            )
+            .unwrap();
+    }
+
+    db.run_migrations().unwrap();
+    assert_eq!(columns_by_table(&db), columns_by_table(&mem_db()));
+    let codes: Vec<String> = db.get_products("uid-ana").unwrap().into_iter().map(|p| p.code).collect();
+    let mut sorted = codes.clone();
+    sorted.sort();
+    assert_eq!(sorted, vec!["B2", "CAJA GRANDE", "PROD-1", "PROD-1-2"]);
+    assert!(db
+        .conn
+        .execute(
+            "INSERT INTO products (owner_uid, code, name, created_at, updated_at) VALUES ('uid-ana', 'b2', 'x', ?1, ?1)",
+            params![now],
+        )
+        .is_err());
+}
+
+#[test]
+fn copy_links_migration_keeps_existing_accounts_opening_links() {
+    let db = Database { conn: Connection::open_in_memory().unwrap(), pepper: None };
+    db.conn.execute_batch(V10_SCHEMA).unwrap();
+    db.conn
+        .execute(
+            "INSERT INTO users (uid, username, display_name, password_hash, created_at)
+             VALUES ('uid-ana', 'ana', 'Ana', 'x', ?1)",
+            params![chrono::Utc::now().to_rfc3339()],
+        )
+        .unwrap();
+
+    db.run_migrations().unwrap();
+    assert!(!db.get_settings("uid-ana").unwrap().copy_links);
+    db.update_copy_links("uid-ana", true).unwrap();
+    assert!(db.get_settings("uid-ana").unwrap().copy_links);
+}
+
+#[test]
+fn product_codes_are_stored_normalized() {
+    let db = mem_db();
+    let owner = new_user(&db, "ana");
+    let id = db.upsert_product(&product(&owner.uid, " abc  1 ", "Arroz", 1.0, 1.0, 2.0)).unwrap();
+    let found = db.find_product_by_code(&owner.uid, "ABC 1").unwrap().unwrap();
+    assert_eq!((found.id, found.code.as_str()), (id, "ABC 1"));
+    assert!(db.product_code_exists(&owner.uid, "abc 1", None).unwrap());
+    assert!(!db.product_code_exists(&owner.uid, "abc 1", Some(id)).unwrap());
+    assert!(db.upsert_product(&product(&owner.uid, "Abc 1", "Otro", 1.0, 1.0, 2.0)).is_err());
+}
+
+#[test]
+fn secure_wipe_removes_the_database_and_its_siblings() {
+    let path = temp_db_path("nimbuzyn.db");
+    let db = Database::open(&path).unwrap();
+    new_user(&db, "ana");
+    // A leftover salt file from an encrypted copy goes too
+    std::fs::write(format!("{}-salt", path), b"sal").unwrap();
+    db.secure_wipe(&path).unwrap();
+    for suffix in ["", "-wal", "-shm", "-salt"] {
+        assert!(!Path::new(&format!("{}{}", path, suffix)).exists(), "quedó {}{}", path, suffix);
+    }
+}
+
+#[test]
+fn name_suggestions_are_prefix_matches_sorted_by_name() {
+    let db = mem_db();
+    let ana = new_user(&db, "ana");
+    let beto = new_user(&db, "beto");
+    for (code, name) in [("A1", "arroz largo"), ("A2", "Arroz integral"), ("A3", "Harina de arroz"), ("A4", "Arvejas"), ("A5", "50%_off")] {
+        db.upsert_product(&product(&ana.uid, code, name, 1.0, 1.0, 2.0)).unwrap();
+    }
+    db.upsert_product(&product(&beto.uid, "B1", "Arroz de Beto", 1.0, 1.0, 2.0)).unwrap();
+
+    let names = |prefix: &str, limit: usize| -> Vec<String> {
+        db.suggest_products(&ana.uid, prefix, limit).unwrap().into_iter().map(|p| p.name).collect()
+    };
+    assert_eq!(names("arr", 5), ["Arroz integral", "arroz largo"]);
+    assert_eq!(names("ar", 2), ["Arroz integral", "arroz largo"]);
+    assert_eq!(names("50%", 5), ["50%_off"]);
+    assert!(names("5_", 5).is_empty());
+    assert!(names("  ", 5).is_empty());
+}
+
+// ── Account export ──────────────────────────────
+
+/// ana (returned first) with beto as a friend, a two-message chat and one product.
+fn exportable_account(db: &Database) -> (User, User) {
+    let ana = new_user(db, "ana");
+    let beto = new_user(db, "beto");
+    db.add_contact(&ana.uid, &beto.uid, "Beto", beto.avatar_color, "friend").unwrap();
+    let chat = db.get_or_create_chat(&ana.uid, &beto.uid).unwrap();
+    text(db, chat.id, &ana.uid, "hola");
+    text(db, chat.id, &beto.uid, "qué tal");
+    db.upsert_product(&product(&ana.uid, "A1", "Arroz", 3.0, 1.0, 2.0)).unwrap();
+    (ana, beto)
+}
+
+#[test]
+fn jsonl_export_writes_one_line_per_record() {
+    let db = mem_db();
+    let (ana, _) = exportable_account(&db);
+    let mut out = Vec::new();
+    db.export_account_jsonl(&ana.uid, &mut out).unwrap();
+
+    let mut kinds: HashMap<String, usize> = HashMap::new();
+    for line in String::from_utf8(out).unwrap().lines() {
+        let value: serde_json::Value = serde_json::from_str(line).unwrap();
+        *kinds.entry(value["kind"].as_str().unwrap().to_string()).or_default() += 1;
+    }
+    let expected: HashMap<String, usize> = [("user", 1), ("contact", 1), ("chat", 1), ("message", 2), ("product", 1)]
+        .into_iter()
+        .map(|(k, n)| (k.to_string(), n))
+        .collect();
+    assert_eq!(kinds, expected);
+}
+
+#[test]
+fn json_export_groups_records_by_kind() {
+    let db = mem_db();
+    let (ana, beto) = exportable_account(&db);
+    let doc: serde_json::Value = serde_json::from_str(&db.export_account_json(&ana.uid).unwrap()).unwrap();
+    assert_eq!(doc["user"]["username"], "ana");
+    assert!(doc["user"].get("kind").is_none());
+    for (key, n) in [("contacts", 1), ("chats", 1), ("messages", 2), ("products", 1)] {
+        assert_eq!(doc[key].as_array().unwrap().len(), n, "{}", key);
+    }
+    // Nothing of beto's that ana doesn't share
+    let mut out = Vec::new();
+    db.export_account_jsonl(&beto.uid, &mut out).unwrap();
+    assert!(!String::from_utf8(out).unwrap().contains("Arroz"));
+}
+
+// ── Encryption at rest ──────────────────────────
+
+#[cfg(feature = "encryption")]
+mod encryption {
+    use super::*;
+
+    #[test]
+    fn encrypted_database_opens_only_with_its_password() {
+        let path = temp_db_path("nimbuzyn.db");
+        let db = Database::open(&path).unwrap();
+        let ana = new_user(&db, "ana");
+        drop(db);
+        assert!(!is_encrypted(&path));
+
+        let key = derive_db_key("secreto123", &database_salt(&path).unwrap()).unwrap();
+        Database::encrypt_plaintext(&path, &key).unwrap();
+        assert!(is_encrypted(&path));
+        assert!(Database::open(&path).is_err());
+        let wrong = derive_db_key("otra-clave", &database_salt(&path).unwrap()).unwrap();
+        assert!(Database::open_encrypted(&path, &wrong).is_err());
+
+        let db = Database::open_encrypted(&path, &key).unwrap();
+        assert_eq!(db.login("ana", "secreto123").unwrap().uid, ana.uid);
+        assert_eq!(db.schema_version().unwrap(), SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn encrypted_backup_carries_its_salt() {
+        let path = temp_db_path("nimbuzyn.db");
+        drop(Database::open(&path).unwrap());
+        let key = derive_db_key("secreto123", &database_salt(&path).unwrap()).unwrap();
+        Database::encrypt_plaintext(&path, &key).unwrap();
+        let db = Database::open_encrypted(&path, &key).unwrap();
+        let ana = new_user(&db, "ana");
+
+        let backup = temp_db_path("respaldo.db");
+        db.backup_to(&backup).unwrap();
+        drop(db);
+        assert!(is_encrypted(&backup));
+        assert_eq!(database_salt(&backup).unwrap(), database_salt(&path).unwrap());
+
+        // Restored onto a fresh device: the backup's salt comes along
+        let other = temp_db_path("nimbuzyn.db");
+        drop(Database::open(&other).unwrap());
+        replace_with_backup(&other, &backup).unwrap();
+        let key = derive_db_key("secreto123", &database_salt(&other).unwrap()).unwrap();
+        let restored = Database::open_encrypted(&other, &key).unwrap();
+        assert_eq!(restored.find_user_by_uid(&ana.uid).unwrap().username, "ana");
+    }
+
+    #[test]
+    fn rekey_follows_a_password_change() {
+        let path = temp_db_path("nimbuzyn.db");
+        drop(Database::open(&path).unwrap());
+        let salt = database_salt(&path).unwrap();
+        let key = derive_db_key("secreto123", &salt).unwrap();
+        Database::encrypt_plaintext(&path, &key).unwrap();
+        let db = Database::open_encrypted(&path, &key).unwrap();
+        let new_key = derive_db_key("nueva-clave-1", &salt).unwrap();
+        db.rekey(&new_key).unwrap();
+        drop(db);
+        assert!(Database::open_encrypted(&path, &key).is_err());
+        assert!(Database::open_encrypted(&path, &new_key).is_ok());
+    }
+}
+
+// ── Drafts ──────────────────────────────────────
+
+#[test]
+fn drafts_are_kept_per_chat_and_account() {
+    let db = mem_db();
+    let ana = new_user(&db, "ana");
+    let beto = new_user(&db, "beto");
+    let caro = new_user(&db, "caro");
+    let with_beto = db.get_or_create_chat(&ana.uid, &beto.uid).unwrap();
+    let with_caro = db.get_or_create_chat(&ana.uid, &caro.uid).unwrap();
+
+    db.save_draft(&ana.uid, with_beto.id, "hola be").unwrap();
+    db.save_draft(&beto.uid, with_beto.id, "hola an").unwrap();
+    db.save_draft(&ana.uid, with_caro.id, "   ").unwrap();
+    assert_eq!(db.get_draft(&ana.uid, with_beto.id).unwrap().as_deref(), Some("hola be"));
+    assert_eq!(db.get_draft(&beto.uid, with_beto.id).unwrap().as_deref(), Some("hola an"));
+    assert_eq!(db.get_draft(&ana.uid, with_caro.id).unwrap(), None);
+
+    let by_contact = db.drafts_by_contact(&ana.uid).unwrap();
+    assert_eq!(by_contact.len(), 1);
+    assert_eq!(by_contact[&beto.uid], "hola be");
+
+    db.clear_draft(&ana.uid, with_beto.id).unwrap();
+    assert!(db.drafts_by_contact(&ana.uid).unwrap().is_empty());
+    db.delete_chat(with_beto.id).unwrap();
+    assert_eq!(db.get_draft(&beto.uid, with_beto.id).unwrap(), None);
+}
+
+#[test]
+fn contact_drafts_migrate_to_their_chat() {
+    let db = mem_db();
+    let ana = new_user(&db, "ana");
+    let beto = new_user(&db, "beto");
+    let chat = db.get_or_create_chat(&beto.uid, &ana.uid).unwrap();
+    db.conn
+        .execute_batch(&format!(
+            "DROP TABLE drafts;
+             CREATE TABLE drafts (
+                 owner_uid TEXT NOT NULL, contact_uid TEXT NOT NULL, content TEXT NOT NULL,
+                 updated_at TEXT NOT NULL, PRIMARY KEY (owner_uid, contact_uid)
+             );
+             INSERT INTO drafts VALUES ('{0}', '{1}', 'pendiente', '2024-01-01T00:00:00Z');
+             INSERT INTO drafts VALUES ('{0}', 'NIM-NADIE', 'sin chat', '2024-01-01T00:00:00Z');",
+            ana.uid, beto.uid
+        ))
+        .unwrap();
+
+    db.migrate_chat_drafts().unwrap();
+    assert_eq!(db.get_draft(&ana.uid, chat.id).unwrap().as_deref(), Some("pendiente"));
+    assert_eq!(db.drafts_by_contact(&ana.uid).unwrap().len(), 1);
+}
+
+// ── Barcodes ────────────────────────────────────
+
+#[test]
+fn barcodes_are_unique_per_owner() {
+    let db = mem_db();
+    let ana = new_user(&db, "ana");
+    let beto = new_user(&db, "beto");
+    let mut arroz = product(&ana.uid, "A1", "Arroz", 1.0, 1.0, 2.0);
+    arroz.barcode = Some(" 7790 001 ".into());
+    let id = db.upsert_product(&arroz).unwrap();
+
+    let found = db.find_product_by_barcode(&ana.uid, "7790001").unwrap().unwrap();
+    assert_eq!((found.id, found.barcode.as_deref()), (id, Some("7790001")));
+    assert!(db.product_barcode_exists(&ana.uid, "7790001", None).unwrap());
+    assert!(!db.product_barcode_exists(&ana.uid, "7790001", Some(id)).unwrap());
+
+    let mut fideos = product(&ana.uid, "A2", "Fideos", 1.0, 1.0, 2.0);
+    fideos.barcode = Some("7790001".into());
+    assert!(db.upsert_product(&fideos).is_err());
+    // Another account may stock the same item
+    let mut theirs = product(&beto.uid, "A1", "Arroz", 1.0, 1.0, 2.0);
+    theirs.barcode = Some("7790001".into());
+    assert!(db.upsert_product(&theirs).is_ok());
+}
+
+#[test]
+fn products_without_barcode_never_collide() {
+    let db = mem_db();
+    let ana = new_user(&db, "ana");
+    let mut blank = product(&ana.uid, "A1", "Arroz", 1.0, 1.0, 2.0);
+    blank.barcode = Some("  ".into());
+    db.upsert_product(&blank).unwrap();
+    db.upsert_product(&product(&ana.uid, "A2", "Fideos", 1.0, 1.0, 2.0)).unwrap();
+    db.upsert_product(&product(&ana.uid, "A3", "Sal", 1.0, 1.0, 2.0)).unwrap();
+    assert!(db.get_products(&ana.uid).unwrap().iter().all(|p| p.barcode.is_none()));
+    assert!(!db.product_barcode_exists(&ana.uid, "", None).unwrap());
+    assert!(db.find_product_by_barcode(&ana.uid, " ").unwrap().is_none());
+}
+
+fn records(rows: &[&str]) -> Vec<Vec<String>> {
+    rows.iter().map(|r| r.split(',').map(str::to_string).collect()).collect()
+}
+
+#[test]
+fn import_rejects_a_barcode_used_by_another_product() {
+    let db = mem_db();
+    let ana = new_user(&db, "ana");
+    let mapping = ColumnMapping::detect(&records(&["codigo,nombre,ean"])[0]);
+    let rows = records(&["A1,Arroz,7790001", "A2,Fideos,7790001", "A3,Sal,", "A4,Azúcar,"]);
+    let report = db.import_products_mapped(&ana.uid, &rows, &mapping, false).unwrap();
+    assert_eq!((report.inserted, report.skipped), (3, 1));
+    assert!(report.errors[0].starts_with("Línea 3:"));
+    // Re-importing a product keeps its own barcode
+    let report = db.import_products_mapped(&ana.uid, &records(&["A1,Arroz largo,7790001"]), &mapping, false).unwrap();
+    assert_eq!((report.updated, report.skipped), (1, 0));
+}
+
+#[test]
+fn reorder_alert_fires_at_the_boundary_in_memory_and_in_sql() {
+    let db = mem_db();
+    let owner = new_user(&db, "ana");
+    // 10 × 1.2 is 12.000000000000002 in floating point
+    for (code, qty, expected) in [("A", 11.9, true), ("B", 12.0, true), ("C", 12.01, false), ("D", 13.0, false)] {
+        let mut p = product(&owner.uid, code, code, qty, 1.0, 2.0);
+        p.reorder_point = Some(10.0);
+        p.alert_pct = 0.2;
+        assert_eq!(p.needs_reorder(), expected, "{} unidades", qty);
+        db.upsert_product(&p).unwrap();
+    }
+    assert_eq!(db.low_stock_count(&owner.uid, None).unwrap(), 2);
+}
+
+#[test]
+fn unread_counts_are_kept_per_participant() {
+    let db = mem_db();
+    let ana = new_user(&db, "ana");
+    let beto = new_user(&db, "beto");
+    let chat = db.get_or_create_chat(&ana.uid, &beto.uid).unwrap();
+    text(&db, chat.id, &ana.uid, "hola");
+    text(&db, chat.id, &ana.uid, "¿estás?");
+    text(&db, chat.id, &beto.uid, "sí");
+
+    assert_eq!(db.total_unread(&ana.uid).unwrap(), 1);
+    assert_eq!(db.total_unread(&beto.uid).unwrap(), 2);
+    db.mark_chat_read(chat.id, &beto.uid, false).unwrap();
+    assert_eq!(db.total_unread(&beto.uid).unwrap(), 0);
+    assert_eq!(db.unread_by_contact(&ana.uid).unwrap().get(&beto.uid), Some(&1));
+    assert!(!columns_by_table(&db).contains(&("chats".to_string(), "unread_count".to_string())));
+}
+
+#[test]
+fn contacts_are_mutual_only_when_added_both_ways() {
+    let db = mem_db();
+    let ana = new_user(&db, "ana");
+    let beto = new_user(&db, "beto");
+    let caro = new_user(&db, "caro");
+    db.add_contact(&ana.uid, &beto.uid, "Beto", beto.avatar_color, "friend").unwrap();
+    db.add_contact(&ana.uid, &caro.uid, "Caro", caro.avatar_color, "acquaintance").unwrap();
+    assert!(!db.is_mutual_contact(&ana.uid, &beto.uid).unwrap());
+    assert!(db.mutual_contact_uids(&ana.uid).unwrap().is_empty());
+
+    db.add_contact(&beto.uid, &ana.uid, "Ana", ana.avatar_color, "acquaintance").unwrap();
+    assert!(db.is_mutual_contact(&ana.uid, &beto.uid).unwrap());
+    assert!(db.is_mutual_contact(&beto.uid, &ana.uid).unwrap());
+    assert!(!db.is_mutual_contact(&ana.uid, &caro.uid).unwrap());
+    assert_eq!(db.mutual_contact_uids(&ana.uid).unwrap(), HashSet::from([beto.uid.clone()]));
+    assert_eq!(db.mutual_contact_uids(&beto.uid).unwrap(), HashSet::from([ana.uid.clone()]));
+}
+
+// ── Contacts ────────────────────────────────────
+
+#[test]
+fn profile_stats_count_contacts_chats_and_sent_messages() {
+    let db = mem_db();
+    let ana = new_user(&db, "ana");
+    let beto = new_user(&db, "beto");
+    let caro = new_user(&db, "caro");
+    db.add_contact(&ana.uid, &beto.uid, "Beto", beto.avatar_color, "friend").unwrap();
+    db.add_contact(&ana.uid, &caro.uid, "Caro", caro.avatar_color, "acquaintance").unwrap();
+    let with_beto = db.get_or_create_chat(&ana.uid, &beto.uid).unwrap();
+    let with_caro = db.get_or_create_chat(&ana.uid, &caro.uid).unwrap();
+    text(&db, with_beto.id, &ana.uid, "hola");
+    text(&db, with_caro.id, &ana.uid, "hola");
+    text(&db, with_beto.id, &beto.uid, "buenas");
+
+    let stats = db.profile_stats(&ana.uid).unwrap();
+    assert_eq!((stats.friends, stats.acquaintances, stats.chats, stats.messages_sent), (1, 1, 2, 2));
+    let stats = db.profile_stats(&beto.uid).unwrap();
+    assert_eq!((stats.friends, stats.acquaintances, stats.chats, stats.messages_sent), (0, 0, 1, 1));
+}
+
+#[test]
+fn contacts_sort_pinned_then_starred_then_by_name() {
+    let db = mem_db();
+    let ana = new_user(&db, "ana");
+    for name in ["zoe", "beto", "maria", "carla"] {
+        let u = new_user(&db, name);
+        db.add_contact(&ana.uid, &u.uid, name, u.avatar_color, "friend").unwrap();
+    }
+    let uid_of = |name: &str| {
+        db.get_contacts(&ana.uid, "friend").unwrap().into_iter().find(|c| c.display_name == name).unwrap().contact_uid
+    };
+    assert!(db.toggle_pin_contact(&ana.uid, &uid_of("zoe")).unwrap());
+    assert!(db.toggle_star(&ana.uid, &uid_of("maria")).unwrap());
+    assert!(db.toggle_star(&ana.uid, &uid_of("zoe")).unwrap());
+
+    let order: Vec<String> = db.get_contacts(&ana.uid, "friend").unwrap().into_iter().map(|c| c.display_name).collect();
+    assert_eq!(order, ["zoe", "maria", "beto", "carla"]);
+    assert!(!db.toggle_pin_contact(&ana.uid, &uid_of("zoe")).unwrap());
+    let order: Vec<String> = db.get_contacts(&ana.uid, "friend").unwrap().into_iter().map(|c| c.display_name).collect();
+    assert_eq!(order, ["maria", "zoe", "beto", "carla"]);
+}
+
+#[test]
+fn removing_a_contact_orphans_the_chat_until_purged() {
+    let db = mem_db();
+    let ana = new_user(&db, "ana");
+    let beto = new_user(&db, "beto");
+    let caro = new_user(&db, "caro");
+    db.add_contact(&ana.uid, &beto.uid, "Beto", beto.avatar_color, "friend").unwrap();
+    db.add_contact(&ana.uid, &caro.uid, "Caro", caro.avatar_color, "friend").unwrap();
+    let chat = db.get_or_create_chat(&ana.uid, &beto.uid).unwrap();
+    let kept = db.get_or_create_chat(&ana.uid, &caro.uid).unwrap();
+    text(&db, chat.id, &beto.uid, "hola");
+    assert!(db.orphaned_chats(&ana.uid).unwrap().is_empty());
+
+    db.remove_contact(&ana.uid, &beto.uid).unwrap();
+    let orphans: Vec<i64> = db.orphaned_chats(&ana.uid).unwrap().iter().map(|c| c.id).collect();
+    assert_eq!(orphans, [chat.id]);
+    assert_eq!(db.purge_orphaned_chats(&ana.uid).unwrap(), 1);
+    assert!(db.orphaned_chats(&ana.uid).unwrap().is_empty());
+    assert!(db.get_messages(chat.id, 10, 0).unwrap().is_empty());
+    assert_eq!(db.profile_stats(&ana.uid).unwrap().chats, 1);
+    assert_eq!(db.get_or_create_chat(&ana.uid, &caro.uid).unwrap().id, kept.id);
+}
+
+// ── Settings ────────────────────────────────────
+
+#[test]
+fn last_chat_tab_is_remembered_and_defaults_to_friends() {
+    let db = mem_db();
+    let ana = new_user(&db, "ana");
+    assert_eq!(db.get_settings(&ana.uid).unwrap().last_chat_tab, ContactType::Friend);
+    db.update_last_chat_tab(&ana.uid, "acquaintance").unwrap();
+    assert_eq!(db.get_settings(&ana.uid).unwrap().last_chat_tab, ContactType::Acquaintance);
+    db.update_last_chat_tab(&ana.uid, "desconocida").unwrap();
+    assert_eq!(db.get_settings(&ana.uid).unwrap().last_chat_tab, ContactType::Friend);
+}
+
+// ── Dev seed ────────────────────────────────────
+
+#[cfg(debug_assertions)]
+#[test]
+fn dev_seed_creates_the_sample_rows_once() {
+    let db = mem_db();
+    let dev = db.seed_dev_user().unwrap();
+    assert_eq!(db.user_count().unwrap(), 3);
+    assert_eq!(db.get_contacts(&dev.uid, "friend").unwrap().len(), 1);
+    assert_eq!(db.get_contacts(&dev.uid, "acquaintance").unwrap().len(), 1);
+    assert_eq!(db.get_products(&dev.uid).unwrap().len(), 3);
+
+    assert_eq!(db.seed_dev_user().unwrap().uid, dev.uid);
+    assert_eq!(db.user_count().unwrap(), 3);
+    assert_eq!(db.get_contacts(&dev.uid, "friend").unwrap().len(), 1);
+    assert_eq!(db.get_products(&dev.uid).unwrap().len(), 3);
+}
+
+#[cfg(debug_assertions)]
+#[test]
+fn dev_seed_finds_accounts_without_logging_in() {
+    let db = mem_db();
+    let dev = db.seed_dev_user().unwrap();
+    db.update_password(&dev.uid, "otra-clave-123").unwrap();
+    assert_eq!(db.seed_dev_user().unwrap().uid, dev.uid);
+    let attempts: i64 = db.conn.query_row("SELECT COUNT(*) FROM login_attempts", [], |r| r.get(0)).unwrap();
+    assert_eq!(attempts, 0);
+}
+
+// ── Valuation ───────────────────────────────────
+
+#[test]
+fn valuation_totals_add_up_the_lines_and_carry_a_timestamp() {
+    let db = mem_db();
+    let ana = new_user(&db, "ana");
+    let beto = new_user(&db, "beto");
+    db.upsert_product(&product(&ana.uid, "B1", "Yerba", 4.0, 2.5, 4.0)).unwrap();
+    db.upsert_product(&product(&ana.uid, "A1", "Arroz", 10.0, 1.2, 2.0)).unwrap();
+    db.upsert_product(&product(&ana.uid, "C1", "Sal", 0.0, 0.8, 1.5)).unwrap();
+    db.upsert_product(&product(&beto.uid, "A1", "Arroz", 99.0, 9.0, 10.0)).unwrap();
+
+    let snapshot = db.valuation_snapshot(&ana.uid).unwrap();
+    let codes: Vec<&str> = snapshot.lines.iter().map(|l| l.code.as_str()).collect();
+    assert_eq!(codes, ["A1", "B1", "C1"]);
+    let sum: f64 = snapshot.lines.iter().map(|l| l.extended_value).sum();
+    assert!((snapshot.total_value - sum).abs() < 1e-9);
+    assert!((snapshot.total_value - 22.0).abs() < 1e-9);
+    assert_eq!(snapshot.total_quantity, 14.0);
+    assert!(chrono::DateTime::parse_from_rfc3339(&snapshot.taken_at).is_ok());
+    assert!(snapshot.to_csv().starts_with(&format!("Valuación al,{}\n", snapshot.taken_at)));
+    assert!(snapshot.to_csv().ends_with("TOTAL,,14,,22.00\n"));
+
+    db.record_valuation(&snapshot).unwrap();
+    let history = db.valuation_history(&ana.uid).unwrap();
+    assert_eq!((history.len(), history[0].taken_at.as_str()), (1, snapshot.taken_at.as_str()));
+}
+
+// ── CSV import ──────────────────────────────────
+
+#[test]
+fn foreign_headers_map_to_product_fields_in_any_order() {
+    let db = mem_db();
+    let ana = new_user(&db, "ana");
+    let csv = "PVP,Stock,SKU,Descripción,Costo,Familia,Notas\n\"$1.234,50\",3,q-1,Queso,\"900,00\",Lácteos,frío\n";
+    let report = db.import_products_csv(&ana.uid, csv).unwrap();
+    assert_eq!((report.inserted, report.skipped), (1, 0));
+    let queso = db.find_product_by_code(&ana.uid, "Q-1").unwrap().unwrap();
+    assert_eq!(queso.name, "Queso");
+    assert_eq!((queso.quantity, queso.net_value, queso.sale_value), (3.0, 900.0, 1234.5));
+    assert_eq!(queso.category.as_deref(), Some("Lácteos"));
+
+    // Headers nobody recognizes are mapped by hand
+    let headers = ["Art".to_string(), "Cant".to_string(), "Desc".to_string()];
+    let mut mapping = import::ColumnMapping::detect(&headers);
+    assert_eq!(mapping.missing_required(), [import::ProductField::Code, import::ProductField::Name]);
+    mapping.columns = vec![Some(import::ProductField::Code), Some(import::ProductField::Quantity), Some(import::ProductField::Name)];
+    let rows = vec![vec!["P-9".to_string(), "7".to_string(), "Pan".to_string()]];
+    db.import_products_mapped(&ana.uid, &rows, &mapping, false).unwrap();
+    let pan = db.find_product_by_code(&ana.uid, "P-9").unwrap().unwrap();
+    assert_eq!((pan.name.as_str(), pan.quantity), ("Pan", 7.0));
+}
@@ -0,0 +1,105 @@
+use anyhow::Result;
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use crate::util::csv_field;
+use super::Database;
+
+// ──────────────────────────────────────────────
+// INVENTORY VALUATION
+// ──────────────────────────────────────────────
+
+/// One product's stock valued at cost.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValuationLine {
+    pub code: String,
+    pub name: String,
+    pub quantity: f64,
+    pub net_value: f64,
+    /// `quantity * net_value`.
+    pub extended_value: f64,
+}
+
+/// Point-in-time valuation of an inventory, for the accountant.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValuationSnapshot {
+    pub owner_uid: String,
+    /// When it was taken (RFC 3339, UTC).
+    pub taken_at: String,
+    pub lines: Vec<ValuationLine>,
+    pub total_quantity: f64,
+    pub total_value: f64,
+}
+
+impl ValuationSnapshot {
+    /// CSV with a header row, one line per product and a closing totals row.
+    pub fn to_csv(&self) -> String {
+        let mut out = format!("Valuación al,{}\n", csv_field(&self.taken_at));
+        out.push_str("Código,Nombre,Cantidad,Valor neto,Valor extendido\n");
+        for line in &self.lines {
+            out.push_str(&format!(
+                "{},{},{},{:.2},{:.2}\n",
+                csv_field(&line.code),
+                csv_field(&line.name),
+                line.quantity,
+                line.net_value,
+                line.extended_value,
+            ));
+        }
+        out.push_str(&format!("TOTAL,,{},,{:.2}\n", self.total_quantity, self.total_value));
+        out
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+impl Database {
+    /// Value `owner_uid`'s inventory at cost as of now, ordered by code.
+    pub fn valuation_snapshot(&self, owner_uid: &str) -> Result<ValuationSnapshot> {
+        let mut stmt = self.conn.prepare(
+            "SELECT code, name, quantity, net_value FROM products
+             WHERE owner_uid = ?1 ORDER BY code COLLATE NOCASE",
+        )?;
+        let lines = stmt
+            .query_map(params![owner_uid], |r| {
+                let quantity: f64 = r.get(2)?;
+                let net_value: f64 = r.get(3)?;
+                Ok(ValuationLine {
+                    code: r.get(0)?,
+                    name: r.get(1)?,
+                    quantity,
+                    net_value,
+                    extended_value: quantity * net_value,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(ValuationSnapshot {
+            owner_uid: owner_uid.to_string(),
+            taken_at: chrono::Utc::now().to_rfc3339(),
+            total_quantity: lines.iter().map(|l| l.quantity).sum(),
+            total_value: lines.iter().map(|l| l.extended_value).sum(),
+            lines,
+        })
+    }
+
+    /// Keep `snapshot` in the valuation history.
+    pub fn record_valuation(&self, snapshot: &ValuationSnapshot) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO valuations (owner_uid, taken_at, total_value, data) VALUES (?1, ?2, ?3, ?4)",
+            params![snapshot.owner_uid, snapshot.taken_at, snapshot.total_value, serde_json::to_string(snapshot)?],
+        )?;
+        Ok(())
+    }
+
+    /// Past valuations of `owner_uid`, newest first.
+    pub fn valuation_history(&self, owner_uid: &str) -> Result<Vec<ValuationSnapshot>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT data FROM valuations WHERE owner_uid = ?1 ORDER BY taken_at DESC, id DESC",
+        )?;
+        let rows = stmt
+            .query_map(params![owner_uid], |r| r.get::<_, String>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        rows.iter().map(|data| Ok(serde_json::from_str(data)?)).collect()
+    }
+}
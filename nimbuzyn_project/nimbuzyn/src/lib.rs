@@ -10,14 +10,14 @@ pub mod db;
 pub mod models;
 pub mod screens;
 pub mod theme;
+pub mod util;
+pub mod validation;
 
 use crate::app::NimbuzynApp;
 
 #[cfg(target_os = "android")]
 #[no_mangle]
 fn android_main(app: android_activity::AndroidApp) {
-    use android_activity::AndroidApp;
-
     // Initialize Android logger
     android_logger::init_once(
         android_logger::Config::default()
@@ -12,10 +12,36 @@ pub struct User {
     pub uid: String,          // unique public ID (e.g. "NIM-4F2A3B")
     pub username: String,
     pub display_name: String,
-    pub avatar_color: u32,    // packed RGBA for avatar placeholder
+    pub avatar_color: u32,    // packed 0xRRGGBBAA, see `pack_rgba`
     pub created_at: String,
 }
 
+/// Pack color channels into the `0xRRGGBBAA` layout `avatar_color` is
+/// stored in.
+pub const fn pack_rgba(r: u8, g: u8, b: u8, a: u8) -> u32 {
+    (r as u32) << 24 | (g as u32) << 16 | (b as u32) << 8 | a as u32
+}
+
+/// Inverse of [`pack_rgba`]: `(r, g, b, a)`.
+pub const fn unpack_rgba(color: u32) -> (u8, u8, u8, u8) {
+    ((color >> 24) as u8, (color >> 16) as u8, (color >> 8) as u8, color as u8)
+}
+
+/// Avatar blue (#4A90E2, opaque) given to new accounts.
+pub const DEFAULT_AVATAR_COLOR: u32 = pack_rgba(0x4A, 0x90, 0xE2, 0xFF);
+
+/// Swatches offered by the avatar color picker in settings.
+pub const AVATAR_COLORS: [u32; 8] = [
+    DEFAULT_AVATAR_COLOR,
+    pack_rgba(0x6C, 0x63, 0xFF, 0xFF), // indigo
+    pack_rgba(0x00, 0xB8, 0xD4, 0xFF), // cyan
+    pack_rgba(0x00, 0xC8, 0x7A, 0xFF), // green
+    pack_rgba(0xF5, 0xA6, 0x23, 0xFF), // amber
+    pack_rgba(0xFF, 0x6B, 0x4A, 0xFF), // coral
+    pack_rgba(0xE8, 0x4A, 0x8A, 0xFF), // pink
+    pack_rgba(0x8A, 0x90, 0x9C, 0xFF), // gray
+];
+
 impl User {
     pub fn new(username: String, display_name: String) -> Self {
         let uid = format!(
@@ -27,7 +53,7 @@ impl User {
             uid,
             username,
             display_name,
-            avatar_color: 0xFF_4A_90_E2,
+            avatar_color: DEFAULT_AVATAR_COLOR,
             created_at: Utc::now().to_rfc3339(),
         }
     }
@@ -61,7 +87,9 @@ pub struct Contact {
     pub avatar_color: u32,
     pub contact_type: ContactType,
     pub starred: bool,          // starred contacts appear at top
+    pub pinned: bool,           // pinned contacts appear above starred ones
     pub added_at: String,
+    pub note: Option<String>,   // private note, only the owner sees it
 }
 
 // ──────────────────────────────────────────────
@@ -100,6 +128,19 @@ impl MessageType {
         }
     }
 
+    /// Message type for an attachment's file extension; `None` when the
+    /// extension isn't one we accept.
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_lowercase().as_str() {
+            "jpg" | "jpeg" | "png" | "gif" | "webp" | "bmp" => Some(MessageType::Image),
+            "mp4" | "mkv" | "avi" | "mov" | "webm" => Some(MessageType::Video),
+            "pdf" | "doc" | "docx" | "xls" | "xlsx"
+            | "ppt" | "pptx" | "txt" | "csv" => Some(MessageType::Document),
+            "rar" | "zip" | "7z" => Some(MessageType::Archive),
+            _ => None,
+        }
+    }
+
     /// Returns the emoji icon for display
     pub fn icon(&self) -> &str {
         match self {
@@ -110,6 +151,17 @@ impl MessageType {
             MessageType::Archive  => "📦",
         }
     }
+
+    /// Plural group label used in the shared files list
+    pub fn label(&self) -> &str {
+        match self {
+            MessageType::Text     => "Mensajes",
+            MessageType::Image    => "Imágenes",
+            MessageType::Video    => "Videos",
+            MessageType::Document => "Documentos",
+            MessageType::Archive  => "Comprimidos",
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -123,10 +175,15 @@ pub struct Message {
     pub file_size: Option<u64>,   // bytes
     pub sent_at: String,
     pub is_read: bool,
+    pub edited_at: Option<String>,
+    pub deleted: bool,            // tombstone: content and file info cleared
+    pub reply_to: Option<i64>,    // message this one quotes
 }
 
 impl Message {
     pub const MAX_TEXT_LEN: usize = 1000;
+    /// Shown in place of a deleted message and as its chat preview.
+    pub const DELETED_TEXT: &'static str = "Mensaje eliminado";
     pub const MAX_FILE_SIZE: u64 = 100 * 1024 * 1024; // 100 MB
 
     pub fn is_valid_file_size(size: u64) -> bool {
@@ -134,17 +191,37 @@ impl Message {
     }
 
     pub fn is_valid_file_type(extension: &str) -> bool {
-        matches!(
-            extension.to_lowercase().as_str(),
-            "jpg" | "jpeg" | "png" | "gif" | "webp" | "bmp"  // images
-            | "mp4" | "mkv" | "avi" | "mov" | "webm"          // videos
-            | "pdf" | "doc" | "docx" | "xls" | "xlsx"
-            | "ppt" | "pptx" | "txt" | "csv"                   // documents
-            | "rar" | "zip" | "7z"                             // archives
-        )
+        MessageType::from_extension(extension).is_some()
     }
 }
 
+/// A message to insert with `Database::send_messages_batch`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewMessage {
+    pub chat_id: i64,
+    pub sender_uid: String,
+    pub content: String,          // text content or file path
+    pub msg_type: String,         // "text", "image", ...
+    pub file_name: Option<String>,
+    pub file_size: Option<u64>,
+}
+
+/// One emoji under a message, aggregated over everyone who used it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ReactionCount {
+    pub emoji: String,
+    pub count: u32,
+    pub mine: bool,              // the current user is among them
+}
+
+/// A message search result, with the contact of the chat it belongs to.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchHit {
+    pub message: Message,
+    pub contact_uid: String,
+    pub contact_name: String,
+}
+
 // ──────────────────────────────────────────────
 // CHAT SESSION MODEL
 // ──────────────────────────────────────────────
@@ -157,7 +234,6 @@ pub struct Chat {
     pub created_at: String,
     pub last_message: Option<String>,
     pub last_message_at: Option<String>,
-    pub unread_count: u32,
 }
 
 // ──────────────────────────────────────────────
@@ -169,15 +245,66 @@ pub struct Product {
     pub id: i64,
     pub owner_uid: String,
     pub code: String,           // product code
+    pub barcode: Option<String>, // EAN/UPC as scanned; None when the product has none
     pub name: String,
     pub quantity: f64,          // can be 0 (triggers red alert)
     pub net_value: f64,         // costo / valor neto
     pub sale_value: f64,        // precio de venta
     pub profit_value: f64,      // ganancias (calculado)
+    pub category: Option<String>,
+    pub reorder_point: Option<f64>, // None = alert only when out of stock
+    pub alert_pct: f64,         // margin above the reorder point, 0.2 = 20 %
+    pub low_stock_threshold: f64, // below this it counts as out of stock (default 1)
     pub created_at: String,
     pub updated_at: String,
 }
 
+/// Order of the inventory list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ProductSort {
+    #[default]
+    Name,
+    /// Lowest stock first, to see what needs restocking.
+    Quantity,
+    /// Highest `total_profit()` first.
+    Profit,
+    /// Most recently edited first.
+    RecentlyUpdated,
+}
+
+impl ProductSort {
+    pub const ALL: [ProductSort; 4] =
+        [ProductSort::Name, ProductSort::Quantity, ProductSort::Profit, ProductSort::RecentlyUpdated];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ProductSort::Name            => "Nombre",
+            ProductSort::Quantity        => "Menor stock",
+            ProductSort::Profit          => "Mayor ganancia",
+            ProductSort::RecentlyUpdated => "Recientes",
+        }
+    }
+}
+
+/// Out-of-stock threshold for products that don't set their own.
+pub const DEFAULT_LOW_STOCK_THRESHOLD: f64 = 1.0;
+
+/// Slack for comparing stock quantities computed in floating point, so 12
+/// still counts as reaching 10 × 1.2 = 12.000000000000002.
+pub const QUANTITY_EPSILON: f64 = 1e-9;
+
+/// Canonical form of a product code: trimmed, uppercase, single spaces.
+/// Codes are compared in this form, so `prod-001` and ` PROD-001 ` collide.
+pub fn normalize_code(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ").to_uppercase()
+}
+
+/// Canonical form of a barcode: digits and letters with the spaces removed;
+/// `None` when nothing is left, so products without one never collide.
+pub fn normalize_barcode(s: &str) -> Option<String> {
+    Some(s.split_whitespace().collect::<String>()).filter(|b| !b.is_empty())
+}
+
 impl Product {
     pub fn calculate_profit(&mut self) {
         self.profit_value = self.sale_value - self.net_value;
@@ -191,8 +318,38 @@ impl Product {
         self.quantity * self.profit_value
     }
 
-    pub fn is_out_of_stock(&self) -> bool {
-        self.quantity < 1.0
+    /// Below the product's own out-of-stock threshold (1 unit unless set,
+    /// e.g. 0.25 kg for bulk goods).
+    pub fn is_low_stock(&self) -> bool {
+        self.quantity < self.low_stock_threshold
+    }
+
+    /// Low-stock alert. With a reorder point it fires at
+    /// `quantity <= reorder_point * (1 + alert_pct)`; without one it falls
+    /// back to the out-of-stock threshold.
+    pub fn needs_reorder(&self) -> bool {
+        match self.reorder_point {
+            Some(point) => self.quantity <= point * (1.0 + self.alert_pct) + QUANTITY_EPSILON,
+            None => self.is_low_stock(),
+        }
+    }
+
+    /// Sale price that leaves `margin_pct` % of it as profit, rounded to
+    /// cents. `None` for margins of 100 % or more (no price can reach them).
+    pub fn sale_from_margin(net: f64, margin_pct: f64) -> Option<f64> {
+        if !net.is_finite() || !margin_pct.is_finite() || margin_pct >= 100.0 {
+            return None;
+        }
+        Some((net / (1.0 - margin_pct / 100.0) * 100.0).round() / 100.0)
+    }
+
+    /// Profit as a percentage of the sale price, rounded to two decimals.
+    /// `None` when the sale price is zero.
+    pub fn margin_from_prices(net: f64, sale: f64) -> Option<f64> {
+        if sale == 0.0 || !net.is_finite() || !sale.is_finite() {
+            return None;
+        }
+        Some(((sale - net) / sale * 10_000.0).round() / 100.0)
     }
 }
 
@@ -204,6 +361,8 @@ impl Product {
 pub enum AppTheme {
     Light,
     Dark,
+    /// Dark with pure-black backgrounds, for OLED screens.
+    AmoledBlack,
 }
 
 impl Default for AppTheme {
@@ -212,11 +371,94 @@ impl Default for AppTheme {
     }
 }
 
+impl AppTheme {
+    pub const ALL: [AppTheme; 3] = [AppTheme::Light, AppTheme::Dark, AppTheme::AmoledBlack];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AppTheme::Light       => "light",
+            AppTheme::Dark        => "dark",
+            AppTheme::AmoledBlack => "amoled",
+        }
+    }
+
+    pub fn from_stored(s: &str) -> Self {
+        match s {
+            "dark"   => AppTheme::Dark,
+            "amoled" => AppTheme::AmoledBlack,
+            _        => AppTheme::Light,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            AppTheme::Light       => "☀️ Claro",
+            AppTheme::Dark        => "🌙 Oscuro",
+            AppTheme::AmoledBlack => "⚫ AMOLED",
+        }
+    }
+}
+
+/// How tightly list rows are packed. Meant to be shared by every list screen.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub enum ListDensity {
+    Comfortable,
+    #[default]
+    Cozy,
+    Compact,
+}
+
+impl ListDensity {
+    pub const ALL: [ListDensity; 3] = [ListDensity::Comfortable, ListDensity::Cozy, ListDensity::Compact];
+
+    /// Row height and font size for this density.
+    pub fn metrics(self) -> (f32, f32) {
+        match self {
+            ListDensity::Comfortable => (60.0, 14.0),
+            ListDensity::Cozy        => (52.0, 13.0),
+            ListDensity::Compact     => (40.0, 12.0),
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ListDensity::Comfortable => "comfortable",
+            ListDensity::Cozy        => "cozy",
+            ListDensity::Compact     => "compact",
+        }
+    }
+
+    pub fn from_db(s: &str) -> Self {
+        match s {
+            "comfortable" => ListDensity::Comfortable,
+            "compact"     => ListDensity::Compact,
+            _             => ListDensity::Cozy,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ListDensity::Comfortable => "Cómoda",
+            ListDensity::Cozy        => "Normal",
+            ListDensity::Compact     => "Compacta",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppSettings {
     pub theme: AppTheme,
     pub notifications_enabled: bool,
     pub font_size: f32,
+    pub last_chat_tab: ContactType,   // which contacts tab the chat screen opens on
+    pub list_density: ListDensity,
+    pub read_receipts: bool,          // let senders know their messages were read
+    pub confirm_acquaintance_send: bool, // ask once per chat before writing to a non-friend
+    pub group_window_mins: u32,       // consecutive messages this close together stack
+    pub text_autoformat: bool,        // turn emoticons like :) into emoji on send
+    pub copy_links: bool,             // tapping a link copies it instead of opening it
+    pub accent_color: Option<u32>,    // packed RGBA replacing the theme's blue
+    pub currency: String,             // ISO code amounts are shown in
 }
 
 impl Default for AppSettings {
@@ -225,6 +467,58 @@ impl Default for AppSettings {
             theme: AppTheme::Dark,
             notifications_enabled: true,
             font_size: 14.0,
+            last_chat_tab: ContactType::Friend,
+            list_density: ListDensity::Cozy,
+            read_receipts: true,
+            confirm_acquaintance_send: false,
+            group_window_mins: 2,
+            text_autoformat: false,
+            copy_links: false,
+            accent_color: None,
+            currency: crate::util::DEFAULT_CURRENCY.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_density_has_its_own_row_metrics() {
+        let metrics: Vec<_> = ListDensity::ALL.iter().map(|d| d.metrics()).collect();
+        assert!(metrics.windows(2).all(|w| w[0].0 > w[1].0 && w[0].1 > w[1].1));
+        for density in ListDensity::ALL {
+            assert_eq!(ListDensity::from_db(density.as_str()), density);
+        }
+        assert_eq!(ListDensity::from_db("gigante"), ListDensity::Cozy);
+    }
+
+    #[test]
+    fn margin_and_sale_price_convert_both_ways() {
+        assert_eq!(Product::sale_from_margin(75.0, 25.0), Some(100.0));
+        assert_eq!(Product::sale_from_margin(10.0, 33.0), Some(14.93));
+        assert_eq!(Product::sale_from_margin(0.0, 40.0), Some(0.0));
+        assert_eq!(Product::sale_from_margin(10.0, -50.0), Some(6.67));
+        assert_eq!(Product::sale_from_margin(10.0, 100.0), None);
+
+        assert_eq!(Product::margin_from_prices(75.0, 100.0), Some(25.0));
+        assert_eq!(Product::margin_from_prices(10.0, 14.93), Some(33.02));
+        assert_eq!(Product::margin_from_prices(0.0, 5.0), Some(100.0));
+        assert_eq!(Product::margin_from_prices(10.0, 0.0), None);
+    }
+
+    #[test]
+    fn rgba_packing_round_trips() {
+        for (r, g, b, a) in [(0, 0, 0, 0), (255, 255, 255, 255), (0x12, 0x34, 0x56, 0x78), (1, 2, 3, 0x80)] {
+            assert_eq!(unpack_rgba(pack_rgba(r, g, b, a)), (r, g, b, a));
         }
+        assert_eq!(pack_rgba(0x12, 0x34, 0x56, 0x78), 0x1234_5678);
+    }
+
+    #[test]
+    fn default_avatar_color_is_opaque_blue() {
+        assert_eq!(unpack_rgba(DEFAULT_AVATAR_COLOR), (0x4A, 0x90, 0xE2, 0xFF));
+        assert!(AVATAR_COLORS.iter().all(|&c| unpack_rgba(c).3 == 0xFF));
     }
 }
@@ -1,15 +1,50 @@
+use std::collections::{HashMap, HashSet};
 use egui::{Align, Color32, Layout, Rounding, RichText, Stroke, Vec2};
 use crate::models::*;
-use crate::theme::NimColors;
+use crate::theme::{elide, elide_wrap, NimColors};
+use crate::util::{
+    apply_autoformat, avatar_initial, day_label, file_url, format_datetime, human_size, linkify, local_day, TextSpan,
+};
+use crate::screens::splash::dot_pulse;
+use crate::screens::thumbnails::{ThumbnailCache, THUMB_MAX_PX};
 use crate::db::{Database};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum ChatTab { Friends, Acquaintances }
 
+impl ChatTab {
+    pub fn from_contact_type(t: &ContactType) -> Self {
+        match t {
+            ContactType::Friend       => ChatTab::Friends,
+            ContactType::Acquaintance => ChatTab::Acquaintances,
+        }
+    }
+
+    pub fn contact_type(&self) -> ContactType {
+        match self {
+            ChatTab::Friends       => ContactType::Friend,
+            ChatTab::Acquaintances => ContactType::Acquaintance,
+        }
+    }
+}
+
 pub struct ChatScreen {
     pub tab: ChatTab,
     pub contacts_friends: Vec<Contact>,
     pub contacts_acquaintances: Vec<Contact>,
+    /// Filters the shown tab by name or UID.
+    pub search: String,
+    /// Show only contacts with unread messages.
+    pub unread_only: bool,
+    /// Contacts that have also added the current user back.
+    pub mutual_uids: HashSet<String>,
+    /// Unsent drafts by the contact UID of their chat, previewed on the
+    /// contact rows.
+    pub drafts: HashMap<String, String>,
+    /// Unread messages from each contact (by UID), badged on the rows.
+    pub unread: HashMap<String, u32>,
+    /// Contacts we've blocked; listed apart under "Bloqueados".
+    pub blocked: HashSet<String>,
 
     // Add contact dialog
     pub show_add_dialog: bool,
@@ -18,10 +53,36 @@ pub struct ChatScreen {
     pub add_error: Option<String>,
     pub add_preview_user: Option<User>,
 
+    // Contact detail (local rename + private note)
+    pub contact_detail: Option<ContactDetail>,
+
     // Active chat
     pub active_chat: Option<ActiveChat>,
 }
 
+/// Contact detail dialog, opened by tapping a contact's avatar.
+pub struct ContactDetail {
+    pub contact: Contact,
+    pub name: String,
+    pub note: String,
+    pub blocked: bool,
+    pub error: Option<String>,
+    pub saved: Option<String>,
+}
+
+impl ContactDetail {
+    pub fn new(contact: &Contact) -> Self {
+        ContactDetail {
+            contact: contact.clone(),
+            name: contact.display_name.clone(),
+            note: contact.note.clone().unwrap_or_default(),
+            blocked: false,
+            error: None,
+            saved: None,
+        }
+    }
+}
+
 pub struct ActiveChat {
     pub contact: Contact,
     pub chat_id: i64,
@@ -30,6 +91,262 @@ pub struct ActiveChat {
     pub scroll_to_bottom: bool,
     pub char_count: usize,
     pub file_error: Option<String>,
+    pub mutual: bool,
+    /// Open "Historial de ediciones" popup: message id and its previous versions.
+    pub edit_history: Option<(i64, Vec<(String, String)>)>,
+    pub reactions: HashMap<i64, Vec<ReactionCount>>,
+    /// Last tap on a bubble (message id, egui time) for double-tap detection.
+    pub last_tap: Option<(i64, f64)>,
+    /// Heart "pop" animation in progress (message id, start time).
+    pub reaction_pop: Option<(i64, f64)>,
+    /// Message whose "Info" panel is open.
+    pub info_message: Option<Message>,
+    /// "Archivos compartidos" list, loaded on demand.
+    pub media: Option<Vec<Message>>,
+    /// Why the last file tapped in that list couldn't be opened.
+    pub media_error: Option<String>,
+
+    // Message search
+    pub search_open: bool,
+    pub search_query: String,
+    /// false = "En este chat", true = "En todos los chats"
+    pub search_all_chats: bool,
+    pub search_results: Vec<SearchHit>,
+    /// Hit (index into `search_results`) the ▲/▼ buttons are on.
+    pub search_cursor: Option<usize>,
+    /// Bubble to bring into view on the next frame.
+    pub scroll_to_message: Option<i64>,
+
+    /// The other side is typing (local-only for now; see `set_peer_typing`).
+    pub peer_typing: bool,
+
+    /// Our own read-receipts preference: when off we don't see others' either.
+    pub read_receipts: bool,
+
+    /// Composed messages not yet stored, shown after the real ones.
+    pub outgoing: Vec<OutgoingMessage>,
+    next_local_id: u64,
+
+    /// Own text message being edited inline.
+    pub editing: Option<MessageEdit>,
+
+    /// "Confirmar antes de escribir a conocidos" setting, whether this chat
+    /// already got the go-ahead, and whether the prompt is up.
+    pub confirm_acquaintance_send: bool,
+    pub send_confirmed: bool,
+    pub confirm_send_open: bool,
+
+    /// We blocked this contact: the chat is read-only.
+    pub blocked: bool,
+
+    /// Consecutive messages from one sender this close together stack.
+    pub group_window_secs: i64,
+
+    /// Turn emoticons into emoji when sending.
+    pub text_autoformat: bool,
+
+    /// Tapping a link copies it instead of opening it.
+    pub copy_links: bool,
+
+    /// Decoded image-message thumbnails; dropped with the chat.
+    pub thumbnails: ThumbnailCache,
+
+    /// How many of the newest messages are loaded: the offset of the next
+    /// older page. False `has_older` once a page came back short.
+    pub history_loaded: usize,
+    pub has_older: bool,
+    /// Message to pin to the top after older ones were prepended above it.
+    pub scroll_anchor: Option<i64>,
+    /// Scroll offset last frame, to catch the moment the top is reached.
+    pub last_scroll_y: f32,
+
+    /// Message the next send replies to, previewed above the input.
+    pub reply_to: Option<Message>,
+    /// Originals quoted by loaded replies but not loaded themselves.
+    pub quoted: HashMap<i64, Message>,
+}
+
+/// Whether sending needs the extra "¿Escribir a un conocido?" tap: only for
+/// acquaintances, with the setting on, and once per opened chat.
+pub fn needs_send_confirmation(contact_type: &ContactType, setting: bool, confirmed: bool) -> bool {
+    setting && !confirmed && *contact_type == ContactType::Acquaintance
+}
+
+/// Inline editor state for one of our own messages.
+#[derive(Debug, Clone)]
+pub struct MessageEdit {
+    pub message_id: i64,
+    pub text: String,
+    pub error: Option<String>,
+}
+
+/// Where a composed message is on its way into the chat.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutgoingState {
+    Pending,
+    Failed(String),
+}
+
+/// A message the user sent that isn't a stored `Message` yet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutgoingMessage {
+    pub local_id: u64,
+    pub content: String,
+    pub reply_to: Option<i64>,
+    pub state: OutgoingState,
+}
+
+impl ActiveChat {
+    /// Show or hide the "escribiendo" indicator. Nothing sends this yet; it is
+    /// the hook a networked transport will drive.
+    pub fn set_peer_typing(&mut self, typing: bool) {
+        self.peer_typing = typing;
+    }
+
+    /// Fresh chat state for `contact` with its first page of messages.
+    pub fn new(chat_id: i64, contact: Contact, messages: Vec<Message>, mutual: bool) -> Self {
+        let history_loaded = messages.len();
+        ActiveChat {
+            chat_id,
+            contact,
+            messages,
+            input_text: String::new(),
+            scroll_to_bottom: true,
+            char_count: 0,
+            file_error: None,
+            mutual,
+            edit_history: None,
+            reactions: HashMap::new(),
+            last_tap: None,
+            reaction_pop: None,
+            info_message: None,
+            media: None,
+            media_error: None,
+            search_open: false,
+            search_query: String::new(),
+            search_all_chats: false,
+            search_results: vec![],
+            search_cursor: None,
+            scroll_to_message: None,
+            peer_typing: false,
+            read_receipts: true,
+            outgoing: vec![],
+            next_local_id: 0,
+            editing: None,
+            confirm_acquaintance_send: false,
+            send_confirmed: false,
+            confirm_send_open: false,
+            blocked: false,
+            group_window_secs: DEFAULT_GROUP_WINDOW_SECS,
+            text_autoformat: false,
+            copy_links: false,
+            thumbnails: ThumbnailCache::default(),
+            history_loaded,
+            has_older: history_loaded >= MESSAGE_PAGE,
+            scroll_anchor: None,
+            last_scroll_y: 0.0,
+            reply_to: None,
+            quoted: HashMap::new(),
+        }
+    }
+
+    /// Queue the typed text as an outgoing message and clear the input.
+    fn send_input(&mut self) -> ChatAction {
+        let mut content = self.input_text.trim().to_string();
+        if self.text_autoformat {
+            content = apply_autoformat(&content);
+        }
+        let reply_to = self.reply_to.take().map(|m| m.id);
+        let local_id = self.queue_outgoing(content.clone(), reply_to);
+        self.input_text.clear();
+        self.scroll_to_bottom = true;
+        ChatAction::SendMessage { chat_id: self.chat_id, local_id, content, reply_to }
+    }
+
+    /// Track a newly composed message as pending; returns its local id.
+    pub fn queue_outgoing(&mut self, content: String, reply_to: Option<i64>) -> u64 {
+        let local_id = self.next_local_id;
+        self.next_local_id += 1;
+        self.outgoing.push(OutgoingMessage { local_id, content, reply_to, state: OutgoingState::Pending });
+        local_id
+    }
+
+    /// The send went through: drop the pseudo-bubble and show the stored message.
+    pub fn mark_sent(&mut self, local_id: u64, message: Message) {
+        self.outgoing.retain(|o| o.local_id != local_id);
+        self.push_message(message);
+    }
+
+    /// Append a newly stored message and follow it down.
+    pub fn push_message(&mut self, message: Message) {
+        self.messages.push(message);
+        self.history_loaded += 1;
+        self.scroll_to_bottom = true;
+    }
+
+    /// Put an older page above what's loaded, keeping the view where it was.
+    pub fn prepend_older(&mut self, older: Vec<Message>) {
+        self.has_older = older.len() >= MESSAGE_PAGE;
+        self.history_loaded += older.len();
+        self.scroll_anchor = self.messages.first().map(|m| m.id);
+        self.messages.splice(0..0, older);
+    }
+
+    /// Swap in a longer stretch of history (newest `messages.len()`), e.g.
+    /// to reach a quoted message further up.
+    pub fn replace_history(&mut self, messages: Vec<Message>) {
+        self.history_loaded = messages.len();
+        // A short page on the next scroll-up settles this
+        self.has_older = true;
+        self.messages = messages;
+    }
+
+    /// Scroll to message `id`, or when it's older than the loaded page, the
+    /// action that loads history up to it first.
+    fn jump_to(&mut self, id: i64) -> Option<ChatAction> {
+        if self.messages.iter().any(|m| m.id == id) {
+            self.scroll_to_message = Some(id);
+            None
+        } else {
+            Some(ChatAction::ShowQuoted { chat_id: self.chat_id, message_id: id })
+        }
+    }
+
+    /// The message `id` a reply quotes, if it's loaded or was fetched.
+    pub fn quoted_message(&self, id: i64) -> Option<&Message> {
+        self.messages.iter().find(|m| m.id == id).or_else(|| self.quoted.get(&id))
+    }
+
+    /// Quoted ids that neither the loaded page nor `quoted` has yet.
+    pub fn missing_quotes(&self) -> Vec<i64> {
+        let loaded: HashSet<i64> = self.messages.iter().map(|m| m.id).collect();
+        let mut ids: Vec<i64> = self
+            .messages
+            .iter()
+            .filter_map(|m| m.reply_to)
+            .filter(|id| !loaded.contains(id) && !self.quoted.contains_key(id))
+            .collect();
+        ids.sort_unstable();
+        ids.dedup();
+        ids
+    }
+
+    /// The send failed: keep the bubble around with a retry affordance.
+    pub fn mark_failed(&mut self, local_id: u64, error: String) {
+        if let Some(o) = self.outgoing.iter_mut().find(|o| o.local_id == local_id) {
+            o.state = OutgoingState::Failed(error);
+        }
+    }
+
+    /// Move a failed message back to pending; returns the content to resend.
+    pub fn retry_outgoing(&mut self, local_id: u64) -> Option<String> {
+        let o = self
+            .outgoing
+            .iter_mut()
+            .find(|o| o.local_id == local_id && matches!(o.state, OutgoingState::Failed(_)))?;
+        o.state = OutgoingState::Pending;
+        Some(o.content.clone())
+    }
 }
 
 impl Default for ChatScreen {
@@ -38,11 +355,18 @@ impl Default for ChatScreen {
             tab: ChatTab::Friends,
             contacts_friends: vec![],
             contacts_acquaintances: vec![],
+            search: String::new(),
+            unread_only: false,
+            mutual_uids: HashSet::new(),
+            drafts: HashMap::new(),
+            unread: HashMap::new(),
+            blocked: HashSet::new(),
             show_add_dialog: false,
             add_uid_input: String::new(),
             add_type: ContactType::Friend,
             add_error: None,
             add_preview_user: None,
+            contact_detail: None,
             active_chat: None,
         }
     }
@@ -51,18 +375,42 @@ impl Default for ChatScreen {
 pub enum ChatAction {
     None,
     LoadContacts,
+    SwitchTab { tab: ChatTab },
     AddContact { uid: String, contact_type: ContactType },
     OpenChat { contact: Contact },
-    SendMessage { chat_id: i64, content: String },
+    /// Leave the active chat, keeping what was typed as a draft.
+    CloseChat { chat_id: i64, draft: String },
+    /// `local_id` ties the result back to its `OutgoingMessage`.
+    SendMessage { chat_id: i64, local_id: u64, content: String, reply_to: Option<i64> },
     SendFile { chat_id: i64, path: String },
+    /// Scrolled to the top: load the page before `before_id`.
+    LoadMoreMessages { chat_id: i64, before_id: i64 },
     ToggleStar { contact_uid: String, contact_type: ContactType },
+    TogglePin { contact_uid: String },
     RemoveContact { contact_uid: String },
+    /// Rename a contact in our own list.
+    RenameContact { contact_uid: String, name: String },
+    SetContactNote { contact_uid: String, note: String },
+    BlockContact { contact_uid: String },
+    UnblockContact { contact_uid: String },
     PreviewUser { uid: String },
+    ShowEditHistory { message_id: i64 },
+    /// Replace the text of one of our own messages.
+    EditMessage { message_id: i64, content: String },
+    /// Delete one of our own messages, leaving a tombstone.
+    DeleteMessage { message_id: i64 },
+    ToggleReaction { message_id: i64, emoji: String },
+    /// Bring a message (a reply's original, a search hit) into view,
+    /// loading history up to it.
+    ShowQuoted { chat_id: i64, message_id: i64 },
+    MarkAllRead,
+    ShowMedia { chat_id: i64 },
+    SearchMessages { chat_id: i64, query: String, all_chats: bool },
 }
 
 impl ChatScreen {
-    pub fn show(&mut self, ctx: &egui::Context, theme: &AppTheme, current_uid: &str) -> ChatAction {
-        let c = NimColors::for_theme(theme);
+    pub fn show(&mut self, ctx: &egui::Context, theme: &AppTheme, accent: Option<u32>, current_uid: &str) -> ChatAction {
+        let c = NimColors::for_theme(theme, accent);
         let mut action = ChatAction::None;
 
         if let Some(ref mut active) = self.active_chat {
@@ -94,6 +442,17 @@ impl ChatScreen {
                     });
                     ui.add_space(12.0);
 
+                    // Search bar
+                    ui.horizontal(|ui| {
+                        ui.add_space(16.0);
+                        ui.add(
+                            egui::TextEdit::singleline(&mut self.search)
+                                .hint_text("🔍 Buscar contacto…")
+                                .desired_width(ui.available_width() - 16.0),
+                        );
+                    });
+                    ui.add_space(8.0);
+
                     // Tab selector
                     ui.horizontal(|ui| {
                         ui.add_space(16.0);
@@ -110,13 +469,33 @@ impl ChatScreen {
                             .min_size(Vec2::new(130.0, 36.0))
                             .fill(if selected { c.primary } else { c.bg_card })
                             .rounding(Rounding::same(8.0));
-                            if ui.add(btn).clicked() {
-                                self.tab = tab;
-                                action = ChatAction::LoadContacts;
+                            if ui.add(btn).clicked() && !selected {
+                                self.tab = tab.clone();
+                                action = ChatAction::SwitchTab { tab };
                             }
                         }
                     });
 
+                    // Unread filter and bulk "read"
+                    ui.add_space(6.0);
+                    ui.horizontal(|ui| {
+                        ui.add_space(16.0);
+                        ui.toggle_value(&mut self.unread_only, RichText::new("Solo no leídos").size(12.0));
+                        ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                            ui.add_space(16.0);
+                            let any_unread = !self.unread.is_empty();
+                            let mark_btn = egui::Button::new(
+                                RichText::new("Marcar todo leído")
+                                    .size(12.0)
+                                    .color(if any_unread { c.primary } else { c.text_muted }),
+                            )
+                            .frame(false);
+                            if ui.add_enabled(any_unread, mark_btn).clicked() {
+                                action = ChatAction::MarkAllRead;
+                            }
+                        });
+                    });
+
                     ui.add_space(8.0);
                     ui.separator();
 
@@ -143,25 +522,58 @@ impl ChatScreen {
                             );
                         });
                     } else {
+                        let query = self.search.trim().to_lowercase();
+                        let (blocked, contacts): (Vec<Contact>, Vec<Contact>) = contacts
+                            .iter()
+                            .filter(|ct| {
+                                query.is_empty()
+                                    || ct.display_name.to_lowercase().contains(&query)
+                                    || ct.contact_uid.to_lowercase().contains(&query)
+                            })
+                            .filter(|ct| !self.unread_only || self.unread.contains_key(&ct.contact_uid))
+                            .cloned()
+                            .partition(|ct| self.blocked.contains(&ct.contact_uid));
+                        if contacts.is_empty() && blocked.is_empty() {
+                            ui.add_space(60.0);
+                            ui.vertical_centered(|ui| {
+                                ui.label(RichText::new("🔍").size(36.0));
+                                ui.add_space(8.0);
+                                ui.label(
+                                    RichText::new("Sin resultados")
+                                        .size(16.0)
+                                        .color(c.text_muted),
+                                );
+                                let hint = if query.is_empty() {
+                                    "No tienes mensajes sin leer aquí".to_string()
+                                } else {
+                                    format!("Ningún contacto coincide con «{}»", self.search.trim())
+                                };
+                                ui.label(RichText::new(hint).size(12.0).color(c.text_muted));
+                            });
+                        }
                         egui::ScrollArea::vertical().show(ui, |ui| {
-                            let contacts_clone = contacts.clone();
-                            for contact in contacts_clone.iter() {
-                                let row_resp = contact_row(ui, &c, contact);
-                                if row_resp.chat_clicked {
-                                    action = ChatAction::OpenChat { contact: contact.clone() };
-                                }
-                                if row_resp.star_clicked {
-                                    action = ChatAction::ToggleStar {
-                                        contact_uid: contact.contact_uid.clone(),
-                                        contact_type: contact.contact_type.clone(),
-                                    };
-                                }
-                                if row_resp.remove_clicked {
-                                    action = ChatAction::RemoveContact {
-                                        contact_uid: contact.contact_uid.clone(),
-                                    };
+                            for contact in contacts.iter() {
+                                if let Some(a) = self.contact_list_row(ui, &c, contact) {
+                                    action = a;
                                 }
                             }
+                            if !blocked.is_empty() {
+                                ui.add_space(8.0);
+                                egui::CollapsingHeader::new(
+                                    RichText::new(format!("🚫 Bloqueados ({})", blocked.len()))
+                                        .size(13.0)
+                                        .color(c.text_muted),
+                                )
+                                .id_source("blocked_contacts")
+                                .default_open(false)
+                                .show(ui, |ui| {
+                                    for contact in blocked.iter() {
+                                        if let Some(a) = self.contact_list_row(ui, &c, contact) {
+                                            action = a;
+                                        }
+                                    }
+                                });
+                            }
                             ui.add_space(80.0);
                         });
                     }
@@ -189,8 +601,44 @@ impl ChatScreen {
             }
         }
 
+        // ── Contact detail ─────────────────────────────────────────────────
+        if let Some(ref mut detail) = self.contact_detail {
+            let (close, detail_action) = show_contact_detail(ctx, &c, detail);
+            if close {
+                self.contact_detail = None;
+            }
+            if let Some(a) = detail_action {
+                action = a;
+            }
+        }
+
         action
     }
+
+    /// One row of the contacts list and the action it triggered, if any.
+    fn contact_list_row(&mut self, ui: &mut egui::Ui, c: &NimColors, contact: &Contact) -> Option<ChatAction> {
+        let mutual = self.mutual_uids.contains(&contact.contact_uid);
+        let draft = self.drafts.get(&contact.contact_uid).map(String::as_str);
+        let unread = self.unread.get(&contact.contact_uid).copied().unwrap_or(0);
+        let row_resp = contact_row(ui, c, contact, mutual, draft, unread);
+        let contact_uid = contact.contact_uid.clone();
+        if row_resp.avatar_clicked {
+            let mut detail = ContactDetail::new(contact);
+            detail.blocked = self.blocked.contains(&contact.contact_uid);
+            self.contact_detail = Some(detail);
+            None
+        } else if row_resp.remove_clicked {
+            Some(ChatAction::RemoveContact { contact_uid })
+        } else if row_resp.star_clicked {
+            Some(ChatAction::ToggleStar { contact_uid, contact_type: contact.contact_type.clone() })
+        } else if row_resp.pin_clicked {
+            Some(ChatAction::TogglePin { contact_uid })
+        } else if row_resp.chat_clicked {
+            Some(ChatAction::OpenChat { contact: contact.clone() })
+        } else {
+            None
+        }
+    }
 }
 
 // ──────────────────────────────────────────────────────────────────────────────
@@ -199,14 +647,25 @@ impl ChatScreen {
 
 struct ContactRowResponse {
     chat_clicked:   bool,
+    avatar_clicked: bool,
     star_clicked:   bool,
+    pin_clicked:    bool,
     remove_clicked: bool,
 }
 
-fn contact_row(ui: &mut egui::Ui, c: &NimColors, contact: &Contact) -> ContactRowResponse {
+fn contact_row(
+    ui: &mut egui::Ui,
+    c: &NimColors,
+    contact: &Contact,
+    mutual: bool,
+    draft: Option<&str>,
+    unread: u32,
+) -> ContactRowResponse {
     let mut resp = ContactRowResponse {
         chat_clicked: false,
+        avatar_clicked: false,
         star_clicked: false,
+        pin_clicked: false,
         remove_clicked: false,
     };
 
@@ -227,18 +686,13 @@ fn contact_row(ui: &mut egui::Ui, c: &NimColors, contact: &Contact) -> ContactRo
         rect.min + Vec2::new(16.0, (row_h - 48.0) / 2.0),
         Vec2::splat(48.0),
     );
-    let initials = contact
-        .display_name
-        .chars()
-        .next()
-        .map(|c| c.to_uppercase().to_string())
-        .unwrap_or_else(|| "?".to_string());
-    let av_color = Color32::from_rgba_premultiplied(
-        ((contact.avatar_color >> 24) & 0xFF) as u8,
-        ((contact.avatar_color >> 16) & 0xFF) as u8,
-        ((contact.avatar_color >> 8)  & 0xFF) as u8,
-        (contact.avatar_color & 0xFF) as u8,
-    );
+    let avatar_resp = ui.allocate_rect(avatar_rect, egui::Sense::click());
+    if avatar_resp.on_hover_text("Detalles del contacto").clicked() {
+        resp.avatar_clicked = true;
+    }
+    let initials = avatar_initial(&contact.display_name);
+    let (r, g, b, a) = unpack_rgba(contact.avatar_color);
+    let av_color = Color32::from_rgba_unmultiplied(r, g, b, a);
     ui.painter().circle_filled(avatar_rect.center(), 24.0, av_color);
     ui.painter().text(
         avatar_rect.center(),
@@ -247,23 +701,62 @@ fn contact_row(ui: &mut egui::Ui, c: &NimColors, contact: &Contact) -> ContactRo
         egui::FontId::proportional(20.0),
         Color32::WHITE,
     );
+    // Unread badge on the avatar's corner
+    if unread > 0 {
+        let center = avatar_rect.right_top() + Vec2::new(-4.0, 4.0);
+        let text = if unread > 99 { "99+".to_string() } else { unread.to_string() };
+        ui.painter().circle_filled(center, 10.0, c.primary);
+        ui.painter().circle_stroke(center, 10.0, Stroke::new(2.0, c.bg_base));
+        ui.painter().text(
+            center,
+            egui::Align2::CENTER_CENTER,
+            text,
+            egui::FontId::proportional(10.0),
+            Color32::WHITE,
+        );
+    }
 
-    // Name & UID
+    // Name & UID, clipped short of the pin and star buttons
     let name_pos = rect.min + Vec2::new(76.0, 14.0);
-    ui.painter().text(
-        name_pos,
-        egui::Align2::LEFT_TOP,
+    let text_w = rect.width() - 76.0 - 112.0;
+    let name_galley = elide(
+        ui.painter(),
         &contact.display_name,
         egui::FontId::proportional(15.0),
         c.text_primary,
+        text_w - 20.0,
     );
-    ui.painter().text(
-        name_pos + Vec2::new(0.0, 22.0),
-        egui::Align2::LEFT_TOP,
-        &contact.contact_uid,
-        egui::FontId::proportional(12.0),
-        c.text_muted,
-    );
+    let name_rect = egui::Rect::from_min_size(name_pos, name_galley.size());
+    ui.painter().galley(name_pos, name_galley, c.text_primary);
+    if mutual {
+        ui.painter().text(
+            name_rect.right_center() + Vec2::new(6.0, 0.0),
+            egui::Align2::LEFT_CENTER,
+            "↔",
+            egui::FontId::proportional(13.0),
+            c.success,
+        );
+    }
+    // Second line: an unsent draft takes over from the UID
+    if let Some(draft) = draft {
+        let mut job = egui::text::LayoutJob::simple_singleline(
+            format!("Borrador: {}", draft.lines().next().unwrap_or("")),
+            egui::FontId::proportional(12.0),
+            c.danger,
+        );
+        job.sections[0].format.italics = true;
+        job.wrap = elide_wrap(text_w);
+        let galley = ui.fonts(|f| f.layout_job(job));
+        ui.painter().galley(name_pos + Vec2::new(0.0, 22.0), galley, c.danger);
+    } else {
+        ui.painter().text(
+            name_pos + Vec2::new(0.0, 22.0),
+            egui::Align2::LEFT_TOP,
+            &contact.contact_uid,
+            egui::FontId::proportional(12.0),
+            c.text_muted,
+        );
+    }
 
     // Star button (top-right)
     let star_center = rect.max - Vec2::new(48.0, row_h / 2.0);
@@ -281,6 +774,21 @@ fn contact_row(ui: &mut egui::Ui, c: &NimColors, contact: &Contact) -> ContactRo
         resp.star_clicked = true;
     }
 
+    // Pin button, left of the star
+    let pin_center = star_center - Vec2::new(40.0, 0.0);
+    let pin_rect = egui::Rect::from_center_size(pin_center, Vec2::splat(32.0));
+    let pin_resp = ui.allocate_rect(pin_rect, egui::Sense::click());
+    ui.painter().text(
+        pin_center,
+        egui::Align2::CENTER_CENTER,
+        "📌",
+        egui::FontId::proportional(18.0),
+        if contact.pinned { c.primary } else { c.star_inactive },
+    );
+    if pin_resp.on_hover_text(if contact.pinned { "Desfijar" } else { "Fijar arriba" }).clicked() {
+        resp.pin_clicked = true;
+    }
+
     // Divider
     ui.painter().line_segment(
         [rect.left_bottom() + Vec2::new(16.0, 0.0), rect.right_bottom() - Vec2::new(16.0, 0.0)],
@@ -290,6 +798,109 @@ fn contact_row(ui: &mut egui::Ui, c: &NimColors, contact: &Contact) -> ContactRo
     resp
 }
 
+// ──────────────────────────────────────────────────────────────────────────────
+// CONTACT DETAIL DIALOG
+// ──────────────────────────────────────────────────────────────────────────────
+
+/// Returns whether the dialog should close, and the action to run.
+fn show_contact_detail(
+    ctx: &egui::Context,
+    c: &NimColors,
+    detail: &mut ContactDetail,
+) -> (bool, Option<ChatAction>) {
+    let mut close = false;
+    let mut action = None;
+
+    egui::Window::new("Contacto")
+        .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+        .resizable(false)
+        .collapsible(false)
+        .frame(
+            egui::Frame::window(&ctx.style())
+                .fill(c.bg_card)
+                .stroke(Stroke::new(1.0, c.border))
+                .rounding(Rounding::same(14.0)),
+        )
+        .show(ctx, |ui| {
+            ui.set_min_width(320.0);
+            ui.set_max_width(380.0);
+
+            ui.label(
+                RichText::new(format!("ID: {}", detail.contact.contact_uid))
+                    .size(12.0)
+                    .color(c.text_muted),
+            );
+            ui.add_space(10.0);
+
+            ui.label(RichText::new("Nombre").size(13.0).color(c.text_secondary));
+            ui.horizontal(|ui| {
+                ui.add(
+                    egui::TextEdit::singleline(&mut detail.name)
+                        .hint_text("Nombre para este contacto")
+                        .desired_width(ui.available_width() - 40.0),
+                );
+                let changed = detail.name.trim() != detail.contact.display_name;
+                if ui
+                    .add_enabled(changed, egui::Button::new("✔"))
+                    .on_hover_text("Renombrar (solo lo ves tú)")
+                    .clicked()
+                {
+                    action = Some(ChatAction::RenameContact {
+                        contact_uid: detail.contact.contact_uid.clone(),
+                        name: detail.name.trim().to_string(),
+                    });
+                }
+            });
+            ui.add_space(10.0);
+
+            ui.label(RichText::new("Nota privada").size(13.0).color(c.text_secondary));
+            ui.add(
+                egui::TextEdit::multiline(&mut detail.note)
+                    .hint_text("Solo tú puedes verla")
+                    .desired_rows(3)
+                    .desired_width(f32::INFINITY),
+            );
+            let note_changed = detail.note.trim() != detail.contact.note.as_deref().unwrap_or("");
+            if ui.add_enabled(note_changed, egui::Button::new("Guardar nota")).clicked() {
+                action = Some(ChatAction::SetContactNote {
+                    contact_uid: detail.contact.contact_uid.clone(),
+                    note: detail.note.clone(),
+                });
+            }
+
+            if let Some(ref err) = detail.error {
+                ui.add_space(6.0);
+                ui.label(RichText::new(format!("⚠ {}", err)).size(12.0).color(c.danger));
+            } else if let Some(ref saved) = detail.saved {
+                ui.add_space(6.0);
+                ui.label(RichText::new(format!("✓ {}", saved)).size(12.0).color(c.success));
+            }
+
+            ui.add_space(10.0);
+            ui.horizontal(|ui| {
+                let contact_uid = detail.contact.contact_uid.clone();
+                if detail.blocked {
+                    if ui.button("Desbloquear").clicked() {
+                        action = Some(ChatAction::UnblockContact { contact_uid });
+                    }
+                } else if ui
+                    .button(RichText::new("🚫 Bloquear").color(c.danger))
+                    .on_hover_text("No podrá enviarte mensajes")
+                    .clicked()
+                {
+                    action = Some(ChatAction::BlockContact { contact_uid });
+                }
+                ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                    if ui.button("Cerrar").clicked() {
+                        close = true;
+                    }
+                });
+            });
+        });
+
+    (close, action)
+}
+
 // ──────────────────────────────────────────────────────────────────────────────
 // ADD CONTACT DIALOG
 // ──────────────────────────────────────────────────────────────────────────────
@@ -434,13 +1045,41 @@ fn show_chat_window(
 ) -> ChatAction {
     let mut action = ChatAction::None;
 
+    // Escape leaves the chat like "←", once nothing has focus (a first
+    // Escape just drops focus from the input or an edit box) and no popup is
+    // open (those don't take focus, so Escape closes them first). Android's
+    // back key never gets here: winit takes it from the activity's input
+    // queue and egui-winit 0.27 has no key to map it to.
+    if ctx.memory(|m| m.focused().is_none())
+        && ctx.input_mut(|i| i.consume_key(egui::Modifiers::NONE, egui::Key::Escape))
+    {
+        if active.media.is_some() {
+            active.media = None;
+            active.media_error = None;
+        } else if active.edit_history.is_some() {
+            active.edit_history = None;
+        } else if active.info_message.is_some() {
+            active.info_message = None;
+        } else if active.confirm_send_open {
+            active.confirm_send_open = false;
+        } else {
+            action = ChatAction::CloseChat {
+                chat_id: active.chat_id,
+                draft: active.input_text.clone(),
+            };
+        }
+    }
+
     // Header
     egui::TopBottomPanel::top("chat_header")
         .frame(egui::Frame::none().fill(c.bg_elevated).inner_margin(egui::style::Margin::symmetric(16.0, 12.0)))
         .show(ctx, |ui| {
             ui.horizontal(|ui| {
                 if ui.button("←").clicked() {
-                    // This will be handled in app.rs by setting active_chat = None
+                    action = ChatAction::CloseChat {
+                        chat_id: active.chat_id,
+                        draft: active.input_text.clone(),
+                    };
                 }
                 ui.add_space(8.0);
                 // Avatar
@@ -448,77 +1087,293 @@ fn show_chat_window(
                 ui.painter().circle_filled(rect.center(), 18.0, c.primary);
                 ui.painter().text(
                     rect.center(), egui::Align2::CENTER_CENTER,
-                    active.contact.display_name.chars().next().unwrap_or('?').to_string(),
+                    avatar_initial(&active.contact.display_name),
                     egui::FontId::proportional(16.0), Color32::WHITE,
                 );
                 ui.add_space(8.0);
+                // Leave room for the mutual mark and the 📁/🔍/🚫 buttons
+                let name_w = ui.available_width() - 140.0;
                 ui.vertical(|ui| {
-                    ui.label(RichText::new(&active.contact.display_name).strong().color(c.text_primary).size(15.0));
+                    ui.horizontal(|ui| {
+                        let name = elide(
+                            ui.painter(),
+                            &active.contact.display_name,
+                            egui::FontId::proportional(15.0),
+                            c.text_primary,
+                            name_w,
+                        );
+                        ui.label(name).on_hover_text(&active.contact.display_name);
+                        if active.mutual {
+                            ui.label(RichText::new("↔").size(13.0).color(c.success))
+                                .on_hover_text("Se agregaron mutuamente");
+                        }
+                    });
                     ui.label(RichText::new(&active.contact.contact_uid).size(11.0).color(c.text_muted));
                 });
+                ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                    let contact_uid = active.contact.contact_uid.clone();
+                    if active.blocked {
+                        if ui.button("🔓").on_hover_text("Desbloquear").clicked() {
+                            action = ChatAction::UnblockContact { contact_uid };
+                        }
+                    } else if ui.button("🚫").on_hover_text("Bloquear").clicked() {
+                        action = ChatAction::BlockContact { contact_uid };
+                    }
+                    if ui.button("📁").on_hover_text("Archivos compartidos").clicked() {
+                        action = ChatAction::ShowMedia { chat_id: active.chat_id };
+                    }
+                    if ui.selectable_label(active.search_open, "🔍").on_hover_text("Buscar mensajes").clicked() {
+                        active.search_open = !active.search_open;
+                        if !active.search_open {
+                            active.search_query.clear();
+                            active.search_results.clear();
+                            active.search_cursor = None;
+                        }
+                    }
+                });
             });
         });
 
-    // Message input at bottom
-    egui::TopBottomPanel::bottom("chat_input")
-        .frame(egui::Frame::none().fill(c.bg_elevated).inner_margin(egui::style::Margin::symmetric(12.0, 10.0)))
-        .show(ctx, |ui| {
-            let remaining = Message::MAX_TEXT_LEN.saturating_sub(active.input_text.len());
-            ui.horizontal(|ui| {
-                // File attach button
-                let attach_btn = egui::Button::new("📎")
-                    .fill(c.bg_input)
-                    .rounding(Rounding::same(8.0))
-                    .min_size(Vec2::splat(42.0));
-                if ui.add(attach_btn).clicked() {
-                    // On Android, file picker would be triggered via JNI
-                    // For now show placeholder message
-                    active.file_error = Some("Selector de archivos (implementar via JNI Android)".into());
+    // Search bar (below the header)
+    if active.search_open {
+        egui::TopBottomPanel::top("chat_search")
+            .frame(egui::Frame::none().fill(c.bg_elevated).inner_margin(egui::Margin::symmetric(16.0, 8.0)))
+            .show(ctx, |ui| {
+                let query_resp = ui.add(
+                    egui::TextEdit::singleline(&mut active.search_query)
+                        .hint_text("Buscar mensajes…")
+                        .desired_width(f32::INFINITY),
+                );
+                let mut changed = query_resp.changed();
+                ui.horizontal(|ui| {
+                    for (label, all) in [("En este chat", false), ("En todos los chats", true)] {
+                        if ui.selectable_label(active.search_all_chats == all, label).clicked()
+                            && active.search_all_chats != all
+                        {
+                            active.search_all_chats = all;
+                            changed = true;
+                        }
+                    }
+                });
+                if changed {
+                    action = ChatAction::SearchMessages {
+                        chat_id: active.chat_id,
+                        query: active.search_query.clone(),
+                        all_chats: active.search_all_chats,
+                    };
                 }
 
-                let text_edit = egui::TextEdit::multiline(&mut active.input_text)
-                    .hint_text("Escribe un mensaje…")
-                    .desired_width(ui.available_width() - 55.0)
-                    .desired_rows(1)
-                    .font(egui::FontId::proportional(14.0));
-                let te_resp = ui.add(text_edit);
+                if !active.search_query.trim().is_empty() {
+                    ui.add_space(4.0);
+                    let in_chat: Vec<usize> = active
+                        .search_results
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, hit)| hit.message.chat_id == active.chat_id)
+                        .map(|(i, _)| i)
+                        .collect();
+                    if active.search_results.is_empty() {
+                        ui.label(RichText::new("Sin resultados").size(12.0).color(c.text_muted));
+                    } else if !in_chat.is_empty() {
+                        // Hits come newest first: ▲ walks back in time, ▼ forward
+                        let pos = active
+                            .search_cursor
+                            .and_then(|cur| in_chat.iter().position(|&i| i == cur));
+                        let mut target = None;
+                        ui.horizontal(|ui| {
+                            let shown = pos.map_or(0, |p| p + 1);
+                            ui.label(RichText::new(format!("{}/{}", shown, in_chat.len())).size(12.0).color(c.text_muted));
+                            let older = pos.map_or(0, |p| p + 1);
+                            if ui.add_enabled(older < in_chat.len(), egui::Button::new("▲"))
+                                .on_hover_text("Anterior")
+                                .clicked()
+                            {
+                                target = Some(in_chat[older]);
+                            }
+                            if ui.add_enabled(pos.is_some_and(|p| p > 0), egui::Button::new("▼"))
+                                .on_hover_text("Siguiente")
+                                .clicked()
+                            {
+                                target = pos.map(|p| in_chat[p - 1]);
+                            }
+                        });
+                        if let Some(i) = target {
+                            active.search_cursor = Some(i);
+                            if let Some(load) = active.jump_to(active.search_results[i].message.id) {
+                                action = load;
+                            }
+                        }
+                    }
+                    let mut clicked = None;
+                    egui::ScrollArea::vertical().id_source("search_results").max_height(180.0).show(ui, |ui| {
+                        for (i, hit) in active.search_results.iter().enumerate() {
+                            ui.horizontal(|ui| {
+                                if active.search_all_chats {
+                                    ui.label(RichText::new(&hit.contact_name).size(12.0).strong().color(c.primary));
+                                }
+                                ui.label(
+                                    RichText::new(format_datetime(&hit.message.sent_at))
+                                        .size(10.0)
+                                        .color(c.text_muted),
+                                );
+                                // Hits in this chat jump to their bubble
+                                if hit.message.chat_id == active.chat_id
+                                    && ui
+                                        .add(
+                                            egui::Label::new(RichText::new("↪ Ir").size(11.0).color(c.primary))
+                                                .sense(egui::Sense::click()),
+                                        )
+                                        .clicked()
+                                {
+                                    clicked = Some(i);
+                                }
+                            });
+                            let snippet: String = hit.message.content.chars().take(80).collect();
+                            ui.label(RichText::new(snippet).size(13.0).color(c.text_secondary));
+                            ui.add_space(4.0);
+                        }
+                    });
+                    if let Some(i) = clicked {
+                        active.search_cursor = Some(i);
+                        if let Some(load) = active.jump_to(active.search_results[i].message.id) {
+                            action = load;
+                        }
+                    }
+                }
+            });
+    }
+
+    // Blocked: read-only, the input bar gives way to a notice
+    if active.blocked {
+        egui::TopBottomPanel::bottom("chat_blocked")
+            .frame(egui::Frame::none().fill(c.bg_elevated).inner_margin(egui::Margin::symmetric(12.0, 14.0)))
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label(
+                        RichText::new("🚫 Bloqueaste a este contacto")
+                            .size(13.0)
+                            .color(c.text_muted),
+                    );
+                    ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                        if ui.button("Desbloquear").clicked() {
+                            action = ChatAction::UnblockContact {
+                                contact_uid: active.contact.contact_uid.clone(),
+                            };
+                        }
+                    });
+                });
+            });
+    } else {
+        // Message input at bottom
+        egui::TopBottomPanel::bottom("chat_input")
+            .frame(egui::Frame::none().fill(c.bg_elevated).inner_margin(egui::Margin::symmetric(12.0, 10.0)))
+            .show(ctx, |ui| {
+                let remaining = Message::MAX_TEXT_LEN.saturating_sub(active.input_text.len());
 
-                // Enforce max length
-                if active.input_text.len() > Message::MAX_TEXT_LEN {
-                    active.input_text.truncate(Message::MAX_TEXT_LEN);
+                // Quoted preview of the message being replied to
+                if let Some(parent) = active.reply_to.clone() {
+                    let author = quote_author(&parent, current_uid, &active.contact);
+                    let mut cancel = false;
+                    egui::Frame::none()
+                        .fill(c.bg_input)
+                        .rounding(Rounding::same(8.0))
+                        .inner_margin(egui::Margin::symmetric(10.0, 6.0))
+                        .show(ui, |ui| {
+                            ui.horizontal(|ui| {
+                                ui.vertical(|ui| {
+                                    ui.set_max_width(ui.available_width() - 32.0);
+                                    ui.label(
+                                        RichText::new(format!("↩ {}", author)).size(12.0).strong().color(c.primary),
+                                    );
+                                    ui.add(
+                                        egui::Label::new(
+                                            RichText::new(quote_snippet(&parent)).size(12.0).color(c.text_secondary),
+                                        )
+                                        .truncate(true),
+                                    );
+                                });
+                                ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                                    if ui.small_button("✕").on_hover_text("No responder").clicked() {
+                                        cancel = true;
+                                    }
+                                });
+                            });
+                        });
+                    if cancel {
+                        active.reply_to = None;
+                    }
+                    ui.add_space(6.0);
                 }
 
-                let send_btn = egui::Button::new(RichText::new("➤").size(18.0).color(Color32::WHITE))
-                    .fill(c.primary)
-                    .rounding(Rounding::same(10.0))
-                    .min_size(Vec2::splat(42.0));
+                ui.horizontal(|ui| {
+                    // File attach button
+                    let attach_btn = egui::Button::new("📎")
+                        .fill(c.bg_input)
+                        .rounding(Rounding::same(8.0))
+                        .min_size(Vec2::splat(42.0));
+                    if ui.add(attach_btn).clicked() {
+                        active.file_error = None;
+                        #[cfg(not(target_os = "android"))]
+                        if let Some(path) = rfd::FileDialog::new().set_title("Adjuntar archivo").pick_file() {
+                            action = ChatAction::SendFile {
+                                chat_id: active.chat_id,
+                                path: path.to_string_lossy().into_owned(),
+                            };
+                        }
+                        // On Android, the file picker would be triggered via JNI
+                        #[cfg(target_os = "android")]
+                        {
+                            active.file_error = Some("Adjuntar archivos aún no está disponible en Android".into());
+                        }
+                    }
 
-                let send = ui.add(send_btn).clicked()
-                    || (te_resp.has_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter) && !i.modifiers.shift));
+                    let text_edit = egui::TextEdit::multiline(&mut active.input_text)
+                        .hint_text("Escribe un mensaje…")
+                        .desired_width(ui.available_width() - 55.0)
+                        .desired_rows(1)
+                        .font(egui::FontId::proportional(14.0));
+                    let te_resp = ui.add(text_edit);
 
-                if send && !active.input_text.trim().is_empty() {
-                    action = ChatAction::SendMessage {
-                        chat_id: active.chat_id,
-                        content: active.input_text.trim().to_string(),
-                    };
-                    active.input_text.clear();
-                    active.scroll_to_bottom = true;
-                }
-            });
+                    // Enforce max length
+                    if active.input_text.len() > Message::MAX_TEXT_LEN {
+                        active.input_text.truncate(Message::MAX_TEXT_LEN);
+                    }
 
-            // Char counter
-            if active.input_text.len() > 800 {
-                ui.label(
-                    RichText::new(format!("{}/1000", active.input_text.len()))
-                        .size(11.0)
-                        .color(if remaining < 50 { c.danger } else { c.text_muted }),
-                );
-            }
+                    let send_btn = egui::Button::new(RichText::new("➤").size(18.0).color(Color32::WHITE))
+                        .fill(c.primary)
+                        .rounding(Rounding::same(10.0))
+                        .min_size(Vec2::splat(42.0));
 
-            if let Some(ref err) = active.file_error {
-                ui.label(RichText::new(err).size(11.0).color(c.warning));
-            }
-        });
+                    let send = ui.add(send_btn).clicked()
+                        || (te_resp.has_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter) && !i.modifiers.shift));
+
+                    if send && !active.input_text.trim().is_empty() {
+                        if needs_send_confirmation(
+                            &active.contact.contact_type,
+                            active.confirm_acquaintance_send,
+                            active.send_confirmed,
+                        ) {
+                            active.confirm_send_open = true;
+                        } else {
+                            action = active.send_input();
+                        }
+                    }
+                });
+
+                // Char counter
+                if active.input_text.len() > 800 {
+                    ui.label(
+                        RichText::new(format!("{}/1000", active.input_text.len()))
+                            .size(11.0)
+                            .color(if remaining < 50 { c.danger } else { c.text_muted }),
+                    );
+                }
+
+                if let Some(ref err) = active.file_error {
+                    ui.label(RichText::new(err).size(11.0).color(c.warning));
+                }
+            });
+    }
 
     // Message bubbles
     egui::CentralPanel::default()
@@ -528,22 +1383,612 @@ fn show_chat_window(
                 .auto_shrink([false; 2])
                 .stick_to_bottom(active.scroll_to_bottom);
 
-            scroll.show(ui, |ui| {
+            let output = scroll.show(ui, |ui| {
                 ui.add_space(8.0);
                 let messages = active.messages.clone();
-                for msg in &messages {
+                // Search hits in this chat get outlined; the current one stands out
+                let hits: HashSet<i64> = if active.search_open {
+                    active
+                        .search_results
+                        .iter()
+                        .filter(|hit| hit.message.chat_id == active.chat_id)
+                        .map(|hit| hit.message.id)
+                        .collect()
+                } else {
+                    HashSet::new()
+                };
+                let current_hit = active
+                    .search_cursor
+                    .and_then(|i| active.search_results.get(i))
+                    .map(|hit| hit.message.id);
+                let today = chrono::Local::now().date_naive();
+                let days: Vec<_> = messages.iter().map(|m| local_day(&m.sent_at)).collect();
+                if messages.is_empty() && active.outgoing.is_empty() {
+                    ui.add_space(24.0);
+                    ui.vertical_centered(|ui| {
+                        ui.label(RichText::new("Sin mensajes todavía").size(13.0).color(c.text_muted));
+                    });
+                }
+                for (i, msg) in messages.iter().enumerate() {
                     let is_mine = msg.sender_uid == current_uid;
-                    message_bubble(ui, c, msg, is_mine);
+                    let window = active.group_window_secs;
+                    // A new calendar day gets a separator and never stacks on the previous bubble
+                    let new_day = i == 0 || days[i] != days[i - 1];
+                    if new_day {
+                        if let Some(day) = days[i] {
+                            day_separator(ui, c, &day_label(day, today));
+                        }
+                    }
+                    let grouped_prev = !new_day && should_group(i.checked_sub(1).map(|p| &messages[p]), msg, window);
+                    let grouped_next = messages
+                        .get(i + 1)
+                        .filter(|_| days.get(i + 1) == Some(&days[i]))
+                        .is_some_and(|next| should_group(Some(msg), next, window));
+                    let reactions = active.reactions.get(&msg.id).map(Vec::as_slice).unwrap_or(&[]);
+                    let read = is_mine && active.read_receipts && msg.is_read;
+                    let thumbnail = (msg.msg_type == MessageType::Image && !msg.deleted)
+                        .then(|| active.thumbnails.get(ctx, &msg.content))
+                        .flatten();
+                    let quote = msg.reply_to.filter(|_| !msg.deleted).map(|id| match active.quoted_message(id) {
+                        Some(q) => (quote_author(q, current_uid, &active.contact), quote_snippet(q)),
+                        None => (String::new(), "Mensaje no disponible".to_string()),
+                    });
+                    let bubble = message_bubble(ui, c, BubbleProps {
+                        msg,
+                        is_mine,
+                        show_read: read,
+                        grouped_prev,
+                        grouped_next,
+                        reactions,
+                        thumbnail: thumbnail.as_ref(),
+                        quote: quote.as_ref().map(|(author, snippet)| (author.as_str(), snippet.as_str())),
+                        copy_links: active.copy_links,
+                    });
+                    if hits.contains(&msg.id) {
+                        let (width, color) = if current_hit == Some(msg.id) {
+                            (2.5, c.accent)
+                        } else {
+                            (1.5, c.warning)
+                        };
+                        ui.painter().rect_stroke(bubble.rect.expand(2.0), Rounding::same(14.0), Stroke::new(width, color));
+                    }
+                    if active.scroll_to_message == Some(msg.id) {
+                        ui.scroll_to_rect(bubble.rect, Some(Align::Center));
+                        active.scroll_to_message = None;
+                    }
+                    if active.scroll_anchor == Some(msg.id) {
+                        ui.scroll_to_rect(bubble.rect, Some(Align::Min));
+                        active.scroll_anchor = None;
+                    }
+                    if bubble.info_clicked {
+                        active.info_message = Some(msg.clone());
+                    }
+                    if bubble.history_clicked {
+                        action = ChatAction::ShowEditHistory { message_id: msg.id };
+                    }
+                    if bubble.delete_clicked {
+                        action = ChatAction::DeleteMessage { message_id: msg.id };
+                    }
+                    if bubble.reply_clicked {
+                        active.reply_to = Some(msg.clone());
+                    }
+                    if let Some(original) = msg.reply_to.filter(|_| bubble.quote_clicked) {
+                        if active.messages.iter().any(|m| m.id == original) {
+                            active.scroll_to_message = Some(original);
+                        } else {
+                            action = ChatAction::ShowQuoted { chat_id: active.chat_id, message_id: original };
+                        }
+                    }
+                    if bubble.edit_clicked {
+                        active.editing = Some(MessageEdit { message_id: msg.id, text: msg.content.clone(), error: None });
+                    }
+                    if let Some(edit) = active.editing.as_mut().filter(|e| e.message_id == msg.id) {
+                        match message_editor(ui, c, edit) {
+                            Some(true) => {
+                                action = ChatAction::EditMessage { message_id: msg.id, content: edit.text.clone() };
+                            }
+                            Some(false) => active.editing = None,
+                            None => {}
+                        }
+                    }
+                    if let Some(emoji) = bubble.reaction_clicked {
+                        action = ChatAction::ToggleReaction { message_id: msg.id, emoji };
+                    }
+
+                    // Double-tap an incoming bubble to toggle ❤️
+                    let now = ui.input(|i| i.time);
+                    if bubble.tapped && !is_mine && !msg.deleted {
+                        let prev = active.last_tap.filter(|(id, _)| *id == msg.id).map(|(_, t)| t);
+                        if is_double_tap(prev, now) {
+                            active.last_tap = None;
+                            active.reaction_pop = Some((msg.id, now));
+                            action = ChatAction::ToggleReaction { message_id: msg.id, emoji: QUICK_REACTION.into() };
+                        } else {
+                            active.last_tap = Some((msg.id, now));
+                        }
+                    }
+                    if let Some((pop_id, start)) = active.reaction_pop {
+                        let t = ((now - start) / REACTION_POP_SECS) as f32;
+                        if pop_id == msg.id && t < 1.0 {
+                            ui.painter().text(
+                                bubble.rect.center(),
+                                egui::Align2::CENTER_CENTER,
+                                QUICK_REACTION,
+                                egui::FontId::proportional(18.0 + 22.0 * (t * std::f32::consts::PI).sin()),
+                                c.danger.linear_multiply(1.0 - t),
+                            );
+                            ui.ctx().request_repaint();
+                        } else if pop_id == msg.id {
+                            active.reaction_pop = None;
+                        }
+                    }
+                }
+
+                // Not yet stored: pending (faded) or failed (tap to retry)
+                if !active.outgoing.is_empty() && days.last().copied().flatten() != Some(today) {
+                    day_separator(ui, c, &day_label(today, today));
+                }
+                for o in active.outgoing.clone() {
+                    if outgoing_bubble(ui, c, &o) {
+                        if let Some(content) = active.retry_outgoing(o.local_id) {
+                            action = ChatAction::SendMessage {
+                                chat_id: active.chat_id,
+                                local_id: o.local_id,
+                                content,
+                                reply_to: o.reply_to,
+                            };
+                        }
+                    }
                 }
+
+                if active.peer_typing {
+                    typing_indicator(ui, c);
+                }
+
                 active.scroll_to_bottom = false;
                 ui.add_space(8.0);
             });
+
+            // Only on arriving at the top: opening a chat starts there for a
+            // frame before sticking to the bottom
+            let scroll_y = output.state.offset.y;
+            if scroll_y <= 0.0 && active.last_scroll_y > 0.0 && active.has_older {
+                if let Some(first) = active.messages.first() {
+                    action = ChatAction::LoadMoreMessages { chat_id: active.chat_id, before_id: first.id };
+                }
+            }
+            active.last_scroll_y = scroll_y;
         });
 
+    // Edit history popup
+    let mut close_history = false;
+    if let Some((_, ref history)) = active.edit_history {
+        egui::Window::new("Historial de ediciones")
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .resizable(false)
+            .collapsible(false)
+            .frame(
+                egui::Frame::window(&ctx.style())
+                    .fill(c.bg_card)
+                    .stroke(Stroke::new(1.0, c.border))
+                    .rounding(Rounding::same(14.0)),
+            )
+            .show(ctx, |ui| {
+                ui.set_max_width(360.0);
+                if history.is_empty() {
+                    ui.label(RichText::new("Sin versiones anteriores").color(c.text_muted));
+                }
+                egui::ScrollArea::vertical().max_height(280.0).show(ui, |ui| {
+                    for (old_content, edited_at) in history {
+                        ui.label(
+                            RichText::new(format!(
+                                "{} {}",
+                                edited_at.get(..10).unwrap_or(""),
+                                edited_at.get(11..16).unwrap_or("")
+                            ))
+                            .size(11.0)
+                            .color(c.text_muted),
+                        );
+                        ui.label(RichText::new(old_content).size(13.0).color(c.text_secondary));
+                        ui.add_space(6.0);
+                    }
+                });
+                ui.add_space(8.0);
+                if ui.button("Cerrar").clicked() {
+                    close_history = true;
+                }
+            });
+    }
+    if close_history {
+        active.edit_history = None;
+    }
+
+    // Message info panel
+    let mut close_info = false;
+    if let Some(ref msg) = active.info_message {
+        egui::Window::new("Info del mensaje")
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .resizable(false)
+            .collapsible(false)
+            .frame(
+                egui::Frame::window(&ctx.style())
+                    .fill(c.bg_card)
+                    .stroke(Stroke::new(1.0, c.border))
+                    .rounding(Rounding::same(14.0)),
+            )
+            .show(ctx, |ui| {
+                ui.set_max_width(360.0);
+                let mut rows: Vec<(&str, String)> = vec![
+                    ("Enviado", format_datetime(&msg.sent_at)),
+                    ("Estado", match (msg.sender_uid == current_uid, active.read_receipts, msg.is_read) {
+                        (true, false, _) => "Enviado".into(),
+                        (_, _, true) => "Leído".into(),
+                        _ => "No leído".into(),
+                    }),
+                ];
+                if let Some(ref edited_at) = msg.edited_at {
+                    rows.push(("Editado", format_datetime(edited_at)));
+                }
+                if msg.msg_type != MessageType::Text {
+                    rows.push(("Archivo", msg.file_name.clone().unwrap_or_else(|| "—".into())));
+                    rows.push(("Tamaño", msg.file_size.map(human_size).unwrap_or_else(|| "—".into())));
+                }
+                rows.push(("ID", msg.id.to_string()));
+
+                egui::Grid::new("message_info").num_columns(2).spacing([16.0, 6.0]).show(ui, |ui| {
+                    for (label, value) in rows {
+                        ui.label(RichText::new(label).size(12.0).color(c.text_muted));
+                        ui.label(RichText::new(value).size(13.0).color(c.text_primary));
+                        ui.end_row();
+                    }
+                });
+                ui.add_space(10.0);
+                if ui.button("Cerrar").clicked() {
+                    close_info = true;
+                }
+            });
+    }
+    if close_info {
+        active.info_message = None;
+    }
+
+    // First message to an acquaintance: ask once
+    if active.confirm_send_open {
+        let mut answer = None;
+        egui::Window::new("¿Escribir a un conocido?")
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .resizable(false)
+            .collapsible(false)
+            .frame(
+                egui::Frame::window(&ctx.style())
+                    .fill(c.bg_card)
+                    .stroke(Stroke::new(1.0, c.border))
+                    .rounding(Rounding::same(14.0)),
+            )
+            .show(ctx, |ui| {
+                ui.set_max_width(320.0);
+                ui.label(
+                    RichText::new(format!(
+                        "{} no está entre tus amigos. ¿Enviar el mensaje igualmente?",
+                        active.contact.display_name
+                    ))
+                    .size(13.0)
+                    .color(c.text_secondary),
+                );
+                ui.add_space(10.0);
+                ui.horizontal(|ui| {
+                    if ui.button("Enviar").clicked() {
+                        answer = Some(true);
+                    }
+                    if ui.button("Cancelar").clicked() {
+                        answer = Some(false);
+                    }
+                });
+            });
+        match answer {
+            Some(true) => {
+                active.confirm_send_open = false;
+                active.send_confirmed = true;
+                if !active.input_text.trim().is_empty() {
+                    action = active.send_input();
+                }
+            }
+            Some(false) => active.confirm_send_open = false,
+            None => {}
+        }
+    }
+
+    // Shared files
+    let mut close_media = false;
+    if let Some(ref media) = active.media {
+        egui::Window::new("Archivos compartidos")
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .resizable(false)
+            .collapsible(false)
+            .frame(
+                egui::Frame::window(&ctx.style())
+                    .fill(c.bg_card)
+                    .stroke(Stroke::new(1.0, c.border))
+                    .rounding(Rounding::same(14.0)),
+            )
+            .show(ctx, |ui| {
+                ui.set_min_width(300.0);
+                ui.set_max_width(400.0);
+                if media.is_empty() {
+                    ui.label(RichText::new("Aún no se han compartido archivos").color(c.text_muted));
+                }
+                egui::ScrollArea::vertical().max_height(360.0).show(ui, |ui| {
+                    for kind in [MessageType::Image, MessageType::Video, MessageType::Document, MessageType::Archive] {
+                        let items: Vec<&Message> = media.iter().filter(|m| m.msg_type == kind).collect();
+                        if items.is_empty() {
+                            continue;
+                        }
+                        ui.label(
+                            RichText::new(format!("{} {} ({})", kind.icon(), kind.label(), items.len()))
+                                .strong()
+                                .color(c.text_primary),
+                        );
+                        for m in items {
+                            let name = m.file_name.as_deref().unwrap_or("archivo");
+                            let resp = ui.horizontal(|ui| {
+                                ui.add(
+                                    egui::Label::new(RichText::new(name).size(13.0).color(c.primary))
+                                        .sense(egui::Sense::click()),
+                                )
+                            }).inner;
+                            ui.label(
+                                RichText::new(format!(
+                                    "{} · {}",
+                                    m.file_size.map(human_size).unwrap_or_else(|| "—".into()),
+                                    format_datetime(&m.sent_at)
+                                ))
+                                .size(11.0)
+                                .color(c.text_muted),
+                            );
+                            if resp.on_hover_text(&m.content).clicked() {
+                                // `content` holds the file path for non-text messages
+                                let url = file_url(&m.content).filter(|_| std::path::Path::new(&m.content).exists());
+                                match url {
+                                    Some(url) => {
+                                        ctx.open_url(egui::OpenUrl::new_tab(url));
+                                        active.media_error = None;
+                                    }
+                                    None => {
+                                        active.media_error = Some(format!("No se encontró «{}» en este equipo", name));
+                                    }
+                                }
+                            }
+                            ui.add_space(4.0);
+                        }
+                        ui.add_space(8.0);
+                    }
+                });
+                if let Some(ref err) = active.media_error {
+                    ui.label(RichText::new(err).size(12.0).color(c.danger));
+                }
+                ui.add_space(8.0);
+                if ui.button("Cerrar").clicked() {
+                    close_media = true;
+                }
+            });
+    }
+    if close_media {
+        active.media = None;
+        active.media_error = None;
+    }
+
     action
 }
 
-fn message_bubble(ui: &mut egui::Ui, c: &NimColors, msg: &Message, is_mine: bool) {
+/// Centered pill between the messages of two calendar days.
+fn day_separator(ui: &mut egui::Ui, c: &NimColors, label: &str) {
+    ui.add_space(8.0);
+    ui.vertical_centered(|ui| {
+        egui::Frame::none()
+            .fill(c.bg_card)
+            .stroke(Stroke::new(1.0, c.border))
+            .rounding(Rounding::same(10.0))
+            .inner_margin(egui::Margin::symmetric(10.0, 3.0))
+            .show(ui, |ui| {
+                ui.add(egui::Label::new(RichText::new(label).size(11.0).color(c.text_secondary)).selectable(false));
+            });
+    });
+    ui.add_space(4.0);
+}
+
+/// Incoming-style bubble with three pulsing dots and "escribiendo".
+fn typing_indicator(ui: &mut egui::Ui, c: &NimColors) {
+    let elapsed = ui.input(|i| i.time) as f32;
+    ui.add_space(4.0);
+    egui::Frame::none()
+        .fill(c.bg_card)
+        .rounding(Rounding { nw: 4.0, ne: 14.0, sw: 14.0, se: 14.0 })
+        .inner_margin(egui::Margin::symmetric(12.0, 8.0))
+        .show(ui, |ui| {
+            ui.horizontal(|ui| {
+                let (rect, _) = ui.allocate_exact_size(Vec2::new(34.0, 12.0), egui::Sense::hover());
+                for i in 0..3 {
+                    let pulse = dot_pulse(elapsed, i);
+                    ui.painter().circle_filled(
+                        egui::pos2(rect.left() + 5.0 + i as f32 * 12.0, rect.center().y),
+                        2.0 + 2.0 * pulse,
+                        c.text_muted.linear_multiply(0.4 + 0.6 * pulse),
+                    );
+                }
+                ui.label(RichText::new("escribiendo").size(11.0).italics().color(c.text_muted));
+            });
+        });
+    ui.ctx().request_repaint();
+}
+
+/// Own-side bubble for an `OutgoingMessage`. Returns true when a failed one is tapped.
+fn outgoing_bubble(ui: &mut egui::Ui, c: &NimColors, msg: &OutgoingMessage) -> bool {
+    let failed = matches!(msg.state, OutgoingState::Failed(_));
+    let mut tapped = false;
+    ui.with_layout(Layout::top_down(Align::Max), |ui| {
+        ui.add_space(4.0);
+        let frame = egui::Frame::none()
+            .fill(if failed { c.danger.linear_multiply(0.25) } else { c.primary.linear_multiply(0.6) })
+            .stroke(Stroke::new(1.0, if failed { c.danger } else { Color32::TRANSPARENT }))
+            .rounding(Rounding { nw: 14.0, ne: 4.0, sw: 14.0, se: 14.0 })
+            .inner_margin(egui::Margin::symmetric(12.0, 8.0))
+            .show(ui, |ui| {
+                ui.set_max_width(ui.available_width() * 0.72);
+                ui.label(RichText::new(&msg.content).size(14.0).color(Color32::WHITE));
+                match msg.state {
+                    OutgoingState::Pending => {
+                        ui.label(RichText::new("🕓 Enviando…").size(10.0).color(Color32::from_white_alpha(150)));
+                    }
+                    OutgoingState::Failed(_) => {
+                        ui.label(RichText::new("⚠ No enviado · tap para reintentar").size(10.0).color(c.danger));
+                    }
+                }
+            });
+        if let OutgoingState::Failed(ref err) = msg.state {
+            let id = ui.id().with(("outgoing_retry", msg.local_id));
+            tapped = ui.interact(frame.response.rect, id, egui::Sense::click()).on_hover_text(err).clicked();
+        }
+        ui.add_space(2.0);
+    });
+    tapped
+}
+
+/// Messages loaded per page when opening a chat or scrolling back.
+pub const MESSAGE_PAGE: usize = 100;
+
+/// Grouping window until the user's setting is loaded (matches `AppSettings`).
+const DEFAULT_GROUP_WINDOW_SECS: i64 = 120;
+
+/// Whether `cur` continues the run started by `prev` (same sender, sent
+/// within `window_secs`). A zero window never groups.
+fn should_group(prev: Option<&Message>, cur: &Message, window_secs: i64) -> bool {
+    let Some(prev) = prev else { return false };
+    if prev.sender_uid != cur.sender_uid {
+        return false;
+    }
+    match (
+        chrono::DateTime::parse_from_rfc3339(&prev.sent_at),
+        chrono::DateTime::parse_from_rfc3339(&cur.sent_at),
+    ) {
+        (Ok(a), Ok(b)) => window_secs > 0 && (0..=window_secs).contains(&(b - a).num_seconds()),
+        _ => false,
+    }
+}
+
+const BUBBLE_TEXT_SELECTABLE: bool = cfg!(not(target_os = "android"));
+
+/// Reaction toggled by double-tapping a bubble.
+const QUICK_REACTION: &str = "❤️";
+/// Reactions offered in a bubble's context menu.
+const REACTION_PICKER: [&str; 6] = ["👍", "❤️", "😂", "😮", "😢", "🙏"];
+/// Max gap between two taps on the same bubble to count as a double-tap.
+const DOUBLE_TAP_SECS: f64 = 0.35;
+const REACTION_POP_SECS: f64 = 0.45;
+
+/// Whether a tap at `now` completes a double-tap started at `prev` (egui seconds).
+fn is_double_tap(prev: Option<f64>, now: f64) -> bool {
+    prev.is_some_and(|p| (0.0..=DOUBLE_TAP_SECS).contains(&(now - p)))
+}
+
+/// What a message bubble shows, gathered by the message list.
+struct BubbleProps<'a> {
+    msg: &'a Message,
+    is_mine: bool,
+    show_read: bool,
+    /// Stacked under / over a bubble from the same sender.
+    grouped_prev: bool,
+    grouped_next: bool,
+    reactions: &'a [ReactionCount],
+    thumbnail: Option<&'a egui::TextureHandle>,
+    /// Author and snippet of the replied-to message.
+    quote: Option<(&'a str, &'a str)>,
+    copy_links: bool,
+}
+
+struct BubbleResponse {
+    rect: egui::Rect,
+    tapped: bool,
+    info_clicked: bool,
+    history_clicked: bool,
+    edit_clicked: bool,
+    delete_clicked: bool,
+    reply_clicked: bool,
+    /// The quoted snippet at the top of a reply was tapped.
+    quote_clicked: bool,
+    reaction_clicked: Option<String>,
+}
+
+/// Who wrote a quoted message, as shown above its snippet.
+fn quote_author(msg: &Message, current_uid: &str, contact: &Contact) -> String {
+    if msg.sender_uid == current_uid {
+        "Tú".to_string()
+    } else {
+        contact.display_name.clone()
+    }
+}
+
+/// One-line preview of a quoted message.
+fn quote_snippet(msg: &Message) -> String {
+    match &msg.msg_type {
+        _ if msg.deleted => Message::DELETED_TEXT.to_string(),
+        MessageType::Text => msg.content.lines().next().unwrap_or("").chars().take(80).collect(),
+        other => format!("{} {}", other.icon(), msg.file_name.as_deref().unwrap_or("archivo")),
+    }
+}
+
+/// Inline editor under an own bubble. `Some(true)` = save, `Some(false)` = cancel.
+fn message_editor(ui: &mut egui::Ui, c: &NimColors, edit: &mut MessageEdit) -> Option<bool> {
+    let mut result = None;
+    ui.with_layout(Layout::top_down(Align::Max), |ui| {
+        egui::Frame::none()
+            .fill(c.bg_card)
+            .stroke(Stroke::new(1.0, c.primary))
+            .rounding(Rounding::same(10.0))
+            .inner_margin(egui::Margin::same(8.0))
+            .show(ui, |ui| {
+                ui.set_max_width(ui.available_width() * 0.72);
+                let resp = ui.add(
+                    egui::TextEdit::multiline(&mut edit.text)
+                        .desired_rows(2)
+                        .desired_width(f32::INFINITY),
+                );
+                if resp.has_focus() && ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                    result = Some(false);
+                }
+                if let Some(ref err) = edit.error {
+                    ui.label(RichText::new(err).size(11.0).color(c.danger));
+                }
+                ui.horizontal(|ui| {
+                    if ui.button("Guardar").clicked() {
+                        result = Some(true);
+                    }
+                    if ui.button("Cancelar").clicked() {
+                        result = Some(false);
+                    }
+                });
+            });
+    });
+    result
+}
+
+fn message_bubble(ui: &mut egui::Ui, c: &NimColors, props: BubbleProps) -> BubbleResponse {
+    let BubbleProps { msg, is_mine, show_read, grouped_prev, grouped_next, reactions, thumbnail, quote, copy_links } =
+        props;
+    let mut resp = BubbleResponse {
+        rect: egui::Rect::NOTHING,
+        tapped: false,
+        info_clicked: false,
+        history_clicked: false,
+        edit_clicked: false,
+        delete_clicked: false,
+        reply_clicked: false,
+        quote_clicked: false,
+        reaction_clicked: None,
+    };
+    let mut quote_rect = None;
+    // Selectable text senses clicks itself; those count as taps on the bubble
+    let mut text_hits: Vec<egui::Response> = Vec::new();
+    let tap_id = ui.id().with(("bubble_tap", msg.id));
     let bubble_max_w = ui.available_width() * 0.72;
     let layout = if is_mine {
         Layout::right_to_left(Align::Min)
@@ -552,11 +1997,12 @@ fn message_bubble(ui: &mut egui::Ui, c: &NimColors, msg: &Message, is_mine: bool
     };
 
     ui.with_layout(Layout::top_down(if is_mine { Align::Max } else { Align::Min }), |ui| {
-        ui.add_space(4.0);
+        ui.add_space(if grouped_prev { 1.0 } else { 4.0 });
         let bg = if is_mine { c.primary } else { c.bg_card };
         let fg = if is_mine { Color32::WHITE } else { c.text_primary };
 
         let content = match &msg.msg_type {
+            _ if msg.deleted => Message::DELETED_TEXT.to_string(),
             MessageType::Text => msg.content.clone(),
             other => format!(
                 "{} {}",
@@ -565,28 +2011,316 @@ fn message_bubble(ui: &mut egui::Ui, c: &NimColors, msg: &Message, is_mine: bool
             ),
         };
 
-        egui::Frame::none()
+        let frame = egui::Frame::none()
             .fill(bg)
-            .rounding(Rounding {
-                nw: if is_mine { 14.0 } else { 4.0 },
-                ne: if is_mine { 4.0 } else { 14.0 },
-                sw: 14.0,
-                se: 14.0,
+            .rounding({
+                // Square the sender-side corners that touch a stacked neighbour
+                let tail = if grouped_prev { 2.0 } else { 4.0 };
+                let bottom = if grouped_next { 4.0 } else { 14.0 };
+                Rounding {
+                    nw: if is_mine { 14.0 } else { tail },
+                    ne: if is_mine { tail } else { 14.0 },
+                    sw: if is_mine { 14.0 } else { bottom },
+                    se: if is_mine { bottom } else { 14.0 },
+                }
             })
             .inner_margin(egui::style::Margin::symmetric(12.0, 8.0))
             .show(ui, |ui| {
+                // Claim the tap area before the contents (its rect is set once
+                // the bubble is laid out) so links and "(editado)" sit on top
+                ui.interact(egui::Rect::from_min_size(ui.min_rect().min, Vec2::ZERO), tap_id, egui::Sense::click());
                 ui.set_max_width(bubble_max_w);
-                ui.label(RichText::new(&content).size(14.0).color(fg));
+                // Selectable on desktop so text can be copied with Ctrl+C;
+                // on Android a long-press selection would fight with taps.
+                let meta_color = if is_mine { Color32::from_white_alpha(150) } else { c.text_muted };
+                if let Some((author, snippet)) = quote {
+                    let quoted = egui::Frame::none()
+                        .fill(if is_mine { Color32::from_black_alpha(40) } else { c.bg_input })
+                        .rounding(Rounding::same(6.0))
+                        .inner_margin(egui::Margin::symmetric(8.0, 4.0))
+                        .show(ui, |ui| {
+                            if !author.is_empty() {
+                                ui.add(
+                                    egui::Label::new(
+                                        RichText::new(author)
+                                            .size(11.0)
+                                            .strong()
+                                            .color(if is_mine { Color32::WHITE } else { c.primary }),
+                                    )
+                                    .selectable(false),
+                                );
+                            }
+                            ui.add(
+                                egui::Label::new(RichText::new(snippet).size(12.0).color(meta_color))
+                                    .truncate(true)
+                                    .selectable(false),
+                            );
+                        });
+                    quote_rect = Some(quoted.response.rect);
+                    ui.add_space(4.0);
+                }
+                if msg.deleted {
+                    ui.add(
+                        egui::Label::new(RichText::new(&content).size(14.0).italics().color(meta_color))
+                            .selectable(false),
+                    );
+                } else if let Some(tex) = thumbnail {
+                    ui.add(
+                        egui::Image::new(tex)
+                            .max_size(Vec2::splat(THUMB_MAX_PX as f32))
+                            .rounding(Rounding::same(8.0)),
+                    );
+                } else {
+                    let spans = linkify(&content);
+                    if spans.iter().any(|span| matches!(span, TextSpan::Link(_))) {
+                        ui.horizontal_wrapped(|ui| {
+                            ui.spacing_mut().item_spacing.x = 0.0;
+                            for span in spans {
+                                match span {
+                                    TextSpan::Plain(text) => {
+                                        text_hits.push(ui.add(
+                                            egui::Label::new(RichText::new(text).size(14.0).color(fg))
+                                                .selectable(BUBBLE_TEXT_SELECTABLE),
+                                        ));
+                                    }
+                                    TextSpan::Link(url) => {
+                                        // White on our own (accent-colored) bubbles
+                                        let link_color = if is_mine { Color32::WHITE } else { c.primary };
+                                        let text = RichText::new(url).size(14.0).underline().color(link_color);
+                                        if copy_links {
+                                            let link = ui
+                                                .add(egui::Label::new(text).sense(egui::Sense::click()))
+                                                .on_hover_cursor(egui::CursorIcon::PointingHand)
+                                                .on_hover_text("Copiar enlace");
+                                            if link.clicked() {
+                                                ui.ctx().copy_text(url.to_string());
+                                            }
+                                        } else {
+                                            ui.hyperlink_to(text, url);
+                                        }
+                                    }
+                                }
+                            }
+                        });
+                    } else {
+                        text_hits.push(ui.add(
+                            egui::Label::new(RichText::new(&content).size(14.0).color(fg))
+                                .selectable(BUBBLE_TEXT_SELECTABLE),
+                        ));
+                    }
+                }
 
-                // Timestamp
-                let time_str = msg.sent_at.get(11..16).unwrap_or("");
-                ui.label(
-                    RichText::new(time_str)
-                        .size(10.0)
-                        .color(if is_mine { Color32::from_white_alpha(150) } else { c.text_muted }),
-                );
+                let edited = msg.edited_at.is_some() && !msg.deleted;
+                if !grouped_next || edited {
+                    ui.horizontal(|ui| {
+                        // Timestamp (only once, on the last bubble of a stack)
+                        if !grouped_next {
+                            let time_str = msg.sent_at.get(11..16).unwrap_or("");
+                            ui.add(
+                                egui::Label::new(RichText::new(time_str).size(10.0).color(meta_color))
+                                    .selectable(false),
+                            );
+                            if is_mine {
+                                let (ticks, color) = if show_read { ("✓✓", c.accent) } else { ("✓", meta_color) };
+                                ui.add(egui::Label::new(RichText::new(ticks).size(10.0).color(color)).selectable(false));
+                            }
+                        }
+                        if edited {
+                            let edited = ui.add(
+                                egui::Label::new(RichText::new("(editado)").size(10.0).italics().color(meta_color))
+                                    .selectable(false)
+                                    .sense(egui::Sense::click()),
+                            );
+                            if edited.on_hover_text("Ver historial de ediciones").clicked() {
+                                resp.history_clicked = true;
+                            }
+                        }
+                    });
+                }
             });
+        resp.rect = frame.response.rect;
+        let tap = ui.interact(resp.rect, tap_id, egui::Sense::click());
+        let tap = text_hits.into_iter().fold(tap, |tap, text| tap | text);
+        resp.tapped = tap.clicked();
+        // The quote is painted, not a widget: route taps on it by position
+        if resp.tapped && quote_rect.zip(tap.interact_pointer_pos()).is_some_and(|(r, p)| r.contains(p)) {
+            resp.tapped = false;
+            resp.quote_clicked = true;
+        }
+        // Right-click on desktop, long-press on touch
+        tap.context_menu(|ui| {
+            if !msg.deleted {
+                ui.horizontal(|ui| {
+                    for emoji in REACTION_PICKER {
+                        let mine = reactions.iter().any(|r| r.mine && r.emoji == emoji);
+                        if ui.selectable_label(mine, RichText::new(emoji).size(16.0)).clicked() {
+                            resp.reaction_clicked = Some(emoji.to_string());
+                            ui.close_menu();
+                        }
+                    }
+                });
+                ui.separator();
+                if ui.button("↩ Responder").clicked() {
+                    resp.reply_clicked = true;
+                    ui.close_menu();
+                }
+            }
+            if ui.button("ℹ Info").clicked() {
+                resp.info_clicked = true;
+                ui.close_menu();
+            }
+            // Only our own text messages can be corrected
+            if is_mine && !msg.deleted {
+                if msg.msg_type == MessageType::Text && ui.button("✏ Editar").clicked() {
+                    resp.edit_clicked = true;
+                    ui.close_menu();
+                }
+                if ui.button("🗑 Eliminar").clicked() {
+                    resp.delete_clicked = true;
+                    ui.close_menu();
+                }
+            }
+        });
 
-        ui.add_space(2.0);
+        // Reaction chips under the bubble
+        if !reactions.is_empty() {
+            ui.horizontal(|ui| {
+                ui.spacing_mut().item_spacing.x = 4.0;
+                for r in reactions {
+                    let label = if r.count > 1 { format!("{} {}", r.emoji, r.count) } else { r.emoji.clone() };
+                    let chip = egui::Button::new(RichText::new(label).size(12.0).color(c.text_primary))
+                        .fill(if r.mine { c.primary.linear_multiply(0.25) } else { c.bg_card })
+                        .stroke(Stroke::new(1.0, if r.mine { c.primary } else { c.border }))
+                        .rounding(Rounding::same(10.0));
+                    if ui.add(chip).clicked() {
+                        resp.reaction_clicked = Some(r.emoji.clone());
+                    }
+                }
+            });
+        }
+
+        ui.add_space(if grouped_next { 0.0 } else { 2.0 });
     });
+    resp
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chat_with(input: &str, autoformat: bool) -> ActiveChat {
+        let contact = Contact {
+            id: 1,
+            owner_uid: "yo".into(),
+            contact_uid: "ana".into(),
+            display_name: "Ana".into(),
+            avatar_color: 0,
+            contact_type: ContactType::Friend,
+            starred: false,
+            pinned: false,
+            added_at: String::new(),
+            note: None,
+        };
+        let mut active = ActiveChat::new(7, contact, vec![], true);
+        active.input_text = input.to_string();
+        active.text_autoformat = autoformat;
+        active
+    }
+
+    fn sent_content(action: ChatAction) -> String {
+        match action {
+            ChatAction::SendMessage { content, .. } => content,
+            _ => panic!("expected SendMessage"),
+        }
+    }
+
+    /// A text message from `sender` sent `secs` seconds after 12:00.
+    fn msg(sender: &str, secs: i64) -> Message {
+        let noon = chrono::DateTime::parse_from_rfc3339("2024-05-01T12:00:00+00:00").unwrap();
+        Message {
+            id: secs,
+            chat_id: 7,
+            sender_uid: sender.into(),
+            content: "hola".into(),
+            msg_type: MessageType::Text,
+            file_name: None,
+            file_size: None,
+            sent_at: (noon + chrono::Duration::seconds(secs)).to_rfc3339(),
+            is_read: false,
+            edited_at: None,
+            deleted: false,
+            reply_to: None,
+        }
+    }
+
+    #[test]
+    fn sending_applies_autoformat_only_when_enabled() {
+        let on = sent_content(chat_with("hola :) https://x.co/:)", true).send_input());
+        assert_eq!(on, "hola 🙂 https://x.co/:)");
+        let off = sent_content(chat_with("hola :) <3", false).send_input());
+        assert_eq!(off, "hola :) <3");
+    }
+
+    #[test]
+    fn grouping_ends_at_the_window_or_a_new_sender() {
+        let first = msg("ana", 0);
+        assert!(!should_group(None, &first, DEFAULT_GROUP_WINDOW_SECS));
+        assert!(should_group(Some(&first), &msg("ana", 120), DEFAULT_GROUP_WINDOW_SECS));
+        assert!(!should_group(Some(&first), &msg("ana", 121), DEFAULT_GROUP_WINDOW_SECS));
+        assert!(!should_group(Some(&first), &msg("beto", 5), DEFAULT_GROUP_WINDOW_SECS));
+        // Out-of-order timestamps start a new group
+        assert!(!should_group(Some(&msg("ana", 10)), &first, DEFAULT_GROUP_WINDOW_SECS));
+    }
+
+    #[test]
+    fn double_tap_needs_a_second_tap_within_the_threshold() {
+        assert!(!is_double_tap(None, 10.0));
+        assert!(is_double_tap(Some(10.0), 10.0 + DOUBLE_TAP_SECS));
+        assert!(is_double_tap(Some(10.0), 10.1));
+        assert!(!is_double_tap(Some(10.0), 10.0 + DOUBLE_TAP_SECS + 0.01));
+        // A clock that went backwards is not a double-tap
+        assert!(!is_double_tap(Some(10.0), 9.9));
+    }
+
+    #[test]
+    fn outgoing_message_goes_pending_failed_pending_sent() {
+        let mut active = chat_with("", false);
+        let first = active.queue_outgoing("hola".into(), None);
+        let second = active.queue_outgoing("¿estás?".into(), None);
+        assert_ne!(first, second);
+        assert!(active.outgoing.iter().all(|o| o.state == OutgoingState::Pending));
+        // Only a failed message can be retried
+        assert_eq!(active.retry_outgoing(first), None);
+
+        active.mark_failed(first, "sin conexión".into());
+        assert_eq!(active.outgoing[0].state, OutgoingState::Failed("sin conexión".into()));
+        assert_eq!(active.retry_outgoing(first).as_deref(), Some("hola"));
+        assert_eq!(active.outgoing[0].state, OutgoingState::Pending);
+
+        active.mark_sent(first, msg("yo", 0));
+        assert_eq!(active.outgoing.len(), 1);
+        assert_eq!(active.outgoing[0].local_id, second);
+        assert_eq!(active.messages.len(), 1);
+    }
+
+    #[test]
+    fn grouping_follows_the_configured_window() {
+        let first = msg("ana", 0);
+        for window in [30, 300, 900] {
+            assert!(should_group(Some(&first), &msg("ana", window), window), "{}s", window);
+            assert!(!should_group(Some(&first), &msg("ana", window + 1), window), "{}s", window);
+        }
+        assert!(should_group(Some(&first), &msg("ana", 200), 300));
+        assert!(!should_group(Some(&first), &msg("ana", 200), 30));
+        // A zero window turns grouping off, even for simultaneous messages
+        assert!(!should_group(Some(&first), &msg("ana", 0), 0));
+    }
+
+    #[test]
+    fn only_acquaintances_need_a_confirmation_and_only_once() {
+        assert!(needs_send_confirmation(&ContactType::Acquaintance, true, false));
+        assert!(!needs_send_confirmation(&ContactType::Acquaintance, true, true));
+        assert!(!needs_send_confirmation(&ContactType::Acquaintance, false, false));
+        assert!(!needs_send_confirmation(&ContactType::Friend, true, false));
+    }
 }
@@ -1,11 +1,14 @@
 use egui::{Align, Color32, Layout, RichText, Rounding, Stroke, Vec2};
 use crate::models::*;
-use crate::theme::NimColors;
-use crate::db::InventorySummary;
+use crate::theme::{category_color, show_toast, NimColors, UNCATEGORIZED};
+use crate::db::{ColumnMapping, ImportReport, InventorySummary, ProductField, StockMovement};
+use crate::util::{currency, format_datetime, format_money, format_money_compact, format_quantity, parse_csv, DEFAULT_CURRENCY};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum InventoryView {
     List,
+    /// Read-only card of `detail_id`, with "Editar" leading to the form.
+    Detail,
     Form,
     OutOfStock,
 }
@@ -14,24 +17,147 @@ pub struct InventoryScreen {
     pub products: Vec<Product>,
     pub summary: InventorySummary,
     pub view: InventoryView,
+    pub density: ListDensity,
 
     // Form state
     pub form: ProductForm,
     pub form_error: Option<String>,
     pub form_success: Option<String>,
     pub editing_id: Option<i64>,
+    pub editing_original: Option<Product>,
+    /// Existing products matching the name typed in a new-product form, and
+    /// the pending lookup (text, when it was typed) waiting out the debounce.
+    pub suggestions: Vec<Product>,
+    pub suggest_pending: Option<(String, f64)>,
 
-    // Search
+    // Search (kept across form round-trips; nothing resets it but logout)
     pub search: String,
+    /// Categories in use, and the one the list is narrowed to (None = all).
+    /// The stat cards follow the filter unless `summary_follows_filter` is off.
+    pub categories: Vec<String>,
+    pub category_filter: Option<String>,
+    pub summary_follows_filter: bool,
+    pub sort: ProductSort,
+    /// Row to scroll back to when the list is shown again after the form.
+    pub scroll_to_product: Option<i64>,
+
+    // Bulk price adjustment dialog
+    pub show_price_dialog: bool,
+    pub price_percent: String,
+    pub price_category: Option<String>,
+
+    // CSV import dialog
+    pub import: Option<ImportDialog>,
+
+    // Outcome of the last file export (CSV or valuation): (message, is_error)
+    pub export_msg: Option<(String, bool)>,
+
+    // "¡Stock agotado!" toast: product name and when it was first shown
+    pub stock_alert: Option<(String, Option<f64>)>,
+
+    // Quick add line ("CÓDIGO | Nombre | Cant | Neto | Venta")
+    pub quick_add: String,
+    pub quick_add_error: Option<String>,
+
+    // Product detail view and its movement history
+    pub detail_id: Option<i64>,
+    pub movements: Vec<StockMovement>,
+
+    /// Currency (ISO code) amounts are shown in, from the user's settings.
+    pub currency: String,
+}
+
+/// State of the "Importar CSV" dialog: paste → map columns → report.
+#[derive(Default)]
+pub struct ImportDialog {
+    pub csv_text: String,
+    pub headers: Vec<String>,
+    pub records: Vec<Vec<String>>,
+    pub mapping: ColumnMapping,
+    pub merge_duplicates: bool,
+    pub report: Option<ImportReport>,
+    pub error: Option<String>,
+}
+
+/// How the form's prices are entered.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum PriceMode {
+    /// Net and sale typed directly.
+    #[default]
+    Absolute,
+    /// Net and a desired margin %; the sale price follows (and vice versa).
+    Margin,
+}
+
+/// When the form's product raises the low-stock alert.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum AlertMode {
+    /// Only once it runs out (less than one unit).
+    #[default]
+    Absolute,
+    /// At a reorder point plus a safety percentage.
+    ReorderPoint,
 }
 
 #[derive(Default, Clone)]
 pub struct ProductForm {
     pub code: String,
+    pub barcode: String,
     pub name: String,
     pub quantity: String,
     pub net_value: String,
     pub sale_value: String,
+    pub category: String,
+    pub price_mode: PriceMode,
+    pub margin_pct: String,
+    pub alert_mode: AlertMode,
+    pub reorder_point: String,
+    pub alert_pct: String,
+    pub low_stock_threshold: String,
+}
+
+/// Signed change of the form values against the stored product.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProductDelta {
+    pub sale_value: f64,
+    pub profit_value: f64,
+}
+
+impl ProductForm {
+    /// Margin mode: recompute the sale price from net + margin %.
+    pub fn sync_sale_from_margin(&mut self) {
+        if let (Some(net), Some(margin)) = (
+            parse_decimal(&self.net_value),
+            parse_decimal(&self.margin_pct),
+        ) {
+            if let Some(sale) = Product::sale_from_margin(net, margin) {
+                self.sale_value = format!("{:.2}", sale);
+            }
+        }
+    }
+
+    /// Margin mode: recompute the margin % after the sale price was edited.
+    pub fn sync_margin_from_sale(&mut self) {
+        if let (Some(net), Some(sale)) = (
+            parse_decimal(&self.net_value),
+            parse_decimal(&self.sale_value),
+        ) {
+            if let Some(margin) = Product::margin_from_prices(net, sale) {
+                self.margin_pct = format!("{:.2}", margin);
+            }
+        }
+    }
+
+    /// Compare the prices being typed with the stored product.
+    /// Returns `None` while the price fields don't parse.
+    pub fn delta_vs(&self, original: &Product) -> Option<ProductDelta> {
+        let net = parse_decimal(&self.net_value)?;
+        let sale = parse_decimal(&self.sale_value)?;
+        Some(ProductDelta {
+            sale_value: sale - original.sale_value,
+            profit_value: (sale - net) - original.profit_value,
+        })
+    }
 }
 
 impl Default for InventoryScreen {
@@ -40,11 +166,31 @@ impl Default for InventoryScreen {
             products: vec![],
             summary: InventorySummary::default(),
             view: InventoryView::List,
+            density: ListDensity::default(),
             form: ProductForm::default(),
             form_error: None,
             form_success: None,
             editing_id: None,
+            editing_original: None,
+            suggestions: vec![],
+            suggest_pending: None,
             search: String::new(),
+            categories: vec![],
+            category_filter: None,
+            summary_follows_filter: true,
+            sort: ProductSort::default(),
+            scroll_to_product: None,
+            show_price_dialog: false,
+            price_percent: String::new(),
+            price_category: None,
+            import: None,
+            export_msg: None,
+            stock_alert: None,
+            quick_add: String::new(),
+            quick_add_error: None,
+            detail_id: None,
+            movements: vec![],
+            currency: DEFAULT_CURRENCY.to_string(),
         }
     }
 }
@@ -52,27 +198,57 @@ impl Default for InventoryScreen {
 pub enum InventoryAction {
     None,
     LoadProducts,
+    /// Fetch the movement history shown in the detail view.
+    LoadMovements { id: i64 },
+    /// Change the list order and re-query.
+    SetSort(ProductSort),
     SaveProduct { product: Product },
     DeleteProduct { id: i64 },
+    RevertLastMovement { id: i64 },
+    /// Add `delta` units to a product's stock from its list row.
+    AdjustQuantity { id: i64, delta: f64 },
+    AdjustPrices { category: Option<String>, percent: f64 },
+    ImportCsv { rows: Vec<Vec<String>>, mapping: ColumnMapping, merge_duplicates: bool },
+    /// Look up existing products whose name starts with `prefix`.
+    SuggestProducts { prefix: String },
+    /// Write a timestamped valuation file of the current stock.
+    SaveValuation,
+    ExportCsv,
+    /// Load a CSV file into the open import dialog.
+    PickImportFile,
 }
 
 impl InventoryScreen {
-    pub fn show(&mut self, ctx: &egui::Context, theme: &AppTheme, owner_uid: &str) -> InventoryAction {
-        let c = NimColors::for_theme(theme);
+    pub fn show(&mut self, ctx: &egui::Context, theme: &AppTheme, accent: Option<u32>, owner_uid: &str) -> InventoryAction {
+        let c = NimColors::for_theme(theme, accent);
         let mut action = InventoryAction::None;
 
         match self.view {
             InventoryView::Form => {
                 action = self.show_form(ctx, &c, owner_uid);
             }
+            InventoryView::Detail => {
+                self.show_detail(ctx, &c);
+            }
             _ => {
                 action = self.show_list(ctx, &c, owner_uid);
             }
         }
 
+        self.show_stock_alert(ctx, &c);
         action
     }
 
+    fn show_stock_alert(&mut self, ctx: &egui::Context, c: &NimColors) {
+        let frame = egui::Frame::none()
+            .fill(c.danger)
+            .rounding(Rounding::same(12.0))
+            .inner_margin(egui::Margin::symmetric(18.0, 12.0));
+        show_toast(ctx, "stock_alert", &mut self.stock_alert, STOCK_ALERT_SECS, 70.0, frame, |name| {
+            RichText::new(format!("🔴 ¡Stock agotado: {}!", name)).size(15.0).strong().color(Color32::WHITE)
+        });
+    }
+
     fn show_list(&mut self, ctx: &egui::Context, c: &NimColors, owner_uid: &str) -> InventoryAction {
         let mut action = InventoryAction::None;
 
@@ -92,10 +268,55 @@ impl InventoryScreen {
                         if ui.add(btn).clicked() {
                             self.form = ProductForm::default();
                             self.editing_id = None;
+                            self.editing_original = None;
+                            self.suggestions.clear();
+                            self.suggest_pending = None;
                             self.form_error = None;
                             self.form_success = None;
                             self.view = InventoryView::Form;
                         }
+
+                        let adjust_btn = egui::Button::new(
+                            RichText::new("％ Precios").size(13.0).color(c.text_secondary),
+                        )
+                        .fill(c.bg_card)
+                        .rounding(Rounding::same(8.0))
+                        .min_size(Vec2::new(90.0, 32.0));
+                        if ui.add(adjust_btn).clicked() {
+                            self.price_percent.clear();
+                            self.price_category = self.category_filter.clone();
+                            self.show_price_dialog = true;
+                        }
+
+                        let import_btn = egui::Button::new(
+                            RichText::new("⬆ CSV").size(13.0).color(c.text_secondary),
+                        )
+                        .fill(c.bg_card)
+                        .rounding(Rounding::same(8.0))
+                        .min_size(Vec2::new(70.0, 32.0));
+                        if ui.add(import_btn).clicked() {
+                            self.import = Some(ImportDialog::default());
+                        }
+
+                        let export_btn = egui::Button::new(
+                            RichText::new("⬇ CSV").size(13.0).color(c.text_secondary),
+                        )
+                        .fill(c.bg_card)
+                        .rounding(Rounding::same(8.0))
+                        .min_size(Vec2::new(70.0, 32.0));
+                        if ui.add(export_btn).on_hover_text("Exportar CSV").clicked() {
+                            action = InventoryAction::ExportCsv;
+                        }
+
+                        let valuation_btn = egui::Button::new(
+                            RichText::new("💾 Valuación").size(13.0).color(c.text_secondary),
+                        )
+                        .fill(c.bg_card)
+                        .rounding(Rounding::same(8.0))
+                        .min_size(Vec2::new(90.0, 32.0));
+                        if ui.add(valuation_btn).on_hover_text("Guardar valuación").clicked() {
+                            action = InventoryAction::SaveValuation;
+                        }
                     });
                 });
 
@@ -104,21 +325,59 @@ impl InventoryScreen {
                 // Stat cards
                 ui.horizontal(|ui| {
                     stat_card(ui, c, "Productos", &self.summary.total_products.to_string(), c.text_primary);
-                    stat_card(ui, c, "Valor Neto", &format_currency(self.summary.total_net_value), c.secondary);
-                    stat_card(ui, c, "Ganancias", &format_currency(self.summary.total_profit_value), c.success);
+                    stat_card(ui, c, "Valor Neto", &format_money(self.summary.total_net_value, &self.currency), c.secondary);
+                    stat_card(ui, c, "Ganancias", &format_money(self.summary.total_profit_value, &self.currency), c.success);
                     if self.summary.out_of_stock_count > 0 {
                         stat_card(ui, c, "Sin Stock", &self.summary.out_of_stock_count.to_string(), c.danger);
                     }
                 });
+
+                ui.add_space(6.0);
+                ui.horizontal(|ui| {
+                    ui.label(RichText::new("Ordenar por").size(12.0).color(c.text_muted));
+                    let mut picked = self.sort;
+                    egui::ComboBox::from_id_source("inv_sort")
+                        .selected_text(RichText::new(picked.label()).size(12.0))
+                        .show_ui(ui, |ui| {
+                            for sort in ProductSort::ALL {
+                                ui.selectable_value(&mut picked, sort, sort.label());
+                            }
+                        });
+                    if picked != self.sort {
+                        action = InventoryAction::SetSort(picked);
+                    }
+                });
+
+                if let Some(ref cat) = self.category_filter {
+                    ui.add_space(6.0);
+                    ui.horizontal(|ui| {
+                        ui.label(RichText::new(format!("● {}", cat)).size(12.0).color(category_color(cat)));
+                        if ui
+                            .checkbox(&mut self.summary_follows_filter, RichText::new("Totales solo de esta categoría").size(12.0))
+                            .changed()
+                        {
+                            action = InventoryAction::LoadProducts;
+                        }
+                    });
+                }
+
+                if let Some((ref msg, is_error)) = self.export_msg {
+                    ui.add_space(6.0);
+                    ui.label(
+                        RichText::new(msg)
+                            .size(12.0)
+                            .color(if is_error { c.danger } else { c.success }),
+                    );
+                }
             });
 
-        // ── Red alert: out-of-stock products (fixed bottom) ───────────────
-        let out_of_stock: Vec<Product> = self.products.iter()
-            .filter(|p| p.is_out_of_stock())
+        // ── Red alert: out-of-stock / low-stock products (fixed bottom) ────
+        let to_reorder: Vec<Product> = self.products.iter()
+            .filter(|p| p.needs_reorder())
             .cloned()
             .collect();
 
-        if !out_of_stock.is_empty() {
+        if !to_reorder.is_empty() {
             egui::TopBottomPanel::bottom("oos_panel")
                 .resizable(false)
                 .min_height(120.0)
@@ -126,19 +385,20 @@ impl InventoryScreen {
                 .frame(
                     egui::Frame::none()
                         .fill(Color32::from_rgb(0x2A, 0x0D, 0x11))
-                        .stroke(Stroke::new(1.5, c.danger))
+                        // Thicker border while a fresh "stock agotado" alert is up
+                        .stroke(Stroke::new(if self.stock_alert.is_some() { 3.0 } else { 1.5 }, c.danger))
                         .inner_margin(egui::style::Margin::symmetric(16.0, 10.0)),
                 )
                 .show(ctx, |ui| {
                     ui.horizontal(|ui| {
                         ui.label(
-                            RichText::new("🔴 SIN STOCK")
+                            RichText::new("🔴 SIN STOCK / REPONER")
                                 .size(13.0)
                                 .strong()
                                 .color(c.danger),
                         );
                         ui.label(
-                            RichText::new(format!("({})", out_of_stock.len()))
+                            RichText::new(format!("({})", to_reorder.len()))
                                 .size(12.0)
                                 .color(c.danger),
                         );
@@ -149,16 +409,25 @@ impl InventoryScreen {
                         .id_source("oos_scroll")
                         .max_height(130.0)
                         .show(ui, |ui| {
-                            for p in &out_of_stock {
+                            for p in &to_reorder {
+                                // Still-stocked products below their reorder level show amber
+                                let color = if p.is_low_stock() { c.danger } else { c.warning };
                                 ui.horizontal(|ui| {
                                     ui.label(
                                         RichText::new(format!("• {} [{}]", p.name, p.code))
                                             .size(13.0)
-                                            .color(c.danger),
+                                            .color(color),
                                     );
                                     ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                                        if !p.is_low_stock() {
+                                            ui.label(
+                                                RichText::new(format!("Quedan {}", format_quantity(p.quantity)))
+                                                    .size(12.0)
+                                                    .color(color),
+                                            );
+                                        }
                                         ui.label(
-                                            RichText::new(format!("Costo: {}", format_currency(p.net_value)))
+                                            RichText::new(format!("Costo: {}", format_money(p.net_value, &self.currency)))
                                                 .size(12.0)
                                                 .color(c.text_muted),
                                         );
@@ -183,6 +452,55 @@ impl InventoryScreen {
                             .desired_width(ui.available_width() - 32.0),
                     );
                 });
+
+                // Category chips
+                if !self.categories.is_empty() {
+                    ui.add_space(6.0);
+                    ui.horizontal_wrapped(|ui| {
+                        ui.add_space(16.0);
+                        let mut picked = self.category_filter.clone();
+                        ui.selectable_value(&mut picked, None, RichText::new("Todas").size(12.0));
+                        for cat in &self.categories {
+                            ui.selectable_value(
+                                &mut picked,
+                                Some(cat.clone()),
+                                RichText::new(format!("● {}", cat)).size(12.0).color(category_color(cat)),
+                            );
+                        }
+                        if picked != self.category_filter {
+                            self.category_filter = picked;
+                            action = InventoryAction::LoadProducts;
+                        }
+                    });
+                }
+
+                // Quick add
+                ui.add_space(6.0);
+                ui.horizontal(|ui| {
+                    ui.add_space(16.0);
+                    let resp = ui.add(
+                        egui::TextEdit::singleline(&mut self.quick_add)
+                            .hint_text("⚡ Alta rápida: CÓDIGO | Nombre | Cant | Neto | Venta")
+                            .desired_width(ui.available_width() - 32.0),
+                    );
+                    if resp.changed() {
+                        self.quick_add_error = None;
+                    }
+                    if resp.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                        match parse_quick_add(&self.quick_add, owner_uid) {
+                            Ok(p) => {
+                                action = InventoryAction::SaveProduct { product: p };
+                            }
+                            Err(e) => self.quick_add_error = Some(e),
+                        }
+                    }
+                });
+                if let Some(ref err) = self.quick_add_error {
+                    ui.horizontal(|ui| {
+                        ui.add_space(16.0);
+                        ui.label(RichText::new(format!("⚠ {}", err)).color(c.danger).size(12.0));
+                    });
+                }
                 ui.add_space(6.0);
                 ui.separator();
 
@@ -210,14 +528,14 @@ impl InventoryScreen {
                             continue;
                         }
 
-                        let row_h = 52.0;
+                        let (row_h, font_size) = self.density.metrics();
                         let (rect, resp) = ui.allocate_exact_size(
                             Vec2::new(ui.available_width(), row_h),
                             egui::Sense::click(),
                         );
 
                         // Highlight out-of-stock with reddish background
-                        let row_bg = if p.is_out_of_stock() {
+                        let row_bg = if p.is_low_stock() {
                             Color32::from_rgba_premultiplied(80, 10, 15, 30)
                         } else if resp.hovered() {
                             c.bg_elevated
@@ -226,42 +544,73 @@ impl InventoryScreen {
                         };
                         ui.painter().rect_filled(rect, Rounding::ZERO, row_bg);
 
+                        // Category stripe on the left edge
+                        let stripe = egui::Rect::from_min_size(
+                            rect.min + Vec2::new(6.0, 6.0),
+                            Vec2::new(4.0, row_h - 12.0),
+                        );
+                        ui.painter().rect_filled(
+                            stripe,
+                            Rounding::same(2.0),
+                            category_color(p.category.as_deref().unwrap_or(UNCATEGORIZED)),
+                        );
+
                         let x = rect.min.x + 16.0;
                         let y_center = rect.center().y;
 
-                        let qty_color = if p.is_out_of_stock() { c.danger } else { c.text_primary };
+                        let qty_color = if p.is_low_stock() { c.danger } else { c.text_primary };
 
                         // Draw columns
                         for (text, col_x, color) in [
                             (p.code.as_str(),                        x,          c.text_muted),
                             (p.name.as_str(),                        x + 86.0,   c.text_primary),
-                            (&format!("{:.1}", p.quantity) as &str,  x + 240.0,  qty_color),
-                            (&format_currency(p.net_value) as &str,  x + 297.0,  c.text_secondary),
-                            (&format_currency(p.sale_value) as &str, x + 390.0,  c.text_secondary),
-                            (&format_currency(p.profit_value) as &str, x + 483.0, c.success),
+                            (&format_quantity(p.quantity) as &str,    x + 240.0,  qty_color),
+                            (&format_money_compact(p.net_value, &self.currency) as &str,  x + 297.0,  c.text_secondary),
+                            (&format_money_compact(p.sale_value, &self.currency) as &str, x + 390.0,  c.text_secondary),
+                            (&format_money_compact(p.profit_value, &self.currency) as &str, x + 483.0, c.success),
                         ] {
                             ui.painter().text(
                                 egui::pos2(col_x, y_center),
                                 egui::Align2::LEFT_CENTER,
                                 text,
-                                egui::FontId::proportional(13.0),
+                                egui::FontId::proportional(font_size),
                                 color,
                             );
                         }
 
-                        // Edit / delete on click
+                        // −/+ stock stepper at the right end of the row
+                        let step_size = Vec2::splat((row_h - 12.0).min(26.0));
+                        let step_rect = egui::Rect::from_min_size(
+                            egui::pos2(rect.right() - 16.0 - step_size.x * 2.0 - 4.0, y_center - step_size.y / 2.0),
+                            Vec2::new(step_size.x * 2.0 + 4.0, step_size.y),
+                        );
+                        ui.allocate_ui_at_rect(step_rect, |ui| {
+                            ui.horizontal(|ui| {
+                                ui.spacing_mut().item_spacing.x = 4.0;
+                                let minus = egui::Button::new(RichText::new("−").size(font_size))
+                                    .fill(c.bg_card)
+                                    .rounding(Rounding::same(6.0))
+                                    .min_size(step_size);
+                                if ui.add_enabled(p.quantity > 0.0, minus).on_hover_text("Quitar 1").clicked() {
+                                    action = InventoryAction::AdjustQuantity { id: p.id, delta: -1.0 };
+                                }
+                                let plus = egui::Button::new(RichText::new("+").size(font_size))
+                                    .fill(c.bg_card)
+                                    .rounding(Rounding::same(6.0))
+                                    .min_size(step_size);
+                                if ui.add(plus).on_hover_text("Agregar 1").clicked() {
+                                    action = InventoryAction::AdjustQuantity { id: p.id, delta: 1.0 };
+                                }
+                            });
+                        });
+
+                        // Details (and from there edit / delete) on click
                         if resp.clicked() {
-                            self.form = ProductForm {
-                                code: p.code.clone(),
-                                name: p.name.clone(),
-                                quantity: p.quantity.to_string(),
-                                net_value: p.net_value.to_string(),
-                                sale_value: p.sale_value.to_string(),
-                            };
-                            self.editing_id = Some(p.id);
-                            self.form_error = None;
-                            self.form_success = None;
-                            self.view = InventoryView::Form;
+                            action = self.open_detail(p);
+                        }
+                        if self.view == InventoryView::List && self.scroll_to_product == Some(p.id) {
+                            ui.scroll_to_rect(rect, Some(Align::Center));
+                            self.scroll_to_product = None;
                         }
 
                         // Row divider
@@ -276,6 +625,300 @@ impl InventoryScreen {
                 });
             });
 
+        if self.show_price_dialog {
+            if let Some(a) = self.show_price_dialog(ctx, c) {
+                action = a;
+            }
+        }
+
+        if self.import.is_some() {
+            if let Some(a) = self.show_import_dialog(ctx, c) {
+                action = a;
+            }
+        }
+
+        action
+    }
+
+    fn show_import_dialog(&mut self, ctx: &egui::Context, c: &NimColors) -> Option<InventoryAction> {
+        let mut action = None;
+        let mut close = false;
+        let dialog = self.import.as_mut()?;
+
+        egui::Window::new("Importar CSV")
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .resizable(false)
+            .collapsible(false)
+            .frame(
+                egui::Frame::window(&ctx.style())
+                    .fill(c.bg_card)
+                    .stroke(Stroke::new(1.0, c.border))
+                    .rounding(Rounding::same(14.0)),
+            )
+            .show(ctx, |ui| {
+                ui.set_min_width(320.0);
+                ui.set_max_width(420.0);
+
+                if let Some(ref report) = dialog.report {
+                    // ── Step 3: result ────────────────────────────────────
+                    ui.label(
+                        RichText::new(format!(
+                            "✓ {} nuevos · {} actualizados · {} omitidos",
+                            report.inserted, report.updated, report.skipped
+                        ))
+                        .color(c.success)
+                        .size(14.0),
+                    );
+                    if report.merged > 0 {
+                        ui.label(
+                            RichText::new(format!("{} filas duplicadas combinadas", report.merged))
+                                .color(c.text_secondary)
+                                .size(12.0),
+                        );
+                    }
+                    if !report.errors.is_empty() {
+                        ui.add_space(6.0);
+                        egui::ScrollArea::vertical().max_height(160.0).show(ui, |ui| {
+                            for err in &report.errors {
+                                ui.label(RichText::new(format!("⚠ {}", err)).color(c.danger).size(12.0));
+                            }
+                        });
+                    }
+                } else if dialog.headers.is_empty() {
+                    // ── Step 1: paste the export ──────────────────────────
+                    ui.label(
+                        RichText::new("Pega el contenido del archivo CSV (la primera fila debe ser el encabezado)")
+                            .size(13.0)
+                            .color(c.text_secondary),
+                    );
+                    ui.add_space(6.0);
+                    egui::ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+                        ui.add(
+                            egui::TextEdit::multiline(&mut dialog.csv_text)
+                                .hint_text("codigo,nombre,cantidad,costo,precio")
+                                .desired_rows(8)
+                                .desired_width(f32::INFINITY)
+                                .font(egui::TextStyle::Monospace),
+                        );
+                    });
+                    ui.add_space(8.0);
+                    ui.horizontal(|ui| {
+                        #[cfg(not(target_os = "android"))]
+                        if ui.button("📂 Abrir archivo…").clicked() {
+                            action = Some(InventoryAction::PickImportFile);
+                        }
+                        if ui.button("Analizar columnas").clicked() {
+                            let mut records = parse_csv(&dialog.csv_text);
+                            if records.len() < 2 {
+                                dialog.error = Some("Se necesita un encabezado y al menos una fila".into());
+                            } else {
+                                dialog.headers = records.remove(0);
+                                dialog.mapping = ColumnMapping::detect(&dialog.headers);
+                                dialog.records = records;
+                                dialog.error = None;
+                            }
+                        }
+                    });
+                } else {
+                    // ── Step 2: map columns to fields ─────────────────────
+                    ui.label(
+                        RichText::new(format!("{} filas detectadas. Asigna cada columna:", dialog.records.len()))
+                            .size(13.0)
+                            .color(c.text_secondary),
+                    );
+                    ui.add_space(6.0);
+                    egui::Grid::new("import_mapping").num_columns(2).spacing([12.0, 6.0]).show(ui, |ui| {
+                        for (i, header) in dialog.headers.iter().enumerate() {
+                            let sample = dialog.records.first().and_then(|r| r.get(i)).map(String::as_str).unwrap_or("");
+                            ui.label(RichText::new(header).strong().color(c.text_primary))
+                                .on_hover_text(format!("Ej: {}", sample));
+                            let current = dialog.mapping.columns[i];
+                            egui::ComboBox::from_id_source(("import_col", i))
+                                .selected_text(current.map_or("— Ignorar —", |f| f.label()))
+                                .show_ui(ui, |ui| {
+                                    ui.selectable_value(&mut dialog.mapping.columns[i], None, "— Ignorar —");
+                                    for field in ProductField::ALL {
+                                        ui.selectable_value(&mut dialog.mapping.columns[i], Some(field), field.label());
+                                    }
+                                });
+                            ui.end_row();
+                        }
+                    });
+
+                    // A field can only come from one column: keep the latest pick
+                    for i in 0..dialog.mapping.columns.len() {
+                        if let Some(field) = dialog.mapping.columns[i] {
+                            for j in 0..i {
+                                if dialog.mapping.columns[j] == Some(field) {
+                                    dialog.mapping.columns[j] = None;
+                                }
+                            }
+                        }
+                    }
+
+                    let missing = dialog.mapping.missing_required();
+                    if !missing.is_empty() {
+                        ui.add_space(6.0);
+                        let names: Vec<&str> = missing.iter().map(|f| f.label()).collect();
+                        ui.label(
+                            RichText::new(format!("⚠ Falta asignar: {}", names.join(", ")))
+                                .color(c.danger)
+                                .size(12.0),
+                        );
+                    }
+
+                    ui.add_space(6.0);
+                    ui.checkbox(&mut dialog.merge_duplicates, "Combinar duplicados")
+                        .on_hover_text("Filas con el mismo código suman su cantidad y usan el último precio");
+
+                    ui.add_space(10.0);
+                    ui.horizontal(|ui| {
+                        if ui.button("← Atrás").clicked() {
+                            dialog.headers.clear();
+                            dialog.records.clear();
+                        }
+                        let can_import = missing.is_empty();
+                        let import_btn = egui::Button::new(
+                            RichText::new("Importar").color(Color32::WHITE).strong(),
+                        )
+                        .fill(if can_import { c.primary } else { c.text_muted })
+                        .rounding(Rounding::same(8.0));
+                        if ui.add(import_btn).clicked() && can_import {
+                            action = Some(InventoryAction::ImportCsv {
+                                rows: dialog.records.clone(),
+                                mapping: dialog.mapping.clone(),
+                                merge_duplicates: dialog.merge_duplicates,
+                            });
+                        }
+                    });
+                }
+
+                if let Some(ref err) = dialog.error {
+                    ui.add_space(6.0);
+                    ui.label(RichText::new(format!("⚠ {}", err)).color(c.danger).size(13.0));
+                }
+
+                ui.add_space(10.0);
+                if ui.button("Cerrar").clicked() {
+                    close = true;
+                }
+            });
+
+        if close {
+            self.import = None;
+        }
+        action
+    }
+
+    fn show_price_dialog(&mut self, ctx: &egui::Context, c: &NimColors) -> Option<InventoryAction> {
+        let mut action = None;
+
+        // The preview works off the loaded list, so a filtered list can
+        // only adjust its own category
+        let categories = match self.category_filter {
+            Some(ref cat) => vec![cat.clone()],
+            None => self.categories.clone(),
+        };
+        let filtered = self.category_filter.is_some();
+
+        egui::Window::new("Ajuste masivo de precios")
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .resizable(false)
+            .collapsible(false)
+            .frame(
+                egui::Frame::window(&ctx.style())
+                    .fill(c.bg_card)
+                    .stroke(Stroke::new(1.0, c.border))
+                    .rounding(Rounding::same(14.0)),
+            )
+            .show(ctx, |ui| {
+                ui.set_min_width(300.0);
+
+                form_field(ui, c, "Porcentaje (ej: 10 o -5)", |ui| {
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            egui::TextEdit::singleline(&mut self.price_percent)
+                                .hint_text("0")
+                                .desired_width(120.0),
+                        );
+                        ui.label(RichText::new("%").color(c.text_muted));
+                    });
+                });
+                ui.add_space(10.0);
+
+                form_field(ui, c, "Categoría", |ui| {
+                    egui::ComboBox::from_id_source("price_category")
+                        .selected_text(self.price_category.as_deref().unwrap_or("Todas"))
+                        .show_ui(ui, |ui| {
+                            if !filtered {
+                                ui.selectable_value(&mut self.price_category, None, "Todas");
+                            }
+                            for cat in &categories {
+                                ui.selectable_value(
+                                    &mut self.price_category,
+                                    Some(cat.clone()),
+                                    RichText::new(format!("● {}", cat)).color(category_color(cat)),
+                                );
+                            }
+                        });
+                });
+
+                // Preview of the resulting average sale price
+                let percent = parse_decimal(&self.price_percent)
+                    .filter(|p| p.is_finite() && *p > -100.0);
+                let in_scope: Vec<&Product> = self.products.iter()
+                    .filter(|p| self.price_category.is_none() || p.category == self.price_category)
+                    .collect();
+                ui.add_space(10.0);
+                if let (Some(pct), false) = (percent, in_scope.is_empty()) {
+                    let avg = in_scope.iter().map(|p| p.sale_value).sum::<f64>() / in_scope.len() as f64;
+                    ui.label(
+                        RichText::new(format!(
+                            "{} productos · precio promedio {} → {}",
+                            in_scope.len(),
+                            format_money(avg, &self.currency),
+                            format_money(avg * (1.0 + pct / 100.0), &self.currency),
+                        ))
+                        .size(13.0)
+                        .color(c.text_secondary),
+                    );
+                } else if in_scope.is_empty() {
+                    ui.label(RichText::new("No hay productos en esta categoría").size(13.0).color(c.text_muted));
+                }
+
+                ui.add_space(16.0);
+                ui.horizontal(|ui| {
+                    if ui
+                        .add(
+                            egui::Button::new(RichText::new("Cancelar").color(c.text_secondary))
+                                .fill(c.bg_input)
+                                .rounding(Rounding::same(8.0))
+                                .min_size(Vec2::new(120.0, 38.0)),
+                        )
+                        .clicked()
+                    {
+                        self.show_price_dialog = false;
+                    }
+
+                    let can_apply = percent.is_some() && !in_scope.is_empty();
+                    let apply_btn = egui::Button::new(
+                        RichText::new("Aplicar").color(Color32::WHITE).strong(),
+                    )
+                    .fill(if can_apply { c.primary } else { c.text_muted })
+                    .rounding(Rounding::same(8.0))
+                    .min_size(Vec2::new(120.0, 38.0));
+                    if ui.add(apply_btn).clicked() && can_apply {
+                        if let Some(pct) = percent {
+                            action = Some(InventoryAction::AdjustPrices {
+                                category: self.price_category.clone(),
+                                percent: pct,
+                            });
+                            self.show_price_dialog = false;
+                        }
+                    }
+                });
+            });
+
         action
     }
 
@@ -328,12 +971,85 @@ impl InventoryScreen {
                                             );
                                         });
                                         ui.add_space(10.0);
-                                        form_field(ui, c, "Nombre del producto", |ui| {
+                                        form_field(ui, c, "Código de barras (opcional)", |ui| {
                                             ui.add(
+                                                egui::TextEdit::singleline(&mut self.form.barcode)
+                                                    .hint_text("EAN / UPC")
+                                                    .desired_width(f32::INFINITY),
+                                            );
+                                        });
+                                        ui.add_space(10.0);
+                                        form_field(ui, c, "Nombre del producto", |ui| {
+                                            let resp = ui.add(
                                                 egui::TextEdit::singleline(&mut self.form.name)
                                                     .hint_text("Nombre descriptivo")
                                                     .desired_width(f32::INFINITY),
                                             );
+                                            if resp.changed() && self.editing_id.is_none() {
+                                                if self.form.name.trim().is_empty() {
+                                                    self.suggestions.clear();
+                                                    self.suggest_pending = None;
+                                                } else {
+                                                    let now = ui.input(|i| i.time);
+                                                    self.suggest_pending = Some((self.form.name.clone(), now));
+                                                }
+                                            }
+                                        });
+                                        // Existing products with that name: edit one instead of duplicating
+                                        if self.editing_id.is_none() && !self.suggestions.is_empty() {
+                                            let mut picked = None;
+                                            egui::Frame::none()
+                                                .fill(c.bg_elevated)
+                                                .rounding(Rounding::same(8.0))
+                                                .stroke(Stroke::new(1.0, c.border))
+                                                .inner_margin(egui::Margin::same(6.0))
+                                                .show(ui, |ui| {
+                                                    ui.label(RichText::new("Ya existen:").size(11.0).color(c.text_muted));
+                                                    for p in &self.suggestions {
+                                                        let row = egui::Button::new(
+                                                            RichText::new(format!("{}  [{}]", p.name, p.code))
+                                                                .size(13.0)
+                                                                .color(c.text_primary),
+                                                        )
+                                                        .fill(Color32::TRANSPARENT)
+                                                        .min_size(Vec2::new(ui.available_width(), 26.0));
+                                                        if ui.add(row).on_hover_text("Editar este producto").clicked() {
+                                                            picked = Some(p.clone());
+                                                        }
+                                                    }
+                                                });
+                                            if let Some(p) = picked {
+                                                self.open_product(&p);
+                                            }
+                                        }
+                                        ui.add_space(10.0);
+                                        form_field(ui, c, "Categoría (opcional)", |ui| {
+                                            ui.horizontal(|ui| {
+                                                // Pick an existing category, or type a new one
+                                                egui::ComboBox::from_id_source("form_category")
+                                                    .width(130.0)
+                                                    .selected_text(if self.form.category.trim().is_empty() {
+                                                        UNCATEGORIZED
+                                                    } else {
+                                                        self.form.category.as_str()
+                                                    })
+                                                    .show_ui(ui, |ui| {
+                                                        if ui.selectable_label(self.form.category.trim().is_empty(), UNCATEGORIZED).clicked() {
+                                                            self.form.category.clear();
+                                                        }
+                                                        for cat in &self.categories {
+                                                            let label = RichText::new(format!("● {}", cat)).color(category_color(cat));
+                                                            if ui.selectable_label(self.form.category.trim() == cat, label).clicked() {
+                                                                self.form.category = cat.clone();
+                                                            }
+                                                        }
+                                                    });
+                                                ui.add(
+                                                    egui::TextEdit::singleline(&mut self.form.category)
+                                                        .hint_text("o nueva, ej: Ferretería")
+                                                        .desired_width(f32::INFINITY),
+                                                );
+                                            });
                                         });
                                         ui.add_space(10.0);
                                         form_field(ui, c, "Cantidad", |ui| {
@@ -344,45 +1060,154 @@ impl InventoryScreen {
                                             );
                                         });
                                         ui.add_space(10.0);
-                                        form_field(ui, c, "Valor Neto (costo)", |ui| {
-                                            ui.horizontal(|ui| {
-                                                ui.label(RichText::new("$").color(c.text_muted));
+                                        ui.horizontal(|ui| {
+                                            ui.label(RichText::new("Alerta:").size(13.0).color(c.text_secondary));
+                                            for (label, mode) in [("Sin stock", AlertMode::Absolute), ("Punto de reorden", AlertMode::ReorderPoint)] {
+                                                if ui.selectable_label(self.form.alert_mode == mode, label).clicked() {
+                                                    self.form.alert_mode = mode;
+                                                }
+                                            }
+                                        });
+                                        ui.add_space(6.0);
+                                        form_field(ui, c, "Sin stock por debajo de", |ui| {
+                                            ui.add(
+                                                egui::TextEdit::singleline(&mut self.form.low_stock_threshold)
+                                                    .hint_text("1")
+                                                    .desired_width(f32::INFINITY),
+                                            );
+                                        });
+                                        if self.form.alert_mode == AlertMode::ReorderPoint {
+                                            ui.add_space(6.0);
+                                            form_field(ui, c, "Punto de reorden", |ui| {
                                                 ui.add(
-                                                    egui::TextEdit::singleline(&mut self.form.net_value)
-                                                        .hint_text("0.00")
+                                                    egui::TextEdit::singleline(&mut self.form.reorder_point)
+                                                        .hint_text("Ej: 10")
                                                         .desired_width(f32::INFINITY),
                                                 );
                                             });
+                                            ui.add_space(10.0);
+                                            form_field(ui, c, "Avisar con margen (% sobre el punto)", |ui| {
+                                                ui.horizontal(|ui| {
+                                                    ui.add(
+                                                        egui::TextEdit::singleline(&mut self.form.alert_pct)
+                                                            .hint_text("20")
+                                                            .desired_width(ui.available_width() - 20.0),
+                                                    );
+                                                    ui.label(RichText::new("%").color(c.text_muted));
+                                                });
+                                            });
+                                            // Live preview of the trigger level
+                                            if let Some(point) = parse_decimal(&self.form.reorder_point) {
+                                                let pct = parse_decimal(&self.form.alert_pct).unwrap_or(0.0);
+                                                ui.add_space(4.0);
+                                                ui.label(
+                                                    RichText::new(format!(
+                                                        "Avisa con {} unidades o menos",
+                                                        format_quantity(point * (1.0 + pct / 100.0))
+                                                    ))
+                                                    .size(12.0)
+                                                    .color(c.text_muted),
+                                                );
+                                            }
+                                        }
+                                        ui.add_space(10.0);
+                                        ui.horizontal(|ui| {
+                                            ui.label(RichText::new("Precio:").size(13.0).color(c.text_secondary));
+                                            for (label, mode) in [("Valores", PriceMode::Absolute), ("Margen %", PriceMode::Margin)] {
+                                                if ui.selectable_label(self.form.price_mode == mode, label).clicked()
+                                                    && self.form.price_mode != mode
+                                                {
+                                                    self.form.price_mode = mode;
+                                                    if mode == PriceMode::Margin {
+                                                        self.form.sync_margin_from_sale();
+                                                    }
+                                                }
+                                            }
+                                        });
+                                        ui.add_space(6.0);
+                                        let margin_mode = self.form.price_mode == PriceMode::Margin;
+                                        form_field(ui, c, "Valor Neto (costo)", |ui| {
+                                            ui.horizontal(|ui| {
+                                                let resp = money_field(ui, c, &mut self.form.net_value, &self.currency);
+                                                if margin_mode && resp.changed() {
+                                                    self.form.sync_sale_from_margin();
+                                                }
+                                            });
                                         });
+                                        if margin_mode {
+                                            ui.add_space(10.0);
+                                            form_field(ui, c, "Margen deseado (% sobre la venta)", |ui| {
+                                                ui.horizontal(|ui| {
+                                                    let resp = ui.add(
+                                                        egui::TextEdit::singleline(&mut self.form.margin_pct)
+                                                            .hint_text("30")
+                                                            .desired_width(ui.available_width() - 20.0),
+                                                    );
+                                                    ui.label(RichText::new("%").color(c.text_muted));
+                                                    if resp.changed() {
+                                                        self.form.sync_sale_from_margin();
+                                                    }
+                                                });
+                                            });
+                                        }
                                         ui.add_space(10.0);
                                         form_field(ui, c, "Valor Venta (precio)", |ui| {
                                             ui.horizontal(|ui| {
-                                                ui.label(RichText::new("$").color(c.text_muted));
-                                                ui.add(
-                                                    egui::TextEdit::singleline(&mut self.form.sale_value)
-                                                        .hint_text("0.00")
-                                                        .desired_width(f32::INFINITY),
-                                                );
+                                                let resp = money_field(ui, c, &mut self.form.sale_value, &self.currency);
+                                                if margin_mode && resp.changed() {
+                                                    self.form.sync_margin_from_sale();
+                                                }
                                             });
                                         });
 
                                         // Live profit preview
-                                        if let (Ok(net), Ok(sale)) = (
-                                            self.form.net_value.parse::<f64>(),
-                                            self.form.sale_value.parse::<f64>(),
+                                        if let (Some(net), Some(sale)) = (
+                                            parse_decimal(&self.form.net_value),
+                                            parse_decimal(&self.form.sale_value),
                                         ) {
                                             let profit = sale - net;
                                             ui.add_space(8.0);
                                             ui.label(
                                                 RichText::new(format!(
                                                     "Ganancia unitaria: {}",
-                                                    format_currency(profit)
+                                                    format_money(profit, &self.currency)
                                                 ))
                                                 .color(if profit >= 0.0 { c.success } else { c.danger })
                                                 .size(13.0),
                                             );
                                         }
 
+                                        // Trend vs the stored values (editing only)
+                                        if let Some(delta) = self
+                                            .editing_original
+                                            .as_ref()
+                                            .and_then(|orig| self.form.delta_vs(orig))
+                                        {
+                                            for (label, d) in [
+                                                ("Venta", delta.sale_value),
+                                                ("Ganancia", delta.profit_value),
+                                            ] {
+                                                if d.abs() < 0.005 {
+                                                    continue;
+                                                }
+                                                let (arrow, color) = if d > 0.0 {
+                                                    ("▲", c.success)
+                                                } else {
+                                                    ("▼", c.danger)
+                                                };
+                                                ui.label(
+                                                    RichText::new(format!(
+                                                        "{} {} {} vs. actual",
+                                                        arrow,
+                                                        label,
+                                                        format_money(d.abs(), &self.currency)
+                                                    ))
+                                                    .color(color)
+                                                    .size(12.0),
+                                                );
+                                            }
+                                        }
+
                                         if let Some(ref err) = self.form_error {
                                             ui.add_space(8.0);
                                             ui.label(RichText::new(format!("⚠ {}", err)).color(c.danger).size(13.0));
@@ -406,6 +1231,16 @@ impl InventoryScreen {
                                                     action = InventoryAction::DeleteProduct { id: pid };
                                                     self.view = InventoryView::List;
                                                 }
+
+                                                let revert_btn = egui::Button::new(
+                                                    RichText::new("↶ Revertir último cambio").color(c.text_secondary),
+                                                )
+                                                .fill(c.bg_input)
+                                                .rounding(Rounding::same(8.0))
+                                                .min_size(Vec2::new(0.0, 42.0));
+                                                if ui.add(revert_btn).clicked() {
+                                                    action = InventoryAction::RevertLastMovement { id: pid };
+                                                }
                                             }
 
                                             ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
@@ -419,6 +1254,7 @@ impl InventoryScreen {
                                                 if ui.add(save_btn).clicked() {
                                                     match self.build_product(owner_uid) {
                                                         Ok(p) => {
+                                                            self.form.code = p.code.clone();
                                                             action = InventoryAction::SaveProduct { product: p };
                                                             self.form_success = Some("Guardado correctamente".into());
                                                             self.form_error = None;
@@ -439,38 +1275,237 @@ impl InventoryScreen {
                 });
             });
 
+        // Debounced name lookup; a button action from this frame goes first
+        if let Some((ref prefix, typed_at)) = self.suggest_pending {
+            let waited = ctx.input(|i| i.time) - typed_at;
+            if waited < SUGGEST_DEBOUNCE_SECS {
+                ctx.request_repaint_after(std::time::Duration::from_secs_f64(SUGGEST_DEBOUNCE_SECS - waited));
+            } else if matches!(action, InventoryAction::None) {
+                action = InventoryAction::SuggestProducts { prefix: prefix.clone() };
+                self.suggest_pending = None;
+            }
+        }
+
         action
     }
 
+    /// Show `p`'s detail view; its history arrives with the returned action.
+    pub fn open_detail(&mut self, p: &Product) -> InventoryAction {
+        self.detail_id = Some(p.id);
+        self.movements.clear();
+        self.scroll_to_product = Some(p.id);
+        self.view = InventoryView::Detail;
+        InventoryAction::LoadMovements { id: p.id }
+    }
+
+    fn show_detail(&mut self, ctx: &egui::Context, c: &NimColors) {
+        // Deleted (or filtered away) meanwhile: nothing to show
+        let Some(p) = self.detail_id.and_then(|id| self.products.iter().find(|p| p.id == id)).cloned() else {
+            self.view = InventoryView::List;
+            return;
+        };
+
+        egui::CentralPanel::default()
+            .frame(egui::Frame::none().fill(c.bg_base))
+            .show(ctx, |ui| {
+                ui.add_space(12.0);
+                ui.horizontal(|ui| {
+                    ui.add_space(16.0);
+                    if ui.button("← Volver").clicked() {
+                        self.view = InventoryView::List;
+                    }
+                    ui.label(RichText::new(&p.name).size(18.0).strong().color(c.text_primary));
+                    ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                        ui.add_space(16.0);
+                        let edit_btn = egui::Button::new(
+                            RichText::new("✏ Editar").size(13.0).color(Color32::WHITE),
+                        )
+                        .fill(c.primary)
+                        .rounding(Rounding::same(8.0))
+                        .min_size(Vec2::new(90.0, 32.0));
+                        if ui.add(edit_btn).clicked() {
+                            self.open_product(&p);
+                        }
+                    });
+                });
+                ui.add_space(16.0);
+
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    let card_width = (ui.available_width() - 32.0).min(500.0);
+                    ui.horizontal(|ui| {
+                        ui.add_space((ui.available_width() - card_width) / 2.0);
+                        ui.allocate_ui_with_layout(Vec2::new(card_width, 0.0), Layout::top_down(Align::Min), |ui| {
+                            egui::Frame::none()
+                                .fill(c.bg_card)
+                                .rounding(Rounding::same(14.0))
+                                .stroke(Stroke::new(1.0, c.border))
+                                .inner_margin(egui::Margin::same(20.0))
+                                .show(ui, |ui| {
+                                    ui.set_width(card_width - 40.0);
+                                    let margin = if p.net_value > 0.0 {
+                                        format!("{:.1} %", p.profit_value / p.net_value * 100.0)
+                                    } else {
+                                        "—".to_string()
+                                    };
+                                    let margin_color = if p.profit_value < 0.0 { c.danger } else { c.success };
+                                    let qty_color = if p.is_low_stock() { c.danger } else { c.text_primary };
+                                    let rows: [(&str, String, Color32); 11] = [
+                                        ("Código", p.code.clone(), c.text_primary),
+                                        ("Categoría", p.category.clone().unwrap_or_else(|| UNCATEGORIZED.to_string()), c.text_primary),
+                                        ("Cantidad", format_quantity(p.quantity), qty_color),
+                                        ("Valor neto", format_money(p.net_value, &self.currency), c.text_primary),
+                                        ("Valor venta", format_money(p.sale_value, &self.currency), c.text_primary),
+                                        ("Ganancia por unidad", format_money(p.profit_value, &self.currency), margin_color),
+                                        ("Margen", margin, margin_color),
+                                        ("Valor neto total", format_money(p.total_net(), &self.currency), c.secondary),
+                                        ("Ganancia total", format_money(p.total_profit(), &self.currency), margin_color),
+                                        ("Creado", format_datetime(&p.created_at), c.text_secondary),
+                                        ("Actualizado", format_datetime(&p.updated_at), c.text_secondary),
+                                    ];
+                                    for (label, value, color) in rows {
+                                        ui.horizontal(|ui| {
+                                            ui.label(RichText::new(label).size(13.0).color(c.text_muted));
+                                            ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                                                ui.label(RichText::new(value).size(14.0).strong().color(color));
+                                            });
+                                        });
+                                        ui.add_space(4.0);
+                                    }
+                                });
+
+                            ui.add_space(16.0);
+                            ui.label(RichText::new("Movimientos").size(15.0).strong().color(c.text_primary));
+                            ui.add_space(6.0);
+                            if self.movements.is_empty() {
+                                ui.label(RichText::new("Sin movimientos registrados").size(12.0).color(c.text_muted));
+                            }
+                            for m in &self.movements {
+                                ui.horizontal(|ui| {
+                                    ui.label(
+                                        RichText::new(format_datetime(&m.created_at)).size(11.0).color(c.text_muted),
+                                    );
+                                    if m.delta != 0.0 {
+                                        let (text, color) = if m.delta > 0.0 {
+                                            (format!("+{}", format_quantity(m.delta)), c.success)
+                                        } else {
+                                            (format_quantity(m.delta), c.danger)
+                                        };
+                                        ui.label(RichText::new(text).size(13.0).strong().color(color));
+                                    }
+                                    if let (Some(old), Some(new)) = (m.old_sale_value, m.new_sale_value) {
+                                        ui.label(
+                                            RichText::new(format!("{} → {}", format_money(old, &self.currency), format_money(new, &self.currency)))
+                                                .size(12.0)
+                                                .color(c.text_secondary),
+                                        );
+                                    }
+                                    if let Some(ref note) = m.note {
+                                        ui.label(RichText::new(note).size(12.0).italics().color(c.text_muted));
+                                    }
+                                });
+                            }
+                        });
+                    });
+                    ui.add_space(60.0);
+                });
+            });
+    }
+
+    /// Load a product into the edit form and switch to it.
+    pub fn open_product(&mut self, p: &Product) {
+        self.suggestions.clear();
+        self.suggest_pending = None;
+        self.form = ProductForm {
+            code: p.code.clone(),
+            barcode: p.barcode.clone().unwrap_or_default(),
+            name: p.name.clone(),
+            quantity: p.quantity.to_string(),
+            net_value: p.net_value.to_string(),
+            sale_value: p.sale_value.to_string(),
+            category: p.category.clone().unwrap_or_default(),
+            alert_mode: if p.reorder_point.is_some() { AlertMode::ReorderPoint } else { AlertMode::Absolute },
+            reorder_point: p.reorder_point.map(|v| v.to_string()).unwrap_or_default(),
+            alert_pct: if p.reorder_point.is_some() { (p.alert_pct * 100.0).to_string() } else { String::new() },
+            low_stock_threshold: p.low_stock_threshold.to_string(),
+            ..ProductForm::default()
+        };
+        self.editing_id = Some(p.id);
+        self.editing_original = Some(p.clone());
+        self.scroll_to_product = Some(p.id);
+        self.form_error = None;
+        self.form_success = None;
+        self.view = InventoryView::Form;
+    }
+
+    /// Show why a save failed where it was asked for: the form, or the
+    /// quick-add line in the list.
+    pub fn save_failed(&mut self, msg: String) {
+        self.form_success = None;
+        if self.view == InventoryView::Form {
+            self.form_error = Some(msg);
+        } else {
+            self.quick_add_error = Some(msg);
+        }
+    }
+
     fn build_product(&self, owner_uid: &str) -> Result<Product, String> {
-        let code = self.form.code.trim().to_string();
+        let code = normalize_code(&self.form.code);
         let name = self.form.name.trim().to_string();
         if code.is_empty() { return Err("El código es obligatorio".into()); }
         if name.is_empty() { return Err("El nombre es obligatorio".into()); }
 
-        let quantity = self.form.quantity.trim().parse::<f64>()
-            .map_err(|_| "Cantidad inválida".to_string())?;
-        let net_value = self.form.net_value.trim().parse::<f64>()
-            .map_err(|_| "Valor neto inválido".to_string())?;
-        let sale_value = self.form.sale_value.trim().parse::<f64>()
-            .map_err(|_| "Valor venta inválido".to_string())?;
+        let quantity = parse_decimal(&self.form.quantity)
+            .ok_or_else(|| "Cantidad inválida".to_string())?;
+        let net_value = parse_decimal(&self.form.net_value)
+            .ok_or_else(|| "Valor neto inválido".to_string())?;
+        let sale_value = parse_decimal(&self.form.sale_value)
+            .ok_or_else(|| "Valor venta inválido".to_string())?;
 
         if net_value < 0.0 || sale_value < 0.0 {
             return Err("Los valores no pueden ser negativos".into());
         }
 
+        let (reorder_point, alert_pct) = match self.form.alert_mode {
+            AlertMode::Absolute => (None, 0.0),
+            AlertMode::ReorderPoint => {
+                let point = parse_decimal(&self.form.reorder_point)
+                    .filter(|v| v.is_finite() && *v >= 0.0)
+                    .ok_or_else(|| "Punto de reorden inválido".to_string())?;
+                let pct = match self.form.alert_pct.trim() {
+                    "" => 0.0,
+                    raw => parse_decimal(raw)
+                        .filter(|v| v.is_finite() && *v >= 0.0)
+                        .ok_or_else(|| "Porcentaje de alerta inválido".to_string())?,
+                };
+                (Some(point), pct / 100.0)
+            }
+        };
+
+        let low_stock_threshold = match self.form.low_stock_threshold.trim() {
+            "" => DEFAULT_LOW_STOCK_THRESHOLD,
+            raw => parse_decimal(raw)
+                .filter(|v| v.is_finite() && *v >= 0.0)
+                .ok_or_else(|| "Umbral de sin stock inválido".to_string())?,
+        };
+
         let profit_value = sale_value - net_value;
+        let category = Some(self.form.category.trim().to_string()).filter(|c| !c.is_empty());
         let now = chrono::Utc::now().to_rfc3339();
 
         Ok(Product {
             id: self.editing_id.unwrap_or(0),
             owner_uid: owner_uid.to_string(),
             code,
+            barcode: normalize_barcode(&self.form.barcode),
             name,
             quantity,
             net_value,
             sale_value,
             profit_value,
+            category,
+            reorder_point,
+            alert_pct,
+            low_stock_threshold,
             created_at: now.clone(),
             updated_at: now,
         })
@@ -487,6 +1522,28 @@ fn form_field(ui: &mut egui::Ui, c: &NimColors, label: &str, add_field: impl FnO
     add_field(ui);
 }
 
+/// Amount input with the currency symbol on the side it's written on.
+fn money_field(ui: &mut egui::Ui, c: &NimColors, value: &mut String, currency_code: &str) -> egui::Response {
+    let cur = currency(currency_code);
+    let symbol = RichText::new(cur.symbol.trim()).color(c.text_muted);
+    if cur.symbol_after {
+        let resp = ui.add(
+            egui::TextEdit::singleline(value)
+                .hint_text("0.00")
+                .desired_width(ui.available_width() - 20.0),
+        );
+        ui.label(symbol);
+        resp
+    } else {
+        ui.label(symbol);
+        ui.add(
+            egui::TextEdit::singleline(value)
+                .hint_text("0.00")
+                .desired_width(f32::INFINITY),
+        )
+    }
+}
+
 fn table_header(ui: &mut egui::Ui, c: &NimColors, label: &str, width: f32) {
     let (rect, _) = ui.allocate_exact_size(Vec2::new(width, 24.0), egui::Sense::hover());
     ui.painter().text(
@@ -498,6 +1555,73 @@ fn table_header(ui: &mut egui::Ui, c: &NimColors, label: &str, width: f32) {
     );
 }
 
+/// A decimal typed by the user; "0,50" is how most of our users write them.
+fn parse_decimal(raw: &str) -> Option<f64> {
+    raw.trim().replace(',', ".").parse().ok()
+}
+
+/// Parse a quick-add line `CÓDIGO | Nombre | Cantidad | Neto | Venta`
+/// (an optional sixth field is the category) into a new product.
+pub fn parse_quick_add(line: &str, owner_uid: &str) -> Result<Product, String> {
+    let fields: Vec<&str> = line.split('|').map(str::trim).collect();
+    if fields.len() < 5 {
+        return Err("Formato: CÓDIGO | Nombre | Cantidad | Neto | Venta".into());
+    }
+    if fields.len() > 6 {
+        return Err("Demasiados campos".into());
+    }
+    let code = normalize_code(fields[0]);
+    let name = fields[1].to_string();
+    if code.is_empty() { return Err("El código es obligatorio".into()); }
+    if name.is_empty() { return Err("El nombre es obligatorio".into()); }
+
+    let number = |raw: &str, what: &str| {
+        parse_decimal(raw)
+            .filter(|v| v.is_finite() && *v >= 0.0)
+            .ok_or_else(|| format!("{} inválido: '{}'", what, raw))
+    };
+    let quantity = number(fields[2], "Cantidad")?;
+    let net_value = number(fields[3], "Valor neto")?;
+    let sale_value = number(fields[4], "Valor venta")?;
+    let category = fields.get(5).map(|c| c.to_string()).filter(|c| !c.is_empty());
+
+    let now = chrono::Utc::now().to_rfc3339();
+    let mut p = Product {
+        id: 0,
+        owner_uid: owner_uid.to_string(),
+        code,
+        barcode: None,
+        name,
+        quantity,
+        net_value,
+        sale_value,
+        profit_value: 0.0,
+        category,
+        reorder_point: None,
+        alert_pct: 0.0,
+        low_stock_threshold: DEFAULT_LOW_STOCK_THRESHOLD,
+        created_at: now.clone(),
+        updated_at: now,
+    };
+    p.calculate_profit();
+    Ok(p)
+}
+
+/// Quiet time after the last keystroke before name suggestions are fetched.
+const SUGGEST_DEBOUNCE_SECS: f64 = 0.3;
+
+/// Max name suggestions shown under the form's name field.
+pub const SUGGEST_LIMIT: usize = 5;
+
+/// How long the "¡Stock agotado!" toast stays up.
+const STOCK_ALERT_SECS: f64 = 3.0;
+
+/// True when a quantity change goes from in-stock to below `threshold`.
+/// Staying at (or starting from) zero is not a crossing.
+pub fn crossed_to_empty(old: f64, new: f64, threshold: f64) -> bool {
+    old >= threshold && new < threshold
+}
+
 fn stat_card(ui: &mut egui::Ui, c: &NimColors, label: &str, value: &str, value_color: Color32) {
     let card_w = (ui.available_width() / 4.0).max(80.0);
     egui::Frame::none()
@@ -512,12 +1636,75 @@ fn stat_card(ui: &mut egui::Ui, c: &NimColors, label: &str, value: &str, value_c
         });
 }
 
-fn format_currency(v: f64) -> String {
-    if v.abs() >= 1_000_000.0 {
-        format!("${:.1}M", v / 1_000_000.0)
-    } else if v.abs() >= 1_000.0 {
-        format!("${:.1}K", v / 1_000.0)
-    } else {
-        format!("${:.2}", v)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quick_add_accepts_comma_decimals() {
+        let p = parse_quick_add("a1 | Arroz | 2,5 | 0,50 | 1.25 | Almacén", "yo").unwrap();
+        assert_eq!((p.quantity, p.net_value, p.sale_value), (2.5, 0.5, 1.25));
+        assert_eq!(p.category.as_deref(), Some("Almacén"));
+        assert!(parse_quick_add("A1 | Arroz | 1,234.5 | 1 | 2", "yo").is_err());
+        assert!(parse_quick_add("A1 | Arroz | -1 | 1 | 2", "yo").is_err());
+    }
+
+    #[test]
+    fn product_form_accepts_comma_decimals() {
+        let form = ProductForm {
+            code: "a1".into(),
+            name: "Arroz".into(),
+            quantity: "2,5".into(),
+            net_value: " 0,50 ".into(),
+            sale_value: "1,25".into(),
+            ..Default::default()
+        };
+        let screen = InventoryScreen { form, ..Default::default() };
+        let p = screen.build_product("yo").unwrap();
+        assert_eq!((p.quantity, p.net_value, p.sale_value), (2.5, 0.5, 1.25));
+        let typo = InventoryScreen {
+            form: ProductForm { quantity: "2,,5".into(), ..screen.form.clone() },
+            ..Default::default()
+        };
+        assert_eq!(typo.build_product("yo").unwrap_err(), "Cantidad inválida");
+    }
+
+    #[test]
+    fn delta_compares_typed_prices_with_the_stored_ones() {
+        let stored = parse_quick_add("A1 | Arroz | 1 | 10 | 15", "yo").unwrap();
+        let form = ProductForm { net_value: "12".into(), sale_value: " 20 ".into(), ..Default::default() };
+        assert_eq!(form.delta_vs(&stored), Some(ProductDelta { sale_value: 5.0, profit_value: 3.0 }));
+        let cheaper = ProductForm { net_value: "10".into(), sale_value: "12".into(), ..Default::default() };
+        assert_eq!(cheaper.delta_vs(&stored), Some(ProductDelta { sale_value: -3.0, profit_value: -3.0 }));
+        let typing = ProductForm { net_value: "10".into(), sale_value: "".into(), ..Default::default() };
+        assert_eq!(typing.delta_vs(&stored), None);
+    }
+
+    #[test]
+    fn only_dropping_below_the_threshold_counts_as_running_out() {
+        assert!(crossed_to_empty(3.0, 0.0, 1.0));
+        assert!(crossed_to_empty(1.0, 0.5, 1.0));
+        assert!(!crossed_to_empty(3.0, 1.0, 1.0));
+        assert!(!crossed_to_empty(0.0, 0.0, 1.0));
+        assert!(!crossed_to_empty(0.5, 0.0, 1.0));
+        assert!(!crossed_to_empty(0.0, 5.0, 1.0));
+    }
+
+    #[test]
+    fn quick_add_parses_lines_and_rejects_missing_fields() {
+        let p = parse_quick_add("A1|Arroz|10|1.5|2", "yo").unwrap();
+        assert_eq!((p.code.as_str(), p.name.as_str(), p.quantity), ("A1", "Arroz", 10.0));
+        assert!((p.profit_value - 0.5).abs() < 1e-9);
+        assert_eq!(p.category, None);
+
+        let spaced = parse_quick_add("   a1   |   Arroz largo  |  10 |1.5|  2  |   ", "yo").unwrap();
+        assert_eq!((spaced.code.as_str(), spaced.name.as_str()), ("A1", "Arroz largo"));
+        assert_eq!(spaced.category, None);
+
+        assert!(parse_quick_add("A1 | Arroz | 10 | 1.5", "yo").is_err());
+        assert!(parse_quick_add(" | Arroz | 10 | 1.5 | 2", "yo").is_err());
+        assert!(parse_quick_add("A1 |  | 10 | 1.5 | 2", "yo").is_err());
+        assert!(parse_quick_add("A1 | Arroz | diez | 1.5 | 2", "yo").is_err());
+        assert!(parse_quick_add("A1 | Arroz | 10 | 1.5 | 2 | Almacén | extra", "yo").is_err());
     }
 }
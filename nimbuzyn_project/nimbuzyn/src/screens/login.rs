@@ -1,6 +1,8 @@
 use egui::{Align, Align2, Color32, Layout, RichText, Rounding, Stroke, Vec2};
 use crate::theme::NimColors;
 use crate::models::AppTheme;
+use crate::db::RECOVERY_PHRASE_WORDS;
+use crate::validation::normalize_username;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum AuthTab { Login, Register }
@@ -14,6 +16,18 @@ pub struct LoginScreen {
     pub login_pass_visible: bool,
     pub login_error: Option<String>,
     pub login_loading: bool,
+    /// Too many failed attempts: login is disabled until this `ctx` time.
+    pub login_locked_until: Option<f64>,
+    /// Good news for the login form, e.g. after a password reset.
+    pub login_notice: Option<String>,
+
+    // Password recovery (replaces the login form while open)
+    pub recovering: bool,
+    pub rec_user: String,
+    pub rec_phrase: String,
+    pub rec_pass: String,
+    pub rec_pass2: String,
+    pub rec_error: Option<String>,
 
     // Register fields
     pub reg_user: String,
@@ -24,6 +38,8 @@ pub struct LoginScreen {
     pub reg_error: Option<String>,
     pub reg_success: Option<String>,
     pub reg_loading: bool,
+    /// Recovery phrase of the account just created, shown once.
+    pub recovery_phrase: Option<String>,
 }
 
 impl Default for LoginScreen {
@@ -35,6 +51,14 @@ impl Default for LoginScreen {
             login_pass_visible: false,
             login_error: None,
             login_loading: false,
+            login_locked_until: None,
+            login_notice: None,
+            recovering: false,
+            rec_user: String::new(),
+            rec_phrase: String::new(),
+            rec_pass: String::new(),
+            rec_pass2: String::new(),
+            rec_error: None,
             reg_user: String::new(),
             reg_display: String::new(),
             reg_pass: String::new(),
@@ -43,6 +67,7 @@ impl Default for LoginScreen {
             reg_error: None,
             reg_success: None,
             reg_loading: false,
+            recovery_phrase: None,
         }
     }
 }
@@ -50,12 +75,15 @@ impl Default for LoginScreen {
 pub enum AuthAction {
     Login { username: String, password: String },
     Register { username: String, display_name: String, password: String },
+    ResetPassword { username: String, phrase: String, new_password: String },
+    #[cfg(debug_assertions)]
+    DevLogin,
     None,
 }
 
 impl LoginScreen {
-    pub fn show(&mut self, ctx: &egui::Context, theme: &AppTheme) -> AuthAction {
-        let c = NimColors::for_theme(theme);
+    pub fn show(&mut self, ctx: &egui::Context, theme: &AppTheme, accent: Option<u32>) -> AuthAction {
+        let c = NimColors::for_theme(theme, accent);
         let mut action = AuthAction::None;
 
         egui::CentralPanel::default()
@@ -109,40 +137,51 @@ impl LoginScreen {
                                     .stroke(Stroke::new(1.0, c.border))
                                     .inner_margin(egui::style::Margin::same(24.0))
                                     .show(ui, |ui| {
-                                        // Tab selector
-                                        ui.horizontal(|ui| {
-                                            let tab_w = (ui.available_width() - 8.0) / 2.0;
-                                            for (tab_label, tab_val) in
-                                                [("Iniciar Sesión", AuthTab::Login),
-                                                 ("Crear Cuenta",   AuthTab::Register)]
-                                            {
-                                                let selected = self.tab == tab_val;
-                                                let btn = egui::Button::new(
-                                                    RichText::new(tab_label)
-                                                        .size(14.0)
-                                                        .color(if selected { c.text_on_primary } else { c.text_secondary }),
-                                                )
-                                                .min_size(Vec2::new(tab_w, 40.0))
-                                                .fill(if selected { c.primary } else { c.bg_input })
-                                                .rounding(Rounding::same(8.0));
-
-                                                if ui.add(btn).clicked() {
-                                                    self.tab = tab_val;
+                                        // Inputs stay locked while an auth request is in flight
+                                        ui.add_enabled_ui(!self.is_busy(), |ui| {
+                                            // Tab selector
+                                            ui.horizontal(|ui| {
+                                                let tab_w = (ui.available_width() - 8.0) / 2.0;
+                                                for (tab_label, tab_val) in
+                                                    [("Iniciar Sesión", AuthTab::Login),
+                                                     ("Crear Cuenta",   AuthTab::Register)]
+                                                {
+                                                    let selected = self.tab == tab_val;
+                                                    let btn = egui::Button::new(
+                                                        RichText::new(tab_label)
+                                                            .size(14.0)
+                                                            .color(if selected { c.text_on_primary } else { c.text_secondary }),
+                                                    )
+                                                    .min_size(Vec2::new(tab_w, 40.0))
+                                                    .fill(if selected { c.primary } else { c.bg_input })
+                                                    .rounding(Rounding::same(8.0));
+
+                                                    if ui.add(btn).clicked() {
+                                                        self.tab = tab_val;
+                                                    }
                                                 }
-                                            }
-                                        });
+                                            });
 
-                                        ui.add_space(20.0);
-                                        ui.separator();
-                                        ui.add_space(16.0);
+                                            ui.add_space(20.0);
+                                            ui.separator();
+                                            ui.add_space(16.0);
 
-                                        match self.tab {
-                                            AuthTab::Login => {
-                                                action = self.show_login_form(ui, &c);
-                                            }
-                                            AuthTab::Register => {
-                                                action = self.show_register_form(ui, &c);
+                                            match self.tab {
+                                                AuthTab::Login if self.recovering => {
+                                                    action = self.show_recovery_form(ui, &c);
+                                                }
+                                                AuthTab::Login => {
+                                                    action = self.show_login_form(ui, &c);
+                                                }
+                                                AuthTab::Register => {
+                                                    action = self.show_register_form(ui, &c);
+                                                }
                                             }
+                                        });
+
+                                        if self.is_busy() {
+                                            ui.add_space(10.0);
+                                            ui.vertical_centered(|ui| ui.spinner());
                                         }
                                     });
                             },
@@ -151,9 +190,78 @@ impl LoginScreen {
                 );
             });
 
+        // ── Recovery phrase, shown once after registering ──────────────────
+        if let Some(phrase) = self.recovery_phrase.clone() {
+            egui::Window::new("Tu frase de recuperación")
+                .anchor(Align2::CENTER_CENTER, [0.0, 0.0])
+                .collapsible(false)
+                .resizable(false)
+                .frame(
+                    egui::Frame::window(&ctx.style())
+                        .fill(c.bg_card)
+                        .stroke(Stroke::new(1.0, c.border))
+                        .rounding(Rounding::same(14.0)),
+                )
+                .show(ctx, |ui| {
+                    ui.set_max_width(340.0);
+                    ui.label(
+                        RichText::new(
+                            "Anótala en un lugar seguro. Es la única forma de recuperar la cuenta \
+                             si olvidas la contraseña, y no volverá a mostrarse.",
+                        )
+                        .color(c.text_secondary),
+                    );
+                    ui.add_space(12.0);
+                    egui::Grid::new("recovery_words").num_columns(3).spacing([16.0, 6.0]).show(ui, |ui| {
+                        for (i, word) in phrase.split(' ').enumerate() {
+                            ui.label(
+                                RichText::new(format!("{}. {}", i + 1, word))
+                                    .size(15.0)
+                                    .monospace()
+                                    .color(c.text_primary),
+                            );
+                            if i % 3 == 2 {
+                                ui.end_row();
+                            }
+                        }
+                    });
+                    ui.add_space(16.0);
+                    let done_btn = egui::Button::new(RichText::new("Ya la anoté").color(Color32::WHITE))
+                        .fill(c.primary)
+                        .rounding(Rounding::same(8.0))
+                        .min_size(Vec2::new(f32::INFINITY, 38.0));
+                    if ui.add(done_btn).clicked() {
+                        self.recovery_phrase = None;
+                    }
+                });
+        }
+
+        self.begin_auth(&action);
         action
     }
 
+    /// True while a login/register request is being processed.
+    pub fn is_busy(&self) -> bool {
+        self.login_loading || self.reg_loading
+    }
+
+    /// Flag the request that is about to be handed to the app as in flight.
+    fn begin_auth(&mut self, action: &AuthAction) {
+        match action {
+            AuthAction::Login { .. } | AuthAction::ResetPassword { .. } => self.login_loading = true,
+            #[cfg(debug_assertions)]
+            AuthAction::DevLogin => self.login_loading = true,
+            AuthAction::Register { .. } => self.reg_loading = true,
+            AuthAction::None => {}
+        }
+    }
+
+    /// Called once the app has processed the pending request (success or error).
+    pub fn finish_auth(&mut self) {
+        self.login_loading = false;
+        self.reg_loading = false;
+    }
+
     fn show_login_form(&mut self, ui: &mut egui::Ui, c: &NimColors) -> AuthAction {
         let mut action = AuthAction::None;
 
@@ -186,6 +294,28 @@ impl LoginScreen {
         });
         ui.add_space(20.0);
 
+        // Lockout countdown replaces whatever error was there
+        let now = ui.input(|i| i.time);
+        let locked = match self.login_locked_until {
+            Some(until) if until > now => {
+                let left = (until - now).ceil() as u64;
+                self.login_error = Some(format!("Demasiados intentos, espera {} segundos", left));
+                ui.ctx().request_repaint_after(std::time::Duration::from_secs(1));
+                true
+            }
+            Some(_) => {
+                self.login_locked_until = None;
+                self.login_error = None;
+                false
+            }
+            None => false,
+        };
+
+        if let Some(notice) = &self.login_notice {
+            ui.label(RichText::new(format!("✓ {}", notice)).size(13.0).color(c.success));
+            ui.add_space(8.0);
+        }
+
         // Error
         if let Some(err) = &self.login_error {
             ui.label(
@@ -210,7 +340,7 @@ impl LoginScreen {
         let enter = user_resp.lost_focus()
             && ui.input(|i| i.key_pressed(egui::Key::Enter));
 
-        if (ui.add(btn).clicked() || enter) && !self.login_loading {
+        if (ui.add_enabled(!locked, btn).clicked() || enter) && !self.login_loading && !locked {
             if self.login_user.trim().is_empty() || self.login_pass.is_empty() {
                 self.login_error = Some("Completa todos los campos".to_string());
             } else {
@@ -222,6 +352,124 @@ impl LoginScreen {
             }
         }
 
+        ui.add_space(8.0);
+        ui.vertical_centered(|ui| {
+            if ui.link(RichText::new("¿Olvidaste tu contraseña?").size(13.0)).clicked() {
+                self.recovering = true;
+                self.rec_user = self.login_user.trim().to_string();
+                self.rec_phrase.clear();
+                self.rec_pass.clear();
+                self.rec_pass2.clear();
+                self.rec_error = None;
+                self.login_notice = None;
+            }
+        });
+
+        // Debug builds only: one-click login with seeded sample data
+        #[cfg(debug_assertions)]
+        {
+            ui.add_space(8.0);
+            let dev_btn = egui::Button::new(
+                RichText::new("🛠 Usuario de prueba").size(13.0).color(c.text_secondary),
+            )
+            .min_size(Vec2::new(f32::INFINITY, 36.0))
+            .fill(c.bg_input)
+            .rounding(Rounding::same(10.0));
+            if ui.add(dev_btn).clicked() {
+                action = AuthAction::DevLogin;
+            }
+        }
+
+        action
+    }
+
+    fn show_recovery_form(&mut self, ui: &mut egui::Ui, c: &NimColors) -> AuthAction {
+        let mut action = AuthAction::None;
+
+        ui.label(
+            RichText::new(format!(
+                "Escribe las {} palabras que anotaste al crear la cuenta y elige una contraseña nueva.",
+                RECOVERY_PHRASE_WORDS
+            ))
+            .size(13.0)
+            .color(c.text_secondary),
+        );
+        ui.add_space(12.0);
+
+        labeled_field(ui, c, "Usuario", |ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.rec_user).desired_width(f32::INFINITY));
+        });
+        ui.add_space(10.0);
+
+        labeled_field(ui, c, "Frase de recuperación", |ui| {
+            ui.add(
+                egui::TextEdit::multiline(&mut self.rec_phrase)
+                    .hint_text("palabra palabra palabra…")
+                    .desired_rows(2)
+                    .desired_width(f32::INFINITY),
+            );
+        });
+        ui.add_space(10.0);
+
+        labeled_field(ui, c, "Nueva contraseña", |ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut self.rec_pass)
+                    .hint_text("Mínimo 8 caracteres")
+                    .password(true)
+                    .desired_width(f32::INFINITY),
+            );
+        });
+        ui.add_space(10.0);
+
+        labeled_field(ui, c, "Confirmar contraseña", |ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut self.rec_pass2)
+                    .hint_text("Repite la contraseña")
+                    .password(true)
+                    .desired_width(f32::INFINITY),
+            );
+        });
+        ui.add_space(20.0);
+
+        if let Some(err) = &self.rec_error {
+            ui.label(RichText::new(format!("⚠ {}", err)).size(13.0).color(c.danger));
+            ui.add_space(6.0);
+        }
+
+        let btn = egui::Button::new(
+            RichText::new(if self.login_loading { "Restableciendo…" } else { "Restablecer contraseña" })
+                .size(15.0)
+                .color(Color32::WHITE)
+                .strong(),
+        )
+        .min_size(Vec2::new(f32::INFINITY, 48.0))
+        .fill(c.primary)
+        .rounding(Rounding::same(10.0));
+
+        if ui.add(btn).clicked() {
+            self.rec_error = None;
+            if self.rec_user.trim().is_empty() || self.rec_phrase.trim().is_empty() {
+                self.rec_error = Some("Completa todos los campos".into());
+            } else if self.rec_pass.len() < 8 {
+                self.rec_error = Some("La contraseña debe tener al menos 8 caracteres".into());
+            } else if self.rec_pass != self.rec_pass2 {
+                self.rec_error = Some("Las contraseñas no coinciden".into());
+            } else {
+                action = AuthAction::ResetPassword {
+                    username: self.rec_user.trim().to_string(),
+                    phrase: self.rec_phrase.clone(),
+                    new_password: self.rec_pass.clone(),
+                };
+            }
+        }
+
+        ui.add_space(8.0);
+        ui.vertical_centered(|ui| {
+            if ui.link(RichText::new("← Volver").size(13.0)).clicked() {
+                self.recovering = false;
+            }
+        });
+
         action
     }
 
@@ -281,7 +529,10 @@ impl LoginScreen {
         }
 
         let btn = egui::Button::new(
-            RichText::new("Crear Cuenta").size(15.0).color(Color32::WHITE).strong(),
+            RichText::new(if self.reg_loading { "Creando…" } else { "Crear Cuenta" })
+                .size(15.0)
+                .color(Color32::WHITE)
+                .strong(),
         )
         .min_size(Vec2::new(f32::INFINITY, 48.0))
         .fill(c.secondary)
@@ -298,21 +549,19 @@ impl LoginScreen {
         self.reg_error = None;
         self.reg_success = None;
 
-        let user = self.reg_user.trim().to_string();
         let display = self.reg_display.trim().to_string();
 
-        if user.is_empty() || display.is_empty() {
+        if self.reg_user.trim().is_empty() || display.is_empty() {
             self.reg_error = Some("Todos los campos son requeridos".into());
             return;
         }
-        if user.contains(' ') {
-            self.reg_error = Some("El usuario no puede contener espacios".into());
-            return;
-        }
-        if user.len() < 3 {
-            self.reg_error = Some("El usuario debe tener al menos 3 caracteres".into());
-            return;
-        }
+        let user = match normalize_username(&self.reg_user) {
+            Ok(user) => user,
+            Err(e) => {
+                self.reg_error = Some(e);
+                return;
+            }
+        };
         if self.reg_pass.len() < 8 {
             self.reg_error = Some("La contraseña debe tener al menos 8 caracteres".into());
             return;
@@ -335,3 +584,29 @@ fn labeled_field(ui: &mut egui::Ui, c: &NimColors, label: &str, add_field: impl
     ui.add_space(4.0);
     add_field(ui);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn form_is_busy_from_request_until_the_app_finishes_it() {
+        let mut screen = LoginScreen::default();
+        screen.begin_auth(&AuthAction::None);
+        assert!(!screen.is_busy());
+
+        screen.begin_auth(&AuthAction::Login { username: "ana".into(), password: "x".into() });
+        assert!(screen.login_loading && !screen.reg_loading);
+        screen.finish_auth();
+        assert!(!screen.is_busy());
+
+        screen.begin_auth(&AuthAction::Register {
+            username: "ana".into(),
+            display_name: "Ana".into(),
+            password: "x".into(),
+        });
+        assert!(screen.reg_loading && !screen.login_loading);
+        screen.finish_auth();
+        assert!(!screen.is_busy());
+    }
+}
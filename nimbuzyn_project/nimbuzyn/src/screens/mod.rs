@@ -1,5 +1,8 @@
 pub mod login;
 pub mod chat;
 pub mod inventory;
+pub mod palette;
 pub mod settings;
 pub mod splash;
+pub mod thumbnails;
+pub mod whats_new;
@@ -0,0 +1,270 @@
+use egui::{Color32, RichText, Rounding, Stroke, Vec2};
+use crate::app::Screen;
+use crate::models::*;
+use crate::theme::NimColors;
+
+/// Max entries listed in the palette.
+pub const PALETTE_LIMIT: usize = 12;
+
+/// Screens the palette can jump to: icon, label (matched), target.
+const SCREENS: [(&str, &str, Screen); 3] = [
+    ("💬", "Chat", Screen::Chat),
+    ("📦", "Inventario", Screen::Inventory),
+    ("⚙", "Cuenta", Screen::Settings),
+];
+
+/// Ctrl+K overlay: jump to a screen, a contact or a product from one box.
+#[derive(Default)]
+pub struct CommandPalette {
+    pub open: bool,
+    pub query: String,
+    pub results: Vec<PaletteItem>,
+    pub selected: usize,
+}
+
+#[derive(Debug, Clone)]
+pub enum PaletteItem {
+    Screen { icon: &'static str, label: &'static str, screen: Screen },
+    Contact(Contact),
+    Product(Box<Product>),
+}
+
+impl PaletteItem {
+    fn label(&self) -> &str {
+        match self {
+            PaletteItem::Screen { label, .. } => label,
+            PaletteItem::Contact(ct) => &ct.display_name,
+            PaletteItem::Product(p) => &p.name,
+        }
+    }
+
+    /// Secondary text matched besides the label (UID, product code).
+    fn detail(&self) -> &str {
+        match self {
+            PaletteItem::Screen { .. } => "",
+            PaletteItem::Contact(ct) => &ct.contact_uid,
+            PaletteItem::Product(p) => &p.code,
+        }
+    }
+
+    /// Tie-break between equally good matches: screens, then contacts, then products.
+    fn kind_order(&self) -> u8 {
+        match self {
+            PaletteItem::Screen { .. } => 0,
+            PaletteItem::Contact(_) => 1,
+            PaletteItem::Product(_) => 2,
+        }
+    }
+}
+
+pub enum PaletteAction {
+    None,
+    /// The query changed; the app should reload candidates and call `set_results`.
+    Search(String),
+    Select(PaletteItem),
+}
+
+/// How well `text` matches `query` (both lowercased): 0 = prefix,
+/// 1 = a word starts with it, 2 = substring, `None` = no match.
+fn match_score(text: &str, query: &str) -> Option<u8> {
+    let text = text.to_lowercase();
+    if text.starts_with(query) {
+        Some(0)
+    } else if text.split_whitespace().any(|w| w.starts_with(query)) {
+        Some(1)
+    } else if text.contains(query) {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+/// Merge screens, contacts and products into one ranked list for `query`:
+/// best match first (label beats detail), then kind, then alphabetical.
+/// An empty query lists just the screens.
+pub fn rank_results(query: &str, contacts: &[Contact], products: &[Product], limit: usize) -> Vec<PaletteItem> {
+    let query = query.trim().to_lowercase();
+    let screens = SCREENS
+        .iter()
+        .map(|&(icon, label, ref screen)| PaletteItem::Screen { icon, label, screen: screen.clone() });
+    if query.is_empty() {
+        return screens.take(limit).collect();
+    }
+
+    let mut scored: Vec<(u8, PaletteItem)> = screens
+        .chain(contacts.iter().cloned().map(PaletteItem::Contact))
+        .chain(products.iter().cloned().map(|p| PaletteItem::Product(Box::new(p))))
+        .filter_map(|item| {
+            let score = match_score(item.label(), &query)
+                .or_else(|| match_score(item.detail(), &query).map(|s| s + 3))?;
+            Some((score, item))
+        })
+        .collect();
+    scored.sort_by(|(sa, a), (sb, b)| {
+        sa.cmp(sb)
+            .then(a.kind_order().cmp(&b.kind_order()))
+            .then_with(|| a.label().to_lowercase().cmp(&b.label().to_lowercase()))
+    });
+    scored.into_iter().map(|(_, item)| item).take(limit).collect()
+}
+
+impl CommandPalette {
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+        self.query.clear();
+        self.selected = 0;
+        self.results = rank_results("", &[], &[], PALETTE_LIMIT);
+    }
+
+    pub fn set_results(&mut self, results: Vec<PaletteItem>) {
+        self.results = results;
+        self.selected = 0;
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context, theme: &AppTheme, accent: Option<u32>) -> PaletteAction {
+        let c = NimColors::for_theme(theme, accent);
+        let mut action = PaletteAction::None;
+
+        // Keyboard navigation, consumed before the text field sees the keys
+        let (up, down, enter, escape) = ctx.input_mut(|i| {
+            (
+                i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowUp),
+                i.consume_key(egui::Modifiers::NONE, egui::Key::ArrowDown),
+                i.consume_key(egui::Modifiers::NONE, egui::Key::Enter),
+                i.consume_key(egui::Modifiers::NONE, egui::Key::Escape),
+            )
+        });
+        if escape {
+            self.open = false;
+            return action;
+        }
+        if up {
+            self.selected = self.selected.saturating_sub(1);
+        }
+        if down && self.selected + 1 < self.results.len() {
+            self.selected += 1;
+        }
+        if enter {
+            if let Some(item) = self.results.get(self.selected).cloned() {
+                self.open = false;
+                return PaletteAction::Select(item);
+            }
+        }
+
+        egui::Window::new("Ir a…")
+            .anchor(egui::Align2::CENTER_TOP, [0.0, 80.0])
+            .resizable(false)
+            .collapsible(false)
+            .title_bar(false)
+            .frame(
+                egui::Frame::window(&ctx.style())
+                    .fill(c.bg_card)
+                    .stroke(Stroke::new(1.0, c.border))
+                    .rounding(Rounding::same(14.0)),
+            )
+            .show(ctx, |ui| {
+                ui.set_width(360.0);
+                let resp = ui.add(
+                    egui::TextEdit::singleline(&mut self.query)
+                        .hint_text("Buscar pantalla, contacto o producto…")
+                        .desired_width(f32::INFINITY),
+                );
+                resp.request_focus();
+                if resp.changed() {
+                    action = PaletteAction::Search(self.query.clone());
+                }
+                ui.add_space(6.0);
+
+                if self.results.is_empty() {
+                    ui.label(RichText::new("Sin resultados").size(12.0).color(c.text_muted));
+                }
+                for (i, item) in self.results.iter().enumerate() {
+                    let selected = i == self.selected;
+                    let (icon, kind) = match item {
+                        PaletteItem::Screen { icon, .. } => (*icon, "Pantalla"),
+                        PaletteItem::Contact(_) => ("👤", "Contacto"),
+                        PaletteItem::Product(_) => ("🏷", "Producto"),
+                    };
+                    let text = if item.detail().is_empty() {
+                        format!("{}  {}", icon, item.label())
+                    } else {
+                        format!("{}  {}  · {}", icon, item.label(), item.detail())
+                    };
+                    let row = egui::Button::new(
+                        RichText::new(text)
+                            .size(13.0)
+                            .color(if selected { Color32::WHITE } else { c.text_primary }),
+                    )
+                    .fill(if selected { c.primary } else { Color32::TRANSPARENT })
+                    .rounding(Rounding::same(6.0))
+                    .min_size(Vec2::new(ui.available_width(), 28.0));
+                    if ui.add(row).on_hover_text(kind).clicked() {
+                        self.open = false;
+                        action = PaletteAction::Select(item.clone());
+                    }
+                }
+            });
+
+        action
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contact(name: &str) -> Contact {
+        Contact {
+            id: 0,
+            owner_uid: "yo".into(),
+            contact_uid: format!("uid-{}", name.to_lowercase()),
+            display_name: name.into(),
+            avatar_color: 0,
+            contact_type: ContactType::Friend,
+            starred: false,
+            pinned: false,
+            added_at: String::new(),
+            note: None,
+        }
+    }
+
+    fn product(code: &str, name: &str) -> Product {
+        Product {
+            id: 0,
+            owner_uid: "yo".into(),
+            code: code.into(),
+            barcode: None,
+            name: name.into(),
+            quantity: 1.0,
+            net_value: 1.0,
+            sale_value: 2.0,
+            profit_value: 1.0,
+            category: None,
+            reorder_point: None,
+            alert_pct: 0.0,
+            low_stock_threshold: DEFAULT_LOW_STOCK_THRESHOLD,
+            created_at: String::new(),
+            updated_at: String::new(),
+        }
+    }
+
+    fn labels(items: &[PaletteItem]) -> Vec<&str> {
+        items.iter().map(PaletteItem::label).collect()
+    }
+
+    #[test]
+    fn results_rank_by_match_then_kind_then_name() {
+        let contacts = [contact("Oscar"), contact("Inés Cardozo"), contact("Carla")];
+        let products = [product("CAR-01", "Arroz"), product("B2", "Caramelos"), product("B3", "Yerba")];
+        let results = rank_results("  CAR ", &contacts, &products, PALETTE_LIMIT);
+        assert_eq!(labels(&results), ["Carla", "Caramelos", "Inés Cardozo", "Oscar", "Arroz"]);
+        assert_eq!(labels(&rank_results("car", &contacts, &products, 2)), ["Carla", "Caramelos"]);
+    }
+
+    #[test]
+    fn empty_query_lists_only_the_screens() {
+        let results = rank_results("", &[contact("Ana")], &[product("A1", "Arroz")], PALETTE_LIMIT);
+        assert_eq!(labels(&results), ["Chat", "Inventario", "Cuenta"]);
+        assert_eq!(labels(&rank_results("inv", &[], &[], PALETTE_LIMIT)), ["Inventario"]);
+    }
+}
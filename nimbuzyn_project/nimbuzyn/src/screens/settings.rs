@@ -1,6 +1,8 @@
 use egui::{Align, Color32, Layout, RichText, Rounding, Stroke, Vec2};
 use crate::models::*;
-use crate::theme::NimColors;
+use crate::theme::{elide, NimColors, DEFAULT_FONT_SIZE, MAX_BASE_FONT_SIZE, MIN_BASE_FONT_SIZE};
+use crate::db::{ProfileStats, MAX_DISPLAY_NAME_CHARS};
+use crate::util::{avatar_initial, currency, format_datetime, format_thousands, CURRENCIES, DEFAULT_CURRENCY};
 
 pub struct SettingsScreen {
     // Display name edit
@@ -18,14 +20,76 @@ pub struct SettingsScreen {
     pub name_error: Option<String>,
     pub name_success: Option<String>,
 
+    /// Dialog showing the user's UID as a QR code.
+    pub show_qr: bool,
+
     pub show_logout_confirm: bool,
+
+    // Account deletion
+    pub show_delete_confirm: bool,
+    pub delete_pass: String,
+    /// Username typed again as a second confirmation.
+    pub delete_confirm_name: String,
+    pub delete_secure_wipe: bool,
+    pub delete_error: Option<String>,
+    /// Only the last account may wipe the whole database file.
+    pub is_last_account: bool,
+
+    pub stats: ProfileStats,
+    pub density: ListDensity,
+    /// Custom primary color (packed RGBA); `None` is the theme's blue.
+    pub accent_color: Option<u32>,
+    /// The color picker changed the accent since it opened; it's saved
+    /// once the picker closes.
+    accent_editing: bool,
+    pub currency: String,
+    pub font_size: f32,
+    pub group_window_mins: u32,
+    pub text_autoformat: bool,
+    pub copy_links: bool,
+    pub notifications_enabled: bool,
+    pub read_receipts: bool,
+    pub confirm_acquaintance_send: bool,
+
+    // Maintenance: chats left without a contact
+    pub orphaned_chats: usize,
+    pub maintenance_msg: Option<String>,
+
+    // Backup / restore of the whole database
+    pub backup_msg: Option<String>,
+    pub backup_error: Option<String>,
+    /// Backup file picked for restoring, awaiting confirmation.
+    pub restore_path: Option<String>,
+
+    // "Acerca de"
+    pub schema_version: i64,
+    pub last_backup_at: Option<String>,
 }
 
 pub enum SettingsAction {
     None,
     UpdateDisplayName(String),
+    SetAvatarColor(u32),
     ChangePassword { old_pass: String, new_pass: String },
-    ToggleTheme,
+    SetTheme(AppTheme),
+    SetAccentColor(Option<u32>),
+    /// Show a color while the picker is being dragged, without saving it.
+    PreviewAccentColor(u32),
+    SetCurrency(String),
+    SetDensity(ListDensity),
+    SetFontSize(f32),
+    SetGroupWindow(u32),
+    SetTextAutoformat(bool),
+    SetCopyLinks(bool),
+    ToggleNotifications(bool),
+    SetReadReceipts(bool),
+    SetConfirmAcquaintanceSend(bool),
+    DeleteAccount { password: String, secure_wipe: bool },
+    PurgeOrphanedChats,
+    BackupTo(String),
+    /// Save everything the account owns as JSON (where the app picks).
+    ExportAccount,
+    RestoreFrom(String),
     Logout,
 }
 
@@ -42,7 +106,33 @@ impl SettingsScreen {
             pass_success: None,
             name_error: None,
             name_success: None,
+            show_qr: false,
             show_logout_confirm: false,
+            show_delete_confirm: false,
+            delete_pass: String::new(),
+            delete_confirm_name: String::new(),
+            delete_secure_wipe: false,
+            delete_error: None,
+            is_last_account: false,
+            stats: ProfileStats::default(),
+            density: ListDensity::default(),
+            accent_color: None,
+            accent_editing: false,
+            currency: DEFAULT_CURRENCY.to_string(),
+            font_size: DEFAULT_FONT_SIZE,
+            group_window_mins: 2,
+            text_autoformat: false,
+            copy_links: false,
+            notifications_enabled: true,
+            read_receipts: true,
+            confirm_acquaintance_send: false,
+            orphaned_chats: 0,
+            maintenance_msg: None,
+            backup_msg: None,
+            backup_error: None,
+            restore_path: None,
+            schema_version: 0,
+            last_backup_at: None,
         }
     }
 
@@ -50,9 +140,10 @@ impl SettingsScreen {
         &mut self,
         ctx: &egui::Context,
         theme: &AppTheme,
+        accent: Option<u32>,
         user: &User,
     ) -> SettingsAction {
-        let c = NimColors::for_theme(theme);
+        let c = NimColors::for_theme(theme, accent);
         let mut action = SettingsAction::None;
 
         egui::CentralPanel::default()
@@ -83,42 +174,97 @@ impl SettingsScreen {
                                     ui.horizontal(|ui| {
                                         // Avatar
                                         let (rect, _) = ui.allocate_exact_size(Vec2::splat(60.0), egui::Sense::hover());
-                                        ui.painter().circle_filled(rect.center(), 30.0, c.primary);
+                                        ui.painter().circle_filled(rect.center(), 30.0, avatar_color32(user.avatar_color));
                                         ui.painter().text(
                                             rect.center(),
                                             egui::Align2::CENTER_CENTER,
-                                            user.display_name.chars().next().unwrap_or('?').to_string(),
+                                            avatar_initial(&user.display_name),
                                             egui::FontId::proportional(26.0),
                                             Color32::WHITE,
                                         );
                                         ui.add_space(12.0);
                                         ui.vertical(|ui| {
-                                            ui.label(
-                                                RichText::new(&user.display_name)
-                                                    .size(17.0)
-                                                    .strong()
-                                                    .color(c.text_primary),
+                                            let name = elide(
+                                                ui.painter(),
+                                                &user.display_name,
+                                                egui::FontId::proportional(17.0),
+                                                c.text_primary,
+                                                ui.available_width(),
                                             );
+                                            ui.label(name).on_hover_text(&user.display_name);
                                             ui.label(
                                                 RichText::new(format!("@{}", user.username))
                                                     .size(13.0)
                                                     .color(c.text_secondary),
                                             );
                                             // UID badge
-                                            egui::Frame::none()
-                                                .fill(c.primary.linear_multiply(0.15))
-                                                .rounding(Rounding::same(6.0))
-                                                .inner_margin(egui::style::Margin::symmetric(8.0, 3.0))
-                                                .show(ui, |ui| {
-                                                    ui.label(
-                                                        RichText::new(format!("ID: {}", user.uid))
-                                                            .size(11.0)
-                                                            .color(c.primary)
-                                                            .monospace(),
-                                                    );
-                                                });
+                                            ui.horizontal(|ui| {
+                                                egui::Frame::none()
+                                                    .fill(c.primary.linear_multiply(0.15))
+                                                    .rounding(Rounding::same(6.0))
+                                                    .inner_margin(egui::Margin::symmetric(8.0, 3.0))
+                                                    .show(ui, |ui| {
+                                                        ui.label(
+                                                            RichText::new(format!("ID: {}", user.uid))
+                                                                .size(11.0)
+                                                                .color(c.primary)
+                                                                .monospace(),
+                                                        );
+                                                    });
+                                                let qr_btn = egui::Button::new(
+                                                    RichText::new("▦ Mostrar QR").size(11.0).color(c.primary),
+                                                )
+                                                .fill(Color32::TRANSPARENT)
+                                                .rounding(Rounding::same(6.0));
+                                                if ui.add(qr_btn).clicked() {
+                                                    self.show_qr = true;
+                                                }
+                                            });
                                         });
                                     });
+
+                                    // Avatar color swatches
+                                    ui.add_space(10.0);
+                                    ui.horizontal_wrapped(|ui| {
+                                        ui.label(RichText::new("Color").size(13.0).color(c.text_secondary));
+                                        ui.add_space(4.0);
+                                        for color in AVATAR_COLORS {
+                                            let (rect, resp) = ui.allocate_exact_size(Vec2::splat(24.0), egui::Sense::click());
+                                            ui.painter().circle_filled(rect.center(), 10.0, avatar_color32(color));
+                                            if color == user.avatar_color {
+                                                ui.painter().circle_stroke(rect.center(), 11.5, Stroke::new(2.0, c.text_primary));
+                                            } else if resp.hovered() {
+                                                ui.painter().circle_stroke(rect.center(), 11.5, Stroke::new(1.0, c.border));
+                                            }
+                                            if resp.clicked() && color != user.avatar_color {
+                                                action = SettingsAction::SetAvatarColor(color);
+                                            }
+                                        }
+                                    });
+
+                                    ui.add_space(12.0);
+                                    ui.separator();
+                                    ui.add_space(6.0);
+
+                                    // Stats strip
+                                    ui.columns(4, |cols| {
+                                        for (col, (value, label)) in cols.iter_mut().zip([
+                                            (self.stats.friends, "Amigos"),
+                                            (self.stats.acquaintances, "Conocidos"),
+                                            (self.stats.chats, "Chats"),
+                                            (self.stats.messages_sent, "Enviados"),
+                                        ]) {
+                                            col.vertical_centered(|ui| {
+                                                ui.label(
+                                                    RichText::new(format_thousands(value, &self.currency))
+                                                        .size(16.0)
+                                                        .strong()
+                                                        .color(c.text_primary),
+                                                );
+                                                ui.label(RichText::new(label).size(11.0).color(c.text_muted));
+                                            });
+                                        }
+                                    });
                                 });
 
                                 ui.add_space(12.0);
@@ -132,25 +278,26 @@ impl SettingsScreen {
                                             .color(c.text_primary),
                                     );
                                     ui.add_space(8.0);
-                                    ui.horizontal(|ui| {
-                                        ui.add(
-                                            egui::TextEdit::singleline(&mut self.display_name)
-                                                .desired_width(ui.available_width() - 90.0),
-                                        );
-                                        let save_btn = egui::Button::new(
-                                            RichText::new("Guardar").size(13.0).color(Color32::WHITE),
-                                        )
-                                        .fill(c.primary)
-                                        .rounding(Rounding::same(8.0));
-                                        if ui.add(save_btn).clicked() {
-                                            let new_name = self.display_name.trim().to_string();
-                                            if new_name.is_empty() {
-                                                self.name_error = Some("El nombre no puede estar vacío".into());
-                                            } else {
-                                                action = SettingsAction::UpdateDisplayName(new_name);
+                                    // Saved when the field loses focus (or on Enter), like the theme toggle
+                                    let name_resp = ui.add(
+                                        egui::TextEdit::singleline(&mut self.display_name)
+                                            .hint_text("Se guarda al salir del campo")
+                                            .desired_width(f32::INFINITY),
+                                    );
+                                    if name_resp.changed() {
+                                        self.name_error = None;
+                                        self.name_success = None;
+                                    }
+                                    if name_resp.lost_focus() {
+                                        match display_name_on_blur(&self.display_name, &user.display_name) {
+                                            Ok(Some(new_name)) => action = SettingsAction::UpdateDisplayName(new_name),
+                                            Ok(None) => {}
+                                            Err(e) => {
+                                                self.name_error = Some(e);
+                                                self.display_name = user.display_name.clone();
                                             }
                                         }
-                                    });
+                                    }
                                     if let Some(ref e) = self.name_error {
                                         ui.label(RichText::new(e).color(c.danger).size(12.0));
                                     }
@@ -230,25 +377,371 @@ impl SettingsScreen {
                                     );
                                     ui.add_space(8.0);
                                     ui.horizontal(|ui| {
-                                        let is_dark = theme == &AppTheme::Dark;
                                         ui.label(
-                                            RichText::new(if is_dark { "🌙 Modo Oscuro" } else { "☀️ Modo Claro" })
+                                            RichText::new("Tema")
+                                                .size(14.0)
+                                                .color(c.text_secondary),
+                                        );
+                                        ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                                            for option in AppTheme::ALL.into_iter().rev() {
+                                                let selected = theme == &option;
+                                                let btn = egui::Button::new(
+                                                    RichText::new(option.label())
+                                                        .size(12.0)
+                                                        .color(if selected { Color32::WHITE } else { c.text_secondary }),
+                                                )
+                                                .fill(if selected { c.primary } else { c.bg_input })
+                                                .rounding(Rounding::same(8.0))
+                                                .min_size(Vec2::new(80.0, 30.0));
+                                                if ui.add(btn).clicked() && !selected {
+                                                    action = SettingsAction::SetTheme(option);
+                                                }
+                                            }
+                                        });
+                                    });
+
+                                    ui.add_space(10.0);
+                                    ui.horizontal(|ui| {
+                                        ui.label(
+                                            RichText::new("Color de acento")
                                                 .size(14.0)
                                                 .color(c.text_secondary),
                                         );
                                         ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
-                                            let toggle_label = if is_dark { "Cambiar a Claro" } else { "Cambiar a Oscuro" };
-                                            let toggle_btn = egui::Button::new(
-                                                RichText::new(toggle_label).size(13.0).color(Color32::WHITE),
+                                            let mut picked = c.primary;
+                                            // Same id the picker gives its popup
+                                            let popup_id = ui.auto_id_with("popup");
+                                            let resp = egui::color_picker::color_edit_button_srgba(
+                                                ui,
+                                                &mut picked,
+                                                egui::color_picker::Alpha::Opaque,
+                                            );
+                                            if resp.changed() {
+                                                let [r, g, b, _] = picked.to_array();
+                                                self.accent_editing = true;
+                                                action = SettingsAction::PreviewAccentColor(pack_rgba(r, g, b, 0xFF));
+                                            } else if self.accent_editing && !ui.memory(|m| m.is_popup_open(popup_id)) {
+                                                self.accent_editing = false;
+                                                action = SettingsAction::SetAccentColor(self.accent_color);
+                                            }
+                                            if self.accent_color.is_some() {
+                                                let reset_btn = egui::Button::new(
+                                                    RichText::new("Restablecer").size(12.0).color(c.text_secondary),
+                                                )
+                                                .fill(c.bg_input)
+                                                .rounding(Rounding::same(8.0));
+                                                if ui.add(reset_btn).clicked() {
+                                                    action = SettingsAction::SetAccentColor(None);
+                                                }
+                                            }
+                                        });
+                                    });
+
+                                    ui.add_space(10.0);
+                                    ui.horizontal(|ui| {
+                                        ui.label(
+                                            RichText::new("Densidad de listas")
+                                                .size(14.0)
+                                                .color(c.text_secondary),
+                                        );
+                                        ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                                            for density in ListDensity::ALL.into_iter().rev() {
+                                                let selected = self.density == density;
+                                                let btn = egui::Button::new(
+                                                    RichText::new(density.label())
+                                                        .size(12.0)
+                                                        .color(if selected { Color32::WHITE } else { c.text_secondary }),
+                                                )
+                                                .fill(if selected { c.primary } else { c.bg_input })
+                                                .rounding(Rounding::same(8.0))
+                                                .min_size(Vec2::new(70.0, 30.0));
+                                                if ui.add(btn).clicked() && !selected {
+                                                    action = SettingsAction::SetDensity(density);
+                                                }
+                                            }
+                                        });
+                                    });
+
+                                    ui.add_space(10.0);
+                                    ui.horizontal(|ui| {
+                                        ui.label(
+                                            RichText::new("Moneda")
+                                                .size(14.0)
+                                                .color(c.text_secondary),
+                                        );
+                                        ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                                            let label = |code: &str| format!("{} ({})", code, currency(code).symbol.trim());
+                                            let mut picked = self.currency.clone();
+                                            egui::ComboBox::from_id_source("settings_currency")
+                                                .selected_text(label(&picked))
+                                                .show_ui(ui, |ui| {
+                                                    for cur in &CURRENCIES {
+                                                        ui.selectable_value(&mut picked, cur.code.to_string(), label(cur.code));
+                                                    }
+                                                });
+                                            if picked != self.currency {
+                                                action = SettingsAction::SetCurrency(picked);
+                                            }
+                                        });
+                                    });
+
+                                    ui.add_space(10.0);
+                                    ui.horizontal(|ui| {
+                                        ui.label(
+                                            RichText::new("Tamaño de texto")
+                                                .size(14.0)
+                                                .color(c.text_secondary),
+                                        );
+                                        ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                                            // Multiplies the system text scale
+                                            let resp = ui.add(
+                                                egui::Slider::new(&mut self.font_size, MIN_BASE_FONT_SIZE..=MAX_BASE_FONT_SIZE)
+                                                    .step_by(1.0)
+                                                    .suffix(" pt"),
+                                            );
+                                            // Applied once let go: the zoom would move the
+                                            // slider under the pointer mid-drag
+                                            if resp.drag_stopped() || (resp.changed() && !resp.dragged()) {
+                                                action = SettingsAction::SetFontSize(self.font_size);
+                                            }
+                                        });
+                                    });
+
+                                    ui.add_space(10.0);
+                                    ui.horizontal(|ui| {
+                                        ui.label(
+                                            RichText::new("Agrupar mensajes")
+                                                .size(14.0)
+                                                .color(c.text_secondary),
+                                        )
+                                        .on_hover_text("Mensajes seguidos del mismo remitente dentro de este margen se apilan");
+                                        ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                                            let resp = ui.add(
+                                                egui::Slider::new(&mut self.group_window_mins, 0..=30)
+                                                    .suffix(" min"),
+                                            );
+                                            if resp.changed() {
+                                                action = SettingsAction::SetGroupWindow(self.group_window_mins);
+                                            }
+                                        });
+                                    });
+
+                                    ui.add_space(10.0);
+                                    let mut autoformat = self.text_autoformat;
+                                    if ui
+                                        .checkbox(&mut autoformat, "Convertir emoticonos en emoji")
+                                        .on_hover_text("Al enviar, :) se convierte en 🙂 (los enlaces no se tocan)")
+                                        .changed()
+                                    {
+                                        action = SettingsAction::SetTextAutoformat(autoformat);
+                                    }
+                                    let mut copy_links = self.copy_links;
+                                    if ui
+                                        .checkbox(&mut copy_links, "Copiar enlaces en vez de abrirlos")
+                                        .on_hover_text("Al tocar un enlace en un mensaje se copia al portapapeles")
+                                        .changed()
+                                    {
+                                        action = SettingsAction::SetCopyLinks(copy_links);
+                                    }
+                                });
+
+                                ui.add_space(12.0);
+
+                                // ── Notifications ──────────────────────────────
+                                section_card(ui, &c, |ui| {
+                                    ui.label(
+                                        RichText::new("Notificaciones")
+                                            .size(15.0)
+                                            .strong()
+                                            .color(c.text_primary),
+                                    );
+                                    ui.add_space(8.0);
+                                    let mut enabled = self.notifications_enabled;
+                                    if ui
+                                        .checkbox(&mut enabled, "Avisar de mensajes nuevos")
+                                        .on_hover_text("Muestra un aviso cuando llega un mensaje a un chat que no tienes abierto")
+                                        .changed()
+                                    {
+                                        action = SettingsAction::ToggleNotifications(enabled);
+                                    }
+                                });
+
+                                ui.add_space(12.0);
+
+                                // ── Privacy ────────────────────────────────────
+                                section_card(ui, &c, |ui| {
+                                    ui.label(
+                                        RichText::new("Privacidad")
+                                            .size(15.0)
+                                            .strong()
+                                            .color(c.text_primary),
+                                    );
+                                    ui.add_space(8.0);
+                                    let mut receipts = self.read_receipts;
+                                    if ui
+                                        .checkbox(&mut receipts, "Confirmaciones de lectura")
+                                        .on_hover_text("Si las desactivas, tampoco verás cuándo leen tus mensajes")
+                                        .changed()
+                                    {
+                                        action = SettingsAction::SetReadReceipts(receipts);
+                                    }
+                                    let mut confirm = self.confirm_acquaintance_send;
+                                    if ui
+                                        .checkbox(&mut confirm, "Confirmar antes de escribir a conocidos")
+                                        .on_hover_text("Pide un toque extra antes del primer mensaje en cada chat con un conocido")
+                                        .changed()
+                                    {
+                                        action = SettingsAction::SetConfirmAcquaintanceSend(confirm);
+                                    }
+                                });
+
+                                ui.add_space(12.0);
+
+                                // ── Maintenance ────────────────────────────────
+                                section_card(ui, &c, |ui| {
+                                    ui.label(
+                                        RichText::new("Mantenimiento")
+                                            .size(15.0)
+                                            .strong()
+                                            .color(c.text_primary),
+                                    );
+                                    ui.add_space(8.0);
+                                    ui.horizontal(|ui| {
+                                        ui.label(
+                                            RichText::new(format!("Chats sin contacto: {}", self.orphaned_chats))
+                                                .size(14.0)
+                                                .color(c.text_secondary),
+                                        )
+                                        .on_hover_text("Chats con personas que ya no están en tus contactos");
+                                        ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                                            let purge_btn = egui::Button::new(
+                                                RichText::new("🧹 Limpiar").size(13.0).color(Color32::WHITE),
                                             )
-                                            .fill(c.primary)
+                                            .fill(if self.orphaned_chats > 0 { c.warning } else { c.text_muted })
                                             .rounding(Rounding::same(8.0))
-                                            .min_size(Vec2::new(150.0, 34.0));
-                                            if ui.add(toggle_btn).clicked() {
-                                                action = SettingsAction::ToggleTheme;
+                                            .min_size(Vec2::new(110.0, 32.0));
+                                            if ui.add(purge_btn).clicked() && self.orphaned_chats > 0 {
+                                                action = SettingsAction::PurgeOrphanedChats;
                                             }
                                         });
                                     });
+                                    if let Some(ref msg) = self.maintenance_msg {
+                                        ui.label(RichText::new(format!("✓ {}", msg)).color(c.success).size(12.0));
+                                    }
+                                });
+
+                                ui.add_space(12.0);
+
+                                // ── Backup ─────────────────────────────────────
+                                section_card(ui, &c, |ui| {
+                                    ui.label(
+                                        RichText::new("Respaldo")
+                                            .size(15.0)
+                                            .strong()
+                                            .color(c.text_primary),
+                                    );
+                                    ui.add_space(4.0);
+                                    ui.label(
+                                        RichText::new("Guarda todos los datos en un archivo para llevarlos a otro teléfono.")
+                                            .size(13.0)
+                                            .color(c.text_secondary),
+                                    );
+                                    ui.add_space(8.0);
+                                    ui.horizontal(|ui| {
+                                        let backup_btn = egui::Button::new(
+                                            RichText::new("💾 Respaldar datos").size(13.0).color(Color32::WHITE),
+                                        )
+                                        .fill(c.accent)
+                                        .rounding(Rounding::same(8.0))
+                                        .min_size(Vec2::new(150.0, 32.0));
+                                        if ui.add(backup_btn).clicked() {
+                                            self.backup_msg = None;
+                                            self.backup_error = None;
+                                            #[cfg(not(target_os = "android"))]
+                                            if let Some(path) = rfd::FileDialog::new()
+                                                .set_title("Respaldar datos")
+                                                .set_file_name(chrono::Local::now().format("nimbuzyn-respaldo-%Y%m%d.db").to_string())
+                                                .save_file()
+                                            {
+                                                action = SettingsAction::BackupTo(path.to_string_lossy().into_owned());
+                                            }
+                                            #[cfg(target_os = "android")]
+                                            {
+                                                self.backup_error = Some("Respaldar aún no está disponible en Android".into());
+                                            }
+                                        }
+                                        let restore_btn = egui::Button::new(
+                                            RichText::new("📂 Restaurar").size(13.0).color(c.text_primary),
+                                        )
+                                        .fill(c.bg_input)
+                                        .rounding(Rounding::same(8.0))
+                                        .min_size(Vec2::new(120.0, 32.0));
+                                        if ui.add(restore_btn).clicked() {
+                                            self.backup_msg = None;
+                                            self.backup_error = None;
+                                            #[cfg(not(target_os = "android"))]
+                                            {
+                                                self.restore_path = rfd::FileDialog::new()
+                                                    .set_title("Restaurar respaldo")
+                                                    .pick_file()
+                                                    .map(|p| p.to_string_lossy().into_owned());
+                                            }
+                                            #[cfg(target_os = "android")]
+                                            {
+                                                self.backup_error = Some("Restaurar aún no está disponible en Android".into());
+                                            }
+                                        }
+                                        let export_btn = egui::Button::new(
+                                            RichText::new("📤 Exportar mis datos").size(13.0).color(c.text_primary),
+                                        )
+                                        .fill(c.bg_input)
+                                        .rounding(Rounding::same(8.0))
+                                        .min_size(Vec2::new(150.0, 32.0));
+                                        if ui
+                                            .add(export_btn)
+                                            .on_hover_text("Contactos, chats, mensajes e inventario de esta cuenta en JSON")
+                                            .clicked()
+                                        {
+                                            self.backup_msg = None;
+                                            self.backup_error = None;
+                                            action = SettingsAction::ExportAccount;
+                                        }
+                                    });
+                                    if let Some(ref msg) = self.backup_msg {
+                                        ui.label(RichText::new(format!("✓ {}", msg)).color(c.success).size(12.0));
+                                    }
+                                    if let Some(ref e) = self.backup_error {
+                                        ui.label(RichText::new(format!("⚠ {}", e)).color(c.danger).size(12.0));
+                                    }
+                                });
+
+                                ui.add_space(12.0);
+
+                                // ── About ──────────────────────────────────────
+                                section_card(ui, &c, |ui| {
+                                    ui.label(
+                                        RichText::new("Acerca de")
+                                            .size(15.0)
+                                            .strong()
+                                            .color(c.text_primary),
+                                    );
+                                    ui.add_space(8.0);
+                                    let backup = self
+                                        .last_backup_at
+                                        .as_deref()
+                                        .map(format_datetime)
+                                        .unwrap_or_else(|| "Nunca".into());
+                                    egui::Grid::new("about_grid").num_columns(2).spacing([16.0, 4.0]).show(ui, |ui| {
+                                        for (label, value) in [
+                                            ("Versión", env!("CARGO_PKG_VERSION").to_string()),
+                                            ("Esquema de datos", format!("v{}", self.schema_version)),
+                                            ("Último respaldo", backup),
+                                        ] {
+                                            ui.label(RichText::new(label).size(13.0).color(c.text_secondary));
+                                            ui.label(RichText::new(value).size(13.0).color(c.text_primary));
+                                            ui.end_row();
+                                        }
+                                    });
                                 });
 
                                 ui.add_space(12.0);
@@ -264,6 +757,21 @@ impl SettingsScreen {
                                     if ui.add(logout_btn).clicked() {
                                         self.show_logout_confirm = true;
                                     }
+                                    ui.add_space(8.0);
+                                    let delete_btn = egui::Button::new(
+                                        RichText::new("Eliminar cuenta").size(13.0).color(c.danger),
+                                    )
+                                    .fill(Color32::TRANSPARENT)
+                                    .stroke(Stroke::new(1.0, c.danger))
+                                    .rounding(Rounding::same(10.0))
+                                    .min_size(Vec2::new(f32::INFINITY, 36.0));
+                                    if ui.add(delete_btn).clicked() {
+                                        self.show_delete_confirm = true;
+                                        self.delete_pass.clear();
+                                        self.delete_confirm_name.clear();
+                                        self.delete_secure_wipe = false;
+                                        self.delete_error = None;
+                                    }
                                 });
 
                                 ui.add_space(40.0);
@@ -319,10 +827,213 @@ impl SettingsScreen {
                 });
         }
 
+        // ── UID QR dialog ──────────────────────────────────────────────────
+        if self.show_qr {
+            let mut open = true;
+            egui::Window::new("Tu código")
+                .open(&mut open)
+                .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                .collapsible(false)
+                .resizable(false)
+                .frame(
+                    egui::Frame::window(&ctx.style())
+                        .fill(c.bg_card)
+                        .stroke(Stroke::new(1.0, c.border))
+                        .rounding(Rounding::same(14.0)),
+                )
+                .show(ctx, |ui| {
+                    ui.vertical_centered(|ui| {
+                        qr_code(ui, &user.uid, 220.0);
+                        ui.add_space(8.0);
+                        ui.label(RichText::new(&user.uid).size(15.0).strong().monospace().color(c.text_primary));
+                        ui.label(
+                            RichText::new("Muéstralo para que te agreguen como contacto")
+                                .size(12.0)
+                                .color(c.text_secondary),
+                        );
+                    });
+                });
+            self.show_qr = open;
+        }
+
+        // ── Restore confirmation dialog ────────────────────────────────────
+        if let Some(path) = self.restore_path.clone() {
+            egui::Window::new("¿Restaurar respaldo?")
+                .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                .collapsible(false)
+                .resizable(false)
+                .frame(
+                    egui::Frame::window(&ctx.style())
+                        .fill(c.bg_card)
+                        .stroke(Stroke::new(1.0, c.border))
+                        .rounding(Rounding::same(14.0)),
+                )
+                .show(ctx, |ui| {
+                    ui.set_max_width(340.0);
+                    ui.label(
+                        RichText::new(
+                            "Todos los datos actuales, de todas las cuentas, se reemplazarán por los del respaldo \
+                             y tendrás que iniciar sesión de nuevo.",
+                        )
+                        .color(c.text_secondary),
+                    );
+                    ui.add_space(6.0);
+                    ui.label(RichText::new(&path).size(12.0).color(c.text_muted));
+                    ui.add_space(16.0);
+                    ui.horizontal(|ui| {
+                        if ui
+                            .add(
+                                egui::Button::new("Cancelar")
+                                    .fill(c.bg_input)
+                                    .rounding(Rounding::same(8.0))
+                                    .min_size(Vec2::new(120.0, 38.0)),
+                            )
+                            .clicked()
+                        {
+                            self.restore_path = None;
+                        }
+                        if ui
+                            .add(
+                                egui::Button::new(RichText::new("Restaurar").color(Color32::WHITE))
+                                    .fill(c.danger)
+                                    .rounding(Rounding::same(8.0))
+                                    .min_size(Vec2::new(120.0, 38.0)),
+                            )
+                            .clicked()
+                        {
+                            self.restore_path = None;
+                            action = SettingsAction::RestoreFrom(path.clone());
+                        }
+                    });
+                });
+        }
+
+        // ── Delete account dialog ──────────────────────────────────────────
+        if self.show_delete_confirm {
+            egui::Window::new("¿Eliminar cuenta?")
+                .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+                .collapsible(false)
+                .resizable(false)
+                .frame(
+                    egui::Frame::window(&ctx.style())
+                        .fill(c.bg_card)
+                        .stroke(Stroke::new(1.0, c.border))
+                        .rounding(Rounding::same(14.0)),
+                )
+                .show(ctx, |ui| {
+                    ui.set_max_width(340.0);
+                    ui.label(
+                        RichText::new("Se borrarán tus contactos, chats, mensajes e inventario. No se puede deshacer.")
+                            .color(c.text_secondary),
+                    );
+                    ui.add_space(10.0);
+                    ui.label(
+                        RichText::new(format!("Escribe «{}» para confirmar", user.username))
+                            .size(12.0)
+                            .color(c.text_secondary),
+                    );
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.delete_confirm_name)
+                            .hint_text(&user.username)
+                            .desired_width(f32::INFINITY),
+                    );
+                    ui.add_space(6.0);
+                    ui.label(RichText::new("Contraseña").size(12.0).color(c.text_secondary));
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.delete_pass)
+                            .password(true)
+                            .desired_width(f32::INFINITY),
+                    );
+                    if self.is_last_account {
+                        ui.add_space(6.0);
+                        ui.checkbox(&mut self.delete_secure_wipe, "Borrado seguro")
+                            .on_hover_text("Sobrescribe y elimina el archivo de la base de datos");
+                    }
+                    if let Some(ref e) = self.delete_error {
+                        ui.label(RichText::new(format!("⚠ {}", e)).color(c.danger).size(12.0));
+                    }
+                    ui.add_space(16.0);
+                    ui.horizontal(|ui| {
+                        if ui
+                            .add(
+                                egui::Button::new("Cancelar")
+                                    .fill(c.bg_input)
+                                    .rounding(Rounding::same(8.0))
+                                    .min_size(Vec2::new(120.0, 38.0)),
+                            )
+                            .clicked()
+                        {
+                            self.show_delete_confirm = false;
+                            self.delete_pass.clear();
+                        }
+                        let confirmed = self.delete_confirm_name.trim() == user.username
+                            && !self.delete_pass.is_empty();
+                        if ui
+                            .add_enabled(
+                                confirmed,
+                                egui::Button::new(RichText::new("Eliminar").color(Color32::WHITE))
+                                    .fill(c.danger)
+                                    .rounding(Rounding::same(8.0))
+                                    .min_size(Vec2::new(120.0, 38.0)),
+                            )
+                            .clicked()
+                        {
+                            action = SettingsAction::DeleteAccount {
+                                password: self.delete_pass.clone(),
+                                secure_wipe: self.is_last_account && self.delete_secure_wipe,
+                            };
+                        }
+                    });
+                });
+        }
+
         action
     }
 }
 
+/// What leaving the display-name field should do: nothing if unchanged,
+/// save the trimmed name, or reject it (same rules as the database).
+fn display_name_on_blur(draft: &str, saved: &str) -> Result<Option<String>, String> {
+    let name = draft.trim();
+    if name.is_empty() {
+        Err("El nombre no puede estar vacío".into())
+    } else if name.chars().count() > MAX_DISPLAY_NAME_CHARS {
+        Err(format!("El nombre no puede tener más de {} caracteres", MAX_DISPLAY_NAME_CHARS))
+    } else if name == saved {
+        Ok(None)
+    } else {
+        Ok(Some(name.to_string()))
+    }
+}
+
+fn avatar_color32(color: u32) -> Color32 {
+    let (r, g, b, a) = unpack_rgba(color);
+    Color32::from_rgba_unmultiplied(r, g, b, a)
+}
+
+/// Paint `text` as a QR code `side` points wide: dark modules on white with
+/// the standard four-module quiet zone, so scanners read it in dark mode too.
+fn qr_code(ui: &mut egui::Ui, text: &str, side: f32) {
+    let Ok(code) = qrcode::QrCode::new(text.as_bytes()) else { return };
+    const QUIET: usize = 4;
+    let modules = code.width();
+    let cell = side / (modules + 2 * QUIET) as f32;
+
+    let (rect, _) = ui.allocate_exact_size(Vec2::splat(side), egui::Sense::hover());
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, Rounding::same(4.0), Color32::WHITE);
+    for (i, color) in code.to_colors().into_iter().enumerate() {
+        if color != qrcode::Color::Dark {
+            continue;
+        }
+        let (x, y) = (i % modules + QUIET, i / modules + QUIET);
+        let min = rect.min + Vec2::new(x as f32, y as f32) * cell;
+        // Round outward so neighbouring squares don't leave hairline gaps
+        let module = egui::Rect::from_min_max(min.floor(), (min + Vec2::splat(cell)).ceil());
+        painter.rect_filled(module, Rounding::ZERO, Color32::BLACK);
+    }
+}
+
 fn section_card(ui: &mut egui::Ui, c: &NimColors, add_contents: impl FnOnce(&mut egui::Ui)) {
     egui::Frame::none()
         .fill(c.bg_card)
@@ -334,3 +1045,18 @@ fn section_card(ui: &mut egui::Ui, c: &NimColors, add_contents: impl FnOnce(&mut
             add_contents(ui);
         });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_name_is_saved_on_blur_only_when_it_changed() {
+        assert_eq!(display_name_on_blur("Ana", "Ana"), Ok(None));
+        assert_eq!(display_name_on_blur("  Ana  ", "Ana"), Ok(None));
+        assert_eq!(display_name_on_blur(" Ana María ", "Ana"), Ok(Some("Ana María".into())));
+        assert!(display_name_on_blur("   ", "Ana").is_err());
+        assert!(display_name_on_blur(&"a".repeat(MAX_DISPLAY_NAME_CHARS + 1), "Ana").is_err());
+        assert!(display_name_on_blur(&"ñ".repeat(MAX_DISPLAY_NAME_CHARS), "Ana").is_ok());
+    }
+}
@@ -204,8 +204,7 @@ impl SplashScreen {
                     let dot_spacing = 14.0;
                     let dot_y = cy + 200.0;
                     for i in 0i32..3 {
-                        let phase = elapsed * 3.0 + i as f32 * 1.0;
-                        let pulse = (phase.sin() * 0.5 + 0.5) * global_alpha;
+                        let pulse = dot_pulse(elapsed, i as usize) * global_alpha;
                         painter.circle_filled(
                             egui::pos2(cx + (i - 1) as f32 * dot_spacing, dot_y),
                             4.0 * pulse,
@@ -401,3 +400,29 @@ fn ease_out_elastic(t: f32) -> f32 {
     let c4 = (2.0 * std::f32::consts::PI) / 3.0;
     2.0f32.powf(-10.0 * t) * ((t * 10.0 - 10.75) * c4).sin() + 1.0
 }
+
+/// Pulse (0..=1) of the `index`-th loading dot after `elapsed` seconds.
+/// Each dot lags the previous one, giving the "•••" wave; shared with the
+/// chat typing indicator.
+pub fn dot_pulse(elapsed: f32, index: usize) -> f32 {
+    let phase = elapsed * 3.0 + index as f32;
+    phase.sin() * 0.5 + 0.5
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dots_pulse_in_range_and_out_of_step() {
+        for step in 0..200 {
+            let t = step as f32 * 0.05;
+            assert!((0..3).all(|i| (0.0..=1.0).contains(&dot_pulse(t, i))));
+        }
+        assert!((dot_pulse(0.0, 0) - 0.5).abs() < 1e-6);
+        assert!((dot_pulse(0.5, 0) - (1.5f32.sin() * 0.5 + 0.5)).abs() < 1e-6);
+        // Each dot is the previous one a third of a second later
+        assert!((dot_pulse(1.0, 1) - dot_pulse(1.0 + 1.0 / 3.0, 0)).abs() < 1e-5);
+        assert!((dot_pulse(0.2, 0) - dot_pulse(0.2, 1)).abs() > 0.1);
+    }
+}
@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Receiver, Sender};
+
+/// Longest side of an image bubble's thumbnail, in pixels.
+pub const THUMB_MAX_PX: u32 = 200;
+
+enum Thumb {
+    Loading,
+    Ready(egui::TextureHandle),
+    Failed,
+}
+
+/// Image-message thumbnails keyed by file path. Files are decoded and
+/// downscaled on a worker thread so a chat full of photos doesn't stall
+/// scrolling; until a thumbnail is ready (or if it can't be decoded) the
+/// caller gets `None` and draws the plain icon bubble instead.
+pub struct ThumbnailCache {
+    entries: HashMap<String, Thumb>,
+    // Started on the first request; dropping the cache stops the worker
+    jobs: Option<Sender<String>>,
+    done_tx: Sender<(String, Option<egui::ColorImage>)>,
+    done_rx: Receiver<(String, Option<egui::ColorImage>)>,
+}
+
+impl Default for ThumbnailCache {
+    fn default() -> Self {
+        let (done_tx, done_rx) = mpsc::channel();
+        ThumbnailCache { entries: HashMap::new(), jobs: None, done_tx, done_rx }
+    }
+}
+
+impl ThumbnailCache {
+    /// The thumbnail for `path` if it's decoded; queues it otherwise.
+    pub fn get(&mut self, ctx: &egui::Context, path: &str) -> Option<egui::TextureHandle> {
+        while let Ok((done, image)) = self.done_rx.try_recv() {
+            let thumb = match image {
+                Some(image) => Thumb::Ready(ctx.load_texture(
+                    format!("thumb:{}", done),
+                    image,
+                    egui::TextureOptions::LINEAR,
+                )),
+                None => Thumb::Failed,
+            };
+            self.entries.insert(done, thumb);
+        }
+
+        match self.entries.get(path) {
+            Some(Thumb::Ready(tex)) => Some(tex.clone()),
+            Some(Thumb::Loading) | Some(Thumb::Failed) => None,
+            None => {
+                let jobs = self.jobs.get_or_insert_with(|| spawn_worker(ctx.clone(), self.done_tx.clone()));
+                let queued = jobs.send(path.to_string()).is_ok();
+                self.entries.insert(path.to_string(), if queued { Thumb::Loading } else { Thumb::Failed });
+                None
+            }
+        }
+    }
+}
+
+fn spawn_worker(ctx: egui::Context, done: Sender<(String, Option<egui::ColorImage>)>) -> Sender<String> {
+    let (jobs, queue) = mpsc::channel::<String>();
+    std::thread::spawn(move || {
+        for path in queue {
+            let image = decode_thumbnail(&path);
+            if done.send((path, image)).is_err() {
+                break;
+            }
+            ctx.request_repaint();
+        }
+    });
+    jobs
+}
+
+/// Decode the file and shrink it to fit [`THUMB_MAX_PX`], keeping its
+/// aspect ratio. `None` when the file is missing or not a readable image.
+fn decode_thumbnail(path: &str) -> Option<egui::ColorImage> {
+    let rgba = image::open(path).ok()?.thumbnail(THUMB_MAX_PX, THUMB_MAX_PX).to_rgba8();
+    let size = [rgba.width() as usize, rgba.height() as usize];
+    Some(egui::ColorImage::from_rgba_unmultiplied(size, rgba.as_raw()))
+}
@@ -0,0 +1,118 @@
+use egui::{Color32, RichText, Rounding, Stroke, Vec2};
+use crate::models::AppTheme;
+use crate::theme::NimColors;
+
+/// Version of the running build.
+pub const APP_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Highlights per release, newest first. Only versions listed here get the
+/// "Novedades" modal; bumping the version without notes updates silently.
+const CHANGELOG: &[(&str, &[&str])] = &[
+    ("0.1.0", &[
+        "Borradores: lo que escribes en un chat se guarda al salir",
+        "Confirmaciones de lectura (✓✓), desactivables en Privacidad",
+        "Ctrl+K abre la búsqueda rápida de pantallas, contactos y productos",
+        "Precios por margen y alertas por punto de reorden en el inventario",
+        "Tamaño de texto ajustable y categorías con color",
+    ]),
+];
+
+/// `major.minor.patch` as numbers; a missing minor/patch counts as 0 and
+/// any pre-release/build suffix (`-beta`, `+abc`) is ignored.
+pub fn parse_version(v: &str) -> Option<(u64, u64, u64)> {
+    let core = v.trim().trim_start_matches('v').split(['-', '+']).next()?;
+    let mut parts = core.split('.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next().map_or(Some(0), |p| p.parse().ok())?;
+    let patch = parts.next().map_or(Some(0), |p| p.parse().ok())?;
+    if parts.next().is_some() {
+        return None;
+    }
+    Some((major, minor, patch))
+}
+
+/// Whether `current` is newer than the last version the user dismissed the
+/// modal for. Nothing stored (first run with this feature) counts as older;
+/// an unparseable stored value is treated the same so it gets overwritten.
+pub fn should_show(current: &str, last_seen: Option<&str>) -> bool {
+    let Some(current) = parse_version(current) else { return false };
+    match last_seen.and_then(parse_version) {
+        Some(seen) => current > seen,
+        None => true,
+    }
+}
+
+/// Release notes for `version`, if any were written.
+pub fn notes_for(version: &str) -> Option<&'static [&'static str]> {
+    CHANGELOG.iter().find(|(v, _)| *v == version).map(|(_, notes)| *notes)
+}
+
+/// One-off "Novedades" modal after an update.
+pub struct WhatsNew {
+    pub version: &'static str,
+    pub notes: &'static [&'static str],
+}
+
+impl WhatsNew {
+    /// Returns true once the user dismisses it.
+    pub fn show(&self, ctx: &egui::Context, theme: &AppTheme, accent: Option<u32>) -> bool {
+        let c = NimColors::for_theme(theme, accent);
+        let mut dismissed = false;
+
+        egui::Window::new("Novedades")
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .resizable(false)
+            .collapsible(false)
+            .frame(
+                egui::Frame::window(&ctx.style())
+                    .fill(c.bg_card)
+                    .stroke(Stroke::new(1.0, c.border))
+                    .rounding(Rounding::same(14.0)),
+            )
+            .show(ctx, |ui| {
+                ui.set_max_width(360.0);
+                ui.label(
+                    RichText::new(format!("✨ Nimbuzyn {}", self.version))
+                        .size(17.0)
+                        .strong()
+                        .color(c.text_primary),
+                );
+                ui.add_space(8.0);
+                for note in self.notes {
+                    ui.label(RichText::new(format!("• {}", note)).size(13.0).color(c.text_secondary));
+                }
+                ui.add_space(12.0);
+                let btn = egui::Button::new(RichText::new("Entendido").size(13.0).color(Color32::WHITE))
+                    .fill(c.primary)
+                    .rounding(Rounding::same(8.0))
+                    .min_size(Vec2::new(ui.available_width(), 34.0));
+                if ui.add(btn).clicked() {
+                    dismissed = true;
+                }
+            });
+
+        dismissed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions_compare_numerically() {
+        assert_eq!(parse_version("v1.2.3"), Some((1, 2, 3)));
+        assert_eq!(parse_version("2.0"), Some((2, 0, 0)));
+        assert_eq!(parse_version("1.4.0-beta+7"), Some((1, 4, 0)));
+        assert_eq!(parse_version("1.2.3.4"), None);
+        assert_eq!(parse_version("uno"), None);
+
+        assert!(should_show("1.10.0", Some("1.9.9")));
+        assert!(should_show("2.0.0", Some("1.99")));
+        assert!(!should_show("1.2.0", Some("1.2")));
+        assert!(!should_show("1.2.0", Some("1.10.0")));
+        assert!(should_show("1.0.0", None));
+        assert!(should_show("1.0.0", Some("basura")));
+        assert!(!should_show("basura", None));
+    }
+}
@@ -1,5 +1,5 @@
-use egui::{Color32, FontId, Rounding, Stroke, Style, Visuals, FontFamily};
-use crate::models::AppTheme;
+use egui::{Color32, FontId, Rounding, Stroke, Visuals, FontFamily};
+use crate::models::{unpack_rgba, AppTheme};
 
 /// Returns a bold, modern color scheme for Nimbuzyn
 pub struct NimColors {
@@ -94,21 +94,53 @@ impl NimColors {
         }
     }
 
-    pub fn for_theme(theme: &AppTheme) -> Self {
-        match theme {
-            AppTheme::Dark  => Self::dark(),
-            AppTheme::Light => Self::light(),
+    /// Dark palette on pure black, so OLED panels can switch pixels off.
+    pub fn amoled() -> Self {
+        NimColors {
+            bg_base:         Color32::BLACK,
+            bg_elevated:     Color32::from_rgb(0x0A, 0x0B, 0x0F),
+            bg_card:         Color32::from_rgb(0x11, 0x13, 0x19),
+            bg_input:        Color32::from_rgb(0x18, 0x1B, 0x24),
+
+            border:          Color32::from_rgb(0x22, 0x27, 0x34),
+            divider:         Color32::from_rgb(0x16, 0x19, 0x22),
+
+            star_inactive:   Color32::from_rgb(0x2E, 0x33, 0x42),
+            ..Self::dark()
         }
     }
+
+    /// Colors of `theme`, with `accent` (packed RGBA, see
+    /// [`crate::models::pack_rgba`]) replacing its primary blue when set.
+    pub fn for_theme(theme: &AppTheme, accent: Option<u32>) -> Self {
+        let mut colors = match theme {
+            AppTheme::Dark        => Self::dark(),
+            AppTheme::Light       => Self::light(),
+            AppTheme::AmoledBlack => Self::amoled(),
+        };
+        if let Some(accent) = accent {
+            let (r, g, b, _) = unpack_rgba(accent);
+            colors.primary = Color32::from_rgb(r, g, b);
+            colors.primary_hover = darken(colors.primary, 0.78);
+        }
+        colors
+    }
+}
+
+/// Scale each channel by `factor`, e.g. for a pressed/hover shade.
+fn darken(color: Color32, factor: f32) -> Color32 {
+    let scale = |v: u8| (v as f32 * factor).round() as u8;
+    Color32::from_rgb(scale(color.r()), scale(color.g()), scale(color.b()))
 }
 
-/// Apply custom egui visuals based on theme.
-pub fn apply_theme(ctx: &egui::Context, theme: &AppTheme) {
-    let c = NimColors::for_theme(theme);
+/// Apply custom egui visuals based on theme, with `accent` (packed RGBA)
+/// replacing the theme's primary blue when set.
+pub fn apply_theme(ctx: &egui::Context, theme: &AppTheme, accent: Option<u32>) {
+    let c = NimColors::for_theme(theme, accent);
 
     let mut visuals = match theme {
-        AppTheme::Dark  => Visuals::dark(),
-        AppTheme::Light => Visuals::light(),
+        AppTheme::Dark | AppTheme::AmoledBlack => Visuals::dark(),
+        AppTheme::Light                        => Visuals::light(),
     };
 
     visuals.window_fill       = c.bg_elevated;
@@ -146,7 +178,217 @@ pub fn apply_theme(ctx: &egui::Context, theme: &AppTheme) {
     ctx.set_visuals(visuals);
 }
 
+/// Label used for products without a category.
+pub const UNCATEGORIZED: &str = "Sin categoría";
+
+/// Stable chip color for a product category: the name (trimmed, case-folded)
+/// is hashed with FNV-1a into a hue, so it's the same on every device.
+/// Blank names and [`UNCATEGORIZED`] get a neutral gray.
+pub fn category_color(name: &str) -> Color32 {
+    let key = name.trim().to_lowercase();
+    if key.is_empty() || key == UNCATEGORIZED.to_lowercase() {
+        return Color32::from_rgb(0x8A, 0x90, 0x9C);
+    }
+    let hash = key.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |h, b| {
+        (h ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+    });
+    let hue = (hash % 360) as f32 / 360.0;
+    egui::ecolor::Hsva::new(hue, 0.55, 0.85, 1.0).into()
+}
+
 /// Convenience: styled button color
 pub fn primary_button_color(c: &NimColors) -> egui::Color32 {
     c.primary
 }
+
+// ──────────────────────────────────────────────
+// TEXT SIZE
+// ──────────────────────────────────────────────
+
+/// Base body size the layout was designed around (matches `AppSettings`).
+pub const DEFAULT_FONT_SIZE: f32 = 14.0;
+
+/// Range of the in-app text size slider, before the system scale applies.
+pub const MIN_BASE_FONT_SIZE: f32 = 12.0;
+pub const MAX_BASE_FONT_SIZE: f32 = 22.0;
+
+/// Bounds for the combined system × in-app text size.
+pub const MIN_FONT_SIZE: f32 = 10.0;
+pub const MAX_FONT_SIZE: f32 = 28.0;
+
+/// The user's in-app `base` size scaled by the platform's text scale,
+/// clamped so huge accessibility scales can't break the layout.
+pub fn effective_font_size(base: f32, system_scale: f32) -> f32 {
+    let scale = if system_scale.is_finite() && system_scale > 0.0 { system_scale } else { 1.0 };
+    (base * scale).clamp(MIN_FONT_SIZE, MAX_FONT_SIZE)
+}
+
+/// The platform's text scale: Android's accessibility font scale. Desktop
+/// systems scale text through the display scale, which egui already
+/// applies, so there it's 1.
+pub fn system_font_scale() -> f32 {
+    #[cfg(target_os = "android")]
+    {
+        match android_font_scale() {
+            Ok(scale) => return scale,
+            Err(e) => log::warn!("No se pudo leer la escala de texto del sistema: {}", e),
+        }
+    }
+    1.0
+}
+
+/// `getResources().getConfiguration().fontScale` of the running activity.
+#[cfg(target_os = "android")]
+fn android_font_scale() -> anyhow::Result<f32> {
+    use jni::objects::JObject;
+    let ctx = ndk_context::android_context();
+    // SAFETY: the pointers come from the activity that is running us
+    let vm = unsafe { jni::JavaVM::from_raw(ctx.vm().cast()) }?;
+    let mut env = vm.attach_current_thread()?;
+    let activity = unsafe { JObject::from_raw(ctx.context().cast()) };
+    let resources = env
+        .call_method(&activity, "getResources", "()Landroid/content/res/Resources;", &[])?
+        .l()?;
+    let config = env
+        .call_method(&resources, "getConfiguration", "()Landroid/content/res/Configuration;", &[])?
+        .l()?;
+    Ok(env.get_field(&config, "fontScale", "F")?.f()?)
+}
+
+/// Make body text `size` points. Screens size most of their text
+/// explicitly (`RichText::size`) against [`DEFAULT_FONT_SIZE`], so this
+/// zooms the whole UI rather than only egui's text styles.
+pub fn apply_font_size(ctx: &egui::Context, size: f32) {
+    ctx.set_zoom_factor(size / DEFAULT_FONT_SIZE);
+}
+
+// ──────────────────────────────────────────────
+// TEXT FITTING
+// ──────────────────────────────────────────────
+
+/// Single-line wrapping that cuts at `max_w` and ends in "…".
+pub fn elide_wrap(max_w: f32) -> egui::text::TextWrapping {
+    egui::text::TextWrapping {
+        max_width: max_w.max(0.0),
+        max_rows: 1,
+        break_anywhere: true,
+        overflow_character: Some('…'),
+    }
+}
+
+/// Lay `text` out on one line no wider than `max_w`, ellipsized if it
+/// doesn't fit. Use for user-chosen names instead of raw `painter.text`.
+pub fn elide(
+    painter: &egui::Painter,
+    text: &str,
+    font: FontId,
+    color: Color32,
+    max_w: f32,
+) -> std::sync::Arc<egui::Galley> {
+    let mut job = egui::text::LayoutJob::simple_singleline(text.to_owned(), font, color);
+    job.wrap = elide_wrap(max_w);
+    painter.layout_job(job)
+}
+
+// ──────────────────────────────────────────────
+// TOASTS
+// ──────────────────────────────────────────────
+
+/// Draw the notice in `toast` (text, time it was first drawn) centered `y`
+/// points below the top of the screen, inside `frame`. It doesn't take
+/// clicks, and clears itself once it has been up for `secs`.
+pub fn show_toast(
+    ctx: &egui::Context,
+    id: &str,
+    toast: &mut Option<(String, Option<f64>)>,
+    secs: f64,
+    y: f32,
+    frame: egui::Frame,
+    label: impl FnOnce(&str) -> egui::RichText,
+) {
+    let Some((ref text, ref mut shown_at)) = *toast else { return };
+    let now = ctx.input(|i| i.time);
+    let since = *shown_at.get_or_insert(now);
+    if now - since > secs {
+        *toast = None;
+        return;
+    }
+    egui::Area::new(egui::Id::new(id))
+        .anchor(egui::Align2::CENTER_TOP, [0.0, y])
+        .order(egui::Order::Foreground)
+        .interactable(false)
+        .show(ctx, |ui| {
+            frame.show(ui, |ui| {
+                ui.label(label(text));
+            });
+        });
+    ctx.request_repaint();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn system_scale_multiplies_the_base_size() {
+        assert_eq!(effective_font_size(14.0, 1.0), 14.0);
+        assert_eq!(effective_font_size(14.0, 1.3), 14.0 * 1.3);
+        assert_eq!(effective_font_size(16.0, 0.85), 16.0 * 0.85);
+    }
+
+    #[test]
+    fn effective_size_is_clamped() {
+        assert_eq!(effective_font_size(12.0, 0.5), MIN_FONT_SIZE);
+        assert_eq!(effective_font_size(22.0, 2.0), MAX_FONT_SIZE);
+        assert_eq!(effective_font_size(MAX_BASE_FONT_SIZE, 1.0), MAX_BASE_FONT_SIZE);
+    }
+
+    #[test]
+    fn accent_replaces_the_primary_color_only_when_set() {
+        let plain = NimColors::for_theme(&AppTheme::Dark, None);
+        assert_eq!(plain.primary, NimColors::dark().primary);
+        let accent = crate::models::pack_rgba(0xFF, 0x40, 0x00, 0xFF);
+        let custom = NimColors::for_theme(&AppTheme::Dark, Some(accent));
+        assert_eq!(custom.primary, Color32::from_rgb(0xFF, 0x40, 0x00));
+        assert_eq!(custom.bg_base, plain.bg_base);
+        // Nothing global: the next call without an accent is back to the theme's
+        assert_eq!(NimColors::for_theme(&AppTheme::Light, None).primary, NimColors::light().primary);
+    }
+
+    #[test]
+    fn bogus_system_scale_is_ignored() {
+        assert_eq!(effective_font_size(14.0, 0.0), 14.0);
+        assert_eq!(effective_font_size(14.0, -1.0), 14.0);
+        assert_eq!(effective_font_size(14.0, f32::NAN), 14.0);
+    }
+
+    #[test]
+    fn category_colors_are_stable_and_spread_out() {
+        assert_eq!(category_color("Bebidas"), category_color("  bebidas "));
+        let names = ["Bebidas", "Limpieza", "Almacén", "Lácteos", "Panadería", "Verdulería", "Carnes", "Golosinas"];
+        let colors: std::collections::HashSet<_> = names.iter().map(|n| category_color(n).to_array()).collect();
+        assert!(colors.len() >= names.len() - 1);
+
+        let neutral = Color32::from_rgb(0x8A, 0x90, 0x9C);
+        assert_eq!(category_color(""), neutral);
+        assert_eq!(category_color("   "), neutral);
+        assert_eq!(category_color(&UNCATEGORIZED.to_uppercase()), neutral);
+        assert!(!colors.contains(&neutral.to_array()));
+    }
+
+    #[test]
+    fn long_names_are_ellipsized_within_the_width() {
+        let ctx = egui::Context::default();
+        let _ = ctx.run(Default::default(), |_| {});
+        let painter = egui::Painter::new(ctx, egui::LayerId::background(), egui::Rect::EVERYTHING);
+        let last_char = |g: &egui::Galley| g.rows.last().and_then(|r| r.glyphs.last()).map(|gl| gl.chr);
+
+        let long = elide(&painter, &"Distribuidora".repeat(10), FontId::proportional(14.0), Color32::WHITE, 120.0);
+        assert_eq!(long.rows.len(), 1);
+        assert!(long.size().x <= 120.0);
+        assert_eq!(last_char(&long), Some('…'));
+
+        let short = elide(&painter, "Ana", FontId::proportional(14.0), Color32::WHITE, 120.0);
+        assert_eq!(last_char(&short), Some('a'));
+    }
+}
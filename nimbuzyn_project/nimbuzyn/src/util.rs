@@ -0,0 +1,488 @@
+// ──────────────────────────────────────────────
+// SHARED FORMATTING HELPERS
+// ──────────────────────────────────────────────
+
+/// Group digits in thousands with the currency's separator:
+/// 1234567 → "1,234,567" (USD), "1.234.567" (COP).
+pub fn format_thousands(n: u64, currency_code: &str) -> String {
+    group_digits(&n.to_string(), currency(currency_code).thousands_sep)
+}
+
+fn group_digits(digits: &str, sep: char) -> String {
+    let mut out = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, ch) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(sep);
+        }
+        out.push(ch);
+    }
+    out
+}
+
+/// How amounts in a currency are written where it's used.
+pub struct Currency {
+    /// ISO 4217 code, as stored in the user's settings.
+    pub code: &'static str,
+    pub symbol: &'static str,
+    pub symbol_after: bool,
+    pub thousands_sep: char,
+    pub decimal_sep: char,
+    pub decimals: usize,
+}
+
+pub const DEFAULT_CURRENCY: &str = "USD";
+
+/// Currencies offered in settings; the first is the default.
+pub const CURRENCIES: [Currency; 8] = [
+    Currency { code: "USD", symbol: "$",   symbol_after: false, thousands_sep: ',', decimal_sep: '.', decimals: 2 },
+    Currency { code: "EUR", symbol: "€",   symbol_after: true,  thousands_sep: '.', decimal_sep: ',', decimals: 2 },
+    Currency { code: "COP", symbol: "$",   symbol_after: false, thousands_sep: '.', decimal_sep: ',', decimals: 0 },
+    Currency { code: "MXN", symbol: "$",   symbol_after: false, thousands_sep: ',', decimal_sep: '.', decimals: 2 },
+    Currency { code: "ARS", symbol: "$",   symbol_after: false, thousands_sep: '.', decimal_sep: ',', decimals: 2 },
+    Currency { code: "CLP", symbol: "$",   symbol_after: false, thousands_sep: '.', decimal_sep: ',', decimals: 0 },
+    Currency { code: "PEN", symbol: "S/ ", symbol_after: false, thousands_sep: ',', decimal_sep: '.', decimals: 2 },
+    Currency { code: "GBP", symbol: "£",   symbol_after: false, thousands_sep: ',', decimal_sep: '.', decimals: 2 },
+];
+
+/// Formatting rules for `code`; unknown codes fall back to the default.
+pub fn currency(code: &str) -> &'static Currency {
+    CURRENCIES.iter().find(|c| c.code == code).unwrap_or(&CURRENCIES[0])
+}
+
+/// Amount with the currency's symbol, grouping and decimals:
+/// 1234.5 → "$1,234.50" (USD), "1.234,50 €" (EUR), "$1.235" (COP).
+pub fn format_money(value: f64, currency_code: &str) -> String {
+    let cur = currency(currency_code);
+    let rounded = format!("{:.*}", cur.decimals, value.abs());
+    let (int_part, frac) = rounded.split_once('.').unwrap_or((&rounded, ""));
+    let mut number = group_digits(int_part, cur.thousands_sep);
+    if !frac.is_empty() {
+        number.push(cur.decimal_sep);
+        number.push_str(frac);
+    }
+    // No "-$0.00" for tiny negatives that round away
+    let sign = if value < 0.0 && rounded.bytes().any(|b| (b'1'..=b'9').contains(&b)) { "-" } else { "" };
+    if cur.symbol_after {
+        format!("{}{} {}", sign, number, cur.symbol)
+    } else {
+        format!("{}{}{}", sign, cur.symbol, number)
+    }
+}
+
+/// [`format_money`] short enough for a narrow list column: from 100 000 up
+/// the amount is scaled with one decimal and a suffix, 1234567 → "$1.2 M"
+/// (USD), "1,2 M €" (EUR). Smaller amounts are written in full.
+pub fn format_money_compact(value: f64, currency_code: &str) -> String {
+    const STEPS: [(f64, &str); 3] = [(1e9, "MM"), (1e6, "M"), (1e3, "k")];
+    let abs = value.abs();
+    if !abs.is_finite() || abs < 100_000.0 {
+        return format_money(value, currency_code);
+    }
+    let cur = currency(currency_code);
+    let mut step = STEPS.iter().position(|(div, _)| abs >= *div).unwrap_or(STEPS.len() - 1);
+    // 999 950 would round to "1000.0 k"; write "1.0 M" instead
+    if step > 0 && abs / STEPS[step].0 >= 999.95 {
+        step -= 1;
+    }
+    let (div, suffix) = STEPS[step];
+    let scaled = format!("{:.1}", abs / div).replace('.', &cur.decimal_sep.to_string());
+    let sign = if value < 0.0 { "-" } else { "" };
+    if cur.symbol_after {
+        format!("{}{} {} {}", sign, scaled, suffix, cur.symbol)
+    } else {
+        format!("{}{}{} {}", sign, cur.symbol, scaled, suffix)
+    }
+}
+
+/// Stock quantity with at most two decimals and no trailing zeros:
+/// 12 → "12", 0.25 → "0.25", 0.1 + 0.2 → "0.3".
+pub fn format_quantity(q: f64) -> String {
+    let s = format!("{:.2}", q);
+    let s = s.trim_end_matches('0').trim_end_matches('.');
+    if s == "-0" { "0".to_string() } else { s.to_string() }
+}
+
+/// Human-readable byte count: "512 B", "1.5 KB", "23.0 MB", "1.2 GB".
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 3] = ["KB", "MB", "GB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+/// `file://` URL for an absolute local path, Unix or Windows: backslashes
+/// become `/` and anything outside the unreserved set is percent-encoded
+/// (`C:\a b.pdf` → `file:///C:/a%20b.pdf`). `None` for a relative path.
+pub fn file_url(path: &str) -> Option<String> {
+    let path = path.replace('\\', "/");
+    let bytes = path.as_bytes();
+    let prefix = if path.starts_with("//") {
+        "file:" // UNC share: //server/share
+    } else if path.starts_with('/') {
+        "file://"
+    } else if bytes.len() > 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' && bytes[2] == b'/' {
+        "file:///"
+    } else {
+        return None;
+    };
+    let drive_colon = (prefix == "file:///").then_some(1);
+    let mut url = prefix.to_string();
+    for (i, &b) in bytes.iter().enumerate() {
+        if b.is_ascii_alphanumeric() || matches!(b, b'/' | b'-' | b'.' | b'_' | b'~') || drive_colon == Some(i) {
+            url.push(b as char);
+        } else {
+            url.push_str(&format!("%{:02X}", b));
+        }
+    }
+    Some(url)
+}
+
+/// Letter shown in an avatar circle: the first user-perceived character of
+/// the trimmed name, uppercased, or "?" for a blank name. Keeps combining
+/// accents, emoji modifiers/ZWJ sequences and flag pairs together.
+pub fn avatar_initial(name: &str) -> String {
+    let mut chars = name.trim().chars().peekable();
+    let Some(first) = chars.next() else { return "?".to_string() };
+    let mut initial: String = first.to_uppercase().collect();
+    let is_regional = |c: char| ('\u{1F1E6}'..='\u{1F1FF}').contains(&c);
+    if is_regional(first) {
+        if let Some(second) = chars.next_if(|&c| is_regional(c)) {
+            initial.push(second);
+        }
+        return initial;
+    }
+    while let Some(&next) = chars.peek() {
+        match next {
+            // Combining marks, variation selectors, skin tones
+            '\u{0300}'..='\u{036F}'
+            | '\u{1AB0}'..='\u{1AFF}'
+            | '\u{20D0}'..='\u{20FF}'
+            | '\u{FE00}'..='\u{FE0F}'
+            | '\u{FE20}'..='\u{FE2F}'
+            | '\u{1F3FB}'..='\u{1F3FF}' => {
+                initial.push(next);
+                chars.next();
+            }
+            // Zero-width joiner glues the next emoji on
+            '\u{200D}' => {
+                initial.push(next);
+                chars.next();
+                if let Some(joined) = chars.next() {
+                    initial.push(joined);
+                }
+            }
+            _ => break,
+        }
+    }
+    initial
+}
+
+/// ASCII emoticons and the emoji they turn into.
+const EMOTICONS: &[(&str, &str)] = &[
+    (":)", "🙂"), (":-)", "🙂"),
+    (":(", "🙁"), (":-(", "🙁"),
+    (":D", "😄"), (":-D", "😄"),
+    (";)", "😉"), (";-)", "😉"),
+    (":P", "😛"), (":p", "😛"),
+    (":O", "😮"), (":o", "😮"),
+    (":'(", "😢"),
+    ("<3", "❤️"),
+];
+
+/// Replace emoticons standing as their own word (`hola :)`) with emoji.
+/// Spacing is kept as typed. Only whole words are converted, so an
+/// emoticon inside a link (`http://x.co/:)`) survives intact.
+pub fn apply_autoformat(input: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+    for piece in input.split_inclusive(char::is_whitespace) {
+        let word = piece.trim_end_matches(char::is_whitespace);
+        match EMOTICONS.iter().find(|(ascii, _)| *ascii == word) {
+            Some((_, emoji)) => {
+                out.push_str(emoji);
+                out.push_str(&piece[word.len()..]);
+            }
+            _ => out.push_str(piece),
+        }
+    }
+    out
+}
+
+/// A run of message text: shown as-is, or a URL to open when tapped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextSpan<'a> {
+    Plain(&'a str),
+    Link(&'a str),
+}
+
+/// Split `text` into plain runs and `http(s)://` links. A link starts at a
+/// word boundary and runs to the next whitespace, minus sentence punctuation
+/// stuck to its end (`mira https://x.co/a.` links `https://x.co/a`). A
+/// closing `)` is only kept when it pairs with a `(` inside the URL.
+pub fn linkify(text: &str) -> Vec<TextSpan<'_>> {
+    let mut spans = Vec::new();
+    let mut plain_start = 0;
+    let mut pos = 0;
+    while let Some(found) = ["http://", "https://"]
+        .iter()
+        .filter_map(|scheme| text[pos..].find(scheme).map(|i| (pos + i, scheme.len())))
+        .min()
+    {
+        let (start, scheme_len) = found;
+        let at_boundary = !matches!(text[..start].chars().next_back(), Some(ch) if ch.is_alphanumeric());
+        let run_end = text[start..].find(char::is_whitespace).map_or(text.len(), |i| start + i);
+        let mut url = &text[start..run_end];
+        loop {
+            let trimmed = url.trim_end_matches(['.', ',', ';', ':', '!', '?', '"', '\'', '»']);
+            let trimmed = match trimmed.strip_suffix(')') {
+                Some(inner) if trimmed.matches(')').count() > trimmed.matches('(').count() => inner,
+                _ => trimmed,
+            };
+            if trimmed.len() == url.len() {
+                break;
+            }
+            url = trimmed;
+        }
+        if at_boundary && url.len() > scheme_len {
+            if plain_start < start {
+                spans.push(TextSpan::Plain(&text[plain_start..start]));
+            }
+            spans.push(TextSpan::Link(url));
+            plain_start = start + url.len();
+        }
+        pos = run_end.max(start + scheme_len);
+    }
+    if plain_start < text.len() {
+        spans.push(TextSpan::Plain(&text[plain_start..]));
+    }
+    spans
+}
+
+/// RFC 3339 timestamp shown in local time as "dd/mm/aaaa hh:mm:ss".
+/// Unparseable input is returned unchanged.
+pub fn format_datetime(rfc3339: &str) -> String {
+    chrono::DateTime::parse_from_rfc3339(rfc3339)
+        .map(|dt| dt.with_timezone(&chrono::Local).format("%d/%m/%Y %H:%M:%S").to_string())
+        .unwrap_or_else(|_| rfc3339.to_string())
+}
+
+const MONTHS_SHORT: [&str; 12] = [
+    "ene", "feb", "mar", "abr", "may", "jun", "jul", "ago", "sep", "oct", "nov", "dic",
+];
+
+/// Local calendar day of an RFC 3339 timestamp, `None` if it doesn't parse.
+pub fn local_day(rfc3339: &str) -> Option<chrono::NaiveDate> {
+    chrono::DateTime::parse_from_rfc3339(rfc3339)
+        .ok()
+        .map(|dt| dt.with_timezone(&chrono::Local).date_naive())
+}
+
+/// Chat day separator text: "Hoy", "Ayer" or "12 mar 2024".
+pub fn day_label(day: chrono::NaiveDate, today: chrono::NaiveDate) -> String {
+    use chrono::Datelike;
+    if day == today {
+        "Hoy".to_string()
+    } else if today.pred_opt() == Some(day) {
+        "Ayer".to_string()
+    } else {
+        format!("{} {} {}", day.day(), MONTHS_SHORT[day.month0() as usize], day.year())
+    }
+}
+
+// ──────────────────────────────────────────────
+// CSV
+// ──────────────────────────────────────────────
+
+/// Quote a CSV field when it holds a delimiter, quote or line break.
+pub fn csv_field(s: &str) -> String {
+    if s.contains([',', ';', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+/// Split CSV text into records. Handles quoted fields (with `""` escapes and
+/// embedded newlines) and picks `;` as the delimiter when the header uses it,
+/// as many Spanish-locale exports do. Blank lines are dropped.
+pub fn parse_csv(text: &str) -> Vec<Vec<String>> {
+    let header = text.lines().next().unwrap_or("");
+    let delim = if header.matches(';').count() > header.matches(',').count() { ';' } else { ',' };
+
+    let mut records = Vec::new();
+    let mut record: Vec<String> = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = text.trim_start_matches('\u{feff}').chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        if in_quotes {
+            match ch {
+                '"' if chars.peek() == Some(&'"') => {
+                    field.push('"');
+                    chars.next();
+                }
+                '"' => in_quotes = false,
+                _ => field.push(ch),
+            }
+            continue;
+        }
+        match ch {
+            '"' => in_quotes = true,
+            c if c == delim => record.push(std::mem::take(&mut field)),
+            '\r' => {}
+            '\n' => {
+                record.push(std::mem::take(&mut field));
+                if record.iter().any(|f| !f.trim().is_empty()) {
+                    records.push(std::mem::take(&mut record));
+                } else {
+                    record.clear();
+                }
+            }
+            _ => field.push(ch),
+        }
+    }
+    record.push(field);
+    if record.iter().any(|f| !f.trim().is_empty()) {
+        records.push(record);
+    }
+    records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quantities_use_at_most_two_decimals() {
+        assert_eq!(format_quantity(12.0), "12");
+        assert_eq!(format_quantity(0.25), "0.25");
+        assert_eq!(format_quantity(0.1 + 0.2), "0.3");
+        assert_eq!(format_quantity(10.0 * 1.2), "12");
+        assert_eq!(format_quantity(-2.5), "-2.5");
+        assert_eq!(format_quantity(-0.001), "0");
+        assert_eq!(format_quantity(100.0), "100");
+    }
+
+    #[test]
+    fn thousands_follow_the_currency_separator() {
+        assert_eq!(format_thousands(1_234_567, "USD"), "1,234,567");
+        assert_eq!(format_thousands(1_234_567, "COP"), "1.234.567");
+        assert_eq!(format_thousands(999, "EUR"), "999");
+    }
+
+    #[test]
+    fn compact_money_leaves_small_amounts_in_full() {
+        assert_eq!(format_money_compact(99_999.5, "USD"), format_money(99_999.5, "USD"));
+        assert_eq!(format_money_compact(-1234.5, "EUR"), "-1.234,50 €");
+    }
+
+    #[test]
+    fn compact_money_scales_large_amounts() {
+        assert_eq!(format_money_compact(250_000.0, "USD"), "$250.0 k");
+        assert_eq!(format_money_compact(1_234_567.0, "USD"), "$1.2 M");
+        assert_eq!(format_money_compact(1_234_567.0, "EUR"), "1,2 M €");
+        assert_eq!(format_money_compact(-3_500_000_000.0, "COP"), "-$3,5 MM");
+        assert_eq!(format_money_compact(999_960.0, "USD"), "$1.0 M");
+    }
+
+    #[test]
+    fn every_emoticon_converts() {
+        for (ascii, emoji) in EMOTICONS {
+            assert_eq!(apply_autoformat(ascii), *emoji, "{}", ascii);
+            assert_eq!(apply_autoformat(&format!("hola {} chau", ascii)), format!("hola {} chau", emoji));
+        }
+    }
+
+    #[test]
+    fn autoformat_keeps_spacing_and_leaves_words_alone() {
+        assert_eq!(apply_autoformat("  :)\n<3  "), "  🙂\n❤️  ");
+        assert_eq!(apply_autoformat("hola:) a:Dios"), "hola:) a:Dios");
+        assert_eq!(apply_autoformat("sin emoticonos"), "sin emoticonos");
+    }
+
+    #[test]
+    fn autoformat_does_not_touch_links() {
+        for url in ["http://x.co/:)", "https://x.co/a:D", "www.x.co/;)", "https://x.co/?q=<3"] {
+            assert_eq!(apply_autoformat(url), url);
+            assert_eq!(apply_autoformat(&format!("mira {} :)", url)), format!("mira {} 🙂", url));
+        }
+    }
+
+    #[test]
+    fn links_drop_trailing_punctuation() {
+        use TextSpan::{Link, Plain};
+        assert_eq!(linkify("mira https://x.co/a."), vec![Plain("mira "), Link("https://x.co/a"), Plain(".")]);
+        assert_eq!(linkify("¿viste http://x.co/?!"), vec![Plain("¿viste "), Link("http://x.co/"), Plain("?!")]);
+        assert_eq!(linkify("«https://x.co/a»"), vec![Plain("«"), Link("https://x.co/a"), Plain("»")]);
+    }
+
+    #[test]
+    fn links_keep_only_balanced_parens() {
+        use TextSpan::{Link, Plain};
+        assert_eq!(linkify("https://x.co/Foo_(bar)"), vec![Link("https://x.co/Foo_(bar)")]);
+        assert_eq!(linkify("(ver https://x.co/a)"), vec![Plain("(ver "), Link("https://x.co/a"), Plain(")")]);
+        assert_eq!(
+            linkify("(https://x.co/Foo_(bar)))."),
+            vec![Plain("("), Link("https://x.co/Foo_(bar)"), Plain(")).")],
+        );
+    }
+
+    #[test]
+    fn links_start_at_a_word_boundary() {
+        use TextSpan::{Link, Plain};
+        assert_eq!(linkify("xhttps://x.co"), vec![Plain("xhttps://x.co")]);
+        assert_eq!(linkify("solo https:// nada"), vec![Plain("solo https:// nada")]);
+        assert_eq!(linkify("http://."), vec![Plain("http://.")]);
+    }
+
+    #[test]
+    fn every_link_in_a_message_is_found() {
+        use TextSpan::{Link, Plain};
+        assert_eq!(
+            linkify("http://a.co y https://b.co/x, listo"),
+            vec![Link("http://a.co"), Plain(" y "), Link("https://b.co/x"), Plain(", listo")],
+        );
+        assert_eq!(linkify("sin enlaces"), vec![Plain("sin enlaces")]);
+        assert_eq!(linkify(""), vec![]);
+    }
+
+    #[test]
+    fn file_urls_are_absolute_and_encoded() {
+        assert_eq!(file_url("/home/ana/Mis docs/#1.pdf").as_deref(), Some("file:///home/ana/Mis%20docs/%231.pdf"));
+        assert_eq!(file_url(r"C:\Users\Ana\año 2024.xlsx").as_deref(), Some("file:///C:/Users/Ana/a%C3%B1o%202024.xlsx"));
+        assert_eq!(file_url(r"\\servidor\compartido\a.txt").as_deref(), Some("file://servidor/compartido/a.txt"));
+        assert_eq!(file_url("files/a.txt"), None);
+        assert_eq!(file_url(""), None);
+    }
+
+    #[test]
+    fn sizes_switch_units_at_each_boundary() {
+        assert_eq!(human_size(0), "0 B");
+        assert_eq!(human_size(1023), "1023 B");
+        assert_eq!(human_size(1024), "1.0 KB");
+        assert_eq!(human_size(1536), "1.5 KB");
+        assert_eq!(human_size(1024 * 1024), "1.0 MB");
+        assert_eq!(human_size(1024 * 1024 * 1024), "1.0 GB");
+        assert_eq!(human_size(5 * 1024 * 1024 * 1024 * 1024), "5120.0 GB");
+    }
+
+    #[test]
+    fn avatar_initial_handles_blank_emoji_and_normal_names() {
+        assert_eq!(avatar_initial(""), "?");
+        assert_eq!(avatar_initial("   \t"), "?");
+        assert_eq!(avatar_initial("  ana"), "A");
+        assert_eq!(avatar_initial("élodie"), "É");
+        assert_eq!(avatar_initial("e\u{0301}mile"), "E\u{0301}");
+        assert_eq!(avatar_initial("😀 feliz"), "😀");
+        assert_eq!(avatar_initial("👍🏽 ok"), "👍🏽");
+        assert_eq!(avatar_initial("👩\u{200D}💻 dev"), "👩\u{200D}💻");
+        assert_eq!(avatar_initial("🇦🇷 Buenos Aires"), "🇦🇷");
+    }
+}
@@ -0,0 +1,70 @@
+// ──────────────────────────────────────────────
+// INPUT VALIDATION
+// ──────────────────────────────────────────────
+
+/// Shortest username accepted at registration.
+pub const MIN_USERNAME_LEN: usize = 3;
+
+/// Usernames nobody may register: they could pass for the app or its staff.
+pub const RESERVED_USERNAMES: &[&str] = &["admin", "root", "system", "nimbuzyn", "support"];
+
+/// The canonical form of a username being registered: trimmed and
+/// lowercased, made only of `a-z`, `0-9`, `_` and `.`, at least
+/// [`MIN_USERNAME_LEN`] long and not reserved. The error is ready to show.
+pub fn normalize_username(input: &str) -> Result<String, String> {
+    let username = input.trim().to_lowercase();
+    if username.is_empty() {
+        return Err("El usuario no puede estar vacío".into());
+    }
+    if let Some(bad) = username
+        .chars()
+        .find(|ch| !(ch.is_ascii_lowercase() || ch.is_ascii_digit() || *ch == '_' || *ch == '.'))
+    {
+        return Err(if bad.is_whitespace() {
+            "El usuario no puede contener espacios".into()
+        } else {
+            format!("El usuario no puede contener «{}»: usa letras, números, _ o .", bad)
+        });
+    }
+    if username.len() < MIN_USERNAME_LEN {
+        return Err(format!("El usuario debe tener al menos {} caracteres", MIN_USERNAME_LEN));
+    }
+    if RESERVED_USERNAMES.contains(&username.as_str()) {
+        return Err(format!("El nombre «{}» está reservado", username));
+    }
+    Ok(username)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn username_is_trimmed_and_lowercased() {
+        assert_eq!(normalize_username("  Ana.Maria_1 "), Ok("ana.maria_1".to_string()));
+        assert_eq!(normalize_username("BETO"), Ok("beto".to_string()));
+    }
+
+    #[test]
+    fn username_rejects_spaces_and_non_ascii() {
+        assert_eq!(normalize_username("ana maria"), Err("El usuario no puede contener espacios".to_string()));
+        assert!(normalize_username("muñoz").unwrap_err().contains("«ñ»"));
+        assert!(normalize_username("ana-maria").is_err());
+    }
+
+    #[test]
+    fn username_rejects_reserved_names_in_any_case() {
+        for name in RESERVED_USERNAMES {
+            assert!(normalize_username(name).is_err());
+            assert!(normalize_username(&name.to_uppercase()).is_err());
+        }
+    }
+
+    #[test]
+    fn username_needs_a_minimum_length() {
+        assert!(normalize_username("").is_err());
+        assert!(normalize_username("   ").is_err());
+        assert!(normalize_username("ab").is_err());
+        assert_eq!(normalize_username("abc"), Ok("abc".to_string()));
+    }
+}